@@ -0,0 +1,56 @@
+//! Resampling throughput, comparing the batched [`Resampler`] against running the scalar kernel on
+//! each channel separately. Run with `cargo bench --bench resample`.
+
+use clocked::resample::{resample, Interpolation, Resampler, FRAME_LOOKBACK};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nalgebra::DMatrix;
+
+const FRAMES: usize = 1024;
+const RATIO: f64 = 1.001;
+
+fn input(channels: usize) -> DMatrix<f32> {
+    DMatrix::from_fn(FRAMES * 2, channels, |row, channel| {
+        (row as f32 * 0.01 * (channel + 1) as f32).sin()
+    })
+}
+
+fn batched(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resample");
+
+    for channels in [2, 8, 32] {
+        let input = input(channels);
+        group.throughput(Throughput::Elements((FRAMES * channels) as u64));
+
+        group.bench_with_input(BenchmarkId::new("batched", channels), &input, |b, input| {
+            let mut resampler = Resampler::new(channels, Interpolation::Hermite);
+            let mut output = DMatrix::zeros(FRAMES, channels);
+
+            b.iter(|| resampler.process(input, &mut output, RATIO));
+        });
+
+        group.bench_with_input(BenchmarkId::new("scalar", channels), &input, |b, input| {
+            let mut histories = vec![[0.0; FRAME_LOOKBACK]; channels];
+            let mut output = DMatrix::<f32>::zeros(FRAMES, channels);
+
+            b.iter(|| {
+                for (channel, history) in histories.iter_mut().enumerate() {
+                    let column = input.column(channel);
+                    let mut samples = column.iter().copied();
+                    let mut time = 0.0;
+
+                    for frame in 0..FRAMES {
+                        let step = resample(RATIO, samples.by_ref(), history, time);
+
+                        output[(frame, channel)] = step.sample;
+                        time = step.time;
+                    }
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, batched);
+criterion_main!(benches);
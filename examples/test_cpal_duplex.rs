@@ -0,0 +1,64 @@
+use std::{sync::atomic::Ordering, thread, time::Duration};
+
+use clocked::cpal::{start_cpal_duplex, CpalConfig};
+use cpal::{
+    traits::{DeviceTrait, HostTrait},
+    BufferSize,
+};
+
+fn main() {
+    let host = cpal::default_host();
+    // both halves have to run on the same device (and clock)
+    let device = host
+        .devices()
+        .unwrap()
+        .find(|device| device.default_input_config().is_ok() && device.default_output_config().is_ok())
+        .expect("no device with both inputs and outputs found");
+
+    let supported_in_config = device.default_input_config().unwrap();
+    let supported_out_config = device.default_output_config().unwrap();
+
+    let in_config = supported_in_config.config();
+    let out_config = supported_out_config.config();
+
+    let buffer_size = match out_config.buffer_size {
+        BufferSize::Fixed(buffer_size) => buffer_size as usize,
+        BufferSize::Default => 512,
+    };
+
+    println!("buffer size: {}", buffer_size);
+    println!("input channels: {}", in_config.channels);
+    println!("output channels: {}", out_config.channels);
+
+    let mut duplex = start_cpal_duplex(
+        &device,
+        &in_config,
+        supported_in_config.sample_format(),
+        &out_config,
+        supported_out_config.sample_format(),
+        buffer_size * 2,
+        &CpalConfig::default(),
+    )
+    .unwrap();
+
+    duplex.measure_xruns.store(true, Ordering::Release);
+
+    let input_channels = duplex.input_channels();
+    let output_channels = duplex.output_channels();
+    let mut frame = vec![0.0; input_channels];
+
+    // pass through, wrapping input channels around if there are more outputs than inputs
+    loop {
+        while duplex.interleaved_in.slots() >= input_channels && duplex.interleaved_out.slots() >= output_channels {
+            for sample in frame.iter_mut() {
+                *sample = duplex.interleaved_in.pop().unwrap();
+            }
+
+            for channel in 0..output_channels {
+                duplex.interleaved_out.push(frame[channel % input_channels]).unwrap();
+            }
+        }
+
+        thread::sleep(Duration::from_millis(1));
+    }
+}
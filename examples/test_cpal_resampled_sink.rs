@@ -0,0 +1,57 @@
+use std::{f64::consts::TAU, sync::atomic::Ordering};
+
+use clocked::{
+    cpal::{start_cpal_sink_resampled, CpalConfig},
+    PacedProducer,
+};
+use cpal::{
+    traits::{DeviceTrait, HostTrait},
+    BufferSize, StreamConfig,
+};
+
+fn main() {
+    let host = cpal::default_host();
+    let output_device = host.default_output_device().unwrap();
+
+    let supported_config = output_device.default_output_config().unwrap();
+    let config = StreamConfig {
+        channels: 2,
+        sample_rate: cpal::SampleRate(44_100),
+        buffer_size: BufferSize::Fixed(512),
+    };
+
+    // the app runs at 48k, the device at 44.1k
+    let app_sample_rate = 48_000;
+    let buffer_size = 512;
+
+    println!("device sample rate: {}", config.sample_rate.0);
+    println!("app sample rate: {}", app_sample_rate);
+
+    let mut t_sin: f64 = 0.0;
+    let sink = start_cpal_sink_resampled(
+        &output_device,
+        &config,
+        supported_config.sample_format(),
+        app_sample_rate,
+        buffer_size * 4,
+        &CpalConfig::default(),
+    )
+    .unwrap();
+
+    sink.measure_xruns.store(true, Ordering::Release);
+
+    let channels = sink.channels();
+    let mut block = vec![0.0; buffer_size * channels];
+    let mut producer = PacedProducer::new(sink.interleaved_out, channels, app_sample_rate as f64);
+
+    loop {
+        for frame in block.chunks_exact_mut(channels) {
+            frame.fill(t_sin.sin() as f32 * 0.02);
+
+            // should come out at 440 Hz, despite the device's different rate
+            t_sin += (440.0 / app_sample_rate as f64) * TAU;
+        }
+
+        producer.push(&block);
+    }
+}
@@ -1,14 +1,6 @@
-use std::{
-    f64::consts::TAU,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-    thread,
-    time::{Duration, Instant},
-};
+use std::{f64::consts::TAU, sync::atomic::Ordering};
 
-use clocked::cpal::start_cpal_sink;
+use clocked::{cpal::start_cpal_sink, PacedProducer};
 use cpal::{
     traits::{DeviceTrait, HostTrait},
     BufferSize, StreamConfig,
@@ -35,7 +27,7 @@ fn main() {
     println!("sample rate: {}", config.sample_rate.0);
 
     let mut t_sin: f64 = 0.0;
-    let (_handle, mut sink) = start_cpal_sink(
+    let sink = start_cpal_sink(
         &output_device,
         &config,
         supported_config.sample_format(),
@@ -45,32 +37,19 @@ fn main() {
 
     sink.measure_xruns.store(true, Ordering::Release);
 
-    let start = Instant::now();
-    let mut frames_processed = 0;
+    let channels = sink.channels();
+    let mut block = vec![0.0; buffer_size * channels];
 
     // test with emitting data faster than the soundcard is running at
     let actual_sample_rate = 50_000;
+    let mut producer = PacedProducer::new(sink.interleaved_out, channels, actual_sample_rate as f64);
 
     loop {
-        'block: for _ in 0..buffer_size {
-            for _ in 0..sink.channels() {
-                if let Err(_) = sink.interleaved_out.push(t_sin.sin() as f32 * 0.02) {
-                    // println!("overrun");
-
-                    break 'block;
-                }
-            }
-
+        for frame in block.chunks_exact_mut(channels) {
+            frame.fill(t_sin.sin() as f32 * 0.02);
             t_sin += (440.0 / config.sample_rate.0 as f64) * TAU;
         }
 
-        frames_processed += buffer_size;
-
-        let buffer_time_secs = frames_processed as f64 / actual_sample_rate as f64;
-        let now_secs = (Instant::now() - start).as_secs_f64();
-
-        if buffer_time_secs > now_secs {
-            thread::sleep(Duration::from_secs_f64(buffer_time_secs - now_secs));
-        }
+        producer.push(&block);
     }
 }
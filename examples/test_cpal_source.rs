@@ -55,7 +55,7 @@ fn main() {
     println!("buffer size: {}", buffer_size);
     println!("sample rate: {}", config.sample_rate.0);
 
-    let (_handle, mut source) = start_cpal_source(
+    let mut source = start_cpal_source(
         &input_device,
         &config,
         supported_config.sample_format(),
@@ -80,27 +80,20 @@ fn main() {
     )
     .unwrap();
 
-    loop {
-        let mut missed = 0;
-
-        'block: for i in 0..buffer_size {
-            for _ in 0..source.channels() {
-                if let Ok(sample) = source.interleaved_in.pop() {
-                    writer.write_sample(sample).unwrap();
-                } else {
-                    print!("u ");
-                    stdout().flush().unwrap();
+    let mut block = vec![0.0; buffer_size * source.channels()];
 
-                    missed = buffer_size - i - 1;
+    loop {
+        let frames_read = source.read_frames(&mut block);
+        if frames_read < buffer_size {
+            print!("u ");
+            stdout().flush().unwrap();
 
-                    break 'block;
-                }
-            }
+            // simulate missed deadline
+            block[(frames_read * source.channels())..].fill(0.0);
         }
 
-        // simulate missed deadline
-        for _ in 0..(missed * source.channels()) {
-            writer.write_sample(0.0).unwrap();
+        for &sample in &block {
+            writer.write_sample(sample).unwrap();
         }
 
         frames_processed += buffer_size;
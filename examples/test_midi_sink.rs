@@ -52,14 +52,14 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
         // We're ignoring errors in here
         let _ = conn_out.sender.send(MidiData::NoteOn {
             channel: 0,
-            note: note,
+            note,
             velocity: 0x64,
         });
 
         thread::sleep(Duration::from_millis(duration * 150));
         let _ = conn_out.sender.send(MidiData::NoteOff {
             channel: 0,
-            note: note,
+            note,
             velocity: 0x64,
         });
     };
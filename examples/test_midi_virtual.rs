@@ -0,0 +1,38 @@
+use std::error::Error;
+
+use clocked::{
+    midi::MidiData,
+    midir::{start_midir_virtual_sink, start_midir_virtual_source},
+};
+use midir::{Ignore, MidiInput, MidiOutput};
+
+// creates a virtual input and output, echoing any notes from the input back out
+fn main() {
+    match run() {
+        Ok(_) => (),
+        Err(err) => println!("Error: {}", err),
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut midi_in = MidiInput::new("clocked virtual input")?;
+    midi_in.ignore(Ignore::None);
+
+    let midi_out = MidiOutput::new("clocked virtual output")?;
+
+    let (_in_handle, conn_in) = start_midir_virtual_source(midi_in, "clocked-echo-in")?;
+    let (_out_handle, conn_out) = start_midir_virtual_sink(midi_out, "clocked-echo-out")?;
+
+    println!("Virtual ports 'clocked-echo-in' and 'clocked-echo-out' are open, echoing notes.");
+
+    while let Ok(message) = conn_in.receiver.recv() {
+        println!("Received: {:?}", message);
+
+        if let MidiData::NoteOn { .. } | MidiData::NoteOff { .. } = message.value {
+            conn_out.sender.send(message.value)?;
+        }
+    }
+
+    println!("Closing connection");
+    Ok(())
+}
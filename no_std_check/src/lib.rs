@@ -0,0 +1,49 @@
+//! Makes sure the parts of clocked that are supposed to work without `std` (the MIDI parser and
+//! the resampling kernels) keep compiling that way.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+
+use clocked::{
+    midi::{parse_midi, MidiData},
+    resample::{resample, FRAME_LOOKBACK},
+};
+
+/// Parses a single message.
+pub fn parse_one(bytes: &[u8]) -> Option<MidiData> {
+    let mut buffer: VecDeque<u8> = bytes.iter().copied().collect();
+
+    parse_midi(&mut buffer)
+}
+
+/// Runs one step of the default kernel over a constant signal.
+pub fn resample_constant(value: f32) -> f32 {
+    let mut last = [value; FRAME_LOOKBACK];
+
+    resample(1.0, core::iter::repeat(value), &mut last, 0.5).sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_note_on() {
+        assert_eq!(
+            parse_one(&[0x91, 60, 100]),
+            Some(MidiData::NoteOn {
+                channel: 1,
+                note: 60,
+                velocity: 100
+            })
+        );
+    }
+
+    #[test]
+    fn resamples_without_std() {
+        assert_eq!(resample_constant(0.25), 0.25);
+    }
+}
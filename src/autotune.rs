@@ -0,0 +1,260 @@
+//! Offline tuning of [`PidSettings`] gains.
+//!
+//! This runs a [`StreamSink`] against a simulated producer whose clock is off by a fixed amount,
+//! drives it with a relay (bang-bang) controller to find the point where the loop oscillates, and
+//! derives PI gains from that using the Ziegler–Nichols rule. Nothing here touches an
+//! audio device, so it can be run ahead of time (or in a test) to pick settings for a given buffer
+//! layout.
+
+use core::f64::consts::PI;
+
+use rtrb::RingBuffer;
+
+use crate::{PidSettings, StreamSink};
+
+/// The simulated setup to tune against.
+#[derive(Debug, Clone)]
+pub struct AutotuneConfig {
+    /// Ring capacity in frames
+    pub ring_size: usize,
+    /// Frames the sink outputs per callback
+    pub buffer_frames: usize,
+    /// How far off the producer's clock is, in parts per million (positive = producing faster)
+    pub ppm_offset: f64,
+    /// How far the relay pushes the resample factor either way. Has to be large enough to overcome
+    /// `ppm_offset`, otherwise the loop never oscillates.
+    pub relay_amplitude: f64,
+    /// How many callbacks to simulate. The first half is used to let the oscillation settle.
+    pub callbacks: usize,
+}
+
+impl Default for AutotuneConfig {
+    fn default() -> Self {
+        AutotuneConfig {
+            ring_size: 4096,
+            buffer_frames: 256,
+            ppm_offset: 1000.0,
+            relay_amplitude: 0.01,
+            callbacks: 20_000,
+        }
+    }
+}
+
+/// A mono sink fed by a producer running `ppm_offset` fast (or slow), starting at `target_fill`.
+struct Simulation {
+    producer: rtrb::Producer<f32>,
+    sink: StreamSink,
+    ring_size: usize,
+    buffer: Vec<f32>,
+    /// Frames per callback the producer pushes
+    produce_rate: f64,
+    /// Fractional frame left over from the last callback
+    produce_remainder: f64,
+}
+
+impl Simulation {
+    fn new(config: &AutotuneConfig, pid_settings: PidSettings) -> Simulation {
+        let (mut producer, consumer) = RingBuffer::new(config.ring_size);
+
+        let prefill = (config.ring_size as f64 * pid_settings.target_fill) as usize;
+        for _ in 0..prefill {
+            let _ = producer.push(0.0);
+        }
+
+        let mut sink = StreamSink::new(consumer, 1, 0, pid_settings);
+        sink.enable_compensation();
+
+        Simulation {
+            producer,
+            sink,
+            ring_size: config.ring_size,
+            buffer: vec![0.0; config.buffer_frames],
+            produce_rate: config.buffer_frames as f64 * (1.0 + config.ppm_offset * 1e-6),
+            produce_remainder: 0.0,
+        }
+    }
+
+    /// Runs one callback, returning how full the ring was when the sink looked at it (as a fraction).
+    fn step(&mut self) -> f64 {
+        self.produce_remainder += self.produce_rate;
+        let frames = self.produce_remainder as usize;
+        self.produce_remainder -= frames as f64;
+
+        for _ in 0..frames {
+            let _ = self.producer.push(0.0);
+        }
+
+        let fill = (self.ring_size - self.producer.slots()) as f64 / self.ring_size as f64;
+        self.sink.output_samples(&mut self.buffer, true);
+
+        fill
+    }
+}
+
+/// The loop [`autotune`] measures: `base` with its gains swapped for a relay that pushes the factor
+/// a full `relay_amplitude` either way.
+fn relay_simulation(base: &PidSettings, config: &AutotuneConfig) -> Simulation {
+    let relay = PidSettings {
+        // large enough that any error saturates the factor, turning the PID into a relay
+        prop_factor: 1e9,
+        integ_factor: 0.0,
+        deriv_factor: 0.0,
+        min_factor: -config.relay_amplitude,
+        max_factor: config.relay_amplitude,
+        ..base.clone()
+    };
+
+    let mut simulation = Simulation::new(config, relay);
+    // the gains are worked out from the relay's amplitude, so the sink's ratio limit can't be
+    // allowed to cut it short
+    simulation.sink.clear_ratio_limit();
+
+    simulation
+}
+
+/// Suggests PID gains for `config`. `base` supplies everything that isn't a gain (the factor
+/// limits, `factor_last_interp`, and `target_fill`), and those are included in the loop being
+/// measured.
+///
+/// Returns `None` if the relay didn't produce a steady oscillation (usually because
+/// `relay_amplitude` is too small, or `callbacks` too few).
+pub fn autotune(base: &PidSettings, config: &AutotuneConfig) -> Option<PidSettings> {
+    let mut simulation = relay_simulation(base, config);
+
+    let settle = config.callbacks / 2;
+    let mut crossings = Vec::new();
+    let mut min_fill = f64::INFINITY;
+    let mut max_fill = f64::NEG_INFINITY;
+    let mut last_error = 0.0;
+
+    for callback_i in 0..config.callbacks {
+        let fill = simulation.step();
+        let error = fill - base.target_fill;
+
+        if callback_i >= settle {
+            min_fill = min_fill.min(fill);
+            max_fill = max_fill.max(fill);
+
+            if last_error < 0.0 && error >= 0.0 {
+                crossings.push(callback_i);
+            }
+        }
+
+        last_error = error;
+    }
+
+    if crossings.len() < 3 {
+        return None;
+    }
+
+    let amplitude = (max_fill - min_fill) / 2.0;
+    if amplitude <= 0.0 {
+        return None;
+    }
+
+    // ultimate gain and period (in callbacks) of the loop
+    let ultimate_gain = 4.0 * config.relay_amplitude / (PI * amplitude);
+    let ultimate_period = (crossings[crossings.len() - 1] - crossings[0]) as f64 / (crossings.len() - 1) as f64;
+
+    // the fill level only moves in whole callbacks, so the derivative is mostly noise; stick to PI
+    let prop_factor = 0.45 * ultimate_gain;
+    let integral_time = ultimate_period / 1.2;
+
+    let tuned = PidSettings {
+        prop_factor,
+        integ_factor: prop_factor / integral_time,
+        deriv_factor: 0.0,
+        ..base.clone()
+    };
+
+    tuned.validate().ok().map(|_| tuned)
+}
+
+/// Counts how many callbacks it takes for `pid_settings` to bring the ring within `tolerance` (as a
+/// fraction of the ring) of `target_fill` and keep it there for the rest of `config.callbacks`.
+/// Returns `None` if it never settles. Handy for comparing settings against each other.
+pub fn callbacks_to_settle(pid_settings: &PidSettings, config: &AutotuneConfig, tolerance: f64) -> Option<usize> {
+    let mut simulation = Simulation::new(config, pid_settings.clone());
+    let mut settled_since = None;
+
+    for callback_i in 0..config.callbacks {
+        let fill = simulation.step();
+
+        if (fill - pid_settings.target_fill).abs() <= tolerance {
+            settled_since.get_or_insert(callback_i);
+        } else {
+            settled_since = None;
+        }
+    }
+
+    settled_since
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuned_settings_settle_faster_than_the_defaults() {
+        let config = AutotuneConfig::default();
+        let defaults = PidSettings::default();
+
+        let tuned = autotune(&defaults, &config).expect("the relay should oscillate");
+        // only the gains are tuned
+        assert_eq!(tuned.target_fill, defaults.target_fill);
+        assert_eq!(tuned.max_factor, defaults.max_factor);
+
+        let tuned_callbacks = callbacks_to_settle(&tuned, &config, 0.02).expect("tuned settings should settle");
+        let default_callbacks = callbacks_to_settle(&defaults, &config, 0.02).unwrap_or(config.callbacks);
+
+        assert!(
+            tuned_callbacks < default_callbacks,
+            "tuned took {tuned_callbacks} callbacks, defaults took {default_callbacks}"
+        );
+    }
+
+    #[test]
+    fn relay_swings_the_full_configured_amplitude() {
+        let config = AutotuneConfig {
+            callbacks: 2000,
+            ..AutotuneConfig::default()
+        };
+        // no gliding, so the ratio goes straight to wherever the relay puts it
+        let base = PidSettings::default().with_factor_last_interp(1.0);
+        let mut simulation = relay_simulation(&base, &config);
+
+        let mut min_ratio = f64::INFINITY;
+        let mut max_ratio = f64::NEG_INFINITY;
+        for _ in 0..config.callbacks {
+            simulation.step();
+
+            if let crate::CompensationStrategy::Resample { resample_ratio, .. } = simulation.sink.get_strategy() {
+                min_ratio = min_ratio.min(*resample_ratio);
+                max_ratio = max_ratio.max(*resample_ratio);
+            }
+        }
+
+        // well past the default ratio limit of ±2000 ppm
+        let expected_max = 2_f64.powf(config.relay_amplitude);
+        let expected_min = 2_f64.powf(-config.relay_amplitude);
+        assert!(
+            (max_ratio - expected_max).abs() < 1e-9,
+            "{max_ratio}, expected {expected_max}"
+        );
+        assert!(
+            (min_ratio - expected_min).abs() < 1e-9,
+            "{min_ratio}, expected {expected_min}"
+        );
+    }
+
+    #[test]
+    fn relay_too_weak_for_the_offset_gives_up() {
+        let config = AutotuneConfig {
+            // 100 ppm can't make up for a 1000 ppm offset
+            relay_amplitude: 0.0001,
+            ..AutotuneConfig::default()
+        };
+
+        assert!(autotune(&PidSettings::default(), &config).is_none());
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A source of time, so code that waits on the clock can be driven by a [`MockClock`] instead of
+/// real time (making it deterministic, and as fast as the CPU allows).
+pub trait Clock {
+    /// Time since the clock's origin. Never goes backwards.
+    fn now(&self) -> Duration;
+
+    /// Blocks until `duration` has passed, according to this clock.
+    fn sleep(&self, duration: Duration);
+}
+
+/// Real time, counted from when the clock was created.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    origin: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> MonotonicClock {
+        MonotonicClock { origin: Instant::now() }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        MonotonicClock::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a test can hold on to one
+/// and hand the other to whatever it's testing. Sleeping just moves the clock forward.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    /// A clock starting at zero.
+    pub fn new() -> MockClock {
+        MockClock::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.nanos.fetch_add(duration_nanos(duration), Ordering::Relaxed);
+    }
+
+    /// Jumps to `now`. Panics if that would move the clock backwards.
+    pub fn set(&self, now: Duration) {
+        let previous = self.nanos.swap(duration_nanos(now), Ordering::Relaxed);
+
+        assert!(previous <= duration_nanos(now), "a clock can't go backwards");
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// `duration` in nanoseconds, saturating (at about 584 years).
+fn duration_nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use rtrb::RingBuffer;
+
+    use super::*;
+    use crate::{CompensationStrategy, PacedProducer, StreamSink};
+
+    #[test]
+    fn mock_clock_only_moves_when_told() {
+        let clock = MockClock::new();
+        let shared = clock.clone();
+
+        assert_eq!(clock.now(), Duration::ZERO);
+        clock.sleep(Duration::from_millis(5));
+        shared.advance(Duration::from_millis(2));
+        assert_eq!(clock.now(), Duration::from_millis(7));
+
+        clock.set(Duration::from_secs(1));
+        assert_eq!(shared.now(), Duration::from_secs(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "a clock can't go backwards")]
+    fn mock_clock_cant_go_backwards() {
+        let clock = MockClock::new();
+        clock.set(Duration::from_secs(1));
+        clock.set(Duration::from_millis(999));
+    }
+
+    #[test]
+    fn monotonic_clock_moves_forward() {
+        let clock = MonotonicClock::new();
+        let before = clock.now();
+        clock.sleep(Duration::from_millis(2));
+
+        assert!(clock.now() - before >= Duration::from_millis(2));
+    }
+
+    /// Feeds a sink from a producer running 0.1% fast, both paced by the same mock clock, for
+    /// `seconds` of mock time. Returns the sink's resample ratio after every callback, and the fill
+    /// at the end.
+    fn run_mock_drift(seconds: u64) -> (Vec<f64>, f64) {
+        const BLOCK: usize = 32;
+        const RATE: f64 = 48_000.0;
+
+        let clock = MockClock::new();
+        let (producer, consumer) = RingBuffer::new(1024);
+        let mut paced = PacedProducer::with_clock(producer, 1, RATE * 1.001, clock.clone());
+        let mut sink = StreamSink::with_defaults(consumer, 1);
+        sink.enable_compensation();
+
+        let callback_period = Duration::from_secs_f64(BLOCK as f64 / RATE);
+        let mut next_callback = Duration::ZERO;
+        let mut ratios = Vec::new();
+
+        while clock.now() < Duration::from_secs(seconds) {
+            // the producer's push sleeps (moving the clock) until its block is due
+            paced.push(&[0.0; BLOCK]);
+
+            while next_callback <= clock.now() {
+                sink.output_samples(&mut [0.0; BLOCK], true);
+                next_callback += callback_period;
+
+                ratios.push(match sink.get_strategy() {
+                    CompensationStrategy::Resample { resample_ratio, .. } => *resample_ratio,
+                    _ => 1.0,
+                });
+            }
+        }
+
+        let fill = 1.0 - paced.ring_out().slots() as f64 / 1024.0;
+
+        (ratios, fill)
+    }
+
+    #[test]
+    fn mock_clock_drives_a_full_convergence() {
+        let (ratios, fill) = run_mock_drift(20);
+
+        let settled = ratios[ratios.len() - 1000..].iter().sum::<f64>() / 1000.0;
+        assert!((settled - 1.001).abs() < 0.0002, "settled at {settled}");
+        assert!((fill - 0.5).abs() < 0.15, "fill of {fill}");
+    }
+
+    #[test]
+    fn mock_clock_runs_are_reproducible() {
+        assert_eq!(run_mock_drift(2), run_mock_drift(2));
+    }
+}
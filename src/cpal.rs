@@ -1,30 +1,229 @@
 use std::{
+    fmt,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
     },
     time::{Duration, Instant},
 };
 
 use cpal::{
     traits::{DeviceTrait, StreamTrait},
-    Device, SampleFormat, Stream, StreamConfig,
+    BufferSize, Device, SampleFormat, Stream, StreamConfig, StreamError,
 };
 use dasp_sample::Sample;
-use rtrb::{Consumer, RingBuffer};
+use rtrb::{Consumer, Producer, RingBuffer};
 
-use crate::{StreamSink, StreamSource};
+use crate::{resample::MAX_RESAMPLE_RATIO, CompensationStrategy, PidSettings, StreamSink, StreamSource};
 
+/// Settings for the [`StreamSink`]/[`StreamSource`] that the CPAL helpers create internally.
+#[derive(Debug, Clone)]
+pub struct CpalConfig {
+    /// PID tuning (including the target ring fill)
+    pub pid_settings: PidSettings,
+    /// How many xruns are tolerated before compensation starts
+    pub compensation_start_threshold: u64,
+    /// If set, compensation also starts once the ring drifts this fast (see
+    /// [`StreamSink::set_drift_threshold`])
+    pub drift_threshold: Option<f64>,
+    /// How long after a stream starts before xruns are counted. Devices tend to be erratic while
+    /// they spin up, and those xruns say nothing about drift.
+    pub warm_up: Duration,
+    /// If set, sinks also copy everything they output into a monitoring ring of this many frames
+    /// (see [`CpalConfig::with_monitor`])
+    pub monitor_ring_size: Option<usize>,
+}
+
+impl Default for CpalConfig {
+    fn default() -> Self {
+        CpalConfig {
+            pid_settings: PidSettings::default(),
+            compensation_start_threshold: 15,
+            drift_threshold: None,
+            warm_up: DEFAULT_WARM_UP,
+            monitor_ring_size: None,
+        }
+    }
+}
+
+impl CpalConfig {
+    /// Has the sink helpers ([`start_cpal_sink_with`], [`start_cpal_sink_resampled`],
+    /// [`start_cpal_managed_sink`], and the output side of [`start_cpal_duplex`]) tap off a copy of
+    /// the output, after resampling, into a ring of `ring_size` frames. It comes out of the
+    /// handle's `monitor` (e.g. [`CpalSink::monitor`]), for metering or recording. When that ring
+    /// is full, frames are dropped from the copy; the device never waits on it.
+    pub fn with_monitor(mut self, ring_size: usize) -> Self {
+        self.monitor_ring_size = Some(ring_size);
+        self
+    }
+}
+
+/// Why a stream couldn't be started.
+#[derive(Debug)]
+pub enum StartStreamError {
+    Build(cpal::BuildStreamError),
+    Play(cpal::PlayStreamError),
+    /// The app's sample rate is more than [`MAX_RESAMPLE_RATIO`] times the device's, which is
+    /// further than the sink can resample
+    RatioTooHigh(f64),
+}
+
+impl fmt::Display for StartStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartStreamError::Build(err) => write!(f, "couldn't build the stream: {err}"),
+            StartStreamError::Play(err) => write!(f, "couldn't play the stream: {err}"),
+            StartStreamError::RatioTooHigh(ratio) => {
+                write!(f, "resample ratio {ratio} is above the maximum of {MAX_RESAMPLE_RATIO}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StartStreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StartStreamError::Build(err) => Some(err),
+            StartStreamError::Play(err) => Some(err),
+            StartStreamError::RatioTooHigh(_) => None,
+        }
+    }
+}
+
+impl From<cpal::BuildStreamError> for StartStreamError {
+    fn from(err: cpal::BuildStreamError) -> Self {
+        StartStreamError::Build(err)
+    }
+}
+
+impl From<cpal::PlayStreamError> for StartStreamError {
+    fn from(err: cpal::PlayStreamError) -> Self {
+        StartStreamError::Play(err)
+    }
+}
+
+/// The default for [`CpalConfig::warm_up`].
+pub const DEFAULT_WARM_UP: Duration = Duration::from_secs(1);
+
+/// Tracks whether a stream is still inside its warm-up window.
+#[derive(Debug, Clone, Copy)]
+struct WarmUp {
+    start: Instant,
+    window: Duration,
+}
+
+impl WarmUp {
+    /// Starts the window now.
+    fn new(window: Duration) -> WarmUp {
+        WarmUp {
+            start: Instant::now(),
+            window,
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.start.elapsed() >= self.window
+    }
+
+    /// Starts the window over, e.g. when a paused stream picks back up.
+    fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+}
+
+/// Stream health, written by the audio callback so it can be read without owning the manager.
 #[derive(Debug)]
+struct StreamHealth {
+    xruns: AtomicU64,
+    compensation_active: AtomicBool,
+    /// Bits of the `f64` resample ratio
+    resample_ratio: AtomicU64,
+    /// Set by the handle to have the callback reset the manager (e.g. after a pause)
+    reset_requested: AtomicBool,
+}
+
+impl Default for StreamHealth {
+    fn default() -> Self {
+        StreamHealth {
+            xruns: AtomicU64::new(0),
+            compensation_active: AtomicBool::new(false),
+            resample_ratio: AtomicU64::new(1.0_f64.to_bits()),
+            reset_requested: AtomicBool::new(false),
+        }
+    }
+}
+
+impl StreamHealth {
+    fn update(&self, xruns: u64, strategy: &CompensationStrategy) {
+        let resample_ratio = match strategy {
+            CompensationStrategy::Resample { resample_ratio, .. } => *resample_ratio,
+            CompensationStrategy::None | CompensationStrategy::Never => 1.0,
+        };
+
+        self.xruns.store(xruns, Ordering::Relaxed);
+        self.compensation_active.store(
+            matches!(strategy, CompensationStrategy::Resample { .. }),
+            Ordering::Relaxed,
+        );
+        self.resample_ratio.store(resample_ratio.to_bits(), Ordering::Relaxed);
+    }
+
+    fn resample_ratio(&self) -> f64 {
+        f64::from_bits(self.resample_ratio.load(Ordering::Relaxed))
+    }
+}
+
 pub struct CpalSource {
     pub interleaved_in: Consumer<f32>,
+    stream: Stream,
     channels: usize,
+    health: Arc<StreamHealth>,
 }
 
 impl CpalSource {
     pub fn channels(&self) -> usize {
         self.channels
     }
+
+    /// Pops as many whole frames as are available (up to what fits in `out`) into `out`, returning
+    /// how many frames were read. Only whole frames are ever taken, so the ring is never left
+    /// mid-frame, and anything in `out` past the frames read is left untouched.
+    pub fn read_frames(&mut self, out: &mut [f32]) -> usize {
+        read_frames(&mut self.interleaved_in, self.channels, out)
+    }
+
+    /// How many xruns the stream has seen.
+    pub fn xruns(&self) -> u64 {
+        self.health.xruns.load(Ordering::Relaxed)
+    }
+
+    /// Whether the stream is currently resampling to compensate for drift.
+    pub fn compensation_active(&self) -> bool {
+        self.health.compensation_active.load(Ordering::Relaxed)
+    }
+
+    /// The ratio the stream is currently resampling at (1.0 when compensation isn't active).
+    pub fn resample_ratio(&self) -> f64 {
+        self.health.resample_ratio()
+    }
+
+    /// The stream feeding this source. Dropping the source stops it.
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+
+    /// Pauses the stream.
+    pub fn pause(&self) -> Result<(), cpal::PauseStreamError> {
+        self.stream.pause()
+    }
+
+    /// Resumes the stream after [`Self::pause`]. The compensation state and xrun count are reset,
+    /// as whatever was measured before the pause is stale by now, and the warm-up starts over.
+    pub fn resume(&self) -> Result<(), cpal::PlayStreamError> {
+        self.health.reset_requested.store(true, Ordering::Relaxed);
+
+        self.stream.play()
+    }
 }
 
 pub fn start_cpal_source(
@@ -32,117 +231,197 @@ pub fn start_cpal_source(
     config: &StreamConfig,
     sample_format: SampleFormat,
     ring_size: usize,
-) -> Result<(Stream, CpalSource), cpal::BuildStreamError> {
+) -> Result<CpalSource, StartStreamError> {
+    start_cpal_source_with(device, config, sample_format, ring_size, &CpalConfig::default())
+}
+
+/// Same as [`start_cpal_source`], but with custom compensation settings.
+pub fn start_cpal_source_with(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    ring_size: usize,
+    cpal_config: &CpalConfig,
+) -> Result<CpalSource, StartStreamError> {
     let channels = config.channels as usize;
     let ring_buffer_size = ring_size * channels;
 
     let (producer, consumer) = RingBuffer::new(ring_buffer_size);
 
-    let mut manager = StreamSource::with_defaults(producer, channels);
-    let callback_start = Instant::now();
+    let health = Arc::new(StreamHealth::default());
+    let stream = build_input_stream(
+        device,
+        config,
+        sample_format,
+        new_source_manager(producer, channels, cpal_config),
+        health.clone(),
+        cpal_config.warm_up,
+    )?;
+    stream.play()?;
+
+    Ok(CpalSource {
+        interleaved_in: consumer,
+        stream,
+        channels,
+        health,
+    })
+}
+
+fn build_input_stream(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    mut manager: StreamSource,
+    health: Arc<StreamHealth>,
+    warm_up: Duration,
+) -> Result<Stream, cpal::BuildStreamError> {
+    let mut warm_up = WarmUp::new(warm_up);
 
     let cfg: StreamConfig = config.clone();
 
-    let stream = match sample_format {
+    match sample_format {
         cpal::SampleFormat::I8 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<i8>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<i8>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::I16 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<i16>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<i16>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::I32 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<i32>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<i32>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::I64 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<i64>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<i64>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::U8 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<u8>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<u8>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::U16 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<u16>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<u16>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::U32 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<u32>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<u32>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::U64 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<u64>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<u64>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::F32 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<f32>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<f32>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         cpal::SampleFormat::F64 => device.build_input_stream(
             &cfg,
-            move |data, _: &_| input_callback::<f64>(data, &mut manager, callback_start),
+            move |data, _: &_| input_callback::<f64>(data, &mut manager, &mut warm_up, &health),
             |_| {},
             None,
-        )?,
+        ),
         _ => {
             unreachable!("this program has crashed due to a `TooManyObfuscatingAbstractions` error")
         }
-    };
-
-    // finally
-    Ok((
-        stream,
-        CpalSource {
-            interleaved_in: consumer,
-            channels,
-        },
-    ))
+    }
 }
 
-fn input_callback<T>(input: &[T], manager: &mut StreamSource, callback_start: Instant)
+fn input_callback<T>(input: &[T], manager: &mut StreamSource, warm_up: &mut WarmUp, health: &StreamHealth)
 where
     T: cpal::Sample + dasp_sample::ToSample<f32>,
 {
-    let callback = Instant::now() - callback_start;
+    if health.reset_requested.swap(false, Ordering::Relaxed) {
+        // a source that's never meant to compensate (like the input side of a duplex) stays that way
+        let disabled = manager.forced_compensation() == Some(false);
+        manager.reset();
+        if disabled {
+            manager.disable_compensation();
+        }
+        warm_up.restart();
+    }
 
-    manager.input_samples(
-        input.iter().map(|x| x.to_sample::<f32>()),
-        input.len(),
-        callback > Duration::from_secs(1),
-    );
+    // CPAL doesn't promise a maximum buffer size, so split up anything the source can't take at once
+    let max_block_samples = manager.max_block_frames() * manager.input_channels();
+    for chunk in input.chunks(max_block_samples) {
+        manager.input_samples(
+            chunk.iter().map(|x| x.to_sample::<f32>()),
+            chunk.len(),
+            warm_up.is_over(),
+        );
+    }
+
+    health.update(manager.xruns, manager.get_strategy());
 }
 
-#[derive(Debug)]
 pub struct CpalSink {
     pub interleaved_out: rtrb::Producer<f32>,
+    /// Copy of what the device is playing (interleaved), if asked for with
+    /// [`CpalConfig::with_monitor`]
+    pub monitor: Option<Consumer<f32>>,
+    /// Xruns are only counted while this is set and [`CpalConfig::warm_up`] has passed
     pub measure_xruns: Arc<AtomicBool>,
+    stream: Stream,
     channels: usize,
+    health: Arc<StreamHealth>,
 }
 
 impl CpalSink {
     pub fn channels(&self) -> usize {
         self.channels
     }
+
+    /// How many xruns the stream has seen.
+    pub fn xruns(&self) -> u64 {
+        self.health.xruns.load(Ordering::Relaxed)
+    }
+
+    /// Whether the stream is currently resampling to compensate for drift.
+    pub fn compensation_active(&self) -> bool {
+        self.health.compensation_active.load(Ordering::Relaxed)
+    }
+
+    /// The ratio the stream is currently resampling at (1.0 when compensation isn't active).
+    pub fn resample_ratio(&self) -> f64 {
+        self.health.resample_ratio()
+    }
+
+    /// The stream this sink is playing on. Dropping the sink stops it.
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+
+    /// Pauses the stream.
+    pub fn pause(&self) -> Result<(), cpal::PauseStreamError> {
+        self.stream.pause()
+    }
+
+    /// Resumes the stream after [`Self::pause`]. The compensation state and xrun count are reset,
+    /// as whatever was measured before the pause is stale by now, and the warm-up starts over.
+    pub fn resume(&self) -> Result<(), cpal::PlayStreamError> {
+        self.health.reset_requested.store(true, Ordering::Relaxed);
+
+        self.stream.play()
+    }
 }
 
 pub fn start_cpal_sink(
@@ -150,107 +429,1272 @@ pub fn start_cpal_sink(
     config: &StreamConfig,
     sample_format: SampleFormat,
     ring_size: usize,
-) -> Result<(Stream, CpalSink), cpal::BuildStreamError> {
+) -> Result<CpalSink, StartStreamError> {
+    start_cpal_sink_with(device, config, sample_format, ring_size, &CpalConfig::default())
+}
+
+/// Same as [`start_cpal_sink`], but with custom compensation settings.
+pub fn start_cpal_sink_with(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    ring_size: usize,
+    cpal_config: &CpalConfig,
+) -> Result<CpalSink, StartStreamError> {
+    start_sink(device, config, sample_format, ring_size, cpal_config, 1.0)
+}
+
+/// Same as [`start_cpal_sink_with`], but the app pushes audio at `app_sample_rate` while the device
+/// runs at whatever `config` says. The sink bridges the two by resampling at `app_sample_rate /
+/// device rate` (see [`StreamSink::set_nominal_ratio`]), with the usual drift compensation applied
+/// on top, so the app never has to know the device's rate. `ring_size` is in app-rate frames.
+///
+/// Since it's always resampling, this costs a bit more CPU than a plain sink even when the rates
+/// happen to match. Fails with [`StartStreamError::RatioTooHigh`] if `app_sample_rate` is more than
+/// [`MAX_RESAMPLE_RATIO`] times the device's rate.
+pub fn start_cpal_sink_resampled(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    app_sample_rate: u32,
+    ring_size: usize,
+    cpal_config: &CpalConfig,
+) -> Result<CpalSink, StartStreamError> {
+    let nominal_ratio = app_sample_rate as f64 / config.sample_rate.0 as f64;
+    if nominal_ratio > MAX_RESAMPLE_RATIO {
+        return Err(StartStreamError::RatioTooHigh(nominal_ratio));
+    }
+
+    start_sink(device, config, sample_format, ring_size, cpal_config, nominal_ratio)
+}
+
+fn start_sink(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    ring_size: usize,
+    cpal_config: &CpalConfig,
+    nominal_ratio: f64,
+) -> Result<CpalSink, StartStreamError> {
     let channels = config.channels;
     let ring_buffer_size = ring_size * channels as usize;
 
     let (producer, consumer) = RingBuffer::new(ring_buffer_size);
 
-    let mut manager = StreamSink::with_defaults(consumer, channels as usize);
-    // scratch to fill with `f32`s and then convert to whatever sample type CPAL is using
-    let mut scratch = Vec::with_capacity(ring_buffer_size);
+    let mut manager = new_sink_manager(consumer, channels as usize, cpal_config);
+    manager.set_nominal_ratio(nominal_ratio);
 
-    let cfg: StreamConfig = config.clone();
+    let (monitor_producer, monitor) = new_monitor(channels as usize, cpal_config);
+
+    let measure_xruns = Arc::new(AtomicBool::new(false));
+    let health = Arc::new(StreamHealth::default());
+    let stream = build_output_stream(
+        device,
+        config,
+        sample_format,
+        manager,
+        ring_buffer_size,
+        measure_xruns.clone(),
+        cpal_config.warm_up,
+        health.clone(),
+        monitor_producer,
+        |_| {},
+    )?;
+    stream.play()?;
+
+    Ok(CpalSink {
+        interleaved_out: producer,
+        monitor,
+        measure_xruns,
+        stream,
+        channels: channels as usize,
+        health,
+    })
+}
+
+/// The sink behind a CPAL output stream, set up from `cpal_config`.
+fn new_sink_manager(consumer: Consumer<f32>, channels: usize, cpal_config: &CpalConfig) -> StreamSink {
+    let mut manager = StreamSink::new(
+        consumer,
+        channels,
+        cpal_config.compensation_start_threshold,
+        cpal_config.pid_settings.clone(),
+    );
+    if let Some(drift_threshold) = cpal_config.drift_threshold {
+        manager.set_drift_threshold(drift_threshold);
+    }
+
+    manager
+}
+
+/// [`CpalSource::read_frames`], on its own so it can be tested without a device.
+fn read_frames(ring: &mut Consumer<f32>, channels: usize, out: &mut [f32]) -> usize {
+    let frames = (out.len() / channels).min(ring.slots() / channels);
+    let samples = frames * channels;
+
+    if let Ok(chunk) = ring.read_chunk(samples) {
+        let (first, second) = chunk.as_slices();
+        out[..first.len()].copy_from_slice(first);
+        out[first.len()..samples].copy_from_slice(second);
+        chunk.commit_all();
+    }
+
+    frames
+}
+
+/// The source behind a CPAL input stream, set up from `cpal_config`.
+fn new_source_manager(producer: Producer<f32>, channels: usize, cpal_config: &CpalConfig) -> StreamSource {
+    let mut manager = StreamSource::new(
+        producer,
+        channels,
+        cpal_config.compensation_start_threshold,
+        cpal_config.pid_settings.clone(),
+    );
+    if let Some(drift_threshold) = cpal_config.drift_threshold {
+        manager.set_drift_threshold(drift_threshold);
+    }
+
+    manager
+}
+
+/// Both ends of the monitoring ring, if `cpal_config` asks for one.
+fn new_monitor(channels: usize, cpal_config: &CpalConfig) -> (Option<Monitor>, Option<Consumer<f32>>) {
+    match cpal_config.monitor_ring_size {
+        Some(monitor_ring_size) => {
+            let (ring, consumer) = RingBuffer::new(monitor_ring_size * channels);
+
+            (Some(Monitor { ring, channels }), Some(consumer))
+        }
+        None => (None, None),
+    }
+}
+
+/// A [`CpalSink`] that can outlive its device. If the device goes away (say, a USB interface gets
+/// unplugged), [`CpalManagedSink::is_disconnected`] starts returning true and the stream can be
+/// rebuilt on another device with [`CpalManagedSink::reconnect`].
+///
+/// Across a reconnect the ring (and anything still queued in it) survives, so `interleaved_out`
+/// stays usable the whole time. The PID accumulators and compensation state do _not_ survive, since
+/// the drift measured against the old device says nothing about the new one.
+pub struct CpalManagedSink {
+    pub interleaved_out: rtrb::Producer<f32>,
+    /// Copy of what the device is playing (interleaved), if asked for with
+    /// [`CpalConfig::with_monitor`]. Like the main ring, it carries on across reconnects.
+    pub monitor: Option<Consumer<f32>>,
+    pub measure_xruns: Arc<AtomicBool>,
+    manager: Arc<Mutex<ManagedOutput>>,
+    stream: Option<Stream>,
+    disconnected: Arc<AtomicBool>,
+    channels: usize,
+    ring_buffer_size: usize,
+    /// Restarts with every reconnect, since the new device has to spin up too
+    warm_up: Duration,
+    health: Arc<StreamHealth>,
+}
+
+impl CpalManagedSink {
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// How many xruns the stream has seen.
+    pub fn xruns(&self) -> u64 {
+        self.health.xruns.load(Ordering::Relaxed)
+    }
+
+    /// Whether the stream is currently resampling to compensate for drift.
+    pub fn compensation_active(&self) -> bool {
+        self.health.compensation_active.load(Ordering::Relaxed)
+    }
+
+    /// The ratio the stream is currently resampling at (1.0 when compensation isn't active).
+    pub fn resample_ratio(&self) -> f64 {
+        self.health.resample_ratio()
+    }
+
+    /// Pauses the stream, if there is one.
+    pub fn pause(&self) -> Result<(), cpal::PauseStreamError> {
+        match &self.stream {
+            Some(stream) => stream.pause(),
+            None => Ok(()),
+        }
+    }
+
+    /// Resumes the stream after [`Self::pause`]. The compensation state and xrun count are reset,
+    /// as whatever was measured before the pause is stale by now, and the warm-up starts over.
+    pub fn resume(&self) -> Result<(), cpal::PlayStreamError> {
+        self.health.reset_requested.store(true, Ordering::Relaxed);
+
+        match &self.stream {
+            Some(stream) => stream.play(),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the device reported that it's no longer available.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+
+    /// The currently running stream, if there is one.
+    pub fn stream(&self) -> Option<&Stream> {
+        self.stream.as_ref()
+    }
+
+    /// Drops the current stream (dead or not) and starts a new one on `device`. The new config
+    /// must have the same channel count, as the ring is reused. If the new stream can't be built or
+    /// played (say, the device went away again), there's no stream until a reconnect succeeds.
+    pub fn reconnect(
+        &mut self,
+        device: &Device,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<(), StartStreamError> {
+        if config.channels as usize != self.channels {
+            return Err(cpal::BuildStreamError::StreamConfigNotSupported.into());
+        }
+
+        // make sure the old callback is gone before touching the manager
+        self.stream = None;
+
+        self.manager.lock().unwrap().sink.reset(false);
+        self.disconnected.store(false, Ordering::Relaxed);
+
+        let stream = build_managed_stream(
+            device,
+            config,
+            sample_format,
+            self.manager.clone(),
+            self.ring_buffer_size,
+            self.measure_xruns.clone(),
+            self.warm_up,
+            self.health.clone(),
+            self.disconnected.clone(),
+        )?;
+        stream.play()?;
+
+        self.stream = Some(stream);
+
+        Ok(())
+    }
+}
+
+/// Same as [`start_cpal_sink_with`], but returns a [`CpalManagedSink`] that can be reconnected to
+/// another device.
+pub fn start_cpal_managed_sink(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    ring_size: usize,
+    cpal_config: &CpalConfig,
+) -> Result<CpalManagedSink, StartStreamError> {
+    let channels = config.channels as usize;
+    let ring_buffer_size = ring_size * channels;
+
+    let (producer, consumer) = RingBuffer::new(ring_buffer_size);
+
+    let (monitor, monitor_consumer) = new_monitor(channels, cpal_config);
+    let manager = Arc::new(Mutex::new(ManagedOutput {
+        sink: new_sink_manager(consumer, channels, cpal_config),
+        monitor,
+    }));
 
     let measure_xruns = Arc::new(AtomicBool::new(false));
-    let measure_xruns_clone = measure_xruns.clone();
+    let health = Arc::new(StreamHealth::default());
+    let disconnected = Arc::new(AtomicBool::new(false));
+
+    let stream = build_managed_stream(
+        device,
+        config,
+        sample_format,
+        manager.clone(),
+        ring_buffer_size,
+        measure_xruns.clone(),
+        cpal_config.warm_up,
+        health.clone(),
+        disconnected.clone(),
+    )?;
+    stream.play()?;
+
+    Ok(CpalManagedSink {
+        interleaved_out: producer,
+        monitor: monitor_consumer,
+        measure_xruns,
+        manager,
+        stream: Some(stream),
+        disconnected,
+        channels,
+        ring_buffer_size,
+        warm_up: cpal_config.warm_up,
+        health,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_managed_stream(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    manager: Arc<Mutex<ManagedOutput>>,
+    ring_buffer_size: usize,
+    measure_xruns: Arc<AtomicBool>,
+    warm_up: Duration,
+    health: Arc<StreamHealth>,
+    disconnected: Arc<AtomicBool>,
+) -> Result<Stream, cpal::BuildStreamError> {
+    build_output_stream(
+        device,
+        config,
+        sample_format,
+        manager,
+        ring_buffer_size,
+        measure_xruns,
+        warm_up,
+        health,
+        None,
+        disconnect_watcher(disconnected),
+    )
+}
+
+/// Error callback that sets `disconnected` once the device goes away.
+fn disconnect_watcher(disconnected: Arc<AtomicBool>) -> impl FnMut(StreamError) + Send + 'static {
+    move |err| {
+        if let StreamError::DeviceNotAvailable = err {
+            disconnected.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Whatever the output callback pulls samples from.
+trait OutputManager: Send + 'static {
+    fn render(&mut self, buffer_out: &mut [f32], measure_xruns: bool, health: &StreamHealth);
+
+    /// Forgets the compensation state after a pause, keeping whatever was queued up meanwhile.
+    fn reset(&mut self);
+}
+
+impl OutputManager for StreamSink {
+    fn render(&mut self, buffer_out: &mut [f32], measure_xruns: bool, health: &StreamHealth) {
+        self.output_samples(buffer_out, measure_xruns);
+        health.update(self.xruns, self.get_strategy());
+    }
+
+    fn reset(&mut self) {
+        StreamSink::reset(self, false);
+    }
+}
+
+/// What a [`CpalManagedSink`] keeps between streams.
+struct ManagedOutput {
+    sink: StreamSink,
+    /// Lives here rather than in the callback, so it isn't dropped along with a dead stream
+    monitor: Option<Monitor>,
+}
 
-    let stream = match sample_format {
+impl OutputManager for Arc<Mutex<ManagedOutput>> {
+    fn render(&mut self, buffer_out: &mut [f32], measure_xruns: bool, health: &StreamHealth) {
+        // the lock is only ever taken elsewhere while reconnecting, so don't wait on it
+        match self.try_lock() {
+            Ok(mut manager) => {
+                manager.sink.render(buffer_out, measure_xruns, health);
+
+                if let Some(monitor) = &mut manager.monitor {
+                    monitor.push(buffer_out);
+                }
+            }
+            Err(_) => buffer_out.fill(0.0),
+        }
+    }
+
+    fn reset(&mut self) {
+        // if it's locked, it's being reconnected, which resets it anyway
+        if let Ok(mut manager) = self.try_lock() {
+            manager.sink.reset(false);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_output_stream(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    mut manager: impl OutputManager,
+    ring_buffer_size: usize,
+    measure_xruns: Arc<AtomicBool>,
+    warm_up: Duration,
+    health: Arc<StreamHealth>,
+    mut monitor: Option<Monitor>,
+    error_callback: impl FnMut(StreamError) + Send + 'static,
+) -> Result<Stream, cpal::BuildStreamError> {
+    let mut warm_up = WarmUp::new(warm_up);
+
+    // scratch to fill with `f32`s and then convert to whatever sample type CPAL is using. It's
+    // allocated up front so the callback never has to
+    let mut scratch = vec![0.0; max_callback_samples(config, ring_buffer_size)];
+
+    let cfg: StreamConfig = config.clone();
+
+    match sample_format {
         cpal::SampleFormat::I8 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<i8>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<i8>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::I16 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<i16>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<i16>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::I32 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<i32>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<i32>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::I64 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<i64>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<i64>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::U8 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<u8>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<u8>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::U16 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<u16>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<u16>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::U32 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<u32>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<u32>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::U64 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<u64>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<u64>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::F32 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<f32>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<f32>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         cpal::SampleFormat::F64 => device.build_output_stream(
             &cfg,
-            move |data, _: &_| output_callback::<f64>(data, &mut manager, &mut scratch, &measure_xruns),
-            |_| {},
+            move |data, _: &_| {
+                output_callback::<f64>(
+                    data,
+                    &mut manager,
+                    &mut scratch,
+                    &mut monitor,
+                    &measure_xruns,
+                    &mut warm_up,
+                    &health,
+                )
+            },
+            error_callback,
             None,
-        )?,
+        ),
         _ => {
             unreachable!("this program has crashed due to a `TooManyObfuscatingAbstractions` error")
         }
+    }
+}
+
+/// The most samples CPAL should hand the output callback at once. If the buffer size isn't fixed
+/// there's no way to know, so fall back to the ring size (a bigger callback than that would
+/// underrun anyway).
+fn max_callback_samples(config: &StreamConfig, ring_buffer_size: usize) -> usize {
+    let samples = match config.buffer_size {
+        BufferSize::Fixed(frames) => frames as usize * config.channels as usize,
+        BufferSize::Default => ring_buffer_size,
     };
-    stream.play().unwrap();
 
-    // finally
-    Ok((
-        stream,
-        CpalSink {
-            interleaved_out: producer,
-            channels: channels as usize,
-            measure_xruns: measure_xruns_clone,
-        },
-    ))
+    // at least one frame, so there's always something to chunk by
+    samples.max(config.channels as usize)
 }
 
-fn output_callback<T>(output: &mut [T], manager: &mut StreamSink, scratch: &mut Vec<f32>, measure_xruns: &AtomicBool)
-where
+/// The producing half of a sink's monitoring ring (see [`CpalConfig::with_monitor`]).
+struct Monitor {
+    ring: Producer<f32>,
+    channels: usize,
+}
+
+impl Monitor {
+    /// Copies as many whole frames of `samples` as fit, dropping the rest.
+    fn push(&mut self, samples: &[f32]) {
+        let len = samples.len().min(self.ring.slots()) / self.channels * self.channels;
+
+        if let Ok(chunk) = self.ring.write_chunk_uninit(len) {
+            chunk.fill_from_iter(samples[..len].iter().copied());
+        }
+    }
+}
+
+fn output_callback<T>(
+    output: &mut [T],
+    manager: &mut impl OutputManager,
+    scratch: &mut [f32],
+    monitor: &mut Option<Monitor>,
+    measure_xruns: &AtomicBool,
+    warm_up: &mut WarmUp,
+    health: &StreamHealth,
+) where
     T: cpal::Sample + dasp_sample::ToSample<T> + cpal::FromSample<f32>,
 {
-    scratch.resize(output.len(), 0.0);
-    manager.output_samples(scratch, measure_xruns.load(Ordering::Relaxed));
+    if health.reset_requested.swap(false, Ordering::Relaxed) {
+        manager.reset();
+        warm_up.restart();
+    }
+
+    let measure_xruns = measure_xruns.load(Ordering::Relaxed) && warm_up.is_over();
+
+    // `render` overwrites the whole slice, so there's no need to clear it first. If CPAL hands over
+    // more than expected, work through it in chunks rather than reallocating in the audio thread.
+    for output_chunk in output.chunks_mut(scratch.len()) {
+        let scratch = &mut scratch[..output_chunk.len()];
+
+        manager.render(scratch, measure_xruns, health);
+
+        if let Some(monitor) = monitor {
+            monitor.push(scratch);
+        }
+
+        for (sample, sample_out) in scratch.iter().zip(output_chunk.iter_mut()) {
+            *sample_out = sample.to_sample::<T>();
+        }
+    }
+}
+
+/// Both halves of a full-duplex stream on one device. Input and output share the device's clock,
+/// so only the output side compensates for drift, which covers the whole round trip.
+pub struct CpalDuplex {
+    pub interleaved_in: Consumer<f32>,
+    pub interleaved_out: Producer<f32>,
+    /// Copy of what the device is playing (interleaved), if asked for with
+    /// [`CpalConfig::with_monitor`]
+    pub monitor: Option<Consumer<f32>>,
+    pub measure_xruns: Arc<AtomicBool>,
+    input_stream: Stream,
+    output_stream: Stream,
+    input_channels: usize,
+    output_channels: usize,
+    input_health: Arc<StreamHealth>,
+    health: Arc<StreamHealth>,
+}
+
+impl CpalDuplex {
+    pub fn input_channels(&self) -> usize {
+        self.input_channels
+    }
+
+    pub fn output_channels(&self) -> usize {
+        self.output_channels
+    }
+
+    /// How many xruns the output side has seen.
+    pub fn xruns(&self) -> u64 {
+        self.health.xruns.load(Ordering::Relaxed)
+    }
+
+    /// Whether the output side is currently resampling to compensate for drift.
+    pub fn compensation_active(&self) -> bool {
+        self.health.compensation_active.load(Ordering::Relaxed)
+    }
+
+    /// The ratio the stream is currently resampling at (1.0 when compensation isn't active).
+    pub fn resample_ratio(&self) -> f64 {
+        self.health.resample_ratio()
+    }
+
+    /// The stream filling [`CpalDuplex::interleaved_in`]. Dropping the duplex stops it.
+    pub fn input_stream(&self) -> &Stream {
+        &self.input_stream
+    }
+
+    /// The stream playing [`CpalDuplex::interleaved_out`]. Dropping the duplex stops it.
+    pub fn output_stream(&self) -> &Stream {
+        &self.output_stream
+    }
+
+    /// Pauses both streams, the output first.
+    pub fn pause(&self) -> Result<(), cpal::PauseStreamError> {
+        self.output_stream.pause()?;
+
+        self.input_stream.pause()
+    }
+
+    /// Resumes both streams after [`Self::pause`], the input first. Same as [`CpalSink::resume`],
+    /// both sides' compensation state and xrun counts are reset, and the warm-up starts over.
+    pub fn resume(&self) -> Result<(), cpal::PlayStreamError> {
+        self.input_health.reset_requested.store(true, Ordering::Relaxed);
+        self.health.reset_requested.store(true, Ordering::Relaxed);
+
+        self.input_stream.play()?;
+        self.output_stream.play()
+    }
+}
+
+/// Starts an input and an output stream on the same device, and plays both. The input and output
+/// configs may have different channel counts. `cpal_config` applies to the output side, since
+/// that's the side that compensates.
+pub fn start_cpal_duplex(
+    device: &Device,
+    in_config: &StreamConfig,
+    in_sample_format: SampleFormat,
+    out_config: &StreamConfig,
+    out_sample_format: SampleFormat,
+    ring_size: usize,
+    cpal_config: &CpalConfig,
+) -> Result<CpalDuplex, StartStreamError> {
+    let input_channels = in_config.channels as usize;
+    let output_channels = out_config.channels as usize;
+
+    let (in_producer, in_consumer) = RingBuffer::new(ring_size * input_channels);
+    let (out_producer, out_consumer) = RingBuffer::new(ring_size * output_channels);
+
+    // the input runs off the same clock as the output, so let the output handle all the drift
+    let mut source = StreamSource::with_defaults(in_producer, input_channels);
+    source.disable_compensation();
+
+    let sink = new_sink_manager(out_consumer, output_channels, cpal_config);
+    let (monitor_producer, monitor) = new_monitor(output_channels, cpal_config);
+
+    let measure_xruns = Arc::new(AtomicBool::new(false));
+    let health = Arc::new(StreamHealth::default());
+    // the input side never compensates, so this is only kept around for resetting it on resume
+    let input_health = Arc::new(StreamHealth::default());
+
+    let input_stream = build_input_stream(
+        device,
+        in_config,
+        in_sample_format,
+        source,
+        input_health.clone(),
+        cpal_config.warm_up,
+    )?;
+    let output_stream = build_output_stream(
+        device,
+        out_config,
+        out_sample_format,
+        sink,
+        ring_size * output_channels,
+        measure_xruns.clone(),
+        cpal_config.warm_up,
+        health.clone(),
+        monitor_producer,
+        |_| {},
+    )?;
+    input_stream.play()?;
+    output_stream.play()?;
+
+    Ok(CpalDuplex {
+        interleaved_in: in_consumer,
+        interleaved_out: out_producer,
+        monitor,
+        measure_xruns,
+        input_stream,
+        output_stream,
+        input_channels,
+        output_channels,
+        input_health,
+        health,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sink on a mono ring of `ring_size` samples, with the producer half to feed it.
+    fn mock_sink(ring_size: usize) -> (Producer<f32>, StreamSink) {
+        let (producer, consumer) = RingBuffer::new(ring_size);
+
+        (producer, StreamSink::with_defaults(consumer, 1))
+    }
+
+    /// Runs one output callback with xrun counting on and no warm-up.
+    fn render(sink: &mut StreamSink, health: &StreamHealth, frames: usize) -> Vec<f32> {
+        let mut output = vec![0.0_f32; frames];
+        let mut scratch = vec![0.0; frames];
+
+        output_callback(
+            &mut output,
+            sink,
+            &mut scratch,
+            &mut None,
+            &AtomicBool::new(true),
+            &mut WarmUp::new(Duration::ZERO),
+            health,
+        );
+
+        output
+    }
+
+    #[test]
+    fn health_tracks_the_sinks_xruns() {
+        let (_producer, mut sink) = mock_sink(16);
+        let health = StreamHealth::default();
+
+        render(&mut sink, &health, 4);
+        render(&mut sink, &health, 4);
+
+        assert_eq!(health.xruns.load(Ordering::Relaxed), 2);
+        assert_eq!(health.xruns.load(Ordering::Relaxed), sink.xruns);
+    }
+
+    #[test]
+    fn health_tracks_compensation() {
+        let (mut producer, mut sink) = mock_sink(64);
+        let health = StreamHealth::default();
+
+        render(&mut sink, &health, 4);
+        assert!(!health.compensation_active.load(Ordering::Relaxed));
+        assert_eq!(health.resample_ratio(), 1.0);
+
+        sink.set_fixed_ratio(1.5);
+        for _ in 0..3 {
+            for _ in 0..16 {
+                producer.push(0.0).unwrap();
+            }
+
+            render(&mut sink, &health, 4);
+        }
+
+        assert!(health.compensation_active.load(Ordering::Relaxed));
+        assert_eq!(health.resample_ratio(), sink.metrics().resample_ratio);
+        assert!(health.resample_ratio() > 1.0);
+    }
+
+    #[test]
+    fn health_tracks_the_sources_xruns() {
+        let (producer, _consumer) = RingBuffer::new(4);
+        let mut source = StreamSource::with_defaults(producer, 1);
+        let health = StreamHealth::default();
+        let mut warm_up = WarmUp::new(Duration::ZERO);
+
+        // the second block finds the ring full
+        input_callback(&[0.0_f32; 4], &mut source, &mut warm_up, &health);
+        input_callback(&[0.0_f32; 4], &mut source, &mut warm_up, &health);
+
+        assert_eq!(health.xruns.load(Ordering::Relaxed), source.xruns);
+        assert!(source.xruns > 0);
+    }
 
-    for (sample, sample_out) in scratch.iter().zip(output.iter_mut()) {
-        *sample_out = sample.to_sample::<T>();
+    fn custom_config() -> CpalConfig {
+        CpalConfig {
+            pid_settings: PidSettings::default().with_prop_factor(0.05).with_target_fill(0.7),
+            compensation_start_threshold: 2,
+            drift_threshold: Some(0.001),
+            ..CpalConfig::default()
+        }
+    }
+
+    #[test]
+    fn sink_manager_takes_the_cpal_config() {
+        let (_producer, consumer) = RingBuffer::new(16);
+        let mut sink = new_sink_manager(consumer, 1, &custom_config());
+
+        assert_eq!(sink.pid_settings().prop_factor, 0.05);
+        assert_eq!(sink.pid_settings().target_fill, 0.7);
+        assert_eq!(sink.drift_threshold(), Some(0.001));
+
+        // compensation starts after 2 xruns instead of the default 15
+        let health = StreamHealth::default();
+        render(&mut sink, &health, 4);
+        render(&mut sink, &health, 4);
+        render(&mut sink, &health, 4);
+        assert!(health.compensation_active.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn source_manager_takes_the_cpal_config() {
+        let (producer, _consumer) = RingBuffer::new(16);
+        let source = new_source_manager(producer, 1, &custom_config());
+
+        assert_eq!(source.pid_settings().prop_factor, 0.05);
+        assert_eq!(source.pid_settings().target_fill, 0.7);
+        assert_eq!(source.drift_threshold(), Some(0.001));
+    }
+
+    #[test]
+    fn only_a_missing_device_counts_as_a_disconnect() {
+        let disconnected = Arc::new(AtomicBool::new(false));
+        let mut watcher = disconnect_watcher(disconnected.clone());
+
+        watcher(StreamError::BackendSpecific {
+            err: cpal::BackendSpecificError {
+                description: "glitch".into(),
+            },
+        });
+        assert!(!disconnected.load(Ordering::Relaxed));
+
+        watcher(StreamError::DeviceNotAvailable);
+        assert!(disconnected.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn ring_survives_a_reconnect() {
+        let (mut producer, consumer) = RingBuffer::new(16);
+        let mut manager = Arc::new(Mutex::new(ManagedOutput {
+            sink: StreamSink::with_defaults(consumer, 1),
+            monitor: None,
+        }));
+        let health = StreamHealth::default();
+
+        for sample in [0.1, 0.2, 0.3, 0.4] {
+            producer.push(sample).unwrap();
+        }
+        let mut out = [0.0; 2];
+        manager.render(&mut out, true, &health);
+        assert_eq!(out, [0.1, 0.2]);
+
+        // the device goes away mid-stream, and the app keeps pushing in the meantime
+        producer.push(0.5).unwrap();
+
+        // what `reconnect` does to the manager before building the new stream
+        manager.lock().unwrap().sink.reset(false);
+
+        let mut out = [0.0; 3];
+        manager.render(&mut out, true, &health);
+        assert_eq!(out, [0.3, 0.4, 0.5]);
+
+        producer.push(0.6).unwrap();
+        let mut out = [0.0; 1];
+        manager.render(&mut out, true, &health);
+        assert_eq!(out, [0.6]);
+    }
+
+    #[test]
+    fn managed_output_is_silent_while_reconnecting() {
+        let (mut producer, consumer) = RingBuffer::new(16);
+        let mut manager = Arc::new(Mutex::new(ManagedOutput {
+            sink: StreamSink::with_defaults(consumer, 1),
+            monitor: None,
+        }));
+        producer.push(0.5).unwrap();
+
+        let other = manager.clone();
+        let guard = other.lock().unwrap();
+        let mut out = [9.0; 2];
+        manager.render(&mut out, true, &StreamHealth::default());
+        drop(guard);
+
+        assert_eq!(out, [0.0, 0.0]);
+        // nothing was taken from the ring while it was locked
+        assert_eq!(producer.slots(), 15);
+    }
+
+    #[test]
+    fn resume_clears_xruns_and_restarts_the_warm_up() {
+        let (_producer, mut sink) = mock_sink(16);
+        let health = StreamHealth::default();
+        // a warm-up that finished long ago
+        let mut warm_up = WarmUp {
+            start: Instant::now() - Duration::from_secs(20),
+            window: Duration::from_secs(10),
+        };
+        let callback = |sink: &mut StreamSink, warm_up: &mut WarmUp| {
+            output_callback(
+                &mut [0.0_f32; 4],
+                sink,
+                &mut [0.0; 4],
+                &mut None,
+                &AtomicBool::new(true),
+                warm_up,
+                &health,
+            )
+        };
+
+        callback(&mut sink, &mut warm_up);
+        assert_eq!(sink.xruns, 1);
+
+        // paused: no callbacks at all. Then `resume` asks for a reset
+        health.reset_requested.store(true, Ordering::Relaxed);
+        callback(&mut sink, &mut warm_up);
+        callback(&mut sink, &mut warm_up);
+
+        // the ring is still empty, but those underruns are inside the new warm-up
+        assert_eq!(sink.xruns, 0);
+        assert_eq!(health.xruns.load(Ordering::Relaxed), 0);
+        assert!(!health.reset_requested.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn resume_clears_the_sources_compensation() {
+        let (producer, _consumer) = RingBuffer::new(64);
+        let mut source = StreamSource::with_defaults(producer, 1);
+        source.enable_compensation();
+        let health = StreamHealth::default();
+        let mut warm_up = WarmUp::new(Duration::ZERO);
+
+        input_callback(&[0.0_f32; 4], &mut source, &mut warm_up, &health);
+        assert!(health.compensation_active.load(Ordering::Relaxed));
+
+        health.reset_requested.store(true, Ordering::Relaxed);
+        input_callback(&[0.0_f32; 4], &mut source, &mut warm_up, &health);
+
+        assert!(!health.compensation_active.load(Ordering::Relaxed));
+        assert_eq!(source.forced_compensation(), None);
+    }
+
+    #[test]
+    fn resume_keeps_a_disabled_source_disabled() {
+        let (producer, _consumer) = RingBuffer::new(64);
+        let mut source = StreamSource::with_defaults(producer, 1);
+        source.disable_compensation();
+        let health = StreamHealth::default();
+        let mut warm_up = WarmUp::new(Duration::ZERO);
+
+        for _ in 0..30 {
+            input_callback(&[0.0_f32; 8], &mut source, &mut warm_up, &health);
+        }
+        health.reset_requested.store(true, Ordering::Relaxed);
+        input_callback(&[0.0_f32; 8], &mut source, &mut warm_up, &health);
+
+        assert_eq!(source.forced_compensation(), Some(false));
+        assert_eq!(*source.get_strategy(), CompensationStrategy::Never);
+        // the count still started over, and only has the last callback's overrun in it
+        assert!(source.xruns <= 2, "{}", source.xruns);
+    }
+
+    /// Counts allocations per thread, so tests running in parallel don't see each other's.
+    struct CountingAlloc;
+
+    std::thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { std::alloc::System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            unsafe { std::alloc::System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|count| count.get())
+    }
+
+    #[test]
+    fn output_callback_doesnt_allocate_after_warm_up() {
+        let (mut producer, consumer) = RingBuffer::new(4096);
+        let mut sink = StreamSink::with_defaults(consumer, 2);
+        sink.set_fixed_ratio(1.01);
+        let health = StreamHealth::default();
+        let measure_xruns = AtomicBool::new(true);
+        let mut warm_up = WarmUp::new(Duration::ZERO);
+
+        // CPAL handing over more than the scratch holds gets chunked rather than growing it
+        let mut scratch = vec![0.0; 64];
+        let mut output = vec![0_i16; 256];
+        let mut callback = |producer: &mut Producer<f32>, sink: &mut StreamSink| {
+            for _ in 0..250 {
+                let _ = producer.push(0.25);
+            }
+
+            output_callback(
+                &mut output,
+                sink,
+                &mut scratch,
+                &mut None,
+                &measure_xruns,
+                &mut warm_up,
+                &health,
+            );
+        };
+
+        // let compensation kick in (which prints a message) before counting
+        for _ in 0..4 {
+            callback(&mut producer, &mut sink);
+        }
+
+        let before = allocations();
+        for _ in 0..100 {
+            callback(&mut producer, &mut sink);
+        }
+
+        assert_eq!(allocations(), before);
+        assert!(sink.metrics().resample_ratio > 1.0);
+    }
+
+    #[test]
+    fn health_tracks_the_sources_compensation() {
+        let (producer, mut consumer) = RingBuffer::new(256);
+        let mut source = StreamSource::with_defaults(producer, 1);
+        let health = StreamHealth::default();
+        let mut warm_up = WarmUp::new(Duration::ZERO);
+
+        input_callback(&[0.0_f32; 16], &mut source, &mut warm_up, &health);
+        assert!(!health.compensation_active.load(Ordering::Relaxed));
+        assert_eq!(health.resample_ratio(), 1.0);
+
+        source.set_fixed_ratio(0.8);
+        for _ in 0..4 {
+            input_callback(&[0.0_f32; 16], &mut source, &mut warm_up, &health);
+            while consumer.pop().is_ok() {}
+        }
+
+        assert!(health.compensation_active.load(Ordering::Relaxed));
+        assert_eq!(health.resample_ratio(), source.metrics().resample_ratio);
+        // still gliding towards 0.8
+        assert!(health.resample_ratio() < 1.0);
+        assert_eq!(health.xruns.load(Ordering::Relaxed), source.xruns);
+    }
+
+    #[test]
+    fn sink_xruns_inside_the_warm_up_arent_counted() {
+        let (_producer, mut sink) = mock_sink(16);
+        let health = StreamHealth::default();
+        let mut warm_up = WarmUp::new(Duration::from_secs(10));
+
+        for _ in 0..4 {
+            output_callback(
+                &mut [0.0_f32; 4],
+                &mut sink,
+                &mut [0.0; 4],
+                &mut None,
+                &AtomicBool::new(true),
+                &mut warm_up,
+                &health,
+            );
+        }
+
+        // the ring was empty every time
+        assert_eq!(sink.xruns, 0);
+
+        // and once the window's over they count again
+        render(&mut sink, &health, 4);
+        assert_eq!(sink.xruns, 1);
+    }
+
+    #[test]
+    fn source_xruns_inside_the_warm_up_arent_counted() {
+        let (producer, _consumer) = RingBuffer::new(4);
+        let mut source = StreamSource::with_defaults(producer, 1);
+        let health = StreamHealth::default();
+        let mut warm_up = WarmUp::new(Duration::from_secs(10));
+
+        for _ in 0..4 {
+            input_callback(&[0.0_f32; 4], &mut source, &mut warm_up, &health);
+        }
+        assert_eq!(source.xruns, 0);
+
+        input_callback(&[0.0_f32; 4], &mut source, &mut WarmUp::new(Duration::ZERO), &health);
+        assert!(source.xruns > 0);
+    }
+
+    #[test]
+    fn default_warm_up_is_a_second() {
+        assert_eq!(CpalConfig::default().warm_up, Duration::from_secs(1));
+    }
+
+    /// Plays `blocks` blocks of 8 stereo frames (of a ramp) through a sink with a monitor of
+    /// `monitor_frames` frames, returning what the device got and what the monitor got.
+    fn play_with_monitor(monitor_frames: usize, blocks: usize) -> (Vec<f32>, Vec<f32>) {
+        let (mut producer, consumer) = RingBuffer::new(1024);
+        let mut sink = StreamSink::with_defaults(consumer, 2);
+        let (mut monitor, monitor_out) = new_monitor(2, &CpalConfig::default().with_monitor(monitor_frames));
+        let mut monitor_out = monitor_out.unwrap();
+        let health = StreamHealth::default();
+
+        let mut device = Vec::new();
+        for block in 0..blocks {
+            for i in 0..16 {
+                producer.push((block * 16 + i) as f32).unwrap();
+            }
+
+            let mut output = [0.0_f32; 16];
+            output_callback(
+                &mut output,
+                &mut sink,
+                &mut [0.0; 16],
+                &mut monitor,
+                &AtomicBool::new(true),
+                &mut WarmUp::new(Duration::ZERO),
+                &health,
+            );
+            device.extend(output);
+        }
+
+        let mut monitored = Vec::new();
+        while let Ok(sample) = monitor_out.pop() {
+            monitored.push(sample);
+        }
+
+        (device, monitored)
+    }
+
+    #[test]
+    fn monitor_sees_what_the_device_plays() {
+        let (device, monitored) = play_with_monitor(64, 4);
+
+        assert_eq!(monitored, device);
+    }
+
+    #[test]
+    fn full_monitor_drops_whole_frames() {
+        // room for 5 frames, but 32 get played
+        let (device, monitored) = play_with_monitor(5, 4);
+
+        assert_eq!(device.len(), 64);
+        assert_eq!(monitored, device[..10]);
+    }
+
+    #[test]
+    fn no_monitor_unless_asked_for() {
+        let (monitor, consumer) = new_monitor(2, &CpalConfig::default());
+
+        assert!(monitor.is_none() && consumer.is_none());
+    }
+
+    #[test]
+    fn partial_reads_stay_frame_aligned() {
+        let (mut producer, mut consumer) = RingBuffer::new(16);
+        // five whole stereo frames and half of a sixth
+        for sample in 0..11 {
+            producer.push(sample as f32).unwrap();
+        }
+
+        // room for three and a half frames only takes three
+        let mut out = [-1.0; 7];
+        assert_eq!(read_frames(&mut consumer, 2, &mut out), 3);
+        assert_eq!(out, [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, -1.0]);
+
+        // and only the whole frames that have arrived are taken
+        let mut out = [-1.0; 8];
+        assert_eq!(read_frames(&mut consumer, 2, &mut out), 2);
+        assert_eq!(out, [6.0, 7.0, 8.0, 9.0, -1.0, -1.0, -1.0, -1.0]);
+        assert_eq!(consumer.slots(), 1);
+
+        // once the rest of the frame arrives, it's read whole
+        producer.push(11.0).unwrap();
+        assert_eq!(read_frames(&mut consumer, 2, &mut out), 1);
+        assert_eq!(out[..2], [10.0, 11.0]);
+    }
+
+    #[test]
+    fn reads_across_the_ring_wrapping_around() {
+        let (mut producer, mut consumer) = RingBuffer::new(6);
+        let mut out = [0.0; 4];
+        for sample in 0..4 {
+            producer.push(sample as f32).unwrap();
+        }
+        assert_eq!(read_frames(&mut consumer, 2, &mut out), 2);
+
+        // this lands on both sides of the end of the ring
+        for sample in 4..8 {
+            producer.push(sample as f32).unwrap();
+        }
+        assert_eq!(read_frames(&mut consumer, 2, &mut out), 2);
+        assert_eq!(out, [4.0, 5.0, 6.0, 7.0]);
     }
 }
@@ -0,0 +1,269 @@
+use crate::{PlaybackStatus, StreamSink, StreamSource};
+
+struct SinkGroup {
+    sink: StreamSink,
+    /// Where the group's channels start in the combined frame
+    first_channel: usize,
+    /// The group's own output, before it's interleaved into the combined buffer
+    scratch: Vec<f32>,
+}
+
+/// Several [`StreamSink`]s side by side, each filling its own channels of one output buffer. This
+/// is for devices whose channels come from independently clocked sub-devices (like an aggregate
+/// device): each group has its own ring, and drifts (and is compensated) on its own, with its own
+/// PID and resample ratio.
+///
+/// Groups take up channels in the order they're given, e.g. a stereo sink followed by a mono sink
+/// fill channels 0-1 and 2.
+pub struct GroupedStreamSink {
+    groups: Vec<SinkGroup>,
+    channels: usize,
+    max_block_frames: usize,
+}
+
+impl GroupedStreamSink {
+    /// * `sinks` - one sink per group, in channel order
+    /// * `max_block_frames` - the most frames a single call will be asked for, so the per-group
+    ///   buffers can be allocated up front
+    pub fn new(sinks: impl IntoIterator<Item = StreamSink>, max_block_frames: usize) -> GroupedStreamSink {
+        let mut channels = 0;
+        let groups: Vec<SinkGroup> = sinks
+            .into_iter()
+            .map(|sink| {
+                let first_channel = channels;
+                channels += sink.output_channels();
+
+                SinkGroup {
+                    scratch: vec![0.0; max_block_frames * sink.output_channels()],
+                    sink,
+                    first_channel,
+                }
+            })
+            .collect();
+
+        assert!(!groups.is_empty(), "there must be at least one group");
+
+        GroupedStreamSink {
+            groups,
+            channels,
+            max_block_frames,
+        }
+    }
+
+    /// Total channels across all groups.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn max_block_frames(&self) -> usize {
+        self.max_block_frames
+    }
+
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn sink(&self, group_i: usize) -> &StreamSink {
+        &self.groups[group_i].sink
+    }
+
+    pub fn sink_mut(&mut self, group_i: usize) -> &mut StreamSink {
+        &mut self.groups[group_i].sink
+    }
+
+    /// Same as [`StreamSink::output_samples`], for every group.
+    pub fn output_samples(&mut self, buffer_out: &mut [f32], measure_xruns: bool) {
+        self.output_samples_with_status(buffer_out, measure_xruns);
+    }
+
+    /// Same as [`StreamSink::output_samples_with_status`], for every group. `frames_written` is
+    /// the least any group wrote, and an under- or overrun in any group is reported.
+    ///
+    /// Panics if `buffer_out` is more than [`GroupedStreamSink::max_block_frames`] frames.
+    pub fn output_samples_with_status(&mut self, buffer_out: &mut [f32], measure_xruns: bool) -> PlaybackStatus {
+        debug_assert_eq!(buffer_out.len() % self.channels, 0);
+
+        let frames = buffer_out.len() / self.channels;
+        assert!(
+            frames <= self.max_block_frames,
+            "block of {} frames is larger than the maximum of {}",
+            frames,
+            self.max_block_frames
+        );
+
+        let mut status = PlaybackStatus {
+            frames_written: frames,
+            underran: false,
+            overran: false,
+        };
+
+        for group in &mut self.groups {
+            let group_channels = group.sink.output_channels();
+            let scratch = &mut group.scratch[..(frames * group_channels)];
+
+            let group_status = group.sink.output_samples_with_status(scratch, measure_xruns);
+            status.frames_written = status.frames_written.min(group_status.frames_written);
+            status.underran |= group_status.underran;
+            status.overran |= group_status.overran;
+
+            for (frame_out, frame) in buffer_out
+                .chunks_exact_mut(self.channels)
+                .zip(scratch.chunks_exact(group_channels))
+            {
+                frame_out[group.first_channel..(group.first_channel + group_channels)].copy_from_slice(frame);
+            }
+        }
+
+        status
+    }
+}
+
+/// Several [`StreamSource`]s side by side, each taking its own channels of one input buffer. The
+/// counterpart of [`GroupedStreamSink`], for when the groups are consumed on independent clocks.
+pub struct GroupedStreamSource {
+    /// Each source, with where its channels start in the combined frame
+    groups: Vec<(StreamSource, usize)>,
+    channels: usize,
+}
+
+impl GroupedStreamSource {
+    /// * `sources` - one source per group, in channel order
+    pub fn new(sources: impl IntoIterator<Item = StreamSource>) -> GroupedStreamSource {
+        let mut channels = 0;
+        let groups: Vec<(StreamSource, usize)> = sources
+            .into_iter()
+            .map(|source| {
+                let first_channel = channels;
+                channels += source.input_channels();
+
+                (source, first_channel)
+            })
+            .collect();
+
+        assert!(!groups.is_empty(), "there must be at least one group");
+
+        GroupedStreamSource { groups, channels }
+    }
+
+    /// Total channels across all groups.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn source(&self, group_i: usize) -> &StreamSource {
+        &self.groups[group_i].0
+    }
+
+    pub fn source_mut(&mut self, group_i: usize) -> &mut StreamSource {
+        &mut self.groups[group_i].0
+    }
+
+    /// Same as [`StreamSource::input_samples`], handing each group its own channels of the
+    /// (interleaved) `buffer_in`.
+    pub fn input_samples(&mut self, buffer_in: &[f32], measure_xruns: bool) {
+        debug_assert_eq!(buffer_in.len() % self.channels, 0);
+
+        let frames = buffer_in.len() / self.channels;
+
+        for (source, first_channel) in &mut self.groups {
+            let group_channels = source.input_channels();
+            let group_in = buffer_in
+                .chunks_exact(self.channels)
+                .flat_map(|frame| &frame[*first_channel..(*first_channel + group_channels)])
+                .copied();
+
+            source.input_samples(group_in, frames * group_channels, measure_xruns);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rtrb::RingBuffer;
+
+    use super::*;
+    use crate::CompensationStrategy;
+
+    fn resample_ratio(sink: &StreamSink) -> f64 {
+        match sink.get_strategy() {
+            CompensationStrategy::Resample { resample_ratio, .. } => *resample_ratio,
+            _ => 1.0,
+        }
+    }
+
+    #[test]
+    fn groups_fill_their_own_channels() {
+        let (mut stereo_in, stereo_ring) = RingBuffer::new(64);
+        let (mut mono_in, mono_ring) = RingBuffer::new(32);
+        let mut grouped = GroupedStreamSink::new(
+            [
+                StreamSink::with_defaults(stereo_ring, 2),
+                StreamSink::with_defaults(mono_ring, 1),
+            ],
+            4,
+        );
+        for frame in 0..4 {
+            stereo_in.push(frame as f32).unwrap();
+            stereo_in.push(frame as f32 + 0.5).unwrap();
+            mono_in.push(-(frame as f32)).unwrap();
+        }
+
+        let mut out = [0.0; 12];
+        let status = grouped.output_samples_with_status(&mut out, true);
+
+        assert_eq!(grouped.channels(), 3);
+        assert_eq!(status.frames_written, 4);
+        assert_eq!(out, [0.0, 0.5, 0.0, 1.0, 1.5, -1.0, 2.0, 2.5, -2.0, 3.0, 3.5, -3.0]);
+    }
+
+    #[test]
+    fn groups_drifting_apart_converge_independently() {
+        const BLOCK: usize = 64;
+        const DRIFT: f64 = 0.001;
+
+        let (mut fast_in, fast_ring) = RingBuffer::new(2048);
+        let (mut slow_in, slow_ring) = RingBuffer::new(2048);
+        let mut grouped = GroupedStreamSink::new(
+            [
+                StreamSink::with_defaults(fast_ring, 1),
+                StreamSink::with_defaults(slow_ring, 1),
+            ],
+            BLOCK,
+        );
+        for group_i in 0..2 {
+            grouped.sink_mut(group_i).enable_compensation();
+        }
+
+        // start both half full, then feed one a little faster than it's played and the other a
+        // little slower
+        let mut fed = [0.0, 0.0];
+        let mut pushed = [1024, 1024];
+        let mut out = [0.0; BLOCK * 2];
+        for _ in 0..1024 {
+            fast_in.push(0.0).unwrap();
+            slow_in.push(0.0).unwrap();
+        }
+
+        for _ in 0..20_000 {
+            fed[0] += BLOCK as f64 * (1.0 + DRIFT);
+            fed[1] += BLOCK as f64 * (1.0 - DRIFT);
+            while (pushed[0] as f64) < fed[0] + 1024.0 && fast_in.push(0.0).is_ok() {
+                pushed[0] += 1;
+            }
+            while (pushed[1] as f64) < fed[1] + 1024.0 && slow_in.push(0.0).is_ok() {
+                pushed[1] += 1;
+            }
+
+            grouped.output_samples(&mut out, true);
+        }
+
+        let fast_ratio = resample_ratio(grouped.sink(0));
+        let slow_ratio = resample_ratio(grouped.sink(1));
+        assert!((fast_ratio - (1.0 + DRIFT)).abs() < 0.0002, "{fast_ratio}");
+        assert!((slow_ratio - (1.0 - DRIFT)).abs() < 0.0002, "{slow_ratio}");
+    }
+}
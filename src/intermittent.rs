@@ -1,12 +1,20 @@
 use std::{collections::VecDeque, sync::mpsc, time::Duration};
 
-use crate::DeltaDuration;
+use crate::util::DeltaDuration;
+
+/// How far back a timestamp has to jump to be treated as the device's clock resetting.
+const REBASE_THRESHOLD: Duration = Duration::from_millis(100);
 
 type MapFunc<In, Out> = Box<dyn FnMut(&mut VecDeque<In>, Duration) -> Option<TimedValue<Out>> + Send>;
+type PeekFunc<In> = Box<dyn Fn(&VecDeque<In>) -> Option<Duration> + Send>;
 
 pub struct StreamMapper<In, Out> {
     pub values_in: VecDeque<In>,
     step: MapFunc<In, Out>,
+    /// Called at the end of the stream to empty out anything left over
+    flush: Option<MapFunc<In, Out>>,
+    /// Reports when the next output would be stamped, without producing it
+    peek: Option<PeekFunc<In>>,
 }
 
 impl<In, Out> StreamMapper<In, Out> {
@@ -17,9 +25,42 @@ impl<In, Out> StreamMapper<In, Out> {
         StreamMapper {
             values_in: VecDeque::new(),
             step: Box::new(step),
+            flush: None,
+            peek: None,
+        }
+    }
+
+    /// Same as [`StreamMapper::new`], but `flush` gets called (until it returns `None`) once the
+    /// stream ends, so anything still buffered (like a partial message) can be emitted.
+    pub fn with_flush<F, G>(step: F, flush: G) -> StreamMapper<In, Out>
+    where
+        F: FnMut(&mut VecDeque<In>, Duration) -> Option<TimedValue<Out>> + Send + 'static,
+        G: FnMut(&mut VecDeque<In>, Duration) -> Option<TimedValue<Out>> + Send + 'static,
+    {
+        StreamMapper {
+            values_in: VecDeque::new(),
+            step: Box::new(step),
+            flush: Some(Box::new(flush)),
+            peek: None,
         }
     }
 
+    /// Sets a function that looks at the buffered input and reports the timestamp of the next
+    /// output (or `None` if nothing is ready yet), without consuming anything. This lets a
+    /// scheduler merge several mappers in time order.
+    pub fn set_peek<P>(&mut self, peek: P)
+    where
+        P: Fn(&VecDeque<In>) -> Option<Duration> + Send + 'static,
+    {
+        self.peek = Some(Box::new(peek));
+    }
+
+    /// When the next output is due, according to the function given to
+    /// [`StreamMapper::set_peek`]. Always `None` if there isn't one.
+    pub fn peek_next_time(&self) -> Option<Duration> {
+        self.peek.as_ref().and_then(|peek| peek(&self.values_in))
+    }
+
     pub fn step(&mut self, since_start: Duration) -> Option<TimedValue<Out>> {
         if !self.values_in.is_empty() {
             (self.step)(&mut self.values_in, since_start)
@@ -27,9 +68,20 @@ impl<In, Out> StreamMapper<In, Out> {
             None
         }
     }
+
+    /// Like [`StreamMapper::step`], but for the end of the stream. Everything `step` can produce
+    /// is produced first, then the flush function takes over.
+    pub fn flush(&mut self, since_start: Duration) -> Option<TimedValue<Out>> {
+        self.step(since_start).or_else(|| {
+            self.flush
+                .as_mut()
+                .and_then(|flush| flush(&mut self.values_in, since_start))
+        })
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TimedValue<T> {
     pub since_start: Duration,
     pub value: T,
@@ -57,12 +109,127 @@ impl<Output> IntermittentSink<Output> {
             (self.send)(value);
         }
     }
+
+    /// Same as [`IntermittentSink::start`], but everything that's already waiting in the channel is
+    /// handed to `send_batch` in one go (instead of going through `send` one by one). Still blocks
+    /// while the channel is empty.
+    pub fn start_batched(&mut self, mut send_batch: impl FnMut(&[Output])) {
+        let mut batch = Vec::new();
+
+        while let Ok(value) = self.channel_in.recv() {
+            batch.push(value);
+            batch.extend(self.channel_in.try_iter());
+
+            send_batch(&batch);
+            batch.clear();
+        }
+    }
+}
+
+/// What a bounded [`IntermittentSource`] does when its channel is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Wait for room. Don't use this from a real-time callback!
+    Block,
+    /// Drop the value that didn't fit
+    #[default]
+    DropNewest,
+    /// Hold on to values that didn't fit (up to the channel's bound), dropping the oldest of those
+    /// first. Held values are retried on the next input or flush (including the one when the
+    /// source is dropped).
+    DropOldest,
+}
+
+enum ChannelOut<T> {
+    Unbounded(mpsc::Sender<T>),
+    Bounded {
+        sender: mpsc::SyncSender<T>,
+        policy: OverflowPolicy,
+        /// Values waiting for room in the channel (only used by `DropOldest`)
+        backlog: VecDeque<T>,
+        bound: usize,
+    },
+}
+
+impl<T> ChannelOut<T> {
+    /// Returns `Err` if the channel hung up.
+    fn send(&mut self, value: T, dropped: &mut u64) -> Result<(), ()> {
+        match self {
+            ChannelOut::Unbounded(sender) => sender.send(value).map_err(|_| ()),
+            ChannelOut::Bounded {
+                sender,
+                policy,
+                backlog,
+                bound,
+            } => match policy {
+                OverflowPolicy::Block => sender.send(value).map_err(|_| ()),
+                OverflowPolicy::DropNewest => match sender.try_send(value) {
+                    Ok(()) => Ok(()),
+                    Err(mpsc::TrySendError::Full(_)) => {
+                        *dropped += 1;
+
+                        Ok(())
+                    }
+                    Err(mpsc::TrySendError::Disconnected(_)) => Err(()),
+                },
+                OverflowPolicy::DropOldest => {
+                    backlog.push_back(value);
+                    send_backlog(sender, backlog)?;
+
+                    while backlog.len() > *bound {
+                        backlog.pop_front();
+                        *dropped += 1;
+                    }
+
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    /// Sends as much of the backlog as there's room for. Returns `Err` if the channel hung up.
+    fn retry_backlog(&mut self) -> Result<(), ()> {
+        match self {
+            ChannelOut::Bounded { sender, backlog, .. } => send_backlog(sender, backlog),
+            ChannelOut::Unbounded(_) => Ok(()),
+        }
+    }
+
+    /// How many values are still waiting for room in the channel.
+    fn backlog_len(&self) -> usize {
+        match self {
+            ChannelOut::Bounded { backlog, .. } => backlog.len(),
+            ChannelOut::Unbounded(_) => 0,
+        }
+    }
+}
+
+/// Sends values from the front of `backlog` until the channel is full.
+fn send_backlog<T>(sender: &mpsc::SyncSender<T>, backlog: &mut VecDeque<T>) -> Result<(), ()> {
+    while let Some(oldest) = backlog.pop_front() {
+        match sender.try_send(oldest) {
+            Ok(()) => {}
+            Err(mpsc::TrySendError::Full(oldest)) => {
+                backlog.push_front(oldest);
+                break;
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => return Err(()),
+        }
+    }
+
+    Ok(())
 }
 
 pub struct IntermittentSource<Input, Converted> {
     relative: Option<DeltaDuration>,
-    channel_out: mpsc::Sender<TimedValue<Converted>>,
+    channel_out: ChannelOut<TimedValue<Converted>>,
+    /// How many values were dropped because the (bounded) channel was full
+    dropped: u64,
     mapper: StreamMapper<Input, Converted>,
+    /// Most recent (rebased) timestamp, used for flushing on drop
+    last_timestamp: Duration,
+    /// Most recent timestamp as the device reported it
+    last_raw_timestamp: Option<Duration>,
 }
 
 impl<Input, Converted> IntermittentSource<Input, Converted> {
@@ -72,31 +239,465 @@ impl<Input, Converted> IntermittentSource<Input, Converted> {
     {
         IntermittentSource {
             relative: None,
-            channel_out: out,
+            channel_out: ChannelOut::Unbounded(out),
+            dropped: 0,
             mapper: StreamMapper::new(convert),
+            last_timestamp: Duration::ZERO,
+            last_raw_timestamp: None,
         }
     }
 
+    /// Same as [`IntermittentSource::new`], but the channel holds at most `bound` values, so a slow
+    /// receiver can't make it grow forever. `policy` decides what happens when it's full.
+    pub fn bounded<F>(bound: usize, policy: OverflowPolicy, convert: F) -> (Self, mpsc::Receiver<TimedValue<Converted>>)
+    where
+        F: FnMut(&mut VecDeque<Input>, Duration) -> Option<TimedValue<Converted>> + 'static + Send,
+    {
+        let (sender, receiver) = mpsc::sync_channel(bound);
+
+        let source = IntermittentSource {
+            relative: None,
+            channel_out: ChannelOut::Bounded {
+                sender,
+                policy,
+                backlog: VecDeque::new(),
+                bound,
+            },
+            dropped: 0,
+            mapper: StreamMapper::new(convert),
+            last_timestamp: Duration::ZERO,
+            last_raw_timestamp: None,
+        };
+
+        (source, receiver)
+    }
+
+    /// Same as [`IntermittentSource::new`], but with a `flush` function that gets to emit whatever
+    /// `convert` left buffered when [`IntermittentSource::flush`] is called or the source is
+    /// dropped. See [`StreamMapper::with_flush`].
+    pub fn with_flush<F, G>(out: mpsc::Sender<TimedValue<Converted>>, convert: F, flush: G) -> Self
+    where
+        F: FnMut(&mut VecDeque<Input>, Duration) -> Option<TimedValue<Converted>> + 'static + Send,
+        G: FnMut(&mut VecDeque<Input>, Duration) -> Option<TimedValue<Converted>> + 'static + Send,
+    {
+        IntermittentSource {
+            relative: None,
+            channel_out: ChannelOut::Unbounded(out),
+            dropped: 0,
+            mapper: StreamMapper::with_flush(convert, flush),
+            last_timestamp: Duration::ZERO,
+            last_raw_timestamp: None,
+        }
+    }
+
+    /// Emits everything still buffered in the mapper, stamped with `now` (relative to the start,
+    /// like `since_start` in [`IntermittentSource::input_messages`]). Values held back by
+    /// [`OverflowPolicy::DropOldest`] are retried first.
+    pub fn flush(&mut self, now: Duration) {
+        if self.channel_out.retry_backlog().is_err() {
+            return;
+        }
+
+        while let Some(value) = self.mapper.flush(now) {
+            if self.channel_out.send(value, &mut self.dropped).is_err() {
+                return; // looks like the channel hung up
+            }
+        }
+    }
+
+    /// How many values have been dropped because the channel was full (see
+    /// [`IntermittentSource::bounded`]).
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Forgets the offset between the device's timestamps and `since_start`, so it's
+    /// re-established on the next input. Timestamps jumping backwards trigger this automatically.
+    pub fn rebase(&mut self) {
+        self.relative = None;
+    }
+
     pub fn input_messages(
         &mut self,
         messages_in: impl IntoIterator<Item = Input>,
         since_start: Duration,
         timestamp: Duration,
     ) {
+        // the device's clock went backwards (it was probably reset or reconnected), so the old
+        // offset is meaningless now
+        if let Some(last_raw_timestamp) = self.last_raw_timestamp {
+            if timestamp + REBASE_THRESHOLD < last_raw_timestamp {
+                self.rebase();
+            }
+        }
+        self.last_raw_timestamp = Some(timestamp);
+
         let processed_timestamp = if let Some(relative) = &self.relative {
             relative.add_to(timestamp)
         } else {
-            self.relative = Some(DeltaDuration::sub(timestamp, since_start));
+            // the offset that takes the device's timestamps onto `since_start`
+            self.relative = Some(DeltaDuration::sub(since_start, timestamp));
 
             since_start
         };
 
+        self.last_timestamp = processed_timestamp;
         self.mapper.values_in.extend(messages_in);
 
+        // held values go out first, even if nothing new comes of this input
+        if self.channel_out.retry_backlog().is_err() {
+            return;
+        }
+
         while let Some(value) = self.mapper.step(processed_timestamp) {
-            if self.channel_out.send(value).is_err() {
+            if self.channel_out.send(value, &mut self.dropped).is_err() {
                 return; // looks like the channel hung up
             }
         }
     }
 }
+
+impl<Input, Converted> Drop for IntermittentSource<Input, Converted> {
+    fn drop(&mut self) {
+        self.flush(self.last_timestamp);
+
+        // whatever still didn't fit is lost now
+        self.dropped += self.channel_out.backlog_len() as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Emits the buffered bytes once a 0 terminates them.
+    fn until_zero(values: &mut VecDeque<u8>, since_start: Duration) -> Option<TimedValue<Vec<u8>>> {
+        let end = values.iter().position(|&byte| byte == 0)?;
+        let value = values.drain(..=end).take(end).collect();
+
+        Some(TimedValue { since_start, value })
+    }
+
+    /// Emits whatever's left, terminated or not.
+    fn the_rest(values: &mut VecDeque<u8>, since_start: Duration) -> Option<TimedValue<Vec<u8>>> {
+        if values.is_empty() {
+            return None;
+        }
+
+        Some(TimedValue {
+            since_start,
+            value: values.drain(..).collect(),
+        })
+    }
+
+    fn values<T>(receiver: &mpsc::Receiver<TimedValue<T>>) -> Vec<T> {
+        receiver.try_iter().map(|timed| timed.value).collect()
+    }
+
+    #[test]
+    fn flush_emits_the_buffered_tail() {
+        let (sender, receiver) = mpsc::channel();
+        let mut source = IntermittentSource::with_flush(sender, until_zero, the_rest);
+
+        source.input_messages([1, 2, 0, 3, 4], Duration::ZERO, Duration::ZERO);
+        assert_eq!(values(&receiver), [vec![1, 2]]);
+
+        source.flush(Duration::from_millis(5));
+        let tail = receiver.try_recv().unwrap();
+        assert_eq!(tail.value, [3, 4]);
+        assert_eq!(tail.since_start, Duration::from_millis(5));
+
+        // nothing's left to flush when it's dropped
+        drop(source);
+        assert_eq!(values(&receiver), Vec::<Vec<u8>>::new());
+    }
+
+    #[test]
+    fn drop_flushes_at_the_last_timestamp() {
+        let (sender, receiver) = mpsc::channel();
+        let mut source = IntermittentSource::with_flush(sender, until_zero, the_rest);
+
+        source.input_messages([7], Duration::from_millis(3), Duration::from_millis(3));
+        source.input_messages([8], Duration::from_millis(4), Duration::from_millis(4));
+        assert_eq!(values(&receiver), Vec::<Vec<u8>>::new());
+
+        drop(source);
+        let tail = receiver.try_recv().unwrap();
+        assert_eq!(tail.value, [7, 8]);
+        assert_eq!(tail.since_start, Duration::from_millis(4));
+    }
+
+    #[test]
+    fn flush_without_a_flush_function_only_steps() {
+        let (sender, receiver) = mpsc::channel();
+        let mut source = IntermittentSource::new(sender, until_zero);
+
+        source.input_messages([1, 2], Duration::ZERO, Duration::ZERO);
+        source.flush(Duration::ZERO);
+
+        assert_eq!(values(&receiver), Vec::<Vec<u8>>::new());
+    }
+
+    fn one_each(values: &mut VecDeque<u8>, since_start: Duration) -> Option<TimedValue<u8>> {
+        values.pop_front().map(|value| TimedValue { since_start, value })
+    }
+
+    #[test]
+    fn drop_newest_is_the_default() {
+        assert_eq!(OverflowPolicy::default(), OverflowPolicy::DropNewest);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_first_values() {
+        let (mut source, receiver) = IntermittentSource::bounded(2, OverflowPolicy::DropNewest, one_each);
+
+        // nobody's receiving, so only two fit
+        source.input_messages([1, 2, 3, 4, 5], Duration::ZERO, Duration::ZERO);
+
+        assert_eq!(source.dropped(), 3);
+        assert_eq!(values(&receiver), [1, 2]);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_values() {
+        let (mut source, receiver) = IntermittentSource::bounded(2, OverflowPolicy::DropOldest, one_each);
+
+        source.input_messages([1, 2, 3, 4, 5, 6], Duration::ZERO, Duration::ZERO);
+
+        // two made it into the channel, the two after them were pushed out of the backlog
+        assert_eq!(source.dropped(), 2);
+        assert_eq!(values(&receiver), [1, 2]);
+
+        // the backlog goes out once there's room
+        source.input_messages([7], Duration::ZERO, Duration::ZERO);
+        assert_eq!(values(&receiver), [5, 6]);
+        source.input_messages([], Duration::ZERO, Duration::ZERO);
+        assert_eq!(values(&receiver), [7]);
+        assert_eq!(source.dropped(), 2);
+    }
+
+    #[test]
+    fn flush_retries_the_backlog() {
+        let (mut source, receiver) = IntermittentSource::bounded(2, OverflowPolicy::DropOldest, one_each);
+
+        source.input_messages([1, 2, 3], Duration::ZERO, Duration::ZERO);
+        assert_eq!(values(&receiver), [1, 2]);
+
+        source.flush(Duration::ZERO);
+        assert_eq!(values(&receiver), [3]);
+        assert_eq!(source.dropped(), 0);
+    }
+
+    #[test]
+    fn drop_sends_the_backlog_if_it_can() {
+        let (mut source, receiver) = IntermittentSource::bounded(2, OverflowPolicy::DropOldest, one_each);
+
+        source.input_messages([1, 2, 3, 4], Duration::ZERO, Duration::ZERO);
+        assert_eq!(values(&receiver), [1, 2]);
+
+        drop(source);
+        assert_eq!(values(&receiver), [3, 4]);
+    }
+
+    #[test]
+    fn block_waits_for_the_receiver() {
+        let (mut source, receiver) = IntermittentSource::bounded(1, OverflowPolicy::Block, one_each);
+
+        let sender = std::thread::spawn(move || {
+            source.input_messages([1, 2, 3, 4], Duration::ZERO, Duration::ZERO);
+
+            source.dropped()
+        });
+
+        // the receiver stalls for a bit before catching up
+        std::thread::sleep(Duration::from_millis(20));
+        let received: Vec<u8> = receiver.iter().take(4).map(|timed| timed.value).collect();
+
+        assert_eq!(received, [1, 2, 3, 4]);
+        assert_eq!(sender.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn bounded_source_stops_when_the_receiver_hangs_up() {
+        let (mut source, receiver) = IntermittentSource::bounded(1, OverflowPolicy::Block, one_each);
+        drop(receiver);
+
+        // would block forever if it kept trying
+        source.input_messages([1, 2, 3], Duration::ZERO, Duration::ZERO);
+        assert_eq!(source.dropped(), 0);
+    }
+
+    #[test]
+    fn burst_is_delivered_in_one_batch() {
+        let (sender, receiver) = mpsc::channel();
+        let mut sink = IntermittentSink::new(receiver, |_: u8| panic!("values should come in batches"));
+
+        for value in 1..=5 {
+            sender.send(value).unwrap();
+        }
+        drop(sender);
+
+        let mut batches = Vec::new();
+        sink.start_batched(|batch| batches.push(batch.to_vec()));
+
+        assert_eq!(batches, [vec![1, 2, 3, 4, 5]]);
+    }
+
+    #[test]
+    fn batches_follow_the_bursts() {
+        let (sender, receiver) = mpsc::channel();
+        let (done_sender, done_receiver) = mpsc::channel();
+
+        // the first burst is already waiting when the sink starts
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        let sink = std::thread::spawn(move || {
+            let mut sink = IntermittentSink::new(receiver, |_: u8| {});
+            let mut batches = Vec::new();
+            sink.start_batched(|batch| {
+                batches.push(batch.to_vec());
+                done_sender.send(()).unwrap();
+            });
+
+            batches
+        });
+
+        // wait for the first burst to go through before sending the next
+        done_receiver.recv().unwrap();
+        sender.send(3).unwrap();
+        drop(sender);
+
+        assert_eq!(sink.join().unwrap(), [vec![1, 2], vec![3]]);
+    }
+
+    /// Values are (timestamp in ms, value) pairs, emitted once `since_start` reaches the timestamp.
+    fn scheduled() -> StreamMapper<(u64, u8), u8> {
+        let mut mapper = StreamMapper::new(|values: &mut VecDeque<(u64, u8)>, since_start| {
+            let &(at, value) = values.front()?;
+            let at = Duration::from_millis(at);
+            if at > since_start {
+                return None;
+            }
+
+            values.pop_front();
+            Some(TimedValue { since_start: at, value })
+        });
+        mapper.set_peek(|values| values.front().map(|&(at, _)| Duration::from_millis(at)));
+
+        mapper
+    }
+
+    #[test]
+    fn peek_reports_readiness_before_emitting() {
+        let mut mapper = scheduled();
+        assert_eq!(mapper.peek_next_time(), None);
+
+        mapper.values_in.extend([(10, 1), (20, 2)]);
+        assert_eq!(mapper.peek_next_time(), Some(Duration::from_millis(10)));
+
+        // peeking doesn't consume, and stepping too early doesn't either
+        assert_eq!(mapper.peek_next_time(), Some(Duration::from_millis(10)));
+        assert_eq!(mapper.step(Duration::from_millis(5)), None);
+        assert_eq!(mapper.peek_next_time(), Some(Duration::from_millis(10)));
+
+        assert_eq!(
+            mapper.step(Duration::from_millis(15)),
+            Some(TimedValue {
+                since_start: Duration::from_millis(10),
+                value: 1,
+            })
+        );
+        assert_eq!(mapper.peek_next_time(), Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn peek_interleaves_two_mappers() {
+        let mut mappers = [scheduled(), scheduled()];
+        mappers[0].values_in.extend([(0, 1), (30, 3)]);
+        mappers[1].values_in.extend([(10, 2), (40, 4)]);
+
+        let mut merged = Vec::new();
+        while let Some(next) = mappers
+            .iter_mut()
+            .filter(|mapper| mapper.peek_next_time().is_some())
+            .min_by_key(|mapper| mapper.peek_next_time())
+        {
+            let at = next.peek_next_time().unwrap();
+            merged.push(next.step(at).unwrap().value);
+        }
+
+        assert_eq!(merged, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn peek_without_a_peek_function_is_none() {
+        let mut mapper = StreamMapper::new(one_each);
+        mapper.values_in.push_back(1);
+
+        assert_eq!(mapper.peek_next_time(), None);
+    }
+
+    fn stamps(receiver: &mpsc::Receiver<TimedValue<u8>>) -> Vec<Duration> {
+        receiver.try_iter().map(|timed| timed.since_start).collect()
+    }
+
+    #[test]
+    fn timestamps_follow_the_device_clock() {
+        let (sender, receiver) = mpsc::channel();
+        let mut source = IntermittentSource::new(sender, one_each);
+
+        // the device's clock started long before the stream did
+        source.input_messages([1], Duration::from_millis(50), Duration::from_secs(10));
+        source.input_messages([2], Duration::from_millis(60), Duration::from_millis(10_007));
+        // and one that's ahead of the stream
+        source.input_messages([3], Duration::from_millis(70), Duration::from_millis(10_030));
+
+        assert_eq!(stamps(&receiver), [50, 57, 80].map(Duration::from_millis),);
+    }
+
+    #[test]
+    fn backward_jump_rebases() {
+        let (sender, receiver) = mpsc::channel();
+        let mut source = IntermittentSource::new(sender, one_each);
+
+        for i in 0..5 {
+            source.input_messages(
+                [i],
+                Duration::from_millis(i as u64 * 10),
+                Duration::from_secs(100 + i as u64),
+            );
+        }
+        // the device reconnected, and its clock started over
+        source.input_messages([5], Duration::from_millis(50), Duration::ZERO);
+        source.input_messages([6], Duration::from_millis(60), Duration::from_millis(12));
+
+        let stamps = stamps(&receiver);
+        assert_eq!(stamps[..5], [0, 1000, 2000, 3000, 4000].map(Duration::from_millis));
+        assert_eq!(stamps[5..], [50, 62].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn small_backward_steps_dont_rebase() {
+        let (sender, receiver) = mpsc::channel();
+        let mut source = IntermittentSource::new(sender, one_each);
+
+        source.input_messages([1], Duration::ZERO, Duration::from_millis(500));
+        source.input_messages([2], Duration::from_millis(10), Duration::from_millis(495));
+
+        assert_eq!(stamps(&receiver), [0, 0].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn explicit_rebase_uses_the_next_input() {
+        let (sender, receiver) = mpsc::channel();
+        let mut source = IntermittentSource::new(sender, one_each);
+
+        source.input_messages([1], Duration::ZERO, Duration::from_secs(1));
+        source.rebase();
+        source.input_messages([2], Duration::from_millis(40), Duration::from_secs(5));
+        source.input_messages([3], Duration::from_millis(50), Duration::from_millis(5_010));
+
+        assert_eq!(stamps(&receiver), [0, 40, 50].map(Duration::from_millis));
+    }
+}
@@ -0,0 +1,359 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::TimedValue;
+
+/// Jitter over the last [`JitterMeter::window`] intervals, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct JitterStats {
+    /// Average deviation from the expected interval. Positive means the intervals ran long, so a
+    /// mean that isn't close to 0 points at a tempo mismatch rather than jitter.
+    pub mean: f64,
+    /// Standard deviation of the deviations, which is the jitter proper
+    pub std_dev: f64,
+    /// Largest deviation either way (as a magnitude)
+    pub max: f64,
+}
+
+/// Measures how far the gaps between timestamps stray from a steady cadence, e.g. the interval
+/// between MIDI clocks. Feed it the `since_start` of each value coming out of an
+/// [`IntermittentSource`](crate::IntermittentSource).
+#[derive(Debug, Clone)]
+pub struct JitterMeter {
+    expected_interval: Duration,
+    window: usize,
+    last_timestamp: Option<Duration>,
+    /// Signed deviation of each interval from `expected_interval`, in seconds
+    deviations: VecDeque<f64>,
+}
+
+impl JitterMeter {
+    /// Creates a meter expecting timestamps `expected_interval` apart, keeping the last `window`
+    /// intervals. Panics if `window` is 0.
+    pub fn new(expected_interval: Duration, window: usize) -> JitterMeter {
+        assert!(window > 0, "the window has to hold at least one interval");
+
+        JitterMeter {
+            expected_interval,
+            window,
+            last_timestamp: None,
+            deviations: VecDeque::with_capacity(window),
+        }
+    }
+
+    pub fn expected_interval(&self) -> Duration {
+        self.expected_interval
+    }
+
+    /// Changes the cadence (e.g. after a tempo change). Intervals already measured keep the
+    /// deviation they had at the time.
+    pub fn set_expected_interval(&mut self, expected_interval: Duration) {
+        self.expected_interval = expected_interval;
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// How many intervals the stats are currently based on (at most the window).
+    pub fn len(&self) -> usize {
+        self.deviations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deviations.is_empty()
+    }
+
+    /// Records a timestamp. The first one only sets the reference for the next interval.
+    pub fn push(&mut self, timestamp: Duration) {
+        if let Some(last_timestamp) = self.last_timestamp {
+            let interval = timestamp.as_secs_f64() - last_timestamp.as_secs_f64();
+
+            if self.deviations.len() == self.window {
+                self.deviations.pop_front();
+            }
+            self.deviations
+                .push_back(interval - self.expected_interval.as_secs_f64());
+        }
+
+        self.last_timestamp = Some(timestamp);
+    }
+
+    /// Forgets everything measured so far, including the last timestamp (so a gap in the stream,
+    /// like a transport stop, doesn't count as a huge deviation).
+    pub fn reset(&mut self) {
+        self.last_timestamp = None;
+        self.deviations.clear();
+    }
+
+    /// Jitter over the window, or `None` before the first interval has been measured.
+    pub fn stats(&self) -> Option<JitterStats> {
+        if self.deviations.is_empty() {
+            return None;
+        }
+
+        let count = self.deviations.len() as f64;
+        let mean = self.deviations.iter().sum::<f64>() / count;
+        let variance = self
+            .deviations
+            .iter()
+            .map(|deviation| (deviation - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        let max = self
+            .deviations
+            .iter()
+            .fold(0.0_f64, |max, deviation| max.max(deviation.abs()));
+
+        Some(JitterStats {
+            mean,
+            std_dev: variance.sqrt(),
+            max,
+        })
+    }
+}
+
+/// Holds values back by a fixed latency, then releases each at `since_start + latency`. Values
+/// that took a varying amount of time to arrive come out with the spacing they were stamped with,
+/// at the cost of the added latency.
+///
+/// A value that arrives after it was due (its delay was bigger than the latency) goes out on the
+/// next poll instead, and is counted in [`JitterBuffer::late`]. If that happens a lot, the latency
+/// is too small.
+#[derive(Debug, Clone)]
+pub struct JitterBuffer<T> {
+    latency: Duration,
+    /// Values with when they're due, in order
+    pending: VecDeque<TimedValue<T>>,
+    late: u64,
+}
+
+impl<T> JitterBuffer<T> {
+    pub fn new(latency: Duration) -> JitterBuffer<T> {
+        JitterBuffer {
+            latency,
+            pending: VecDeque::new(),
+            late: 0,
+        }
+    }
+
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Changes the latency for values pushed from now on. Values already waiting keep their slot.
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+    }
+
+    /// How many values have arrived too late to be released on schedule.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// When the next value is due, if there is one.
+    pub fn next_due(&self) -> Option<Duration> {
+        self.pending.front().map(|value| value.since_start)
+    }
+
+    /// Adds a value that arrived at `now`.
+    pub fn push(&mut self, value: TimedValue<T>, now: Duration) {
+        let mut due = value.since_start + self.latency;
+        if due < now {
+            self.late += 1;
+            due = now;
+        }
+
+        // after anything due at the same time, so values with equal timestamps stay in order
+        let index = self.pending.partition_point(|pending| pending.since_start <= due);
+        self.pending.insert(
+            index,
+            TimedValue {
+                since_start: due,
+                value: value.value,
+            },
+        );
+    }
+
+    /// Returns everything due by `now`, stamped with when it was due (which for a late value is
+    /// when it arrived).
+    pub fn poll(&mut self, now: Duration) -> impl Iterator<Item = TimedValue<T>> + '_ {
+        let due = self.pending.partition_point(|pending| pending.since_start <= now);
+
+        self.pending.drain(..due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The interval between MIDI clocks at 120 BPM (24 per quarter note).
+    const CLOCK_INTERVAL: Duration = Duration::from_micros(20_833);
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Clock `i`, late by `jitter` on every odd one.
+    fn clock_at(i: u32, jitter: Duration) -> Duration {
+        CLOCK_INTERVAL * i + if i % 2 == 1 { jitter } else { Duration::ZERO }
+    }
+
+    #[test]
+    fn known_jitter_gives_the_right_std_dev() {
+        let mut meter = JitterMeter::new(CLOCK_INTERVAL, 48);
+        for i in 0..49 {
+            meter.push(clock_at(i, ms(1)));
+        }
+
+        // the intervals alternate between 1ms long and 1ms short
+        let stats = meter.stats().unwrap();
+        assert_eq!(meter.len(), 48);
+        assert!(stats.mean.abs() < 1e-6, "{stats:?}");
+        assert!((stats.std_dev - 0.001).abs() < 1e-6, "{stats:?}");
+        assert!((stats.max - 0.001).abs() < 1e-6, "{stats:?}");
+    }
+
+    #[test]
+    fn steady_clock_has_no_jitter() {
+        let mut meter = JitterMeter::new(CLOCK_INTERVAL, 24);
+        assert_eq!(meter.stats(), None);
+
+        for i in 0..30 {
+            meter.push(CLOCK_INTERVAL * i);
+        }
+
+        let stats = meter.stats().unwrap();
+        assert!(stats.std_dev < 1e-9 && stats.max < 1e-9, "{stats:?}");
+    }
+
+    #[test]
+    fn old_jitter_falls_out_of_the_window() {
+        let mut meter = JitterMeter::new(CLOCK_INTERVAL, 8);
+        for i in 0..8 {
+            meter.push(clock_at(i, ms(3)));
+        }
+        for i in 8..20 {
+            meter.push(CLOCK_INTERVAL * i);
+        }
+
+        assert!(meter.stats().unwrap().max < 1e-9);
+    }
+
+    #[test]
+    fn slow_tempo_shows_up_in_the_mean() {
+        let mut meter = JitterMeter::new(CLOCK_INTERVAL, 16);
+        for i in 0..17 {
+            meter.push((CLOCK_INTERVAL + ms(1)) * i);
+        }
+
+        let stats = meter.stats().unwrap();
+        assert!((stats.mean - 0.001).abs() < 1e-6, "{stats:?}");
+        assert!(stats.std_dev < 1e-6, "{stats:?}");
+    }
+
+    #[test]
+    fn reset_forgets_the_gap() {
+        let mut meter = JitterMeter::new(CLOCK_INTERVAL, 16);
+        meter.push(Duration::ZERO);
+        meter.push(CLOCK_INTERVAL);
+
+        meter.reset();
+        assert!(meter.is_empty());
+
+        // a transport stop's worth of silence later
+        meter.push(ms(5000));
+        meter.push(ms(5000) + CLOCK_INTERVAL);
+        assert!(meter.stats().unwrap().max < 1e-9);
+    }
+
+    #[test]
+    fn jittered_input_comes_out_evenly_spaced() {
+        let mut buffer = JitterBuffer::new(ms(5));
+        // stamped every 10ms, but each took a different amount of time to arrive
+        let delays = [0, 3, 1, 4, 2, 0, 4, 1];
+        let arrivals: Vec<_> = delays
+            .iter()
+            .enumerate()
+            .map(|(i, delay)| (ms(i as u64 * 10 + delay), ms(i as u64 * 10), i))
+            .collect();
+
+        // polled every 0.5ms, noting when each value actually came out
+        let mut released = Vec::new();
+        for now in (0..200).map(|tick| Duration::from_micros(tick * 500)) {
+            for (arrived, since_start, value) in &arrivals {
+                if *arrived == now {
+                    buffer.push(
+                        TimedValue {
+                            since_start: *since_start,
+                            value: *value,
+                        },
+                        now,
+                    );
+                }
+            }
+
+            released.extend(buffer.poll(now).map(|timed| (now, timed)));
+        }
+
+        assert_eq!(released.len(), delays.len());
+        for (i, (polled_at, timed)) in released.iter().enumerate() {
+            assert_eq!(timed.value, i);
+            assert_eq!(timed.since_start, ms(i as u64 * 10 + 5));
+            assert_eq!(*polled_at, timed.since_start);
+        }
+        assert_eq!(buffer.late(), 0);
+    }
+
+    #[test]
+    fn late_values_go_out_straight_away() {
+        let mut buffer = JitterBuffer::new(ms(5));
+
+        // due at 5ms, but it only turns up at 8ms
+        buffer.push(
+            TimedValue {
+                since_start: ms(0),
+                value: 'a',
+            },
+            ms(8),
+        );
+        assert_eq!(buffer.late(), 1);
+        assert_eq!(buffer.next_due(), Some(ms(8)));
+
+        let released: Vec<_> = buffer.poll(ms(8)).collect();
+        assert_eq!(
+            released,
+            [TimedValue {
+                since_start: ms(8),
+                value: 'a'
+            }]
+        );
+    }
+
+    #[test]
+    fn equal_stamps_keep_their_order() {
+        let mut buffer = JitterBuffer::new(ms(5));
+        for value in ['a', 'b', 'c'] {
+            buffer.push(
+                TimedValue {
+                    since_start: ms(10),
+                    value,
+                },
+                ms(11),
+            );
+        }
+
+        assert_eq!(buffer.poll(ms(14)).count(), 0);
+        let values: String = buffer.poll(ms(15)).map(|timed| timed.value).collect();
+        assert_eq!(values, "abc");
+        assert!(buffer.is_empty());
+    }
+}
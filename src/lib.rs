@@ -1,29 +1,81 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod autotune;
+#[cfg(feature = "std")]
+mod clock;
+#[cfg(feature = "std")]
+mod grouped;
+#[cfg(feature = "std")]
 mod intermittent;
+#[cfg(feature = "std")]
+mod jitter;
+#[cfg(feature = "std")]
+mod merge;
 pub mod midi;
+#[cfg(feature = "std")]
+pub mod mix;
+#[cfg(feature = "std")]
+mod paced;
+#[cfg(feature = "std")]
+mod quantize;
+#[cfg(feature = "std")]
+mod replay;
 pub mod resample;
+#[cfg(feature = "std")]
 mod stream;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod util;
 
 #[cfg(feature = "client_impls")]
 pub mod cpal;
 #[cfg(feature = "client_impls")]
 pub mod midir;
 
-use std::time::Duration;
-
-pub use intermittent::{IntermittentSink, IntermittentSource, TimedValue};
-pub use stream::{StreamSink, StreamSource};
+#[cfg(feature = "std")]
+pub use clock::{Clock, MockClock, MonotonicClock};
+#[cfg(feature = "std")]
+pub use grouped::{GroupedStreamSink, GroupedStreamSource};
+#[cfg(feature = "std")]
+pub use intermittent::{IntermittentSink, IntermittentSource, OverflowPolicy, StreamMapper, TimedValue};
+#[cfg(feature = "std")]
+pub use jitter::{JitterBuffer, JitterMeter, JitterStats};
+#[cfg(feature = "std")]
+pub use merge::TimedMerge;
+#[cfg(feature = "std")]
+pub use paced::PacedProducer;
+#[cfg(feature = "std")]
+pub use quantize::Quantizer;
+#[cfg(feature = "std")]
+pub use replay::{Player, Recorder};
+#[cfg(feature = "std")]
+pub use stream::{
+    LoopbackSink, OutputLimiter, PlaybackStatus, SinkMetrics, SourceMetrics, StreamSink, StreamSinkBuilder,
+    StreamSource, UnderrunFill, DEFAULT_LOW_WATER_MARK, DEFAULT_RATIO_LIMIT,
+};
+pub use util::{deinterleave, interleave, lerp, DeltaDuration, InterleaveError};
 
-pub fn lerp(start: f64, end: f64, amount: f64) -> f64 {
-    (end - start) * amount + start
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CompensationStrategy {
     Never,
     None,
     Resample { resample_ratio: f64, time: f64 },
 }
 
+/// Settings for the PID controller that steers the resample ratio.
+///
+/// Start from the defaults and change what's needed, either with the `with_*` methods (e.g.
+/// `PidSettings::default().with_prop_factor(0.02).with_target_fill(0.6)`) or with
+/// `..Default::default()` in a struct literal. Listing every field breaks whenever a new one is
+/// added.
+///
+/// The fields are public for convenience, but they have to satisfy a few invariants (see
+/// [`PidSettings::validate`]): the gains can't be negative, `min_factor <= max_factor`, and
+/// `factor_last_interp` and `target_fill` have to be between 0 and 1. Streams check this when
+/// they're created, and [`PidSettings::validated`] checks it up front.
 #[derive(Debug, Clone)]
 pub struct PidSettings {
     /// Proportional strength
@@ -39,6 +91,8 @@ pub struct PidSettings {
     pub max_factor: f64,
     /// how much of the new factor is applied (`lerp(last, new, factor_last_interp)`)
     pub factor_last_interp: f64,
+    /// how full the ring should be kept, as a fraction of its capacity
+    pub target_fill: f64,
 }
 
 impl Default for PidSettings {
@@ -50,28 +104,163 @@ impl Default for PidSettings {
             min_factor: -0.2,
             max_factor: 0.2,
             factor_last_interp: 0.1,
+            target_fill: 0.5,
         }
     }
 }
 
-pub(crate) enum DeltaDuration {
-    Positive(Duration),
-    Negative(Duration),
-}
+impl PidSettings {
+    pub fn with_prop_factor(mut self, prop_factor: f64) -> Self {
+        self.prop_factor = prop_factor;
+        self
+    }
+
+    pub fn with_integ_factor(mut self, integ_factor: f64) -> Self {
+        self.integ_factor = integ_factor;
+        self
+    }
+
+    pub fn with_deriv_factor(mut self, deriv_factor: f64) -> Self {
+        self.deriv_factor = deriv_factor;
+        self
+    }
+
+    /// Sets `min_factor` and `max_factor`.
+    pub fn with_factor_range(mut self, min_factor: f64, max_factor: f64) -> Self {
+        self.min_factor = min_factor;
+        self.max_factor = max_factor;
+        self
+    }
+
+    pub fn with_factor_last_interp(mut self, factor_last_interp: f64) -> Self {
+        self.factor_last_interp = factor_last_interp;
+        self
+    }
+
+    pub fn with_target_fill(mut self, target_fill: f64) -> Self {
+        self.target_fill = target_fill;
+        self
+    }
+
+    /// Passes the settings through if [`PidSettings::validate`] is happy with them, for checking
+    /// them at the end of a chain of `with_*` calls.
+    pub fn validated(self) -> Result<PidSettings, PidSettingsError> {
+        self.validate().map(|_| self)
+    }
+
+    /// Checks that the settings make sense. NaNs are always rejected.
+    pub fn validate(&self) -> Result<(), PidSettingsError> {
+        let gains = [self.prop_factor, self.integ_factor, self.deriv_factor];
+        // written this way round so NaN fails too
+        if !gains.iter().all(|gain| *gain >= 0.0) {
+            return Err(PidSettingsError::NegativeGain);
+        }
+
+        if self.min_factor.is_nan() || self.max_factor.is_nan() || self.min_factor > self.max_factor {
+            return Err(PidSettingsError::FactorRangeInverted);
+        }
+
+        if !(0.0..=1.0).contains(&self.factor_last_interp) {
+            return Err(PidSettingsError::InterpOutOfRange);
+        }
 
-impl DeltaDuration {
-    pub(crate) fn sub(first: Duration, second: Duration) -> DeltaDuration {
-        if second > first {
-            DeltaDuration::Negative(second - first)
-        } else {
-            DeltaDuration::Positive(first - second)
+        if !(0.0..=1.0).contains(&self.target_fill) {
+            return Err(PidSettingsError::TargetFillOutOfRange);
         }
+
+        Ok(())
     }
+}
 
-    pub(crate) fn add_to(&self, other: Duration) -> Duration {
+/// Why [`PidSettings`] were rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PidSettingsError {
+    /// `prop_factor`, `integ_factor`, or `deriv_factor` is negative (or NaN)
+    NegativeGain,
+    /// `min_factor` is greater than `max_factor`
+    FactorRangeInverted,
+    /// `factor_last_interp` isn't between 0 and 1
+    InterpOutOfRange,
+    /// `target_fill` isn't between 0 and 1
+    TargetFillOutOfRange,
+}
+
+impl core::fmt::Display for PidSettingsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            DeltaDuration::Positive(duration) => other + *duration,
-            DeltaDuration::Negative(duration) => other - *duration,
+            PidSettingsError::NegativeGain => write!(f, "PID gains can't be negative"),
+            PidSettingsError::FactorRangeInverted => write!(f, "min_factor is greater than max_factor"),
+            PidSettingsError::InterpOutOfRange => write!(f, "factor_last_interp must be between 0 and 1"),
+            PidSettingsError::TargetFillOutOfRange => write!(f, "target_fill must be between 0 and 1"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PidSettingsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(PidSettings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn negative_gains_are_rejected() {
+        for settings in [
+            PidSettings::default().with_prop_factor(-0.01),
+            PidSettings::default().with_integ_factor(-0.01),
+            PidSettings::default().with_deriv_factor(-0.01),
+            PidSettings::default().with_prop_factor(f64::NAN),
+        ] {
+            assert_eq!(settings.validate(), Err(PidSettingsError::NegativeGain));
+        }
+    }
+
+    #[test]
+    fn inverted_factor_range_is_rejected() {
+        assert_eq!(
+            PidSettings::default().with_factor_range(0.1, -0.1).validate(),
+            Err(PidSettingsError::FactorRangeInverted)
+        );
+        assert_eq!(
+            PidSettings::default().with_factor_range(f64::NAN, 0.1).validate(),
+            Err(PidSettingsError::FactorRangeInverted)
+        );
+        // an empty range is fine
+        assert_eq!(PidSettings::default().with_factor_range(0.0, 0.0).validate(), Ok(()));
+    }
+
+    #[test]
+    fn interp_out_of_range_is_rejected() {
+        for factor_last_interp in [-0.1, 1.1, f64::NAN] {
+            assert_eq!(
+                PidSettings::default()
+                    .with_factor_last_interp(factor_last_interp)
+                    .validate(),
+                Err(PidSettingsError::InterpOutOfRange)
+            );
         }
     }
+
+    #[test]
+    fn target_fill_out_of_range_is_rejected() {
+        for target_fill in [-0.1, 1.1, f64::NAN] {
+            assert_eq!(
+                PidSettings::default().with_target_fill(target_fill).validate(),
+                Err(PidSettingsError::TargetFillOutOfRange)
+            );
+        }
+    }
+
+    #[test]
+    fn validated_passes_good_settings_through() {
+        let settings = PidSettings::default().with_target_fill(0.7).validated().unwrap();
+        assert_eq!(settings.target_fill, 0.7);
+
+        assert!(PidSettings::default().with_target_fill(2.0).validated().is_err());
+    }
 }
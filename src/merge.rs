@@ -0,0 +1,195 @@
+use std::{
+    sync::mpsc::{self, RecvTimeoutError, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use crate::TimedValue;
+
+struct MergeInput<T> {
+    receiver: mpsc::Receiver<TimedValue<T>>,
+    /// The oldest value received but not sent on yet
+    head: Option<TimedValue<T>>,
+    /// Didn't send anything within `max_wait` last time, so it isn't waited on until it does
+    stalled: bool,
+    closed: bool,
+}
+
+impl<T> MergeInput<T> {
+    /// Tries to fill `head`, waiting until `deadline` at the latest (unless stalled).
+    fn fill(&mut self, deadline: Instant) {
+        if self.head.is_some() || self.closed {
+            return;
+        }
+
+        if self.stalled {
+            match self.receiver.try_recv() {
+                Ok(value) => {
+                    self.head = Some(value);
+                    self.stalled = false;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.closed = true,
+            }
+        } else {
+            match self
+                .receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(value) => self.head = Some(value),
+                Err(RecvTimeoutError::Timeout) => self.stalled = true,
+                Err(RecvTimeoutError::Disconnected) => self.closed = true,
+            }
+        }
+    }
+}
+
+/// Merges several [`TimedValue`] channels into one, in `since_start` order. Each input has to be in
+/// order already (which anything coming out of an [`IntermittentSource`](crate::IntermittentSource)
+/// is).
+///
+/// To know what's next, the merge needs a value from every input, so it waits on whichever are
+/// empty. An input that stays quiet for longer than `max_wait` stops holding the others up until it
+/// sends something again; anything it sends in the meantime that's older than what's already gone
+/// out is passed on late rather than dropped.
+pub struct TimedMerge<T> {
+    inputs: Vec<MergeInput<T>>,
+    channel_out: mpsc::Sender<TimedValue<T>>,
+    max_wait: Duration,
+}
+
+impl<T> TimedMerge<T> {
+    pub fn new(
+        inputs: impl IntoIterator<Item = mpsc::Receiver<TimedValue<T>>>,
+        channel_out: mpsc::Sender<TimedValue<T>>,
+        max_wait: Duration,
+    ) -> TimedMerge<T> {
+        TimedMerge {
+            inputs: inputs
+                .into_iter()
+                .map(|receiver| MergeInput {
+                    receiver,
+                    head: None,
+                    stalled: false,
+                    closed: false,
+                })
+                .collect(),
+            channel_out,
+            max_wait,
+        }
+    }
+
+    /// Sends on the next value in order, blocking (up to `max_wait` per input) until it's known.
+    /// Returns false once every input has hung up and been emptied, or the output hung up.
+    pub fn step(&mut self) -> bool {
+        loop {
+            let deadline = Instant::now() + self.max_wait;
+            for input in &mut self.inputs {
+                input.fill(deadline);
+            }
+
+            let next = self
+                .inputs
+                .iter()
+                .enumerate()
+                .filter_map(|(input_i, input)| input.head.as_ref().map(|head| (head.since_start, input_i)))
+                .min();
+
+            if let Some((_, input_i)) = next {
+                let value = self.inputs[input_i].head.take().expect("head was just checked");
+
+                return self.channel_out.send(value).is_ok();
+            }
+
+            if self.inputs.iter().all(|input| input.closed) {
+                return false;
+            }
+
+            // everything still open is stalled, so go back to waiting on them instead of spinning
+            for input in &mut self.inputs {
+                input.stalled = false;
+            }
+        }
+    }
+
+    /// This function blocks until all of the inputs hang up; probably best to run in a thread.
+    pub fn start(&mut self) {
+        while self.step() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn timed<T>(millis: u64, value: T) -> TimedValue<T> {
+        TimedValue {
+            since_start: Duration::from_millis(millis),
+            value,
+        }
+    }
+
+    /// A channel that's already been sent `values` (in order), and hung up.
+    fn finished_input<T>(values: impl IntoIterator<Item = TimedValue<T>>) -> mpsc::Receiver<TimedValue<T>> {
+        let (sender, receiver) = mpsc::channel();
+        for value in values {
+            sender.send(value).unwrap();
+        }
+
+        receiver
+    }
+
+    #[test]
+    fn interleaved_streams_come_out_in_order() {
+        let first = finished_input([timed(0, 'a'), timed(20, 'c'), timed(40, 'e')]);
+        let second = finished_input([timed(10, 'b'), timed(30, 'd'), timed(50, 'f')]);
+        let (sender, receiver) = mpsc::channel();
+
+        TimedMerge::new([first, second], sender, Duration::from_millis(100)).start();
+
+        let merged: String = receiver.iter().map(|timed| timed.value).collect();
+        assert_eq!(merged, "abcdef");
+    }
+
+    #[test]
+    fn ties_go_to_the_earlier_input() {
+        let first = finished_input([timed(10, 'a')]);
+        let second = finished_input([timed(10, 'b')]);
+        let (sender, receiver) = mpsc::channel();
+
+        TimedMerge::new([second, first], sender, Duration::from_millis(100)).start();
+
+        let merged: String = receiver.iter().map(|timed| timed.value).collect();
+        assert_eq!(merged, "ba");
+    }
+
+    #[test]
+    fn a_silent_input_doesnt_stall_the_merge() {
+        let talking = finished_input([timed(0, 'a'), timed(10, 'b')]);
+        // never sends anything, but doesn't hang up either
+        let (_silent_sender, silent) = mpsc::channel();
+        let (sender, receiver) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let mut merge = TimedMerge::new([talking, silent], sender, Duration::from_millis(20));
+            merge.step();
+            merge.step();
+        });
+
+        let start = Instant::now();
+        let merged: String = receiver.iter().map(|timed| timed.value).collect();
+        handle.join().unwrap();
+
+        assert_eq!(merged, "ab");
+        assert!(start.elapsed() < Duration::from_secs(1), "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn step_stops_once_everything_hangs_up() {
+        let (sender, _receiver) = mpsc::channel::<TimedValue<()>>();
+        let mut merge = TimedMerge::new([finished_input([])], sender, Duration::from_millis(10));
+
+        assert!(!merge.step());
+    }
+}
@@ -1,15 +1,41 @@
-use std::{collections::VecDeque, time::Duration};
+use alloc::{collections::VecDeque, vec::Vec};
+
+use smallvec::SmallVec;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+mod clock;
+mod filter;
+mod gate;
+mod high_res;
+mod mmc;
+mod mpe;
+mod mtc;
+mod notes;
+mod sds;
+mod sensing;
+mod sysex;
+mod transform;
+mod transport;
+
+pub use clock::{MidiClockFollower, MidiClockGenerator, CLOCKS_PER_QUARTER};
+pub use filter::{MessageKind, MidiFilter};
+pub use gate::ClockGate;
+pub use high_res::HighResCcDecoder;
+pub use mmc::MmcCommand;
+pub use mpe::{MpeNote, MpeZone};
+pub use mtc::{MtcGenerator, MtcRate, MtcTime};
+pub use notes::NoteTracker;
+pub use sds::SdsHandshake;
+pub use sensing::{ActiveSensingMonitor, SensingState, ACTIVE_SENSING_TIMEOUT};
+pub use sysex::Manufacturer;
+pub use transform::{MidiTransform, OutOfRange};
+pub use transport::TransportState;
+
 /// low and high are nibbles
 #[derive(Debug, Clone, PartialEq, Eq)]
-#[cfg_attr(
-    feature = "serde",
-    derive(Serialize, Deserialize),
-    serde(tag = "variant", content = "data")
-)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Timecode {
     FrameLow(u8),
     FrameHigh(u8),
@@ -22,11 +48,7 @@ pub enum Timecode {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-#[cfg_attr(
-    feature = "serde",
-    derive(Serialize, Deserialize),
-    serde(tag = "variant", content = "data")
-)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SysCommon {
     QuarterFrame { time_fragment: Timecode },
     SongPositionPointer { position: u16 },
@@ -36,11 +58,7 @@ pub enum SysCommon {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
-#[cfg_attr(
-    feature = "serde",
-    derive(Serialize, Deserialize),
-    serde(tag = "variant", content = "data")
-)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SysRt {
     MidiClock = 0xF8,
     Tick = 0xF9,
@@ -51,301 +69,1124 @@ pub enum SysRt {
     Reset = 0xFF,
 }
 
+/// A parsed MIDI message.
+///
+/// With the `serde` feature this (and the other MIDI enums) serializes in serde's default
+/// externally tagged form, e.g. `{"NoteOn":{"channel":0,"note":60,"velocity":100}}` in JSON, which
+/// also works with formats that aren't self-describing like bincode.
 #[derive(Debug, Clone, PartialEq, Eq)]
-#[cfg_attr(
-    feature = "serde",
-    derive(Serialize, Deserialize),
-    serde(tag = "variant", content = "data")
-)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MidiData {
-    NoteOff { channel: u8, note: u8, velocity: u8 },
-    NoteOn { channel: u8, note: u8, velocity: u8 },
-    Aftertouch { channel: u8, note: u8, pressure: u8 },
-    ControlChange { channel: u8, controller: u8, value: u8 },
-    ProgramChange { channel: u8, patch: u8 },
-    ChannelPressure { channel: u8, pressure: u8 },
-    PitchBend { channel: u8, pitch_bend: u16 },
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    Aftertouch {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    /// A high resolution control change (see [`HighResCcDecoder`]). `controller` is the MSB's
+    /// controller (0-31), and `value` is 14 bits.
+    ControlChange14 {
+        channel: u8,
+        controller: u8,
+        value: u16,
+    },
+    ProgramChange {
+        channel: u8,
+        patch: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    PitchBend {
+        channel: u8,
+        pitch_bend: u16,
+    },
     SysCommon(SysCommon),
     SysRt(SysRt),
-    SysEx { id_and_data: Vec<u8> },
+    SysEx {
+        id_and_data: Vec<u8>,
+    },
+    /// MIDI Machine Control (a universal real-time sysex message)
+    Mmc {
+        device: u8,
+        command: MmcCommand,
+    },
+    /// A Sample Dump Standard handshake (a universal non-real-time sysex message). `packet` is the
+    /// packet number being answered (0-127).
+    SampleDumpHandshake {
+        device: u8,
+        kind: SdsHandshake,
+        packet: u8,
+    },
+    /// One of the undefined system status bytes (0xF4, 0xF5, or 0xFD). These are always treated as
+    /// a single byte: even though some devices follow 0xF5 with a data byte (a "port select"), that
+    /// isn't standard, so any data bytes after it are skipped like other stray data.
+    Unknown {
+        status: u8,
+    },
+    /// A sysex message that went on for longer than the parser's limit, and was abandoned (see
+    /// [`parse_midi_with_limit`]). `len` is how many bytes were thrown away, though the rest of the
+    /// message is skipped as well.
+    SysExOverflow {
+        len: usize,
+    },
     MidiNone,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub struct MidiMessage {
-    pub data: MidiData,
-    pub timestamp: Duration,
+#[allow(deprecated)]
+pub use message::MidiMessage;
+
+// in its own module so the derives on a deprecated struct don't warn
+#[allow(deprecated)]
+mod message {
+    use core::time::Duration;
+
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    use super::MidiData;
+
+    /// Superseded by `TimedValue<MidiData>`, which is what the MIDI sources produce (with the
+    /// timestamp already rebased against the start of the stream).
+    #[deprecated(note = "use `TimedValue<MidiData>` instead")]
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct MidiMessage {
+        pub data: MidiData,
+        pub timestamp: Duration,
+    }
 }
 
-/// returns `None` if there isn't enough data to tell what length is needed
-fn prep_message(buffer: &mut VecDeque<u8>) -> Option<usize> {
-    while !buffer.is_empty() && buffer[0] & 0x80 == 0 {
-        // shift through the buffer until we hit a viable message
-        buffer.pop_front();
-    }
-
-    if let Some(first_byte) = buffer.get(0).copied() {
-        if first_byte >= 0x80 && first_byte <= 0xEF {
-            // Voice messages
-            let message = first_byte >> 4;
-
-            match message {
-                0x8 => Some(3), // note on
-                0x9 => Some(3), // note off
-                0xA => Some(3), // aftertouch
-                0xB => Some(3), // control change
-                0xC => Some(2), // program change
-                0xD => Some(2), // channel pressure
-                0xE => Some(3), // pitch bend
-                _ => unreachable!("already checked message bounds"),
-            }
-        } else if first_byte >> 4 == 0xF {
-            // sysex message
-
-            match first_byte & 0x0F {
-                0x0 => {
-                    for (i, value) in buffer.iter().enumerate().skip(1) {
-                        if *value == 0xF7 {
-                            return Some(i + 1);
-                        } else if *value & 0x80 != 0 {
-                            // if we had a normal message come up, we better
-                            // drop all of the (failed) sysex message
-                            buffer.drain(0..i);
-
-                            return prep_message(buffer);
-                        }
-                    }
+/// Length of the message starting at `input[0]` (which must be a status byte).
+enum MessageLen {
+    Complete(usize),
+    /// Not enough data to tell yet
+    Incomplete,
+    /// A sysex message that was cut off by another status byte after this many bytes
+    Aborted(usize),
+    /// A sysex message that went past the length limit after this many bytes
+    Overflow(usize),
+}
+
+fn message_len(input: &[u8], max_sysex_len: usize) -> MessageLen {
+    let first_byte = input[0];
+
+    if (0x80..=0xEF).contains(&first_byte) {
+        // Voice messages
+        let message = first_byte >> 4;
+
+        MessageLen::Complete(match message {
+            0x8 => 3, // note on
+            0x9 => 3, // note off
+            0xA => 3, // aftertouch
+            0xB => 3, // control change
+            0xC => 2, // program change
+            0xD => 2, // channel pressure
+            0xE => 3, // pitch bend
+            _ => unreachable!("already checked message bounds"),
+        })
+    } else {
+        // sysex message
+        debug_assert_eq!(first_byte >> 4, 0xF);
 
-                    None
+        MessageLen::Complete(match first_byte & 0x0F {
+            0x0 => {
+                for (i, value) in input.iter().enumerate().skip(1) {
+                    if *value == 0xF7 {
+                        return MessageLen::Complete(i + 1);
+                    } else if *value & 0x80 != 0 {
+                        // if we had a normal message come up, we better
+                        // drop all of the (failed) sysex message
+                        return MessageLen::Aborted(i);
+                    } else if i > max_sysex_len {
+                        return MessageLen::Overflow(i);
+                    }
                 }
-                0x1 => Some(2), // quarter frame
-                0x2 => Some(3), // song position
-                0x3 => Some(2), // song select
-                0x4 => Some(1), // reserved?
-                0x5 => Some(1), // reserved?
-                0x6 => Some(1), // tune request
-                0x7 => Some(1), // sysex end message (will be ignored)
-                0x8 => Some(1), // midi clock
-                0x9 => Some(1), // midi tick
-                0xA => Some(1), // midi start
-                0xB => Some(1), // midi continue
-                0xC => Some(1), // midi stop
-                0xD => Some(1), // reserved?
-                0xE => Some(1), // active sensing
-                0xF => Some(1), // system reset
-                _ => unreachable!("only matching & 0x0F"),
+
+                return MessageLen::Incomplete;
             }
-        } else {
-            unreachable!("no message header. Should have been established by beginning while loop");
-        }
-    } else {
-        None
+            0x1 => 2, // quarter frame
+            0x2 => 3, // song position
+            0x3 => 2, // song select
+            0x4 => 1, // undefined
+            0x5 => 1, // undefined (see `MidiData::Unknown`)
+            0x6 => 1, // tune request
+            0x7 => 1, // sysex end message (will be ignored)
+            0x8 => 1, // midi clock
+            0x9 => 1, // midi tick
+            0xA => 1, // midi start
+            0xB => 1, // midi continue
+            0xC => 1, // midi stop
+            0xD => 1, // undefined
+            0xE => 1, // active sensing
+            0xF => 1, // system reset
+            _ => unreachable!("only matching & 0x0F"),
+        })
     }
 }
 
-// so I don't have to type so much
-fn n(buffer: &mut VecDeque<u8>) -> u8 {
-    buffer.pop_front().unwrap()
+/// What [`parse_midi_ev`] found at the front of a buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseOutcome {
+    /// A complete message, which was removed from the buffer
+    Message(MidiData),
+    /// What's left is the start of a message that isn't all there yet (or nothing at all)
+    NeedMoreData,
+    /// This many bytes were thrown away without making up a message: data bytes with no status
+    /// byte in front of them, a sysex cut off by another message, or a lone sysex end byte. There
+    /// may be more to parse after them.
+    Dropped(usize),
 }
 
+/// Parses the next message out of `buffer`, removing whatever was parsed (or skipped).
+///
+/// There's no limit on how long a sysex message can be, so if a stream might never terminate one,
+/// use [`parse_midi_with_limit`] to keep `buffer` from growing forever.
+///
+/// Anything that isn't a message is skipped silently; see [`parse_midi_ev`] to find out about it.
 pub fn parse_midi(buffer: &mut VecDeque<u8>) -> Option<MidiData> {
-    let needed = prep_message(buffer);
+    parse_midi_with_limit(buffer, usize::MAX)
+}
 
-    let enough_in_buffer = if let Some(needed) = needed {
-        buffer.len() >= needed
-    } else {
-        false
+/// Same as [`parse_midi`], but a sysex message with more than `max_sysex_len` bytes (between the
+/// start and end bytes) is abandoned: what's been buffered of it is removed, and
+/// [`MidiData::SysExOverflow`] is returned so the caller knows. The rest of the message is then
+/// skipped like any other stray data.
+pub fn parse_midi_with_limit(buffer: &mut VecDeque<u8>, max_sysex_len: usize) -> Option<MidiData> {
+    loop {
+        match parse_midi_ev_with_limit(buffer, max_sysex_len) {
+            ParseOutcome::Message(message) => return Some(message),
+            ParseOutcome::NeedMoreData => return None,
+            // keep going past anything that was skipped
+            ParseOutcome::Dropped(_) => {}
+        }
+    }
+}
+
+/// Like [`parse_midi`], but takes one step at a time and says what happened, so skipped bytes can
+/// be told apart from a message that's still arriving. Bytes skipped ahead of a message are
+/// reported (as [`ParseOutcome::Dropped`]) before the message itself.
+pub fn parse_midi_ev(buffer: &mut VecDeque<u8>) -> ParseOutcome {
+    parse_midi_ev_with_limit(buffer, usize::MAX)
+}
+
+/// Same as [`parse_midi_ev`], but with a limit on sysex length (see [`parse_midi_with_limit`]).
+pub fn parse_midi_ev_with_limit(buffer: &mut VecDeque<u8>, max_sysex_len: usize) -> ParseOutcome {
+    let input = buffer.make_contiguous();
+
+    // report stray data on its own, so it isn't folded into the message after it
+    let start = input.iter().position(|byte| byte & 0x80 != 0).unwrap_or(input.len());
+    if start > 0 {
+        buffer.drain(..start);
+
+        return ParseOutcome::Dropped(start);
+    }
+
+    let (message, consumed) = parse_midi_slice_with_limit(input, max_sysex_len);
+    buffer.drain(..consumed);
+
+    match message {
+        Some(message) => ParseOutcome::Message(message),
+        None if consumed == 0 => ParseOutcome::NeedMoreData,
+        None => ParseOutcome::Dropped(consumed),
+    }
+}
+
+/// Parses the next message straight out of `input`, returning it along with how many bytes were
+/// consumed. Stray data bytes and ignored messages are consumed without returning a message, while
+/// a partial message at the end of `input` isn't consumed at all (so `(None, 0)` means more data is
+/// needed).
+pub fn parse_midi_slice(input: &[u8]) -> (Option<MidiData>, usize) {
+    parse_midi_slice_with_limit(input, usize::MAX)
+}
+
+/// Same as [`parse_midi_slice`], but with a limit on sysex length (see [`parse_midi_with_limit`]).
+pub fn parse_midi_slice_with_limit(input: &[u8], max_sysex_len: usize) -> (Option<MidiData>, usize) {
+    // shift through the buffer until we hit a viable message
+    let Some(start) = input.iter().position(|byte| byte & 0x80 != 0) else {
+        return (None, input.len());
     };
 
-    if enough_in_buffer {
-        let first_byte = n(buffer);
+    let message = &input[start..];
 
-        if first_byte >= 0x80 && first_byte <= 0xEF {
-            // Voice messages
-            let message = first_byte >> 4;
-            let channel = first_byte & 0x0F;
+    let needed = match message_len(message, max_sysex_len) {
+        MessageLen::Complete(needed) if needed <= message.len() => needed,
+        MessageLen::Complete(_) | MessageLen::Incomplete => return (None, start),
+        MessageLen::Aborted(len) => return (None, start + len),
+        MessageLen::Overflow(len) => return (Some(MidiData::SysExOverflow { len }), start + len),
+    };
 
-            match message {
-                // note off
-                0x8 => Some(MidiData::NoteOff {
-                    channel,
-                    note: n(buffer) & 0x7F,
-                    velocity: n(buffer) & 0x7F,
-                }),
-                // note on
-                0x9 => Some(MidiData::NoteOn {
-                    channel,
-                    note: n(buffer) & 0x7F,
-                    velocity: n(buffer) & 0x7F,
-                }),
-                0xA => Some(MidiData::Aftertouch {
-                    channel,
-                    note: n(buffer) & 0x7F,
-                    pressure: n(buffer) & 0x7F,
-                }), // aftertouch
-                0xB => Some(MidiData::ControlChange {
-                    channel,
-                    controller: n(buffer) & 0x7F,
-                    value: n(buffer) & 0x7F,
-                }), // control change
-                0xC => Some(MidiData::ProgramChange {
-                    channel,
-                    patch: n(buffer) & 0x7F,
-                }), // program change
-                0xD => Some(MidiData::ChannelPressure {
-                    channel,
-                    pressure: n(buffer) & 0x7F,
-                }), // channel pressure
-                0xE => Some(MidiData::PitchBend {
-                    channel,
-                    pitch_bend: (n(buffer) as u16 & 0x7F) | ((n(buffer) as u16 & 0x7F) << 7),
-                }), // pitch bend
-                _ => unreachable!("already checked message bounds"),
-            }
-        } else if first_byte >> 4 == 0xF {
-            match first_byte & 0x0F {
-                0x0 => {
-                    // sysex
-                    let mut data = Vec::new();
-
-                    for _ in 0..needed.unwrap() {
-                        if let Some(next_data) = buffer.pop_front() {
-                            if next_data & 0x80 != 0 {
-                                // gotta do this in the case there isn't a sysex end message
-                                break;
-                            }
-
-                            data.push(next_data);
-                        } else {
-                            break;
-                        }
-                    }
+    (decode_message(&message[..needed]), start + needed)
+}
 
-                    Some(MidiData::SysEx { id_and_data: data })
-                }
-                0x1 => {
-                    // quarter frame
-                    let data_byte = n(buffer) & 0x7F;
-                    let value_type = (data_byte >> 4) & 0x0F;
-                    let value = data_byte & 0x0F;
-
-                    Some(MidiData::SysCommon(SysCommon::QuarterFrame {
-                        time_fragment: match value_type {
-                            0 => Timecode::FrameLow(value),
-                            1 => Timecode::FrameHigh(value),
-                            2 => Timecode::SecondsLow(value),
-                            3 => Timecode::SecondsHigh(value),
-                            4 => Timecode::MinutesLow(value),
-                            5 => Timecode::MinutesHigh(value),
-                            6 => Timecode::HoursLow(value),
-                            7 => Timecode::HoursHigh(value),
-                            _ => unreachable!("value_type cannot be more than 7"),
+/// Decodes a complete message, as measured by [`message_len`].
+fn decode_message(message: &[u8]) -> Option<MidiData> {
+    let first_byte = message[0];
+
+    if (0x80..=0xEF).contains(&first_byte) {
+        // Voice messages
+        let message_type = first_byte >> 4;
+        let channel = first_byte & 0x0F;
+
+        match message_type {
+            // note off
+            0x8 => Some(MidiData::NoteOff {
+                channel,
+                note: message[1] & 0x7F,
+                velocity: message[2] & 0x7F,
+            }),
+            // note on
+            0x9 => Some(MidiData::NoteOn {
+                channel,
+                note: message[1] & 0x7F,
+                velocity: message[2] & 0x7F,
+            }),
+            0xA => Some(MidiData::Aftertouch {
+                channel,
+                note: message[1] & 0x7F,
+                pressure: message[2] & 0x7F,
+            }), // aftertouch
+            0xB => Some(MidiData::ControlChange {
+                channel,
+                controller: message[1] & 0x7F,
+                value: message[2] & 0x7F,
+            }), // control change
+            0xC => Some(MidiData::ProgramChange {
+                channel,
+                patch: message[1] & 0x7F,
+            }), // program change
+            0xD => Some(MidiData::ChannelPressure {
+                channel,
+                pressure: message[1] & 0x7F,
+            }), // channel pressure
+            0xE => Some(MidiData::PitchBend {
+                channel,
+                pitch_bend: (message[1] as u16 & 0x7F) | ((message[2] as u16 & 0x7F) << 7),
+            }), // pitch bend
+            _ => unreachable!("already checked message bounds"),
+        }
+    } else {
+        match first_byte & 0x0F {
+            0x0 => {
+                // sysex, minus the start and end bytes
+                let data = &message[1..(message.len() - 1)];
+
+                match *data {
+                    // universal real-time, MMC command
+                    [0x7F, device, 0x06, command] => Some(MidiData::Mmc {
+                        device,
+                        command: MmcCommand::from_byte(command),
+                    }),
+                    // universal non-real-time, sample dump handshake
+                    [0x7E, device, kind, packet] => Some(match SdsHandshake::from_byte(kind) {
+                        Some(kind) => MidiData::SampleDumpHandshake { device, kind, packet },
+                        // some other universal non-real-time message that happens to be as short
+                        None => MidiData::SysEx {
+                            id_and_data: data.to_vec(),
                         },
-                    }))
-                }
-                // song position
-                0x2 => Some(MidiData::SysCommon(SysCommon::SongPositionPointer {
-                    position: (n(buffer) as u16 & 0x7F) | ((n(buffer) as u16 & 0x7F) << 7),
-                })),
-                // song select
-                0x3 => Some(MidiData::SysCommon(SysCommon::SongSelect { song: n(buffer) })),
-                // reserved?
-                0x4 | 0x5 | 0xD => {
-                    n(buffer);
-                    None
-                }
-                // tune request
-                0x6 => Some(MidiData::SysCommon(SysCommon::TuneRequest)),
-                // sysex end message (will be ignored)
-                0x7 => {
-                    n(buffer);
-                    None
+                    }),
+                    _ => Some(MidiData::SysEx {
+                        id_and_data: data.to_vec(),
+                    }),
                 }
-                // midi clock
-                0x8 => Some(MidiData::SysRt(SysRt::MidiClock)),
-                // midi tick
-                0x9 => Some(MidiData::SysRt(SysRt::Tick)),
-                // midi start
-                0xA => Some(MidiData::SysRt(SysRt::Start)),
-                // midi continue
-                0xB => Some(MidiData::SysRt(SysRt::Continue)),
-                // midi stop
-                0xC => Some(MidiData::SysRt(SysRt::Stop)),
-                // active sensing
-                0xE => Some(MidiData::SysRt(SysRt::ActiveSensing)),
-                // system reset
-                0xF => Some(MidiData::SysRt(SysRt::Reset)),
-                _ => unreachable!("only matching & 0x0F"),
             }
-        } else {
-            unreachable!("no message header. Should have been established by beginning while loop");
+            0x1 => {
+                // quarter frame
+                let data_byte = message[1] & 0x7F;
+                let value_type = (data_byte >> 4) & 0x0F;
+                let value = data_byte & 0x0F;
+
+                Some(MidiData::SysCommon(SysCommon::QuarterFrame {
+                    time_fragment: match value_type {
+                        0 => Timecode::FrameLow(value),
+                        1 => Timecode::FrameHigh(value),
+                        2 => Timecode::SecondsLow(value),
+                        3 => Timecode::SecondsHigh(value),
+                        4 => Timecode::MinutesLow(value),
+                        5 => Timecode::MinutesHigh(value),
+                        6 => Timecode::HoursLow(value),
+                        7 => Timecode::HoursHigh(value),
+                        _ => unreachable!("value_type cannot be more than 7"),
+                    },
+                }))
+            }
+            // song position
+            0x2 => Some(MidiData::SysCommon(SysCommon::SongPositionPointer {
+                position: (message[1] as u16 & 0x7F) | ((message[2] as u16 & 0x7F) << 7),
+            })),
+            // song select
+            0x3 => Some(MidiData::SysCommon(SysCommon::SongSelect {
+                song: message[1] & 0x7F,
+            })),
+            // undefined
+            0x4 | 0x5 | 0xD => Some(MidiData::Unknown { status: first_byte }),
+            // tune request
+            0x6 => Some(MidiData::SysCommon(SysCommon::TuneRequest)),
+            // sysex end message (will be ignored)
+            0x7 => None,
+            // midi clock
+            0x8 => Some(MidiData::SysRt(SysRt::MidiClock)),
+            // midi tick
+            0x9 => Some(MidiData::SysRt(SysRt::Tick)),
+            // midi start
+            0xA => Some(MidiData::SysRt(SysRt::Start)),
+            // midi continue
+            0xB => Some(MidiData::SysRt(SysRt::Continue)),
+            // midi stop
+            0xC => Some(MidiData::SysRt(SysRt::Stop)),
+            // active sensing
+            0xE => Some(MidiData::SysRt(SysRt::ActiveSensing)),
+            // system reset
+            0xF => Some(MidiData::SysRt(SysRt::Reset)),
+            _ => unreachable!("only matching & 0x0F"),
         }
-    } else {
-        None
     }
 }
 
+/// The longest a (non-sysex) message can be when encoded.
+const MAX_MESSAGE_LEN: usize = 6;
+
+#[cfg(feature = "std")]
 pub fn write_midi_bytes(message: &MidiData, writer: &mut impl std::io::Write) -> Result<usize, std::io::Error> {
+    match message {
+        MidiData::SysEx { id_and_data } => writer
+            .write(&[0xF0])
+            .and_then(|written| {
+                // same masking as `encode_midi_bytes`
+                let data: Vec<u8> = id_and_data.iter().map(|byte| byte & 0x7F).collect();
+
+                writer.write(&data).map(|x| x + written)
+            })
+            .and_then(|written| writer.write(&[0xF7]).map(|x| x + written)),
+        _ => {
+            let mut buffer = [0; MAX_MESSAGE_LEN];
+            let len = encode_midi_bytes(message, &mut buffer).expect("only sysex can be longer than MAX_MESSAGE_LEN");
+
+            writer.write(&buffer[..len])
+        }
+    }
+}
+
+/// Encodes `message` into `buffer`, returning how many bytes were written, or `None` if `buffer`
+/// is too small.
+///
+/// Data bytes only have 7 bits (and 14 bit values 14), so anything above that is masked off rather
+/// than being allowed to turn into a status byte. Every value that fits survives a round trip
+/// through [`parse_midi_slice`], apart from a sysex that happens to spell out an MMC command or sample
+/// dump handshake (which come back as [`MidiData::Mmc`] and [`MidiData::SampleDumpHandshake`]).
+pub fn encode_midi_bytes(message: &MidiData, buffer: &mut [u8]) -> Option<usize> {
     match message {
         MidiData::NoteOff {
             channel,
             note,
             velocity,
-        } => writer.write(&[0x80 | (channel & 0x0F), *note, *velocity]),
+        } => put(buffer, &[0x80 | (channel & 0x0F), note & 0x7F, velocity & 0x7F]),
         MidiData::NoteOn {
             channel,
             note,
             velocity,
-        } => writer.write(&[0x90 | (channel & 0x0F), *note, *velocity]),
+        } => put(buffer, &[0x90 | (channel & 0x0F), note & 0x7F, velocity & 0x7F]),
         MidiData::Aftertouch {
             channel,
             note,
             pressure,
-        } => writer.write(&[0xA0 | (channel & 0x0F), *note, *pressure]),
+        } => put(buffer, &[0xA0 | (channel & 0x0F), note & 0x7F, pressure & 0x7F]),
         MidiData::ControlChange {
             channel,
             controller,
             value,
-        } => writer.write(&[0xB0 | (channel & 0x0F), *controller, *value]),
-        MidiData::ProgramChange { channel, patch } => writer.write(&[0xC0 | (channel & 0x0F), *patch]),
-        MidiData::ChannelPressure { channel, pressure } => writer.write(&[0xD0 | (channel & 0x0F), *pressure]),
+        } => put(buffer, &[0xB0 | (channel & 0x0F), controller & 0x7F, value & 0x7F]),
+        MidiData::ControlChange14 {
+            channel,
+            controller,
+            value,
+        } => {
+            let split_value = u16_to_midi_bytes(*value);
+
+            put(
+                buffer,
+                &[
+                    0xB0 | (channel & 0x0F),
+                    controller & 0x7F,
+                    split_value[1],
+                    0xB0 | (channel & 0x0F),
+                    controller.wrapping_add(32) & 0x7F,
+                    split_value[0],
+                ],
+            )
+        }
+        MidiData::ProgramChange { channel, patch } => put(buffer, &[0xC0 | (channel & 0x0F), patch & 0x7F]),
+        MidiData::ChannelPressure { channel, pressure } => put(buffer, &[0xD0 | (channel & 0x0F), pressure & 0x7F]),
         MidiData::PitchBend { channel, pitch_bend } => {
             let split_pitch_bend = u16_to_midi_bytes(*pitch_bend);
 
-            writer.write(&[0xE0 | (channel & 0x0F), split_pitch_bend[0], split_pitch_bend[1]])
+            put(
+                buffer,
+                &[0xE0 | (channel & 0x0F), split_pitch_bend[0], split_pitch_bend[1]],
+            )
         }
         MidiData::SysCommon(msg) => match msg {
             SysCommon::QuarterFrame { time_fragment } => match time_fragment {
-                Timecode::FrameLow(u8) => writer.write(&[0xF1, 0x00 | (u8 & 0x0F)]),
-                Timecode::FrameHigh(u8) => writer.write(&[0xF1, 0x10 | (u8 & 0x0F)]),
-                Timecode::SecondsLow(u8) => writer.write(&[0xF1, 0x20 | (u8 & 0x0F)]),
-                Timecode::SecondsHigh(u8) => writer.write(&[0xF1, 0x30 | (u8 & 0x0F)]),
-                Timecode::MinutesLow(u8) => writer.write(&[0xF1, 0x40 | (u8 & 0x0F)]),
-                Timecode::MinutesHigh(u8) => writer.write(&[0xF1, 0x50 | (u8 & 0x0F)]),
-                Timecode::HoursLow(u8) => writer.write(&[0xF1, 0x60 | (u8 & 0x0F)]),
-                Timecode::HoursHigh(u8) => writer.write(&[0xF1, 0x70 | (u8 & 0x0F)]),
+                Timecode::FrameLow(u8) => put(buffer, &[0xF1, u8 & 0x0F]),
+                Timecode::FrameHigh(u8) => put(buffer, &[0xF1, 0x10 | (u8 & 0x0F)]),
+                Timecode::SecondsLow(u8) => put(buffer, &[0xF1, 0x20 | (u8 & 0x0F)]),
+                Timecode::SecondsHigh(u8) => put(buffer, &[0xF1, 0x30 | (u8 & 0x0F)]),
+                Timecode::MinutesLow(u8) => put(buffer, &[0xF1, 0x40 | (u8 & 0x0F)]),
+                Timecode::MinutesHigh(u8) => put(buffer, &[0xF1, 0x50 | (u8 & 0x0F)]),
+                Timecode::HoursLow(u8) => put(buffer, &[0xF1, 0x60 | (u8 & 0x0F)]),
+                Timecode::HoursHigh(u8) => put(buffer, &[0xF1, 0x70 | (u8 & 0x0F)]),
             },
             SysCommon::SongPositionPointer { position } => {
                 let split_position = u16_to_midi_bytes(*position);
 
-                writer.write(&[0xF2, split_position[0], split_position[1]])
+                put(buffer, &[0xF2, split_position[0], split_position[1]])
             }
-            SysCommon::SongSelect { song } => writer.write(&[0xF3, *song]),
-            SysCommon::TuneRequest => writer.write(&[0xF6]),
+            SysCommon::SongSelect { song } => put(buffer, &[0xF3, song & 0x7F]),
+            SysCommon::TuneRequest => put(buffer, &[0xF6]),
         },
-        MidiData::SysRt(msg) => writer.write(&[*msg as u8]),
-        MidiData::SysEx { id_and_data } => writer
-            .write(&[0xF0])
-            .and_then(|written| writer.write(id_and_data).map(|x| x + written))
-            .and_then(|written| writer.write(&[0xF7]).map(|x| x + written)),
-        MidiData::MidiNone => Ok(0),
+        MidiData::SysRt(msg) => put(buffer, &[*msg as u8]),
+        MidiData::SysEx { id_and_data } => {
+            let len = id_and_data.len() + 2;
+
+            if buffer.len() < len {
+                return None;
+            }
+
+            buffer[0] = 0xF0;
+            for (byte_out, byte) in buffer[1..(len - 1)].iter_mut().zip(id_and_data) {
+                *byte_out = byte & 0x7F;
+            }
+            buffer[len - 1] = 0xF7;
+
+            Some(len)
+        }
+        MidiData::Mmc { device, command } => put(
+            buffer,
+            &[0xF0, 0x7F, device & 0x7F, 0x06, command.to_byte() & 0x7F, 0xF7],
+        ),
+        MidiData::SampleDumpHandshake { device, kind, packet } => put(
+            buffer,
+            &[0xF0, 0x7E, device & 0x7F, kind.to_byte(), packet & 0x7F, 0xF7],
+        ),
+        MidiData::Unknown { status } => put(buffer, &[*status]),
+        MidiData::SysExOverflow { .. } | MidiData::MidiNone => Some(0),
     }
 }
 
+/// Raw bytes of one message. Anything but sysex fits inline, so these don't allocate.
+pub type MidiBytes = SmallVec<[u8; 8]>;
+
+impl MidiData {
+    /// The channel of a channel voice message, or `None` for system messages.
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            MidiData::NoteOff { channel, .. }
+            | MidiData::NoteOn { channel, .. }
+            | MidiData::Aftertouch { channel, .. }
+            | MidiData::ControlChange { channel, .. }
+            | MidiData::ControlChange14 { channel, .. }
+            | MidiData::ProgramChange { channel, .. }
+            | MidiData::ChannelPressure { channel, .. }
+            | MidiData::PitchBend { channel, .. } => Some(*channel),
+            _ => None,
+        }
+    }
+
+    /// The note of a note on/off or polyphonic aftertouch message.
+    pub fn note(&self) -> Option<u8> {
+        match self {
+            MidiData::NoteOff { note, .. } | MidiData::NoteOn { note, .. } | MidiData::Aftertouch { note, .. } => {
+                Some(*note)
+            }
+            _ => None,
+        }
+    }
+
+    /// Encodes the message with [`encode_midi_bytes`]. Only sysex longer than 6 bytes spills onto
+    /// the heap.
+    pub fn to_smallvec(&self) -> MidiBytes {
+        let len = match self {
+            MidiData::SysEx { id_and_data } => id_and_data.len() + 2,
+            _ => MAX_MESSAGE_LEN,
+        };
+
+        let mut bytes = SmallVec::from_elem(0, len);
+        let written = encode_midi_bytes(self, &mut bytes).expect("buffer was sized for the message");
+        bytes.truncate(written);
+
+        bytes
+    }
+
+    /// Parses the first message in `bytes` (see [`parse_midi_slice`]), without allocating unless
+    /// it's sysex. Returns `None` if there isn't a complete message in there.
+    pub fn from_bytes(bytes: &[u8]) -> Option<MidiData> {
+        parse_midi_slice(bytes).0
+    }
+}
+
+fn put(buffer: &mut [u8], bytes: &[u8]) -> Option<usize> {
+    buffer.get_mut(..bytes.len())?.copy_from_slice(bytes);
+
+    Some(bytes.len())
+}
+
 fn u16_to_midi_bytes(x: u16) -> [u8; 2] {
     let high = ((x >> 7) & 0x7F) as u8;
     let low = (x & 0x7F) as u8;
 
     [low, high]
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const NOTE_ON: [u8; 3] = [0x91, 60, 100];
+
+    fn note_on() -> MidiData {
+        MidiData::NoteOn {
+            channel: 1,
+            note: 60,
+            velocity: 100,
+        }
+    }
+
+    #[test]
+    fn slice_parse_reports_bytes_consumed() {
+        let input = [0x91, 60, 100, 0xF8];
+
+        assert_eq!(parse_midi_slice(&input), (Some(note_on()), 3));
+        assert_eq!(
+            parse_midi_slice(&input[3..]),
+            (Some(MidiData::SysRt(SysRt::MidiClock)), 1)
+        );
+    }
+
+    #[test]
+    fn partial_message_consumes_nothing() {
+        assert_eq!(parse_midi_slice(&NOTE_ON[..2]), (None, 0));
+        assert_eq!(parse_midi_slice(&[0xF0, 0x41, 0x10]), (None, 0));
+        assert_eq!(parse_midi_slice(&[]), (None, 0));
+    }
+
+    #[test]
+    fn stray_data_is_consumed_up_to_the_message() {
+        assert_eq!(parse_midi_slice(&[0x12, 0x34]), (None, 2));
+        assert_eq!(parse_midi_slice(&[0x12, 0x91, 60]), (None, 1));
+    }
+
+    #[test]
+    fn message_split_across_two_slices() {
+        let stream = [0x91, 60, 100, 0x81, 60, 0];
+        let (first, second) = stream.split_at(4);
+
+        // the caller keeps whatever wasn't consumed and puts it in front of the next slice
+        let (message, consumed) = parse_midi_slice(first);
+        assert_eq!((message, consumed), (Some(note_on()), 3));
+
+        let mut carried = first[consumed..].to_vec();
+        assert_eq!(parse_midi_slice(&carried), (None, 0));
+
+        carried.extend_from_slice(second);
+        assert_eq!(
+            parse_midi_slice(&carried),
+            (
+                Some(MidiData::NoteOff {
+                    channel: 1,
+                    note: 60,
+                    velocity: 0,
+                }),
+                3
+            )
+        );
+    }
+
+    #[test]
+    fn slice_parse_matches_deque_parse() {
+        let stream = [0x12, 0x91, 60, 100, 0xF0, 0x41, 0x7F, 0xF7, 0xC3, 5];
+        let mut deque: VecDeque<u8> = stream.iter().copied().collect();
+
+        let mut from_slice = Vec::new();
+        let mut input = &stream[..];
+        loop {
+            let (message, consumed) = parse_midi_slice(input);
+            from_slice.extend(message);
+            if consumed == 0 {
+                break;
+            }
+            input = &input[consumed..];
+        }
+
+        let from_deque: Vec<_> = core::iter::from_fn(|| parse_midi(&mut deque)).collect();
+        assert_eq!(from_slice, from_deque);
+        assert_eq!(from_slice.len(), 3);
+    }
+
+    #[test]
+    fn undefined_status_bytes_are_reported() {
+        for status in [0xF4, 0xF5, 0xFD] {
+            assert_eq!(parse_midi_slice(&[status]), (Some(MidiData::Unknown { status }), 1));
+        }
+    }
+
+    #[test]
+    fn port_select_data_byte_is_skipped() {
+        let mut buffer: VecDeque<u8> = [0xF5, 0x03, 0x91, 60, 100].into_iter().collect();
+
+        assert_eq!(
+            parse_midi_ev(&mut buffer),
+            ParseOutcome::Message(MidiData::Unknown { status: 0xF5 })
+        );
+        // the "port number" is stray data as far as the parser's concerned
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::Dropped(1));
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::Message(note_on()));
+    }
+
+    #[test]
+    fn unknown_round_trips() {
+        let mut buffer = [0; 1];
+
+        assert_eq!(
+            encode_midi_bytes(&MidiData::Unknown { status: 0xF4 }, &mut buffer),
+            Some(1)
+        );
+        assert_eq!(buffer, [0xF4]);
+    }
+
+    /// The start of a sysex message that never ends.
+    fn unterminated_sysex(len: usize) -> VecDeque<u8> {
+        core::iter::once(0xF0).chain(core::iter::repeat_n(0x01, len)).collect()
+    }
+
+    #[test]
+    fn unterminated_sysex_is_abandoned_at_the_cap() {
+        let mut buffer = unterminated_sysex(10_000);
+
+        assert_eq!(
+            parse_midi_with_limit(&mut buffer, 1024),
+            Some(MidiData::SysExOverflow { len: 1025 })
+        );
+        // the rest of it is stray data
+        assert_eq!(parse_midi_with_limit(&mut buffer, 1024), None);
+        assert!(buffer.is_empty());
+
+        // and things pick back up after the end byte
+        buffer.extend([0x01, 0xF7, 0x91, 60, 100]);
+        assert_eq!(parse_midi_with_limit(&mut buffer, 1024), Some(note_on()));
+    }
+
+    #[test]
+    fn sysex_under_the_cap_keeps_waiting() {
+        let mut buffer = unterminated_sysex(1000);
+
+        assert_eq!(parse_midi_with_limit(&mut buffer, 1024), None);
+        assert_eq!(buffer.len(), 1001);
+
+        // no limit at all
+        let mut buffer = unterminated_sysex(10_000);
+        assert_eq!(parse_midi(&mut buffer), None);
+        assert_eq!(buffer.len(), 10_001);
+    }
+
+    #[test]
+    fn parse_ev_returns_a_clean_message() {
+        let mut buffer: VecDeque<u8> = [0x91, 60, 100].into_iter().collect();
+
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::Message(note_on()));
+        assert!(buffer.is_empty());
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::NeedMoreData);
+    }
+
+    #[test]
+    fn parse_ev_waits_on_a_partial_message() {
+        let mut buffer: VecDeque<u8> = [0x91, 60].into_iter().collect();
+
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::NeedMoreData);
+        // nothing was thrown away
+        assert_eq!(buffer.len(), 2);
+
+        buffer.push_back(100);
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::Message(note_on()));
+    }
+
+    #[test]
+    fn parse_ev_reports_stray_data_before_the_message() {
+        let mut buffer: VecDeque<u8> = [0x12, 0x34, 0x56, 0x91, 60, 100].into_iter().collect();
+
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::Dropped(3));
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::Message(note_on()));
+
+        // `parse_midi` skips straight past it
+        let mut buffer: VecDeque<u8> = [0x12, 0x34, 0x56, 0x91, 60, 100].into_iter().collect();
+        assert_eq!(parse_midi(&mut buffer), Some(note_on()));
+    }
+
+    #[test]
+    fn parse_ev_reports_an_aborted_sysex() {
+        let mut buffer: VecDeque<u8> = [0xF0, 0x41, 0x10, 0x91, 60, 100].into_iter().collect();
+
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::Dropped(3));
+        assert_eq!(parse_midi_ev(&mut buffer), ParseOutcome::Message(note_on()));
+    }
+
+    /// Any message that survives a round trip through the encoder and parser. Left out are
+    /// [`MidiData::ControlChange14`] (which goes out as two plain control changes),
+    /// [`MidiData::SysExOverflow`] and [`MidiData::MidiNone`] (which don't encode to anything), and
+    /// sysex that spells out an MMC command or sample dump handshake.
+    fn representable() -> impl Strategy<Value = MidiData> {
+        let channel = || 0..16_u8;
+        let data = || 0..0x80_u8;
+        let data14 = || 0..0x4000_u16;
+
+        let timecode = (0..8_u8, 0..16_u8).prop_map(|(kind, value)| match kind {
+            0 => Timecode::FrameLow(value),
+            1 => Timecode::FrameHigh(value),
+            2 => Timecode::SecondsLow(value),
+            3 => Timecode::SecondsHigh(value),
+            4 => Timecode::MinutesLow(value),
+            5 => Timecode::MinutesHigh(value),
+            6 => Timecode::HoursLow(value),
+            _ => Timecode::HoursHigh(value),
+        });
+        let sys_rt = prop::sample::select(
+            &[
+                SysRt::MidiClock,
+                SysRt::Tick,
+                SysRt::Start,
+                SysRt::Continue,
+                SysRt::Stop,
+                SysRt::ActiveSensing,
+                SysRt::Reset,
+            ][..],
+        );
+        let sds_handshake = prop::sample::select(
+            &[
+                SdsHandshake::Ack,
+                SdsHandshake::Nak,
+                SdsHandshake::Wait,
+                SdsHandshake::Cancel,
+            ][..],
+        );
+        let sysex = prop::collection::vec(data(), 0..64).prop_filter("spells out a universal message", |id_and_data| {
+            !matches!(id_and_data[..], [0x7F, _, 0x06, _] | [0x7E, _, 0x7C..=0x7F, _])
+        });
+
+        prop_oneof![
+            (channel(), data(), data()).prop_map(|(channel, note, velocity)| MidiData::NoteOff {
+                channel,
+                note,
+                velocity
+            }),
+            (channel(), data(), data()).prop_map(|(channel, note, velocity)| MidiData::NoteOn {
+                channel,
+                note,
+                velocity
+            }),
+            (channel(), data(), data()).prop_map(|(channel, note, pressure)| MidiData::Aftertouch {
+                channel,
+                note,
+                pressure
+            }),
+            (channel(), data(), data()).prop_map(|(channel, controller, value)| MidiData::ControlChange {
+                channel,
+                controller,
+                value
+            }),
+            (channel(), data()).prop_map(|(channel, patch)| MidiData::ProgramChange { channel, patch }),
+            (channel(), data()).prop_map(|(channel, pressure)| MidiData::ChannelPressure { channel, pressure }),
+            (channel(), data14()).prop_map(|(channel, pitch_bend)| MidiData::PitchBend { channel, pitch_bend }),
+            timecode.prop_map(|time_fragment| MidiData::SysCommon(SysCommon::QuarterFrame { time_fragment })),
+            data14().prop_map(|position| MidiData::SysCommon(SysCommon::SongPositionPointer { position })),
+            data().prop_map(|song| MidiData::SysCommon(SysCommon::SongSelect { song })),
+            Just(MidiData::SysCommon(SysCommon::TuneRequest)),
+            sys_rt.prop_map(MidiData::SysRt),
+            sysex.prop_map(|id_and_data| MidiData::SysEx { id_and_data }),
+            (data(), data()).prop_map(|(device, command)| MidiData::Mmc {
+                device,
+                command: MmcCommand::from_byte(command)
+            }),
+            (data(), sds_handshake, data()).prop_map(|(device, kind, packet)| MidiData::SampleDumpHandshake {
+                device,
+                kind,
+                packet
+            }),
+            prop::sample::select(&[0xF4, 0xF5, 0xFD][..]).prop_map(|status| MidiData::Unknown { status }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn every_representable_message_round_trips(message in representable()) {
+            let mut buffer = vec![0; 128];
+            let len = encode_midi_bytes(&message, &mut buffer).unwrap();
+            let mut bytes: VecDeque<u8> = buffer[..len].iter().copied().collect();
+
+            prop_assert_eq!(parse_midi(&mut bytes), Some(message));
+            prop_assert!(bytes.is_empty());
+        }
+
+        #[test]
+        fn fourteen_bit_values_are_masked_not_mangled(channel in 0..16_u8, value in any::<u16>()) {
+            let mut buffer = [0; 3];
+            encode_midi_bytes(&MidiData::PitchBend { channel, pitch_bend: value }, &mut buffer).unwrap();
+
+            // only the low 14 bits make it, and none of them leak into a status byte
+            prop_assert!(buffer[1..].iter().all(|byte| byte & 0x80 == 0));
+            prop_assert_eq!(
+                parse_midi_slice(&buffer).0,
+                Some(MidiData::PitchBend { channel, pitch_bend: value & 0x3FFF })
+            );
+        }
+    }
+
+    #[test]
+    fn fourteen_bit_boundaries_keep_their_byte_order() {
+        for (value, bytes) in [
+            (0, [0x00, 0x00]),
+            (0x7F, [0x7F, 0x00]),
+            (0x80, [0x00, 0x01]),
+            (0x3FFF, [0x7F, 0x7F]),
+        ] {
+            let mut buffer = [0; 3];
+            encode_midi_bytes(
+                &MidiData::SysCommon(SysCommon::SongPositionPointer { position: value }),
+                &mut buffer,
+            );
+
+            // least significant 7 bits first
+            assert_eq!(buffer, [0xF2, bytes[0], bytes[1]], "{value:#x}");
+        }
+    }
+
+    #[test]
+    fn sysex_with_high_bits_is_masked() {
+        let mut buffer = [0; 5];
+        let len = encode_midi_bytes(
+            &MidiData::SysEx {
+                id_and_data: vec![0x41, 0xF7, 0x90],
+            },
+            &mut buffer,
+        );
+
+        assert_eq!(len, Some(5));
+        assert_eq!(buffer, [0xF0, 0x41, 0x77, 0x10, 0xF7]);
+    }
+
+    /// Checks that `value` survives a trip through both JSON (self-describing) and bincode (not).
+    #[cfg(feature = "serde")]
+    pub(crate) fn serde_round_trip<T>(value: &T)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + core::fmt::Debug,
+    {
+        let json = serde_json::to_string(value).unwrap();
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), value, "{json}");
+
+        let bytes = bincode::serialize(value).unwrap();
+        assert_eq!(&bincode::deserialize::<T>(&bytes).unwrap(), value);
+    }
+
+    /// One of every kind of message, with every sub-kind spelled out.
+    #[cfg(feature = "serde")]
+    fn every_variant() -> Vec<MidiData> {
+        let mut messages = vec![
+            MidiData::NoteOff {
+                channel: 1,
+                note: 60,
+                velocity: 0,
+            },
+            note_on(),
+            MidiData::Aftertouch {
+                channel: 2,
+                note: 61,
+                pressure: 90,
+            },
+            MidiData::ControlChange {
+                channel: 3,
+                controller: 7,
+                value: 127,
+            },
+            MidiData::ControlChange14 {
+                channel: 4,
+                controller: 1,
+                value: 0x3FFF,
+            },
+            MidiData::ProgramChange { channel: 5, patch: 12 },
+            MidiData::ChannelPressure {
+                channel: 6,
+                pressure: 33,
+            },
+            MidiData::PitchBend {
+                channel: 7,
+                pitch_bend: 0x2000,
+            },
+            MidiData::SysCommon(SysCommon::SongPositionPointer { position: 1000 }),
+            MidiData::SysCommon(SysCommon::SongSelect { song: 3 }),
+            MidiData::SysCommon(SysCommon::TuneRequest),
+            MidiData::SysEx {
+                id_and_data: vec![0x00, 0x20, 0x29, 0x01, 0x7F],
+            },
+            MidiData::SysEx { id_and_data: vec![] },
+            MidiData::Unknown { status: 0xF5 },
+            MidiData::SysExOverflow { len: 4097 },
+            MidiData::MidiNone,
+        ];
+
+        messages.extend(
+            [
+                Timecode::FrameLow(1),
+                Timecode::FrameHigh(1),
+                Timecode::SecondsLow(2),
+                Timecode::SecondsHigh(3),
+                Timecode::MinutesLow(4),
+                Timecode::MinutesHigh(2),
+                Timecode::HoursLow(5),
+                Timecode::HoursHigh(1),
+            ]
+            .map(|time_fragment| MidiData::SysCommon(SysCommon::QuarterFrame { time_fragment })),
+        );
+        messages.extend(
+            [
+                SysRt::MidiClock,
+                SysRt::Tick,
+                SysRt::Start,
+                SysRt::Continue,
+                SysRt::Stop,
+                SysRt::ActiveSensing,
+                SysRt::Reset,
+            ]
+            .map(MidiData::SysRt),
+        );
+        messages.extend((0..=0x0D).chain([0x44]).map(|command| MidiData::Mmc {
+            device: 0x7F,
+            command: MmcCommand::from_byte(command),
+        }));
+        messages.extend(
+            [
+                SdsHandshake::Ack,
+                SdsHandshake::Nak,
+                SdsHandshake::Wait,
+                SdsHandshake::Cancel,
+            ]
+            .map(|kind| MidiData::SampleDumpHandshake {
+                device: 0x10,
+                kind,
+                packet: 9,
+            }),
+        );
+
+        messages
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn every_variant_round_trips_through_serde() {
+        for message in every_variant() {
+            serde_round_trip(&message);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_uses_the_externally_tagged_form() {
+        assert_eq!(
+            serde_json::to_string(&note_on()).unwrap(),
+            r#"{"NoteOn":{"channel":1,"note":60,"velocity":100}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&MidiData::SysRt(SysRt::Start)).unwrap(),
+            r#"{"SysRt":"Start"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&MidiData::Mmc {
+                device: 1,
+                command: MmcCommand::Other(0x44)
+            })
+            .unwrap(),
+            r#"{"Mmc":{"device":1,"command":{"Other":68}}}"#
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    #[allow(deprecated)]
+    fn midi_message_round_trips_through_serde() {
+        serde_round_trip(&MidiMessage {
+            data: note_on(),
+            timestamp: core::time::Duration::from_millis(1500),
+        });
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "std"))]
+    fn timed_values_round_trip_through_serde() {
+        for value in every_variant() {
+            serde_round_trip(&crate::TimedValue {
+                since_start: core::time::Duration::from_micros(2_500_001),
+                value,
+            });
+        }
+    }
+
+    #[test]
+    fn note_on_stays_inline() {
+        let bytes = note_on().to_smallvec();
+
+        assert!(!bytes.spilled());
+        assert_eq!(&bytes[..], NOTE_ON);
+        assert_eq!(MidiData::from_bytes(&bytes), Some(note_on()));
+    }
+
+    #[test]
+    fn long_sysex_spills() {
+        let sysex = MidiData::SysEx {
+            id_and_data: (0..32).collect(),
+        };
+        let bytes = sysex.to_smallvec();
+
+        assert!(bytes.spilled());
+        assert_eq!(bytes.len(), 34);
+        assert_eq!((bytes[0], bytes[33]), (0xF0, 0xF7));
+        assert_eq!(MidiData::from_bytes(&bytes), Some(sysex));
+    }
+
+    #[test]
+    fn short_sysex_and_six_byte_messages_stay_inline() {
+        let cc14 = MidiData::ControlChange14 {
+            channel: 0,
+            controller: 1,
+            value: 0x1234,
+        };
+        assert_eq!(cc14.to_smallvec().len(), 6);
+        assert!(!cc14.to_smallvec().spilled());
+
+        let sysex = MidiData::SysEx {
+            id_and_data: vec![0x41, 0x10],
+        };
+        assert!(!sysex.to_smallvec().spilled());
+    }
+
+    #[test]
+    fn from_bytes_needs_a_whole_message() {
+        assert_eq!(MidiData::from_bytes(&NOTE_ON[..2]), None);
+        assert_eq!(MidiData::from_bytes(&[]), None);
+        assert_eq!(MidiData::MidiNone.to_smallvec().len(), 0);
+    }
+}
@@ -0,0 +1,375 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use core::time::Duration;
+
+use super::{MidiData, SysRt};
+use crate::lerp;
+
+/// MIDI clock resolution, in ticks per quarter note.
+pub const CLOCKS_PER_QUARTER: u32 = 24;
+
+/// Generates a steady 24 PPQN MIDI clock, plus Start/Stop/Continue transport messages.
+///
+/// Time is passed in through [`MidiClockGenerator::poll`], so the clock can be driven by wall-clock
+/// time (`Instant::now() - start`) or by an audio callback's sample count alike.
+#[derive(Debug, Clone)]
+pub struct MidiClockGenerator {
+    bpm: f64,
+    running: bool,
+    /// Progress towards the next tick, in ticks
+    phase: f64,
+    last_poll: Option<Duration>,
+    /// Transport messages to send on the next poll
+    pending: Vec<SysRt>,
+}
+
+impl MidiClockGenerator {
+    pub fn new(bpm: f64) -> MidiClockGenerator {
+        MidiClockGenerator {
+            bpm,
+            running: false,
+            phase: 0.0,
+            last_poll: None,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Changes the tempo. The tick in progress keeps its phase, so the clock doesn't jump.
+    pub fn set_bpm(&mut self, bpm: f64) {
+        self.bpm = bpm;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Sends Start, followed immediately by the first clock (the downbeat). Time spent stopped
+    /// doesn't count towards the clocks after it.
+    pub fn start(&mut self) {
+        self.pending.push(SysRt::Start);
+        self.running = true;
+        self.phase = 1.0;
+        self.last_poll = None;
+    }
+
+    /// Sends Stop. No clocks are sent while stopped.
+    pub fn stop(&mut self) {
+        self.pending.push(SysRt::Stop);
+        self.running = false;
+    }
+
+    /// Sends Continue, picking the clock back up where it was stopped.
+    pub fn resume(&mut self) {
+        self.pending.push(SysRt::Continue);
+        self.running = true;
+        self.last_poll = None;
+    }
+
+    /// Returns all the messages due since the last poll. `now` must not go backwards.
+    pub fn poll(&mut self, now: Duration) -> impl Iterator<Item = MidiData> + '_ {
+        let elapsed = match self.last_poll {
+            Some(last_poll) => now.saturating_sub(last_poll).as_secs_f64(),
+            None => 0.0,
+        };
+        self.last_poll = Some(now);
+
+        let mut ticks = 0;
+
+        if self.running {
+            self.phase += elapsed * self.bpm / 60.0 * CLOCKS_PER_QUARTER as f64;
+
+            // phase is never negative, so this floors it
+            ticks = self.phase as usize;
+            self.phase -= ticks as f64;
+        }
+
+        self.pending
+            .drain(..)
+            .map(MidiData::SysRt)
+            .chain(core::iter::repeat_n(MidiData::SysRt(SysRt::MidiClock), ticks))
+    }
+}
+
+/// How far (as a fraction) a clock interval can stray from the current estimate before it's treated
+/// as jitter and ignored.
+const OUTLIER_THRESHOLD: f64 = 0.5;
+/// How many outliers in a row it takes to believe the tempo actually changed.
+const MAX_OUTLIERS: usize = 3;
+
+/// Locks onto an external MIDI clock, estimating its tempo and the position within the current
+/// quarter note.
+///
+/// The raw estimate is averaged over the last quarter note's worth of clocks, then eased towards
+/// (like [`crate::PidSettings::factor_last_interp`]) so jitter doesn't make it jump around.
+#[derive(Debug, Clone)]
+pub struct MidiClockFollower {
+    /// How much of each new estimate is applied (`lerp(last, new, smoothing)`)
+    smoothing: f64,
+    bpm: Option<f64>,
+    last_clock: Option<Duration>,
+    /// Recent clock intervals, in seconds
+    intervals: VecDeque<f64>,
+    outliers: usize,
+    running: bool,
+    /// Clocks since Start (the first clock after Start is tick 0)
+    ticks: Option<u64>,
+}
+
+impl Default for MidiClockFollower {
+    fn default() -> Self {
+        MidiClockFollower::new(0.1)
+    }
+}
+
+impl MidiClockFollower {
+    pub fn new(smoothing: f64) -> MidiClockFollower {
+        MidiClockFollower {
+            smoothing,
+            bpm: None,
+            last_clock: None,
+            intervals: VecDeque::with_capacity(CLOCKS_PER_QUARTER as usize),
+            outliers: 0,
+            running: false,
+            ticks: None,
+        }
+    }
+
+    /// The smoothed tempo, or `None` if there haven't been enough clocks to tell yet.
+    pub fn current_bpm(&self) -> Option<f64> {
+        self.bpm
+    }
+
+    /// Whether the external transport is playing.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Position within the current quarter note [0.0, 1.0), as of the last clock.
+    pub fn phase(&self) -> f64 {
+        match self.ticks {
+            Some(ticks) => (ticks % CLOCKS_PER_QUARTER as u64) as f64 / CLOCKS_PER_QUARTER as f64,
+            None => 0.0,
+        }
+    }
+
+    /// Feeds in a message from one of the MIDI sources.
+    #[cfg(feature = "std")]
+    pub fn process_timed(&mut self, message: &crate::TimedValue<MidiData>) {
+        self.process(&message.value, message.since_start);
+    }
+
+    /// Feeds in a message received at `timestamp`. Anything other than clock and transport
+    /// messages is ignored.
+    pub fn process(&mut self, data: &MidiData, timestamp: Duration) {
+        match data {
+            MidiData::SysRt(SysRt::MidiClock) => self.clock(timestamp),
+            MidiData::SysRt(SysRt::Start) => {
+                self.running = true;
+                self.ticks = None;
+            }
+            MidiData::SysRt(SysRt::Continue) => {
+                self.running = true;
+            }
+            MidiData::SysRt(SysRt::Stop) => {
+                self.running = false;
+            }
+            MidiData::SysRt(SysRt::Reset) => {
+                *self = MidiClockFollower::new(self.smoothing);
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self, timestamp: Duration) {
+        if self.running {
+            self.ticks = Some(self.ticks.map_or(0, |ticks| ticks + 1));
+        }
+
+        let Some(last_clock) = self.last_clock.replace(timestamp) else {
+            return;
+        };
+
+        let interval = timestamp.saturating_sub(last_clock).as_secs_f64();
+
+        if let Some(bpm) = self.bpm {
+            let expected = bpm_to_interval(bpm);
+
+            if (interval - expected).abs() > expected * OUTLIER_THRESHOLD {
+                self.outliers += 1;
+
+                if self.outliers < MAX_OUTLIERS {
+                    return;
+                }
+
+                // it's not jitter, the tempo really changed
+                self.intervals.clear();
+                self.bpm = None;
+            }
+        }
+
+        self.outliers = 0;
+
+        if self.intervals.len() == CLOCKS_PER_QUARTER as usize {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(interval);
+
+        let average = self.intervals.iter().sum::<f64>() / self.intervals.len() as f64;
+
+        if average > 0.0 {
+            let raw_bpm = interval_to_bpm(average);
+
+            self.bpm = Some(match self.bpm {
+                Some(bpm) => lerp(bpm, raw_bpm, self.smoothing),
+                None => raw_bpm,
+            });
+        }
+    }
+}
+
+/// Seconds per clock at `bpm`.
+fn bpm_to_interval(bpm: f64) -> f64 {
+    60.0 / (bpm * CLOCKS_PER_QUARTER as f64)
+}
+
+fn interval_to_bpm(interval: f64) -> f64 {
+    60.0 / (interval * CLOCKS_PER_QUARTER as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clocks(messages: impl Iterator<Item = MidiData>) -> usize {
+        messages
+            .filter(|message| *message == MidiData::SysRt(SysRt::MidiClock))
+            .count()
+    }
+
+    #[test]
+    fn generator_ticks_at_24_ppqn() {
+        let mut generator = MidiClockGenerator::new(120.0);
+        generator.start();
+
+        let first: Vec<MidiData> = generator.poll(Duration::ZERO).collect();
+        assert_eq!(
+            first,
+            [MidiData::SysRt(SysRt::Start), MidiData::SysRt(SysRt::MidiClock)]
+        );
+
+        // 120 BPM is 2 quarter notes a second, so 48 clocks (minus a hair of rounding)
+        let ticks: usize = (1..=100)
+            .map(|i| clocks(generator.poll(Duration::from_millis(i * 10))))
+            .sum();
+        assert!((47..=48).contains(&ticks), "got {ticks} clocks");
+    }
+
+    #[test]
+    fn generator_is_silent_while_stopped() {
+        let mut generator = MidiClockGenerator::new(120.0);
+        generator.start();
+        generator.poll(Duration::ZERO).for_each(drop);
+
+        generator.stop();
+        assert_eq!(
+            generator.poll(Duration::from_secs(1)).collect::<Vec<_>>(),
+            [MidiData::SysRt(SysRt::Stop)]
+        );
+        assert_eq!(generator.poll(Duration::from_secs(2)).count(), 0);
+    }
+
+    #[test]
+    fn generator_start_after_idle_doesnt_burst() {
+        let mut generator = MidiClockGenerator::new(120.0);
+        generator.poll(Duration::ZERO).for_each(drop);
+
+        generator.start();
+        let messages: Vec<MidiData> = generator.poll(Duration::from_secs(10)).collect();
+        assert_eq!(
+            messages,
+            [MidiData::SysRt(SysRt::Start), MidiData::SysRt(SysRt::MidiClock)]
+        );
+    }
+
+    #[test]
+    fn generator_resume_after_stop_doesnt_burst() {
+        let mut generator = MidiClockGenerator::new(120.0);
+        generator.start();
+        generator.poll(Duration::ZERO).for_each(drop);
+        generator.stop();
+        generator.poll(Duration::from_secs(1)).for_each(drop);
+
+        generator.resume();
+        let messages: Vec<MidiData> = generator.poll(Duration::from_secs(10)).collect();
+        assert_eq!(messages, [MidiData::SysRt(SysRt::Continue)]);
+
+        // and carries on at the usual rate from there
+        assert_eq!(clocks(generator.poll(Duration::from_millis(10_500))), 24);
+    }
+
+    /// Deterministic jitter in [-1ms, 1ms], so the test doesn't need a random number generator.
+    fn jitter(i: u64) -> f64 {
+        let hashed = i.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 40;
+
+        (hashed as f64 / (1_u64 << 24) as f64) * 2e-3 - 1e-3
+    }
+
+    #[test]
+    fn follower_converges_on_a_jittery_clock() {
+        let mut follower = MidiClockFollower::default();
+        follower.process(&MidiData::SysRt(SysRt::Start), Duration::ZERO);
+
+        // 100 BPM is a clock every 25ms
+        for i in 0..(24 * 16) {
+            let timestamp = 1.0 + i as f64 * 0.025 + jitter(i);
+            follower.process(&MidiData::SysRt(SysRt::MidiClock), Duration::from_secs_f64(timestamp));
+        }
+
+        let bpm = follower.current_bpm().unwrap();
+        assert!((bpm - 100.0).abs() < 0.5, "estimated {bpm} BPM");
+    }
+
+    #[test]
+    fn follower_ignores_a_single_late_clock() {
+        let mut follower = MidiClockFollower::new(1.0);
+        let mut timestamp = 0.0;
+        for _ in 0..48 {
+            timestamp += 0.025;
+            follower.process(&MidiData::SysRt(SysRt::MidiClock), Duration::from_secs_f64(timestamp));
+        }
+
+        // one clock shows up 20ms late
+        follower.process(
+            &MidiData::SysRt(SysRt::MidiClock),
+            Duration::from_secs_f64(timestamp + 0.045),
+        );
+
+        let bpm = follower.current_bpm().unwrap();
+        assert!((bpm - 100.0).abs() < 1e-6, "estimated {bpm} BPM");
+    }
+
+    #[test]
+    fn follower_tracks_transport_and_phase() {
+        let mut follower = MidiClockFollower::default();
+        assert!(!follower.is_running());
+
+        follower.process(&MidiData::SysRt(SysRt::Start), Duration::ZERO);
+        for i in 0..31 {
+            follower.process(&MidiData::SysRt(SysRt::MidiClock), Duration::from_millis(i * 25));
+        }
+        assert!(follower.is_running());
+        // 31 clocks after Start is tick 30, a quarter of the way into the second beat
+        assert_eq!(follower.phase(), 0.25);
+
+        follower.process(&MidiData::SysRt(SysRt::Stop), Duration::from_secs(1));
+        assert!(!follower.is_running());
+
+        // Start goes back to the downbeat
+        follower.process(&MidiData::SysRt(SysRt::Start), Duration::from_secs(2));
+        follower.process(&MidiData::SysRt(SysRt::MidiClock), Duration::from_secs(2));
+        assert_eq!(follower.phase(), 0.0);
+    }
+}
@@ -0,0 +1,259 @@
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use super::{MidiData, SysRt};
+#[cfg(feature = "std")]
+use crate::TimedValue;
+
+/// The type of a [`MidiData`], for blocking with [`MidiFilter::block`]. System real-time messages
+/// are split out by kind, so e.g. Active Sensing can be dropped while clocks go through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    NoteOff,
+    NoteOn,
+    Aftertouch,
+    /// Both 7 and 14 bit control changes
+    ControlChange,
+    ProgramChange,
+    ChannelPressure,
+    PitchBend,
+    SysCommon,
+    SysRt(SysRt),
+    /// Sysex, including the universal messages parsed out of it (MMC and sample dump handshakes)
+    /// and overflowed sysex
+    SysEx,
+    Unknown,
+}
+
+impl MessageKind {
+    /// The kind of `data`, or `None` for [`MidiData::MidiNone`].
+    pub fn of(data: &MidiData) -> Option<MessageKind> {
+        Some(match data {
+            MidiData::NoteOff { .. } => MessageKind::NoteOff,
+            MidiData::NoteOn { .. } => MessageKind::NoteOn,
+            MidiData::Aftertouch { .. } => MessageKind::Aftertouch,
+            MidiData::ControlChange { .. } | MidiData::ControlChange14 { .. } => MessageKind::ControlChange,
+            MidiData::ProgramChange { .. } => MessageKind::ProgramChange,
+            MidiData::ChannelPressure { .. } => MessageKind::ChannelPressure,
+            MidiData::PitchBend { .. } => MessageKind::PitchBend,
+            MidiData::SysCommon(_) => MessageKind::SysCommon,
+            MidiData::SysRt(rt) => MessageKind::SysRt(*rt),
+            MidiData::SysEx { .. }
+            | MidiData::Mmc { .. }
+            | MidiData::SampleDumpHandshake { .. }
+            | MidiData::SysExOverflow { .. } => MessageKind::SysEx,
+            MidiData::Unknown { .. } => MessageKind::Unknown,
+            MidiData::MidiNone => return None,
+        })
+    }
+}
+
+impl From<SysRt> for MessageKind {
+    fn from(rt: SysRt) -> Self {
+        MessageKind::SysRt(rt)
+    }
+}
+
+/// Decides which messages to forward, by channel, note, and message type. Everything passes until
+/// narrowed down, e.g. `MidiFilter::new().channels(0..=3).allow_note_range(36..=96)
+/// .block(SysRt::ActiveSensing)`.
+///
+/// System messages don't have a channel, so they pass the channel filter unless
+/// [`MidiFilter::pass_channelless`] says otherwise (they can still be blocked by type).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MidiFilter {
+    /// One bit per channel
+    channels: u16,
+    pass_channelless: bool,
+    notes: RangeInclusive<u8>,
+    blocked: Vec<MessageKind>,
+}
+
+impl Default for MidiFilter {
+    fn default() -> Self {
+        MidiFilter {
+            channels: u16::MAX,
+            pass_channelless: true,
+            notes: 0..=127,
+            blocked: Vec::new(),
+        }
+    }
+}
+
+impl MidiFilter {
+    /// A filter that lets everything through.
+    pub fn new() -> MidiFilter {
+        MidiFilter::default()
+    }
+
+    /// Only lets channel messages on `channels` (0-15) through. Replaces any earlier channel
+    /// selection.
+    pub fn channels(mut self, channels: impl IntoIterator<Item = u8>) -> Self {
+        self.channels = channels
+            .into_iter()
+            .fold(0, |mask, channel| mask | (1 << (channel & 0x0F)));
+        self
+    }
+
+    /// Whether messages without a channel (system common, real-time, and sysex) pass the channel
+    /// filter. Defaults to true.
+    pub fn pass_channelless(mut self, pass: bool) -> Self {
+        self.pass_channelless = pass;
+        self
+    }
+
+    /// Only lets note on/off and polyphonic aftertouch for `notes` through (both ends included).
+    /// Other messages aren't affected.
+    pub fn allow_note_range(mut self, notes: RangeInclusive<u8>) -> Self {
+        self.notes = notes;
+        self
+    }
+
+    /// Drops every message of type `kind`.
+    pub fn block(mut self, kind: impl Into<MessageKind>) -> Self {
+        let kind = kind.into();
+
+        if !self.blocked.contains(&kind) {
+            self.blocked.push(kind);
+        }
+        self
+    }
+
+    /// Whether `data` should be forwarded. [`MidiData::MidiNone`] never is.
+    pub fn allows(&self, data: &MidiData) -> bool {
+        let Some(kind) = MessageKind::of(data) else {
+            return false;
+        };
+
+        if self.blocked.contains(&kind) {
+            return false;
+        }
+
+        let channel_passes = match data.channel() {
+            Some(channel) => self.channels & (1 << (channel & 0x0F)) != 0,
+            None => self.pass_channelless,
+        };
+
+        channel_passes && data.note().is_none_or(|note| self.notes.contains(&note))
+    }
+
+    /// Convenience for filtering a whole batch of messages.
+    pub fn filter<'a>(
+        &'a self,
+        messages: impl IntoIterator<Item = MidiData> + 'a,
+    ) -> impl Iterator<Item = MidiData> + 'a {
+        messages.into_iter().filter(move |data| self.allows(data))
+    }
+
+    /// Same as [`MidiFilter::filter`], for timestamped messages (like what comes out of a MIDI
+    /// source).
+    #[cfg(feature = "std")]
+    pub fn filter_timed<'a>(
+        &'a self,
+        messages: impl IntoIterator<Item = TimedValue<MidiData>> + 'a,
+    ) -> impl Iterator<Item = TimedValue<MidiData>> + 'a {
+        messages.into_iter().filter(move |message| self.allows(&message.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    fn note_on(channel: u8, note: u8) -> MidiData {
+        MidiData::NoteOn {
+            channel,
+            note,
+            velocity: 100,
+        }
+    }
+
+    #[test]
+    fn everything_passes_by_default() {
+        let filter = MidiFilter::new();
+
+        assert!(filter.allows(&note_on(15, 127)));
+        assert!(filter.allows(&MidiData::SysRt(SysRt::ActiveSensing)));
+        assert!(!filter.allows(&MidiData::MidiNone));
+    }
+
+    #[test]
+    fn only_the_chosen_channels_pass() {
+        let filter = MidiFilter::new().channels(0..=3);
+
+        for channel in 0..16 {
+            assert_eq!(filter.allows(&note_on(channel, 60)), channel <= 3, "channel {channel}");
+        }
+
+        let program_change = MidiData::ProgramChange { channel: 9, patch: 1 };
+        assert!(!filter.allows(&program_change));
+        assert!(MidiFilter::new().channels([9]).allows(&program_change));
+    }
+
+    #[test]
+    fn channelless_messages_pass_the_channel_filter_unless_told_otherwise() {
+        let clock = MidiData::SysRt(SysRt::MidiClock);
+        let sysex = MidiData::SysEx {
+            id_and_data: vec![0x41, 0x10],
+        };
+
+        let filter = MidiFilter::new().channels([0]);
+        assert!(filter.allows(&clock));
+        assert!(filter.allows(&sysex));
+
+        let filter = filter.pass_channelless(false);
+        assert!(!filter.allows(&clock));
+        assert!(!filter.allows(&sysex));
+        assert!(filter.allows(&note_on(0, 60)));
+    }
+
+    #[test]
+    fn note_range_includes_both_ends() {
+        let filter = MidiFilter::new().allow_note_range(36..=96);
+
+        for (note, allowed) in [(35, false), (36, true), (60, true), (96, true), (97, false)] {
+            assert_eq!(filter.allows(&note_on(0, note)), allowed, "note {note}");
+            assert_eq!(
+                filter.allows(&MidiData::NoteOff {
+                    channel: 0,
+                    note,
+                    velocity: 0
+                }),
+                allowed,
+                "note off {note}"
+            );
+        }
+
+        // messages without a note aren't affected
+        assert!(filter.allows(&MidiData::ControlChange {
+            channel: 0,
+            controller: 7,
+            value: 100
+        }));
+    }
+
+    #[test]
+    fn blocked_kinds_are_dropped() {
+        let filter = MidiFilter::new().block(SysRt::ActiveSensing).block(MessageKind::SysEx);
+
+        assert!(!filter.allows(&MidiData::SysRt(SysRt::ActiveSensing)));
+        assert!(filter.allows(&MidiData::SysRt(SysRt::MidiClock)));
+        assert!(!filter.allows(&MidiData::SysEx {
+            id_and_data: vec![0x41]
+        }));
+        assert!(filter.allows(&note_on(0, 60)));
+    }
+
+    #[test]
+    fn filter_keeps_the_order() {
+        let filter = MidiFilter::new().channels([1]).allow_note_range(60..=60);
+        let messages = vec![note_on(1, 60), note_on(2, 60), note_on(1, 61), note_on(1, 60)];
+
+        assert_eq!(
+            filter.filter(messages).collect::<Vec<_>>(),
+            [note_on(1, 60), note_on(1, 60)]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use super::{MidiData, SysRt};
+
+/// Drops MIDI clocks while the transport isn't running, so gear downstream of a clock forwarder
+/// doesn't start moving before a Start (or keep going after a Stop). Transport messages
+/// themselves, and everything that isn't a clock, always pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClockGate {
+    running: bool,
+}
+
+impl ClockGate {
+    pub fn new() -> ClockGate {
+        ClockGate::default()
+    }
+
+    /// Whether clocks are currently being let through.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Updates the gate with `data`, returning whether it should be forwarded.
+    pub fn process(&mut self, data: &MidiData) -> bool {
+        match data {
+            MidiData::SysRt(SysRt::Start | SysRt::Continue) => self.running = true,
+            MidiData::SysRt(SysRt::Stop | SysRt::Reset) => self.running = false,
+            MidiData::SysRt(SysRt::MidiClock) => return self.running,
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Convenience for filtering a whole batch of messages in order.
+    pub fn filter<'a>(
+        &'a mut self,
+        messages: impl IntoIterator<Item = MidiData> + 'a,
+    ) -> impl Iterator<Item = MidiData> + 'a {
+        messages.into_iter().filter(move |data| self.process(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    const CLOCK: MidiData = MidiData::SysRt(SysRt::MidiClock);
+
+    fn note_on() -> MidiData {
+        MidiData::NoteOn {
+            channel: 0,
+            note: 60,
+            velocity: 100,
+        }
+    }
+
+    #[test]
+    fn clocks_before_start_are_dropped() {
+        let mut gate = ClockGate::new();
+
+        assert!(!gate.process(&CLOCK));
+        assert!(!gate.is_running());
+
+        assert!(gate.process(&MidiData::SysRt(SysRt::Start)));
+        assert!(gate.process(&CLOCK));
+    }
+
+    #[test]
+    fn clocks_resume_after_continue() {
+        let messages = [
+            MidiData::SysRt(SysRt::Start),
+            CLOCK,
+            MidiData::SysRt(SysRt::Stop),
+            CLOCK,
+            CLOCK,
+            MidiData::SysRt(SysRt::Continue),
+            CLOCK,
+        ];
+
+        let passed: Vec<_> = ClockGate::new().filter(messages).collect();
+
+        assert_eq!(
+            passed,
+            [
+                MidiData::SysRt(SysRt::Start),
+                CLOCK,
+                MidiData::SysRt(SysRt::Stop),
+                MidiData::SysRt(SysRt::Continue),
+                CLOCK,
+            ]
+        );
+    }
+
+    #[test]
+    fn everything_else_always_passes() {
+        let mut gate = ClockGate::new();
+
+        assert!(gate.process(&note_on()));
+        assert!(gate.process(&MidiData::SysRt(SysRt::Stop)));
+        assert!(gate.process(&note_on()));
+
+        // a reset stops the clock too
+        gate.process(&MidiData::SysRt(SysRt::Start));
+        assert!(gate.process(&MidiData::SysRt(SysRt::Reset)));
+        assert!(!gate.process(&CLOCK));
+    }
+}
@@ -0,0 +1,249 @@
+use alloc::collections::VecDeque;
+use core::time::Duration;
+
+use super::MidiData;
+
+/// Pairs up high resolution control changes (MSB on controllers 0-31, LSB on 32-63) into
+/// [`MidiData::ControlChange14`]. An MSB that doesn't get an LSB within the timeout is passed on
+/// as a normal 7-bit control change, as is any MSB still waiting when some other message (not part
+/// of a high resolution pair) arrives on its channel, so it never comes out after that message.
+/// Everything else passes through untouched.
+#[derive(Debug, Clone)]
+pub struct HighResCcDecoder {
+    timeout: Duration,
+    /// MSBs waiting on their LSB, per channel and controller
+    pending: [[Option<(u8, Duration)>; 32]; 16],
+    /// Last MSB per channel and controller, for controllers that only send a new LSB
+    last_msb: [[Option<u8>; 32]; 16],
+    ready: VecDeque<MidiData>,
+}
+
+impl HighResCcDecoder {
+    pub fn new(timeout: Duration) -> HighResCcDecoder {
+        HighResCcDecoder {
+            timeout,
+            pending: [[None; 32]; 16],
+            last_msb: [[None; 32]; 16],
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Feeds in a message received at `timestamp`. Decoded messages come out of
+    /// [`HighResCcDecoder::poll`].
+    pub fn push(&mut self, data: MidiData, timestamp: Duration) {
+        let MidiData::ControlChange {
+            channel,
+            controller,
+            value,
+        } = data
+        else {
+            // anything else on the channel might depend on the MSBs before it (like a program
+            // change after a bank select), so those can't be held back past it
+            if let Some(channel) = data.channel() {
+                self.flush_channel(channel);
+            }
+
+            self.ready.push_back(data);
+            return;
+        };
+
+        let ch = (channel & 0x0F) as usize;
+
+        match controller {
+            0..=31 => {
+                let cc = controller as usize;
+
+                // a second MSB before any LSB means the first one is on its own
+                if let Some((old_value, _)) = self.pending[ch][cc].take() {
+                    self.ready.push_back(MidiData::ControlChange {
+                        channel,
+                        controller,
+                        value: old_value,
+                    });
+                }
+
+                self.pending[ch][cc] = Some((value, timestamp));
+                self.last_msb[ch][cc] = Some(value);
+            }
+            32..=63 => {
+                let cc = controller as usize - 32;
+                let msb = self.pending[ch][cc]
+                    .take()
+                    .map(|(msb, _)| msb)
+                    .or(self.last_msb[ch][cc]);
+
+                self.ready.push_back(match msb {
+                    Some(msb) => MidiData::ControlChange14 {
+                        channel,
+                        controller: controller - 32,
+                        value: ((msb as u16 & 0x7F) << 7) | (value as u16 & 0x7F),
+                    },
+                    None => data,
+                });
+            }
+            _ => {
+                self.flush_channel(channel);
+                self.ready.push_back(data);
+            }
+        }
+    }
+
+    /// Passes on every MSB still waiting on `channel` as a 7-bit control change, oldest first.
+    fn flush_channel(&mut self, channel: u8) {
+        let pending = &mut self.pending[(channel & 0x0F) as usize];
+
+        while let Some(controller) = (0..32)
+            .filter(|&controller| pending[controller].is_some())
+            .min_by_key(|&controller| pending[controller].map(|(_, since)| since))
+        {
+            let (value, _) = pending[controller].take().expect("only pending controllers are picked");
+
+            self.ready.push_back(MidiData::ControlChange {
+                channel,
+                controller: controller as u8,
+                value,
+            });
+        }
+    }
+
+    /// Returns decoded messages, including any MSBs that have waited longer than the timeout.
+    pub fn poll(&mut self, now: Duration) -> impl Iterator<Item = MidiData> + '_ {
+        for (channel, controllers) in self.pending.iter_mut().enumerate() {
+            for (controller, pending) in controllers.iter_mut().enumerate() {
+                if let Some((value, since)) = *pending {
+                    if now.saturating_sub(since) >= self.timeout {
+                        *pending = None;
+
+                        self.ready.push_back(MidiData::ControlChange {
+                            channel: channel as u8,
+                            controller: controller as u8,
+                            value,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.ready.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn cc(controller: u8, value: u8) -> MidiData {
+        MidiData::ControlChange {
+            channel: 2,
+            controller,
+            value,
+        }
+    }
+
+    #[test]
+    fn pairs_an_msb_with_its_lsb() {
+        let mut decoder = HighResCcDecoder::new(Duration::from_millis(10));
+
+        decoder.push(cc(7, 0x12), Duration::ZERO);
+        assert_eq!(decoder.poll(Duration::from_millis(1)).count(), 0);
+
+        decoder.push(cc(39, 0x34), Duration::from_millis(2));
+        assert_eq!(
+            decoder.poll(Duration::from_millis(2)).collect::<Vec<_>>(),
+            [MidiData::ControlChange14 {
+                channel: 2,
+                controller: 7,
+                value: (0x12 << 7) | 0x34,
+            }]
+        );
+    }
+
+    #[test]
+    fn lone_msb_times_out_as_a_7_bit_value() {
+        let mut decoder = HighResCcDecoder::new(Duration::from_millis(10));
+
+        decoder.push(cc(1, 100), Duration::ZERO);
+        assert_eq!(decoder.poll(Duration::from_millis(9)).count(), 0);
+        assert_eq!(
+            decoder.poll(Duration::from_millis(10)).collect::<Vec<_>>(),
+            [cc(1, 100)]
+        );
+
+        // and it doesn't come out twice
+        assert_eq!(decoder.poll(Duration::from_millis(20)).count(), 0);
+    }
+
+    #[test]
+    fn new_lsb_reuses_the_last_msb() {
+        let mut decoder = HighResCcDecoder::new(Duration::from_millis(10));
+        decoder.push(cc(7, 1), Duration::ZERO);
+        decoder.push(cc(39, 2), Duration::ZERO);
+        decoder.poll(Duration::ZERO).for_each(drop);
+
+        decoder.push(cc(39, 3), Duration::from_millis(50));
+
+        assert_eq!(
+            decoder.poll(Duration::from_millis(50)).collect::<Vec<_>>(),
+            [MidiData::ControlChange14 {
+                channel: 2,
+                controller: 7,
+                value: (1 << 7) | 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn bank_select_msb_comes_out_before_a_program_change() {
+        let mut decoder = HighResCcDecoder::new(Duration::from_millis(10));
+        let program_change = MidiData::ProgramChange { channel: 2, patch: 5 };
+
+        decoder.push(cc(0, 3), Duration::ZERO);
+        decoder.push(program_change.clone(), Duration::from_millis(1));
+
+        assert_eq!(
+            decoder.poll(Duration::from_millis(1)).collect::<Vec<_>>(),
+            [cc(0, 3), program_change]
+        );
+        // and it isn't repeated once the timeout passes
+        assert_eq!(decoder.poll(Duration::from_millis(20)).count(), 0);
+    }
+
+    #[test]
+    fn other_channels_dont_flush_pending_msbs() {
+        let mut decoder = HighResCcDecoder::new(Duration::from_millis(10));
+        let program_change = MidiData::ProgramChange { channel: 3, patch: 5 };
+
+        decoder.push(cc(0, 3), Duration::ZERO);
+        decoder.push(program_change.clone(), Duration::ZERO);
+        decoder.push(cc(32, 1), Duration::ZERO);
+
+        assert_eq!(
+            decoder.poll(Duration::ZERO).collect::<Vec<_>>(),
+            [
+                program_change,
+                MidiData::ControlChange14 {
+                    channel: 2,
+                    controller: 0,
+                    value: 3 << 7 | 1,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn everything_else_passes_through() {
+        let mut decoder = HighResCcDecoder::new(Duration::from_millis(10));
+        let note = MidiData::NoteOn {
+            channel: 0,
+            note: 60,
+            velocity: 90,
+        };
+
+        decoder.push(cc(74, 5), Duration::ZERO);
+        decoder.push(note.clone(), Duration::ZERO);
+
+        assert_eq!(decoder.poll(Duration::ZERO).collect::<Vec<_>>(), [cc(74, 5), note]);
+    }
+}
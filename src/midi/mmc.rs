@@ -0,0 +1,118 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// MIDI Machine Control commands (sent as `F0 7F <device> 06 <command> F7`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MmcCommand {
+    Stop,
+    Play,
+    DeferredPlay,
+    FastForward,
+    Rewind,
+    RecordStrobe,
+    RecordExit,
+    RecordPause,
+    Pause,
+    Eject,
+    Chase,
+    CommandErrorReset,
+    MmcReset,
+    /// Any command byte not listed above
+    Other(u8),
+}
+
+impl MmcCommand {
+    pub fn from_byte(byte: u8) -> MmcCommand {
+        match byte {
+            0x01 => MmcCommand::Stop,
+            0x02 => MmcCommand::Play,
+            0x03 => MmcCommand::DeferredPlay,
+            0x04 => MmcCommand::FastForward,
+            0x05 => MmcCommand::Rewind,
+            0x06 => MmcCommand::RecordStrobe,
+            0x07 => MmcCommand::RecordExit,
+            0x08 => MmcCommand::RecordPause,
+            0x09 => MmcCommand::Pause,
+            0x0A => MmcCommand::Eject,
+            0x0B => MmcCommand::Chase,
+            0x0C => MmcCommand::CommandErrorReset,
+            0x0D => MmcCommand::MmcReset,
+            other => MmcCommand::Other(other),
+        }
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            MmcCommand::Stop => 0x01,
+            MmcCommand::Play => 0x02,
+            MmcCommand::DeferredPlay => 0x03,
+            MmcCommand::FastForward => 0x04,
+            MmcCommand::Rewind => 0x05,
+            MmcCommand::RecordStrobe => 0x06,
+            MmcCommand::RecordExit => 0x07,
+            MmcCommand::RecordPause => 0x08,
+            MmcCommand::Pause => 0x09,
+            MmcCommand::Eject => 0x0A,
+            MmcCommand::Chase => 0x0B,
+            MmcCommand::CommandErrorReset => 0x0C,
+            MmcCommand::MmcReset => 0x0D,
+            MmcCommand::Other(byte) => *byte,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::{encode_midi_bytes, parse_midi_slice, MidiData};
+
+    fn round_trip(device: u8, command: MmcCommand, bytes: [u8; 6]) {
+        let message = MidiData::Mmc { device, command };
+
+        assert_eq!(parse_midi_slice(&bytes), (Some(message.clone()), 6));
+
+        let mut buffer = [0; 6];
+        assert_eq!(encode_midi_bytes(&message, &mut buffer), Some(6));
+        assert_eq!(buffer, bytes);
+    }
+
+    #[test]
+    fn play_round_trips() {
+        round_trip(0x7F, MmcCommand::Play, [0xF0, 0x7F, 0x7F, 0x06, 0x02, 0xF7]);
+    }
+
+    #[test]
+    fn stop_round_trips() {
+        round_trip(0x10, MmcCommand::Stop, [0xF0, 0x7F, 0x10, 0x06, 0x01, 0xF7]);
+    }
+
+    #[test]
+    fn unknown_commands_are_kept() {
+        round_trip(0x00, MmcCommand::Other(0x44), [0xF0, 0x7F, 0x00, 0x06, 0x44, 0xF7]);
+    }
+
+    #[test]
+    fn command_bytes_round_trip() {
+        for byte in 0..0x80 {
+            assert_eq!(MmcCommand::from_byte(byte).to_byte(), byte);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_midi_bytes_matches_encode() {
+        let mut written = alloc::vec::Vec::new();
+        let len = crate::midi::write_midi_bytes(
+            &MidiData::Mmc {
+                device: 1,
+                command: MmcCommand::Play,
+            },
+            &mut written,
+        )
+        .unwrap();
+
+        assert_eq!(len, 6);
+        assert_eq!(written, [0xF0, 0x7F, 0x01, 0x06, 0x02, 0xF7]);
+    }
+}
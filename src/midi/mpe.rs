@@ -0,0 +1,281 @@
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use super::MidiData;
+
+/// MPE's "timbre" controller.
+const TIMBRE_CC: u8 = 74;
+const PITCH_BEND_CENTER: u16 = 8192;
+
+/// Expression state of one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChannelExpression {
+    pitch_bend: u16,
+    pressure: u8,
+    timbre: u8,
+}
+
+impl Default for ChannelExpression {
+    fn default() -> Self {
+        ChannelExpression {
+            pitch_bend: PITCH_BEND_CENTER,
+            pressure: 0,
+            timbre: 64,
+        }
+    }
+}
+
+/// A sounding note in an [`MpeZone`], with its expression resolved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MpeNote {
+    /// Member channel the note is on
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+    /// Member channel bend plus the zone-wide (master channel) bend, in semitones
+    pub pitch_bend: f64,
+    pub pressure: u8,
+    /// CC74
+    pub timbre: u8,
+}
+
+/// Tracks the notes of an MPE zone, where every note gets its own member channel for per-note
+/// pitch bend, pressure and timbre. Zone-wide messages go on the master channel.
+#[derive(Debug, Clone)]
+pub struct MpeZone {
+    master: u8,
+    members: RangeInclusive<u8>,
+    /// Member channel pitch bend range, in semitones
+    member_bend_range: f64,
+    /// Master channel pitch bend range, in semitones
+    master_bend_range: f64,
+    expression: [ChannelExpression; 16],
+    /// (channel, note, velocity) of every sounding note
+    notes: Vec<(u8, u8, u8)>,
+}
+
+impl MpeZone {
+    /// A lower zone: master channel 0 (channel 1 in MIDI speak), members counting up from 1.
+    pub fn lower(member_channels: u8) -> MpeZone {
+        let member_channels = member_channels.clamp(1, 15);
+
+        MpeZone::new(0, 1..=member_channels)
+    }
+
+    /// An upper zone: master channel 15 (channel 16 in MIDI speak), members counting down from 14.
+    pub fn upper(member_channels: u8) -> MpeZone {
+        let member_channels = member_channels.clamp(1, 15);
+
+        MpeZone::new(15, (15 - member_channels)..=14)
+    }
+
+    fn new(master: u8, members: RangeInclusive<u8>) -> MpeZone {
+        MpeZone {
+            master,
+            members,
+            // the MPE spec defaults
+            member_bend_range: 48.0,
+            master_bend_range: 2.0,
+            expression: [ChannelExpression::default(); 16],
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn master_channel(&self) -> u8 {
+        self.master
+    }
+
+    pub fn member_channels(&self) -> RangeInclusive<u8> {
+        self.members.clone()
+    }
+
+    /// Sets the pitch bend ranges (in semitones) of the member channels and the master channel.
+    pub fn set_bend_ranges(&mut self, member_bend_range: f64, master_bend_range: f64) {
+        self.member_bend_range = member_bend_range;
+        self.master_bend_range = master_bend_range;
+    }
+
+    /// Updates the zone with `data`. Returns whether the message belonged to this zone.
+    pub fn process(&mut self, data: &MidiData) -> bool {
+        let channel = match data {
+            MidiData::NoteOn { channel, .. }
+            | MidiData::NoteOff { channel, .. }
+            | MidiData::PitchBend { channel, .. }
+            | MidiData::ChannelPressure { channel, .. }
+            | MidiData::ControlChange { channel, .. } => *channel & 0x0F,
+            _ => return false,
+        };
+
+        if channel != self.master && !self.members.contains(&channel) {
+            return false;
+        }
+
+        let expression = &mut self.expression[channel as usize];
+
+        match *data {
+            MidiData::NoteOn { note, velocity: 0, .. } | MidiData::NoteOff { note, .. } => {
+                self.notes.retain(|&(ch, n, _)| !(ch == channel && n == note));
+            }
+            MidiData::NoteOn { note, velocity, .. } => {
+                if channel == self.master {
+                    // notes belong on member channels
+                    return false;
+                }
+
+                self.notes.retain(|&(ch, n, _)| !(ch == channel && n == note));
+                self.notes.push((channel, note, velocity));
+            }
+            MidiData::PitchBend { pitch_bend, .. } => expression.pitch_bend = pitch_bend,
+            MidiData::ChannelPressure { pressure, .. } => expression.pressure = pressure,
+            MidiData::ControlChange {
+                controller: TIMBRE_CC,
+                value,
+                ..
+            } => expression.timbre = value,
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Every sounding note, with its expression resolved.
+    pub fn notes(&self) -> impl Iterator<Item = MpeNote> + '_ {
+        let master = &self.expression[self.master as usize];
+        let master_bend = normalize_bend(master.pitch_bend) * self.master_bend_range;
+
+        self.notes.iter().map(move |&(channel, note, velocity)| {
+            let member = &self.expression[channel as usize];
+
+            MpeNote {
+                channel,
+                note,
+                velocity,
+                pitch_bend: normalize_bend(member.pitch_bend) * self.member_bend_range + master_bend,
+                pressure: member.pressure,
+                timbre: member.timbre,
+            }
+        })
+    }
+
+    /// The sounding note on `channel`, if any.
+    pub fn note_on_channel(&self, channel: u8) -> Option<MpeNote> {
+        self.notes().find(|note| note.channel == channel)
+    }
+}
+
+/// Maps a 14-bit pitch bend to [-1.0, 1.0].
+fn normalize_bend(pitch_bend: u16) -> f64 {
+    (pitch_bend as f64 - PITCH_BEND_CENTER as f64) / (PITCH_BEND_CENTER - 1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(channel: u8, note: u8) -> MidiData {
+        MidiData::NoteOn {
+            channel,
+            note,
+            velocity: 100,
+        }
+    }
+
+    fn bend(channel: u8, pitch_bend: u16) -> MidiData {
+        MidiData::PitchBend { channel, pitch_bend }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn lower_zone_has_one_master_and_fourteen_members() {
+        let zone = MpeZone::lower(14);
+
+        assert_eq!(zone.master_channel(), 0);
+        assert_eq!(zone.member_channels(), 1..=14);
+    }
+
+    #[test]
+    fn tracks_two_notes_bends_separately() {
+        let mut zone = MpeZone::lower(14);
+        zone.set_bend_ranges(48.0, 2.0);
+
+        assert!(zone.process(&note_on(1, 60)));
+        assert!(zone.process(&note_on(2, 64)));
+        // full bend up on one, full bend down (as far as 14 bits go) on the other
+        assert!(zone.process(&bend(1, 16383)));
+        assert!(zone.process(&bend(2, 1)));
+
+        let first = zone.note_on_channel(1).unwrap();
+        let second = zone.note_on_channel(2).unwrap();
+        assert_eq!((first.note, second.note), (60, 64));
+        assert_close(first.pitch_bend, 48.0);
+        assert_close(second.pitch_bend, -48.0);
+
+        // bending one doesn't touch the other
+        assert!(zone.process(&bend(1, PITCH_BEND_CENTER)));
+        assert_close(zone.note_on_channel(1).unwrap().pitch_bend, 0.0);
+        assert_close(zone.note_on_channel(2).unwrap().pitch_bend, -48.0);
+    }
+
+    #[test]
+    fn master_bend_applies_to_every_note() {
+        let mut zone = MpeZone::lower(14);
+        zone.process(&note_on(1, 60));
+        zone.process(&note_on(2, 64));
+        zone.process(&bend(2, 16383));
+
+        assert!(zone.process(&bend(0, 16383)));
+
+        assert_close(zone.note_on_channel(1).unwrap().pitch_bend, 2.0);
+        assert_close(zone.note_on_channel(2).unwrap().pitch_bend, 50.0);
+    }
+
+    #[test]
+    fn pressure_and_timbre_are_per_note() {
+        let mut zone = MpeZone::lower(14);
+        zone.process(&note_on(3, 60));
+        zone.process(&note_on(4, 62));
+
+        zone.process(&MidiData::ChannelPressure {
+            channel: 3,
+            pressure: 90,
+        });
+        zone.process(&MidiData::ControlChange {
+            channel: 4,
+            controller: TIMBRE_CC,
+            value: 10,
+        });
+
+        let first = zone.note_on_channel(3).unwrap();
+        let second = zone.note_on_channel(4).unwrap();
+        assert_eq!((first.pressure, first.timbre), (90, 64));
+        assert_eq!((second.pressure, second.timbre), (0, 10));
+    }
+
+    #[test]
+    fn note_off_removes_only_that_note() {
+        let mut zone = MpeZone::lower(14);
+        zone.process(&note_on(1, 60));
+        zone.process(&note_on(2, 64));
+
+        zone.process(&MidiData::NoteOff {
+            channel: 1,
+            note: 60,
+            velocity: 0,
+        });
+
+        assert_eq!(zone.notes().map(|note| note.channel).collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn ignores_channels_outside_the_zone() {
+        let mut zone = MpeZone::lower(14);
+
+        assert!(!zone.process(&note_on(15, 60)));
+        // and notes on the master channel
+        assert!(!zone.process(&note_on(0, 60)));
+        assert_eq!(zone.notes().count(), 0);
+    }
+}
@@ -0,0 +1,271 @@
+use core::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{MidiData, SysCommon, Timecode};
+
+/// MIDI timecode frame rates, as encoded in the hours-high quarter frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MtcRate {
+    Fps24 = 0,
+    Fps25 = 1,
+    /// 29.97 fps, drop frame
+    Fps2997Drop = 2,
+    Fps30 = 3,
+}
+
+impl MtcRate {
+    pub fn from_bits(bits: u8) -> MtcRate {
+        match bits & 0x3 {
+            0 => MtcRate::Fps24,
+            1 => MtcRate::Fps25,
+            2 => MtcRate::Fps2997Drop,
+            3 => MtcRate::Fps30,
+            _ => unreachable!("masked to two bits"),
+        }
+    }
+
+    /// Actual frames per second.
+    pub fn fps(&self) -> f64 {
+        match self {
+            MtcRate::Fps24 => 24.0,
+            MtcRate::Fps25 => 25.0,
+            MtcRate::Fps2997Drop => 30_000.0 / 1001.0,
+            MtcRate::Fps30 => 30.0,
+        }
+    }
+
+    /// Frame numbers per second (what the frame count wraps at).
+    pub fn frames_per_second(&self) -> u8 {
+        match self {
+            MtcRate::Fps24 => 24,
+            MtcRate::Fps25 => 25,
+            MtcRate::Fps2997Drop | MtcRate::Fps30 => 30,
+        }
+    }
+}
+
+/// A full timecode position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MtcTime {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+}
+
+impl MtcTime {
+    pub fn new(hours: u8, minutes: u8, seconds: u8, frames: u8) -> MtcTime {
+        MtcTime {
+            hours,
+            minutes,
+            seconds,
+            frames,
+        }
+    }
+
+    /// Advances one frame, skipping dropped frames and wrapping hours at 24.
+    pub fn next_frame(&mut self, rate: MtcRate) {
+        self.frames += 1;
+
+        if self.frames >= rate.frames_per_second() {
+            self.frames = 0;
+            self.seconds += 1;
+
+            if self.seconds >= 60 {
+                self.seconds = 0;
+                self.minutes += 1;
+
+                if self.minutes >= 60 {
+                    self.minutes = 0;
+                    self.hours = (self.hours + 1) % 24;
+                }
+
+                // drop frame skips frames 0 and 1 every minute, except every tenth minute
+                if rate == MtcRate::Fps2997Drop && !self.minutes.is_multiple_of(10) {
+                    self.frames = 2;
+                }
+            }
+        }
+    }
+}
+
+/// Generates MTC quarter frames. Each run of eight quarter frames spans two frames and encodes the
+/// timecode from when the run started.
+#[derive(Debug, Clone)]
+pub struct MtcGenerator {
+    rate: MtcRate,
+    /// Timecode the current run of quarter frames is encoding
+    time: MtcTime,
+    /// Which quarter frame (0-7) is next
+    piece: u8,
+    emitted: u64,
+    start: Option<Duration>,
+}
+
+impl MtcGenerator {
+    pub fn new(start: MtcTime, rate: MtcRate) -> MtcGenerator {
+        MtcGenerator {
+            rate,
+            time: start,
+            piece: 0,
+            emitted: 0,
+            start: None,
+        }
+    }
+
+    pub fn rate(&self) -> MtcRate {
+        self.rate
+    }
+
+    /// Timecode of the run of quarter frames currently being sent.
+    pub fn timecode(&self) -> MtcTime {
+        self.time
+    }
+
+    /// Returns all the quarter frames due since the last poll. The first poll sets time zero (and
+    /// sends the first quarter frame right away). `now` must not go backwards.
+    pub fn poll(&mut self, now: Duration) -> impl Iterator<Item = MidiData> + '_ {
+        let start = *self.start.get_or_insert(now);
+
+        // counted from the start, so rounding doesn't add up over time
+        let due = (now.saturating_sub(start).as_secs_f64() * self.rate.fps() * 4.0) as u64 + 1;
+        let count = due.saturating_sub(self.emitted);
+
+        (0..count).map(|_| self.next_quarter_frame())
+    }
+
+    fn next_quarter_frame(&mut self) -> MidiData {
+        let time = &self.time;
+
+        let time_fragment = match self.piece {
+            0 => Timecode::FrameLow(time.frames & 0x0F),
+            1 => Timecode::FrameHigh(time.frames >> 4),
+            2 => Timecode::SecondsLow(time.seconds & 0x0F),
+            3 => Timecode::SecondsHigh(time.seconds >> 4),
+            4 => Timecode::MinutesLow(time.minutes & 0x0F),
+            5 => Timecode::MinutesHigh(time.minutes >> 4),
+            6 => Timecode::HoursLow(time.hours & 0x0F),
+            7 => Timecode::HoursHigh(((self.rate as u8) << 1) | (time.hours >> 4)),
+            _ => unreachable!("piece wraps at 8"),
+        };
+
+        self.emitted += 1;
+        self.piece += 1;
+
+        if self.piece == 8 {
+            self.piece = 0;
+
+            // a whole run covers two frames
+            self.time.next_frame(self.rate);
+            self.time.next_frame(self.rate);
+        }
+
+        MidiData::SysCommon(SysCommon::QuarterFrame { time_fragment })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Puts each run of eight quarter frames back together into the timecode it encodes.
+    fn decode_runs(quarter_frames: &[MidiData]) -> Vec<(MtcTime, MtcRate)> {
+        quarter_frames
+            .chunks_exact(8)
+            .map(|run| {
+                let mut time = MtcTime::default();
+                let mut rate = MtcRate::Fps24;
+
+                for (piece, message) in run.iter().enumerate() {
+                    let MidiData::SysCommon(SysCommon::QuarterFrame { time_fragment }) = message else {
+                        panic!("expected a quarter frame, got {message:?}");
+                    };
+
+                    match (piece, time_fragment.clone()) {
+                        (0, Timecode::FrameLow(bits)) => time.frames |= bits,
+                        (1, Timecode::FrameHigh(bits)) => time.frames |= bits << 4,
+                        (2, Timecode::SecondsLow(bits)) => time.seconds |= bits,
+                        (3, Timecode::SecondsHigh(bits)) => time.seconds |= bits << 4,
+                        (4, Timecode::MinutesLow(bits)) => time.minutes |= bits,
+                        (5, Timecode::MinutesHigh(bits)) => time.minutes |= bits << 4,
+                        (6, Timecode::HoursLow(bits)) => time.hours |= bits,
+                        (7, Timecode::HoursHigh(bits)) => {
+                            time.hours |= (bits & 0x1) << 4;
+                            rate = MtcRate::from_bits(bits >> 1);
+                        }
+                        (piece, fragment) => panic!("piece {piece} out of order: {fragment:?}"),
+                    }
+                }
+
+                (time, rate)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn one_second_at_25_fps_is_100_quarter_frames() {
+        let mut generator = MtcGenerator::new(MtcTime::new(1, 2, 3, 0), MtcRate::Fps25);
+
+        let mut quarter_frames: Vec<MidiData> = generator.poll(Duration::ZERO).collect();
+        for millis in (10..1000).step_by(10) {
+            quarter_frames.extend(generator.poll(Duration::from_millis(millis)));
+        }
+
+        assert_eq!(quarter_frames.len(), 100);
+
+        let runs = decode_runs(&quarter_frames);
+        assert_eq!(runs.len(), 12);
+        for (i, (time, rate)) in runs.iter().enumerate() {
+            assert_eq!(*rate, MtcRate::Fps25);
+            assert_eq!(*time, MtcTime::new(1, 2, 3, i as u8 * 2));
+        }
+    }
+
+    #[test]
+    fn runs_carry_into_the_next_second() {
+        let mut generator = MtcGenerator::new(MtcTime::new(0, 0, 59, 22), MtcRate::Fps24);
+
+        let mut quarter_frames: Vec<MidiData> = generator.poll(Duration::ZERO).collect();
+        quarter_frames.extend(generator.poll(Duration::from_millis(300)));
+
+        let runs = decode_runs(&quarter_frames);
+        assert_eq!(runs[0].0, MtcTime::new(0, 0, 59, 22));
+        assert_eq!(runs[1].0, MtcTime::new(0, 1, 0, 0));
+        assert_eq!(runs[2].0, MtcTime::new(0, 1, 0, 2));
+    }
+
+    #[test]
+    fn hours_wrap_at_24() {
+        let mut time = MtcTime::new(23, 59, 59, 29);
+        time.next_frame(MtcRate::Fps30);
+
+        assert_eq!(time, MtcTime::new(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn drop_frame_skips_two_frames_most_minutes() {
+        let mut time = MtcTime::new(0, 0, 59, 29);
+        time.next_frame(MtcRate::Fps2997Drop);
+        assert_eq!(time, MtcTime::new(0, 1, 0, 2));
+
+        // except every tenth minute
+        let mut time = MtcTime::new(0, 9, 59, 29);
+        time.next_frame(MtcRate::Fps2997Drop);
+        assert_eq!(time, MtcTime::new(0, 10, 0, 0));
+    }
+
+    #[test]
+    fn generator_copes_with_time_before_the_first_poll() {
+        let mut generator = MtcGenerator::new(MtcTime::default(), MtcRate::Fps30);
+        assert_eq!(generator.poll(Duration::from_secs(5)).count(), 1);
+
+        // an earlier timestamp than the first poll just has nothing due
+        assert_eq!(generator.poll(Duration::from_secs(4)).count(), 0);
+    }
+}
@@ -0,0 +1,185 @@
+use super::{sensing::ALL_NOTES_OFF_CC, MidiData, SysRt};
+
+/// The All Sound Off controller
+const ALL_SOUND_OFF_CC: u8 = 120;
+
+/// Keeps track of which notes are sounding, so they can be silenced if their note offs go missing
+/// (say, the sender disconnected or the transport stopped mid-note). Pairs well with an
+/// [`ActiveSensingMonitor`](super::ActiveSensingMonitor).
+///
+/// A note on with velocity 0 counts as a note off, and All Notes Off, All Sound Off, and System
+/// Reset clear whatever they cover.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoteTracker {
+    /// One bit per note, per channel
+    sounding: [u128; 16],
+}
+
+impl NoteTracker {
+    pub fn new() -> NoteTracker {
+        NoteTracker::default()
+    }
+
+    /// Updates the tracker with a message on its way through.
+    pub fn process(&mut self, data: &MidiData) {
+        match *data {
+            MidiData::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity > 0 => self.sounding[channel as usize & 0x0F] |= 1 << (note & 0x7F),
+            MidiData::NoteOn { channel, note, .. } | MidiData::NoteOff { channel, note, .. } => {
+                self.sounding[channel as usize & 0x0F] &= !(1 << (note & 0x7F));
+            }
+            MidiData::ControlChange {
+                channel, controller, ..
+            } if controller == ALL_NOTES_OFF_CC || controller == ALL_SOUND_OFF_CC => {
+                self.sounding[channel as usize & 0x0F] = 0;
+            }
+            MidiData::SysRt(SysRt::Reset) => self.sounding = [0; 16],
+            _ => {}
+        }
+    }
+
+    pub fn is_sounding(&self, channel: u8, note: u8) -> bool {
+        self.sounding[channel as usize & 0x0F] & (1 << (note & 0x7F)) != 0
+    }
+
+    /// How many notes are sounding, across all channels.
+    pub fn sounding_count(&self) -> usize {
+        self.sounding.iter().map(|notes| notes.count_ones() as usize).sum()
+    }
+
+    /// Every sounding note, as `(channel, note)`.
+    pub fn sounding(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        sounding_notes(self.sounding)
+    }
+
+    /// A note off (with release velocity 0) for every sounding note, after which nothing is
+    /// considered sounding.
+    pub fn panic_off(&mut self) -> impl Iterator<Item = MidiData> {
+        sounding_notes(core::mem::take(&mut self.sounding)).map(|(channel, note)| MidiData::NoteOff {
+            channel,
+            note,
+            velocity: 0,
+        })
+    }
+
+    /// Same as [`NoteTracker::panic_off`], followed by All Notes Off on each channel that had
+    /// something sounding, for receivers that track notes differently (e.g. stacked note ons).
+    pub fn all_notes_off(&mut self) -> impl Iterator<Item = MidiData> {
+        let sounding = self.sounding;
+
+        self.panic_off().chain(
+            (0..16)
+                .filter(move |channel| sounding[*channel as usize] != 0)
+                .map(|channel| MidiData::ControlChange {
+                    channel,
+                    controller: ALL_NOTES_OFF_CC,
+                    value: 0,
+                }),
+        )
+    }
+}
+
+fn sounding_notes(sounding: [u128; 16]) -> impl Iterator<Item = (u8, u8)> {
+    (0..16_u8).flat_map(move |channel| {
+        (0..128_u8)
+            .filter(move |note| sounding[channel as usize] & (1 << note) != 0)
+            .map(move |note| (channel, note))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn note_on(channel: u8, note: u8, velocity: u8) -> MidiData {
+        MidiData::NoteOn {
+            channel,
+            note,
+            velocity,
+        }
+    }
+
+    fn note_off(channel: u8, note: u8) -> MidiData {
+        MidiData::NoteOff {
+            channel,
+            note,
+            velocity: 0,
+        }
+    }
+
+    #[test]
+    fn panic_off_releases_each_sounding_note_once() {
+        let mut tracker = NoteTracker::new();
+        tracker.process(&note_on(0, 60, 100));
+        tracker.process(&note_on(3, 127, 100));
+
+        let offs: Vec<_> = tracker.panic_off().collect();
+        assert_eq!(offs, [note_off(0, 60), note_off(3, 127)]);
+        assert_eq!(tracker.sounding_count(), 0);
+        assert_eq!(tracker.panic_off().count(), 0);
+    }
+
+    #[test]
+    fn velocity_zero_note_on_is_a_note_off() {
+        let mut tracker = NoteTracker::new();
+        tracker.process(&note_on(0, 60, 100));
+        tracker.process(&note_on(0, 62, 100));
+        tracker.process(&note_on(0, 60, 0));
+        tracker.process(&note_off(0, 62));
+
+        assert_eq!(tracker.sounding_count(), 0);
+    }
+
+    #[test]
+    fn repeated_note_ons_count_once() {
+        let mut tracker = NoteTracker::new();
+        tracker.process(&note_on(1, 0, 100));
+        tracker.process(&note_on(1, 0, 90));
+
+        assert!(tracker.is_sounding(1, 0));
+        assert!(!tracker.is_sounding(0, 0));
+        assert_eq!(tracker.sounding().collect::<Vec<_>>(), [(1, 0)]);
+    }
+
+    #[test]
+    fn all_notes_off_and_reset_clear_what_they_cover() {
+        let mut tracker = NoteTracker::new();
+        tracker.process(&note_on(0, 60, 100));
+        tracker.process(&note_on(1, 60, 100));
+        tracker.process(&MidiData::ControlChange {
+            channel: 0,
+            controller: ALL_SOUND_OFF_CC,
+            value: 0,
+        });
+        assert_eq!(tracker.sounding().collect::<Vec<_>>(), [(1, 60)]);
+
+        tracker.process(&MidiData::SysRt(SysRt::Reset));
+        assert_eq!(tracker.sounding_count(), 0);
+    }
+
+    #[test]
+    fn all_notes_off_adds_a_cc_per_sounding_channel() {
+        let mut tracker = NoteTracker::new();
+        tracker.process(&note_on(2, 60, 100));
+        tracker.process(&note_on(2, 64, 100));
+
+        let out: Vec<_> = tracker.all_notes_off().collect();
+        assert_eq!(
+            out,
+            [
+                note_off(2, 60),
+                note_off(2, 64),
+                MidiData::ControlChange {
+                    channel: 2,
+                    controller: ALL_NOTES_OFF_CC,
+                    value: 0
+                }
+            ]
+        );
+    }
+}
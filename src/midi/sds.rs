@@ -0,0 +1,105 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Sample Dump Standard handshake messages (sent as `F0 7E <device> <kind> <packet> F7`), which the
+/// receiver of a dump uses to pace the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SdsHandshake {
+    /// The packet was received fine, send the next one
+    Ack,
+    /// The packet's checksum didn't match, send it again
+    Nak,
+    /// Hold off until the next message
+    Wait,
+    /// Abort the dump
+    Cancel,
+}
+
+impl SdsHandshake {
+    /// The sub-ID for `byte`, if it's one of the handshake messages.
+    pub fn from_byte(byte: u8) -> Option<SdsHandshake> {
+        match byte {
+            0x7F => Some(SdsHandshake::Ack),
+            0x7E => Some(SdsHandshake::Nak),
+            0x7C => Some(SdsHandshake::Wait),
+            0x7D => Some(SdsHandshake::Cancel),
+            _ => None,
+        }
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            SdsHandshake::Ack => 0x7F,
+            SdsHandshake::Nak => 0x7E,
+            SdsHandshake::Wait => 0x7C,
+            SdsHandshake::Cancel => 0x7D,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::midi::{encode_midi_bytes, parse_midi_slice, MidiData};
+
+    /// Encodes `message`, checks it came out as `bytes`, and parses it back.
+    fn round_trip(message: MidiData, bytes: &[u8]) {
+        let mut buffer = [0; 6];
+        let len = encode_midi_bytes(&message, &mut buffer).unwrap();
+
+        assert_eq!(&buffer[..len], bytes);
+        assert_eq!(parse_midi_slice(bytes), (Some(message), bytes.len()));
+    }
+
+    #[test]
+    fn ack_round_trips() {
+        round_trip(
+            MidiData::SampleDumpHandshake {
+                device: 0x10,
+                kind: SdsHandshake::Ack,
+                packet: 0x05,
+            },
+            &[0xF0, 0x7E, 0x10, 0x7F, 0x05, 0xF7],
+        );
+    }
+
+    #[test]
+    fn nak_round_trips() {
+        round_trip(
+            MidiData::SampleDumpHandshake {
+                device: 0x7F,
+                kind: SdsHandshake::Nak,
+                packet: 0x7F,
+            },
+            &[0xF0, 0x7E, 0x7F, 0x7E, 0x7F, 0xF7],
+        );
+    }
+
+    #[test]
+    fn every_kind_maps_back_to_its_byte() {
+        for kind in [
+            SdsHandshake::Ack,
+            SdsHandshake::Nak,
+            SdsHandshake::Wait,
+            SdsHandshake::Cancel,
+        ] {
+            assert_eq!(SdsHandshake::from_byte(kind.to_byte()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn other_universal_messages_stay_sysex() {
+        // a sample dump request (sub-ID 0x03) is just as short, but isn't a handshake
+        let bytes = [0xF0, 0x7E, 0x10, 0x03, 0x05, 0xF7];
+
+        assert_eq!(
+            parse_midi_slice(&bytes).0,
+            Some(MidiData::SysEx {
+                id_and_data: vec![0x7E, 0x10, 0x03, 0x05]
+            })
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use core::time::Duration;
+
+use super::{MidiData, SysRt};
+
+/// How long the spec allows between messages once Active Sensing has started.
+pub const ACTIVE_SENSING_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// The All Notes Off controller
+pub(super) const ALL_NOTES_OFF_CC: u8 = 123;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensingState {
+    /// No Active Sensing seen yet (or since the last disconnect), so gaps don't mean anything
+    Inactive,
+    /// Active Sensing is running, and the sender has been heard from recently
+    Connected,
+    /// Active Sensing stopped for longer than the timeout. Stays this way until the next Active
+    /// Sensing message.
+    Disconnected,
+}
+
+/// Watches for Active Sensing lapsing. Per the spec, this only kicks in once the first Active
+/// Sensing message arrives; after that, a gap of more than the timeout (300ms by default) without
+/// _any_ message means the sender went away, and anything it left sounding should be silenced.
+#[derive(Debug, Clone)]
+pub struct ActiveSensingMonitor {
+    timeout: Duration,
+    state: SensingState,
+    last_heard: Duration,
+}
+
+impl Default for ActiveSensingMonitor {
+    fn default() -> Self {
+        ActiveSensingMonitor::new(ACTIVE_SENSING_TIMEOUT)
+    }
+}
+
+impl ActiveSensingMonitor {
+    pub fn new(timeout: Duration) -> ActiveSensingMonitor {
+        ActiveSensingMonitor {
+            timeout,
+            state: SensingState::Inactive,
+            last_heard: Duration::ZERO,
+        }
+    }
+
+    pub fn state(&self) -> SensingState {
+        self.state
+    }
+
+    /// Feeds in a message received at `timestamp`.
+    pub fn process(&mut self, data: &MidiData, timestamp: Duration) {
+        match (self.state, data) {
+            (_, MidiData::SysRt(SysRt::ActiveSensing)) => {
+                self.state = SensingState::Connected;
+                self.last_heard = timestamp;
+            }
+            // any message counts as a sign of life once sensing is running
+            (SensingState::Connected, _) => self.last_heard = timestamp,
+            _ => {}
+        }
+    }
+
+    /// Checks for a lapse as of `now`. Returns true exactly once per lapse, at which point
+    /// [`ActiveSensingMonitor::all_notes_off`] is a good thing to send downstream.
+    pub fn poll(&mut self, now: Duration) -> bool {
+        if self.state == SensingState::Connected && now.saturating_sub(self.last_heard) > self.timeout {
+            self.state = SensingState::Disconnected;
+
+            true
+        } else {
+            false
+        }
+    }
+
+    /// All Notes Off on every channel.
+    pub fn all_notes_off() -> impl Iterator<Item = MidiData> {
+        (0..16).map(|channel| MidiData::ControlChange {
+            channel,
+            controller: ALL_NOTES_OFF_CC,
+            value: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Active Sensing every 100ms up to (but not including) `until`.
+    fn sense_until(monitor: &mut ActiveSensingMonitor, until: u64) {
+        for t in (0..until).step_by(100) {
+            monitor.process(&MidiData::SysRt(SysRt::ActiveSensing), ms(t));
+            assert!(!monitor.poll(ms(t)));
+        }
+    }
+
+    #[test]
+    fn gap_after_sensing_disconnects() {
+        let mut monitor = ActiveSensingMonitor::default();
+        sense_until(&mut monitor, 1000);
+        assert_eq!(monitor.state(), SensingState::Connected);
+
+        // last heard at 900ms
+        assert!(!monitor.poll(ms(1200)));
+        assert!(monitor.poll(ms(1201)));
+        assert_eq!(monitor.state(), SensingState::Disconnected);
+
+        // only fires once per lapse
+        assert!(!monitor.poll(ms(2000)));
+
+        // and sensing coming back reconnects
+        monitor.process(&MidiData::SysRt(SysRt::ActiveSensing), ms(2100));
+        assert_eq!(monitor.state(), SensingState::Connected);
+    }
+
+    #[test]
+    fn nothing_happens_before_sensing_starts() {
+        let mut monitor = ActiveSensingMonitor::default();
+
+        monitor.process(&MidiData::SysRt(SysRt::MidiClock), ms(0));
+        assert!(!monitor.poll(ms(10_000)));
+        assert_eq!(monitor.state(), SensingState::Inactive);
+    }
+
+    #[test]
+    fn any_message_keeps_the_connection_alive() {
+        let mut monitor = ActiveSensingMonitor::default();
+        sense_until(&mut monitor, 100);
+
+        for t in (250..2000).step_by(250) {
+            monitor.process(&MidiData::SysRt(SysRt::MidiClock), ms(t));
+            assert!(!monitor.poll(ms(t)));
+        }
+        assert_eq!(monitor.state(), SensingState::Connected);
+    }
+
+    #[test]
+    fn all_notes_off_covers_every_channel() {
+        let messages: Vec<_> = ActiveSensingMonitor::all_notes_off().collect();
+
+        assert_eq!(messages.len(), 16);
+        assert_eq!(
+            messages[15],
+            MidiData::ControlChange {
+                channel: 15,
+                controller: ALL_NOTES_OFF_CC,
+                value: 0
+            }
+        );
+    }
+}
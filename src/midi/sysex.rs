@@ -0,0 +1,131 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::MidiData;
+
+/// Who a sysex message is from (or for), taken from the ID at the start of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Manufacturer {
+    /// A one byte ID (0x01-0x7C)
+    Standard(u8),
+    /// A three byte ID: 0x00 followed by these two bytes
+    Extended([u8; 2]),
+    /// 0x7D, for educational or in-house use
+    NonCommercial,
+    /// 0x7E
+    UniversalNonRealTime,
+    /// 0x7F
+    UniversalRealTime,
+}
+
+impl Manufacturer {
+    /// Splits `id_and_data` (as found in [`MidiData::SysEx`]) into the manufacturer and the payload
+    /// after the ID. Returns `None` if it's too short to hold an ID.
+    pub fn split(id_and_data: &[u8]) -> Option<(Manufacturer, &[u8])> {
+        match *id_and_data {
+            [0x00, first, second, ref payload @ ..] => Some((Manufacturer::Extended([first, second]), payload)),
+            [0x00, ..] | [] => None,
+            [0x7D, ref payload @ ..] => Some((Manufacturer::NonCommercial, payload)),
+            [0x7E, ref payload @ ..] => Some((Manufacturer::UniversalNonRealTime, payload)),
+            [0x7F, ref payload @ ..] => Some((Manufacturer::UniversalRealTime, payload)),
+            [id, ref payload @ ..] => Some((Manufacturer::Standard(id), payload)),
+        }
+    }
+
+    /// How many bytes the ID takes up.
+    pub fn id_len(&self) -> usize {
+        match self {
+            Manufacturer::Extended(_) => 3,
+            _ => 1,
+        }
+    }
+}
+
+impl MidiData {
+    /// The manufacturer of a [`MidiData::SysEx`] message (see [`Manufacturer::split`]). `None` for
+    /// anything else.
+    pub fn sysex_manufacturer(&self) -> Option<Manufacturer> {
+        self.split_sysex().map(|(manufacturer, _)| manufacturer)
+    }
+
+    /// The data of a [`MidiData::SysEx`] message after the manufacturer ID. `None` for anything
+    /// else.
+    pub fn sysex_payload(&self) -> Option<&[u8]> {
+        self.split_sysex().map(|(_, payload)| payload)
+    }
+
+    fn split_sysex(&self) -> Option<(Manufacturer, &[u8])> {
+        match self {
+            MidiData::SysEx { id_and_data } => Manufacturer::split(id_and_data),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sysex(id_and_data: &[u8]) -> MidiData {
+        MidiData::SysEx {
+            id_and_data: id_and_data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn one_byte_id() {
+        let message = sysex(&[0x41, 0x10, 0x42]);
+
+        assert_eq!(message.sysex_manufacturer(), Some(Manufacturer::Standard(0x41)));
+        assert_eq!(message.sysex_payload(), Some(&[0x10, 0x42][..]));
+        assert_eq!(Manufacturer::Standard(0x41).id_len(), 1);
+    }
+
+    #[test]
+    fn extended_id() {
+        let message = sysex(&[0x00, 0x20, 0x29, 0x02, 0x0C]);
+
+        assert_eq!(message.sysex_manufacturer(), Some(Manufacturer::Extended([0x20, 0x29])));
+        assert_eq!(message.sysex_payload(), Some(&[0x02, 0x0C][..]));
+        assert_eq!(Manufacturer::Extended([0x20, 0x29]).id_len(), 3);
+
+        // cut off partway through the ID
+        assert_eq!(sysex(&[0x00, 0x20]).sysex_manufacturer(), None);
+    }
+
+    #[test]
+    fn universal_ids() {
+        let message = sysex(&[0x7E, 0x7F, 0x06, 0x01]);
+
+        assert_eq!(message.sysex_manufacturer(), Some(Manufacturer::UniversalNonRealTime));
+        assert_eq!(message.sysex_payload(), Some(&[0x7F, 0x06, 0x01][..]));
+
+        assert_eq!(
+            sysex(&[0x7F]).sysex_manufacturer(),
+            Some(Manufacturer::UniversalRealTime)
+        );
+        assert_eq!(sysex(&[0x7D]).sysex_manufacturer(), Some(Manufacturer::NonCommercial));
+    }
+
+    #[test]
+    fn other_messages_have_no_manufacturer() {
+        assert_eq!(sysex(&[]).sysex_manufacturer(), None);
+        assert_eq!(MidiData::MidiNone.sysex_manufacturer(), None);
+        assert_eq!(MidiData::MidiNone.sysex_payload(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn every_manufacturer_round_trips_through_serde() {
+        for manufacturer in [
+            Manufacturer::Standard(0x41),
+            Manufacturer::Extended([0x20, 0x29]),
+            Manufacturer::NonCommercial,
+            Manufacturer::UniversalNonRealTime,
+            Manufacturer::UniversalRealTime,
+        ] {
+            crate::midi::tests::serde_round_trip(&manufacturer);
+        }
+    }
+}
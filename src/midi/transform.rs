@@ -0,0 +1,307 @@
+use super::MidiData;
+
+/// What [`MidiTransform`] does with a note that transposes past 0 or 127.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfRange {
+    /// Pin it to the nearest valid note
+    #[default]
+    Clamp,
+    /// Drop it (along with its note off and aftertouch)
+    Drop,
+}
+
+/// Where a sounding note was sent when it started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Route {
+    To(u8),
+    Dropped,
+}
+
+/// Transposes notes and reshapes velocities, passing everything else through untouched.
+///
+/// Each note remembers where it was sent when it started, so its note off (and aftertouch) goes to
+/// the same place even if the transposition changes while it's held. Otherwise notes would hang.
+#[derive(Debug, Clone)]
+pub struct MidiTransform {
+    transpose: i8,
+    out_of_range: OutOfRange,
+    velocity_scale: f32,
+    velocity_curve: f32,
+    /// Indexed by channel, then incoming note
+    routes: [[Option<Route>; 128]; 16],
+}
+
+impl Default for MidiTransform {
+    fn default() -> Self {
+        MidiTransform {
+            transpose: 0,
+            out_of_range: OutOfRange::Clamp,
+            velocity_scale: 1.0,
+            velocity_curve: 0.0,
+            routes: [[None; 128]; 16],
+        }
+    }
+}
+
+impl MidiTransform {
+    /// A transform that leaves everything as it is.
+    pub fn new() -> MidiTransform {
+        MidiTransform::default()
+    }
+
+    pub fn transpose(&self) -> i8 {
+        self.transpose
+    }
+
+    /// Shifts notes by `semitones`. Notes already held keep the shift they started with.
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.transpose = semitones;
+    }
+
+    pub fn out_of_range(&self) -> OutOfRange {
+        self.out_of_range
+    }
+
+    pub fn set_out_of_range(&mut self, out_of_range: OutOfRange) {
+        self.out_of_range = out_of_range;
+    }
+
+    pub fn velocity_scale(&self) -> f32 {
+        self.velocity_scale
+    }
+
+    /// Multiplies velocities by `scale` (after the curve). Results are clamped to 0-127, except that
+    /// a note on never drops below 1, since velocity 0 would turn it into a note off.
+    pub fn set_velocity_scale(&mut self, scale: f32) {
+        assert!(scale >= 0.0, "velocity scale can't be negative");
+
+        self.velocity_scale = scale;
+    }
+
+    pub fn velocity_curve(&self) -> f32 {
+        self.velocity_curve
+    }
+
+    /// Bends the velocity response, from -1 to 1. Positive values lift soft notes (and negative
+    /// ones push them down) while leaving 0 and 127 where they are; 0 is linear.
+    pub fn set_velocity_curve(&mut self, curve: f32) {
+        assert!((-1.0..=1.0).contains(&curve), "velocity curve must be between -1 and 1");
+
+        self.velocity_curve = curve;
+    }
+
+    /// Transforms one message, returning `None` if it was dropped.
+    pub fn process(&mut self, data: MidiData) -> Option<MidiData> {
+        match data {
+            MidiData::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity > 0 => {
+                let route = self.transposed(note);
+                self.routes[channel as usize & 0x0F][note as usize & 0x7F] = Some(route);
+
+                match route {
+                    Route::To(note) => Some(MidiData::NoteOn {
+                        channel,
+                        note,
+                        velocity: self.scale_velocity(velocity).max(1),
+                    }),
+                    Route::Dropped => None,
+                }
+            }
+            // a note on with velocity 0 is a note off, and stays that way
+            MidiData::NoteOn { channel, note, .. } => match self.release(channel, note) {
+                Route::To(note) => Some(MidiData::NoteOn {
+                    channel,
+                    note,
+                    velocity: 0,
+                }),
+                Route::Dropped => None,
+            },
+            MidiData::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => match self.release(channel, note) {
+                Route::To(note) => Some(MidiData::NoteOff {
+                    channel,
+                    note,
+                    velocity: self.scale_velocity(velocity),
+                }),
+                Route::Dropped => None,
+            },
+            MidiData::Aftertouch {
+                channel,
+                note,
+                pressure,
+            } => {
+                let route =
+                    self.routes[channel as usize & 0x0F][note as usize & 0x7F].unwrap_or_else(|| self.transposed(note));
+
+                match route {
+                    Route::To(note) => Some(MidiData::Aftertouch {
+                        channel,
+                        note,
+                        pressure,
+                    }),
+                    Route::Dropped => None,
+                }
+            }
+            data => Some(data),
+        }
+    }
+
+    /// Convenience for transforming a whole batch of messages in order.
+    pub fn map<'a>(
+        &'a mut self,
+        messages: impl IntoIterator<Item = MidiData> + 'a,
+    ) -> impl Iterator<Item = MidiData> + 'a {
+        messages.into_iter().filter_map(move |data| self.process(data))
+    }
+
+    /// Where `note` goes with the current transposition.
+    fn transposed(&self, note: u8) -> Route {
+        let shifted = note as i16 + self.transpose as i16;
+
+        match self.out_of_range {
+            _ if (0..=127).contains(&shifted) => Route::To(shifted as u8),
+            OutOfRange::Clamp => Route::To(shifted.clamp(0, 127) as u8),
+            OutOfRange::Drop => Route::Dropped,
+        }
+    }
+
+    /// Where a note off for `note` goes: wherever its note on went, if it's still held.
+    fn release(&mut self, channel: u8, note: u8) -> Route {
+        self.routes[channel as usize & 0x0F][note as usize & 0x7F]
+            .take()
+            .unwrap_or_else(|| self.transposed(note))
+    }
+
+    fn scale_velocity(&self, velocity: u8) -> u8 {
+        let velocity = velocity as f32;
+        let curved = velocity + self.velocity_curve * velocity * (127.0 - velocity) / 127.0;
+
+        // clamped to be positive, so adding 0.5 and truncating rounds it
+        ((curved * self.velocity_scale).clamp(0.0, 127.0) + 0.5) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn note_on(note: u8, velocity: u8) -> MidiData {
+        MidiData::NoteOn {
+            channel: 0,
+            note,
+            velocity,
+        }
+    }
+
+    fn note_off(note: u8) -> MidiData {
+        MidiData::NoteOff {
+            channel: 0,
+            note,
+            velocity: 64,
+        }
+    }
+
+    #[test]
+    fn notes_are_transposed() {
+        let mut transform = MidiTransform::new();
+        transform.set_transpose(-12);
+
+        let out: Vec<_> = transform.map([note_on(60, 100), note_off(60)]).collect();
+        assert_eq!(out, [note_on(48, 100), note_off(48)]);
+    }
+
+    #[test]
+    fn other_messages_pass_through() {
+        let mut transform = MidiTransform::new();
+        transform.set_transpose(5);
+        transform.set_velocity_scale(0.5);
+
+        let control_change = MidiData::ControlChange {
+            channel: 0,
+            controller: 60,
+            value: 100,
+        };
+        assert_eq!(transform.process(control_change.clone()), Some(control_change));
+    }
+
+    #[test]
+    fn note_off_follows_its_note_on_after_the_transposition_changes() {
+        let mut transform = MidiTransform::new();
+        transform.set_transpose(2);
+        assert_eq!(transform.process(note_on(60, 100)), Some(note_on(62, 100)));
+
+        transform.set_transpose(7);
+        assert_eq!(
+            transform.process(MidiData::Aftertouch {
+                channel: 0,
+                note: 60,
+                pressure: 50
+            }),
+            Some(MidiData::Aftertouch {
+                channel: 0,
+                note: 62,
+                pressure: 50
+            })
+        );
+        assert_eq!(transform.process(note_off(60)), Some(note_off(62)));
+        // velocity 0 counts as a note off too
+        assert_eq!(transform.process(note_on(60, 100)), Some(note_on(67, 100)));
+        transform.set_transpose(0);
+        assert_eq!(transform.process(note_on(60, 0)), Some(note_on(67, 0)));
+    }
+
+    #[test]
+    fn out_of_range_notes_are_clamped() {
+        let mut transform = MidiTransform::new();
+        transform.set_transpose(12);
+
+        assert_eq!(transform.process(note_on(120, 100)), Some(note_on(127, 100)));
+        assert_eq!(transform.process(note_off(120)), Some(note_off(127)));
+    }
+
+    #[test]
+    fn out_of_range_notes_can_be_dropped_along_with_their_note_off() {
+        let mut transform = MidiTransform::new();
+        transform.set_transpose(12);
+        transform.set_out_of_range(OutOfRange::Drop);
+
+        assert_eq!(transform.process(note_on(120, 100)), None);
+        // still dropped, even though it would fit now
+        transform.set_transpose(0);
+        assert_eq!(transform.process(note_off(120)), None);
+        assert_eq!(transform.process(note_off(115)), Some(note_off(115)));
+
+        transform.set_transpose(-12);
+        assert_eq!(transform.process(note_on(11, 100)), None);
+        assert_eq!(transform.process(note_on(12, 100)), Some(note_on(0, 100)));
+    }
+
+    #[test]
+    fn velocity_is_scaled_and_curved() {
+        let mut transform = MidiTransform::new();
+        transform.set_velocity_scale(0.5);
+        assert_eq!(transform.process(note_on(60, 100)), Some(note_on(60, 50)));
+        // never turned into a note off
+        assert_eq!(transform.process(note_on(61, 1)), Some(note_on(61, 1)));
+
+        transform.set_velocity_scale(2.0);
+        assert_eq!(transform.process(note_on(62, 100)), Some(note_on(62, 127)));
+
+        transform.set_velocity_scale(1.0);
+        transform.set_velocity_curve(1.0);
+        assert!(matches!(
+            transform.process(note_on(63, 32)),
+            Some(MidiData::NoteOn { velocity, .. }) if velocity > 32
+        ));
+        // the ends stay put
+        assert_eq!(transform.process(note_on(64, 127)), Some(note_on(64, 127)));
+    }
+}
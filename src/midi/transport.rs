@@ -0,0 +1,114 @@
+use super::{MidiData, SysCommon, SysRt};
+
+/// MIDI clocks per MIDI beat (a 16th note).
+const CLOCKS_PER_MIDI_BEAT: u64 = 6;
+
+/// Folds transport messages (Start/Stop/Continue, clocks and song position pointers) into
+/// whether the transport is playing and where it is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportState {
+    playing: bool,
+    /// Clocks since the start of the song
+    clocks: u64,
+}
+
+impl TransportState {
+    pub fn new() -> TransportState {
+        TransportState::default()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Position in MIDI beats (16th notes), including partial beats.
+    pub fn position(&self) -> f64 {
+        self.clocks as f64 / CLOCKS_PER_MIDI_BEAT as f64
+    }
+
+    /// Position in MIDI clocks (6 per MIDI beat).
+    pub fn position_clocks(&self) -> u64 {
+        self.clocks
+    }
+
+    /// Updates the state with `data`. Anything that doesn't affect the transport is ignored.
+    pub fn process(&mut self, data: &MidiData) {
+        match data {
+            MidiData::SysRt(SysRt::Start) => {
+                self.playing = true;
+                self.clocks = 0;
+            }
+            MidiData::SysRt(SysRt::Continue) => {
+                self.playing = true;
+            }
+            MidiData::SysRt(SysRt::Stop) => {
+                self.playing = false;
+            }
+            MidiData::SysRt(SysRt::MidiClock) if self.playing => {
+                self.clocks += 1;
+            }
+            MidiData::SysRt(SysRt::Reset) => {
+                *self = TransportState::default();
+            }
+            MidiData::SysCommon(SysCommon::SongPositionPointer { position }) => {
+                self.clocks = *position as u64 * CLOCKS_PER_MIDI_BEAT;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clocks(transport: &mut TransportState, count: usize) {
+        for _ in 0..count {
+            transport.process(&MidiData::SysRt(SysRt::MidiClock));
+        }
+    }
+
+    #[test]
+    fn start_clocks_stop_position_continue() {
+        let mut transport = TransportState::new();
+
+        transport.process(&MidiData::SysRt(SysRt::Start));
+        clocks(&mut transport, 15);
+        assert!(transport.is_playing());
+        assert_eq!(transport.position(), 2.5);
+
+        transport.process(&MidiData::SysRt(SysRt::Stop));
+        // clocks keep coming while stopped, but don't move anything
+        clocks(&mut transport, 6);
+        assert!(!transport.is_playing());
+        assert_eq!(transport.position_clocks(), 15);
+
+        transport.process(&MidiData::SysCommon(SysCommon::SongPositionPointer { position: 32 }));
+        assert_eq!(transport.position(), 32.0);
+
+        transport.process(&MidiData::SysRt(SysRt::Continue));
+        clocks(&mut transport, 3);
+        assert!(transport.is_playing());
+        assert_eq!(transport.position(), 32.5);
+    }
+
+    #[test]
+    fn start_goes_back_to_the_beginning() {
+        let mut transport = TransportState::new();
+        transport.process(&MidiData::SysCommon(SysCommon::SongPositionPointer { position: 8 }));
+
+        transport.process(&MidiData::SysRt(SysRt::Start));
+        assert_eq!(transport.position(), 0.0);
+    }
+
+    #[test]
+    fn reset_stops_and_rewinds() {
+        let mut transport = TransportState::new();
+        transport.process(&MidiData::SysRt(SysRt::Start));
+        clocks(&mut transport, 12);
+
+        transport.process(&MidiData::SysRt(SysRt::Reset));
+
+        assert_eq!(transport, TransportState::new());
+    }
+}
@@ -1,7 +1,11 @@
 use core::fmt;
 use std::{
+    collections::VecDeque,
     io,
-    sync::mpsc::{self},
+    sync::{
+        mpsc::{self},
+        Arc, Mutex,
+    },
     thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
@@ -11,7 +15,7 @@ use midir::{
 };
 
 use crate::{
-    midi::{self, parse_midi, MidiData},
+    midi::{parse_midi, MidiData},
     IntermittentSource, TimedValue,
 };
 
@@ -32,12 +36,7 @@ pub fn start_midir_source(
 ) -> Result<(MidiInputConnection<()>, MidirSource), ConnectError<MidiInput>> {
     let (sender, receiver) = mpsc::channel();
 
-    let mut interm = IntermittentSource::new(sender, |buffer, time| {
-        parse_midi(buffer).map(|parsed| TimedValue {
-            since_start: time,
-            value: parsed,
-        })
-    });
+    let mut interm = midi_source(sender);
 
     let start = Instant::now();
 
@@ -54,12 +53,264 @@ pub fn start_midir_source(
         (),
     )?;
 
-    Ok((instance, MidirSource { receiver: receiver }))
+    Ok((instance, MidirSource { receiver }))
+}
+
+fn midi_source(sender: mpsc::Sender<TimedValue<MidiData>>) -> IntermittentSource<u8, MidiData> {
+    IntermittentSource::new(sender, |buffer, time| {
+        parse_midi(buffer).map(|parsed| TimedValue {
+            since_start: time,
+            value: parsed,
+        })
+    })
+}
+
+/// A MIDI input that can be reconnected (say, after the device was unplugged) while `receiver`
+/// keeps going, so downstream code doesn't have to re-subscribe.
+pub struct MidirManagedSource {
+    pub receiver: mpsc::Receiver<TimedValue<MidiData>>,
+    connection: Option<MidiInputConnection<()>>,
+    interm: Arc<Mutex<IntermittentSource<u8, MidiData>>>,
+    start: Instant,
+    name: String,
+    port_name: Option<String>,
+}
+
+impl fmt::Debug for MidirManagedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MidirManagedSource")
+            .field("connected", &self.is_connected())
+            .field("port_name", &self.port_name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MidirManagedSource {
+    /// Whether there's currently an open connection. midir doesn't report a port going away, so
+    /// this only changes through [`MidirManagedSource::disconnect`] and
+    /// [`MidirManagedSource::reconnect`].
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Name of the port last connected to, handy for finding it again.
+    pub fn port_name(&self) -> Option<&str> {
+        self.port_name.as_deref()
+    }
+
+    /// Closes the connection, handing back the device so it can be used to reconnect.
+    pub fn disconnect(&mut self) -> Option<MidiInput> {
+        self.connection.take().map(|connection| connection.close().0)
+    }
+
+    /// Connects to `port`, closing the current connection first. Messages keep flowing into the
+    /// same `receiver`, with timestamps rebased to the new connection.
+    pub fn reconnect(&mut self, device: MidiInput, port: &MidiInputPort) -> Result<(), ConnectError<MidiInput>> {
+        self.disconnect();
+
+        self.port_name = device.port_name(port).ok();
+        self.connection = Some(device.connect(port, &self.name, self.new_input(), ())?);
+
+        Ok(())
+    }
+
+    /// A disconnected source, for [`MidirManagedSource::reconnect`] to connect.
+    fn new(name: &str) -> MidirManagedSource {
+        let (sender, receiver) = mpsc::channel();
+
+        MidirManagedSource {
+            receiver,
+            connection: None,
+            interm: Arc::new(Mutex::new(midi_source(sender))),
+            start: Instant::now(),
+            name: name.to_string(),
+            port_name: None,
+        }
+    }
+
+    /// The input callback for a new connection, which feeds the same `receiver` as before.
+    fn new_input(&self) -> impl FnMut(u64, &[u8], &mut ()) + Send + 'static {
+        // the new connection's timestamps start from scratch
+        self.interm.lock().unwrap().rebase();
+
+        let interm = self.interm.clone();
+        let start = self.start;
+
+        move |stamp, message, _| {
+            interm.lock().unwrap().input_messages(
+                message.iter().copied(),
+                Instant::now() - start,
+                Duration::from_micros(stamp),
+            );
+        }
+    }
+}
+
+/// Same as [`start_midir_source`], but returns a [`MidirManagedSource`] that can be reconnected.
+pub fn start_midir_managed_source(
+    device: MidiInput,
+    port: &MidiInputPort,
+    name: &str,
+) -> Result<MidirManagedSource, ConnectError<MidiInput>> {
+    let mut source = MidirManagedSource::new(name);
+    source.reconnect(device, port)?;
+
+    Ok(source)
+}
+
+/// Several MIDI inputs merged into one stream, ordered by timestamp. Each message is tagged with
+/// the index of the input it came from.
+pub struct MidirAggregateSource {
+    pub receiver: mpsc::Receiver<TimedValue<(usize, MidiData)>>,
+    connections: Vec<MidiInputConnection<()>>,
+}
+
+impl fmt::Debug for MidirAggregateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MidirAggregateSource")
+            .field("inputs", &self.connections.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl MidirAggregateSource {
+    pub fn inputs(&self) -> usize {
+        self.connections.len()
+    }
+}
+
+/// Connects to every `(device, port)` in `inputs` and merges them into one [`MidirAggregateSource`].
+/// Every input's timestamps are rebased against the same start, so they line up. Messages are held
+/// for `reorder_window` before being passed on, which gives a message from one input a chance to
+/// overtake a later message from another.
+pub fn start_midir_aggregate_source(
+    inputs: impl IntoIterator<Item = (MidiInput, MidiInputPort)>,
+    name: &str,
+    reorder_window: Duration,
+) -> Result<MidirAggregateSource, ConnectError<MidiInput>> {
+    let (merge_sender, merge_receiver) = mpsc::channel();
+    let (sender, receiver) = mpsc::channel();
+
+    let start = Instant::now();
+    let mut connections = Vec::new();
+
+    for (i, (device, port)) in inputs.into_iter().enumerate() {
+        let mut interm = aggregate_input(i, merge_sender.clone());
+
+        connections.push(device.connect(
+            &port,
+            name,
+            move |stamp, message, _| {
+                interm.input_messages(
+                    message.iter().copied(),
+                    Instant::now() - start,
+                    Duration::from_micros(stamp),
+                );
+            },
+            (),
+        )?);
+    }
+
+    // the merge thread stops once every connection (and so every sender) is gone
+    drop(merge_sender);
+    thread::spawn(move || merge_inputs(merge_receiver, sender, start, reorder_window));
+
+    Ok(MidirAggregateSource { receiver, connections })
+}
+
+/// Parses input `index`'s messages, tagged with `index`.
+fn aggregate_input(
+    index: usize,
+    sender: mpsc::Sender<TimedValue<(usize, MidiData)>>,
+) -> IntermittentSource<u8, (usize, MidiData)> {
+    IntermittentSource::new(sender, move |buffer, time| {
+        parse_midi(buffer).map(|parsed| TimedValue {
+            since_start: time,
+            value: (index, parsed),
+        })
+    })
+}
+
+fn merge_inputs<T>(
+    merge_receiver: mpsc::Receiver<TimedValue<T>>,
+    sender: mpsc::Sender<TimedValue<T>>,
+    start: Instant,
+    reorder_window: Duration,
+) {
+    // sorted by timestamp
+    let mut pending: VecDeque<TimedValue<T>> = VecDeque::new();
+
+    loop {
+        let received = match pending.front() {
+            Some(oldest) => {
+                let release_in = (oldest.since_start + reorder_window).saturating_sub(start.elapsed());
+
+                merge_receiver.recv_timeout(release_in)
+            }
+            None => merge_receiver.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(value) => {
+                let position = pending
+                    .iter()
+                    .rposition(|other| other.since_start <= value.since_start)
+                    .map_or(0, |i| i + 1);
+
+                pending.insert(position, value);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                // everything's hung up, so there's nothing left to wait for
+                for value in pending {
+                    let _ = sender.send(value);
+                }
+
+                return;
+            }
+        }
+
+        let now = start.elapsed();
+
+        while pending
+            .front()
+            .is_some_and(|oldest| oldest.since_start + reorder_window <= now)
+        {
+            if sender.send(pending.pop_front().unwrap()).is_err() {
+                return; // looks like the channel hung up
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct MidirSink {
     pub sender: mpsc::Sender<MidiData>,
+    /// Errors from writing to the port
+    errors: mpsc::Receiver<io::Error>,
+}
+
+impl MidirSink {
+    /// Returns the oldest send error that hasn't been taken yet. If the port went away, the sink's
+    /// thread stops after reporting it, and sending any more messages will fail.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.errors.try_recv().ok()
+    }
+
+    /// Stops the sink's thread (which sends anything still queued first), and waits for it to
+    /// finish. `handle` is the one returned along with this sink. Returns the first send error
+    /// that wasn't already taken, if any.
+    pub fn stop(self, handle: JoinHandle<()>) -> io::Result<()> {
+        let MidirSink { sender, errors } = self;
+
+        drop(sender);
+        let _ = handle.join();
+
+        match errors.try_recv() {
+            Ok(err) => Err(err),
+            Err(_) => Ok(()),
+        }
+    }
 }
 
 struct MidiOutputConnectionWrapper(MidiOutputConnection);
@@ -70,7 +321,7 @@ impl io::Write for MidiOutputConnectionWrapper {
             Ok(()) => Ok(buffer.len()),
             Err(err) => match err {
                 midir::SendError::InvalidData(msg) => Err(io::Error::new(io::ErrorKind::InvalidData, msg)),
-                midir::SendError::Other(msg) => Err(io::Error::new(io::ErrorKind::Other, msg)),
+                midir::SendError::Other(msg) => Err(io::Error::other(msg)),
             },
         }
     }
@@ -85,16 +336,282 @@ pub fn start_midir_sink(
     port: &MidiOutputPort,
     name: &str,
 ) -> Result<(JoinHandle<()>, MidirSink), ConnectError<MidiOutput>> {
-    let (sender, receiver) = mpsc::channel();
+    let conn_out = device.connect(port, name)?;
 
-    let mut conn_out = MidiOutputConnectionWrapper(device.connect(port, name)?);
+    Ok(spawn_sink(MidiOutputConnectionWrapper(conn_out)))
+}
+
+fn spawn_sink(mut conn_out: impl io::Write + Send + 'static) -> (JoinHandle<()>, MidirSink) {
+    let (sender, receiver) = mpsc::channel();
+    let (error_sender, errors) = mpsc::channel();
 
-    Ok((
+    (
         thread::spawn(move || {
             while let Ok(message) = receiver.recv() {
-                let _ = midi::write_midi_bytes(&message, &mut conn_out);
+                // in one go, since each write is sent as a message of its own
+                if let Err(err) = conn_out.write(&MidiData::to_smallvec(&message)) {
+                    // invalid data is just that message, anything else means the port is in trouble
+                    let fatal = err.kind() != io::ErrorKind::InvalidData;
+                    let _ = error_sender.send(err);
+
+                    if fatal {
+                        break;
+                    }
+                }
             }
         }),
-        MidirSink { sender },
-    ))
+        MidirSink { sender, errors },
+    )
+}
+
+/// Same as [`start_midir_source`], but creates a virtual port called `name` for other
+/// applications to connect to, instead of connecting to an existing port.
+#[cfg(all(feature = "virtual_ports", unix))]
+pub fn start_midir_virtual_source(
+    device: MidiInput,
+    name: &str,
+) -> Result<(MidiInputConnection<()>, MidirSource), ConnectError<MidiInput>> {
+    use midir::os::unix::VirtualInput;
+
+    let (sender, receiver) = mpsc::channel();
+
+    let mut interm = midi_source(sender);
+
+    let start = Instant::now();
+
+    let instance = device.create_virtual(
+        name,
+        move |stamp, message, _| {
+            interm.input_messages(
+                message.iter().copied(),
+                Instant::now() - start,
+                Duration::from_micros(stamp),
+            );
+        },
+        (),
+    )?;
+
+    Ok((instance, MidirSource { receiver }))
+}
+
+/// Same as [`start_midir_sink`], but creates a virtual port called `name` for other applications
+/// to connect to, instead of connecting to an existing port.
+#[cfg(all(feature = "virtual_ports", unix))]
+pub fn start_midir_virtual_sink(
+    device: MidiOutput,
+    name: &str,
+) -> Result<(JoinHandle<()>, MidirSink), ConnectError<MidiOutput>> {
+    use midir::os::unix::VirtualOutput;
+
+    let conn_out = device.create_virtual(name)?;
+
+    Ok(spawn_sink(MidiOutputConnectionWrapper(conn_out)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(note: u8) -> MidiData {
+        MidiData::NoteOn {
+            channel: 0,
+            note,
+            velocity: 100,
+        }
+    }
+
+    #[test]
+    fn messages_keep_flowing_after_a_reconnect() {
+        let source = MidirManagedSource::new("test");
+
+        let mut input = source.new_input();
+        input(5_000_000, &[0x90, 60, 100], &mut ());
+        input(5_001_000, &[0x90, 61, 100], &mut ());
+        drop(input);
+
+        // the device came back with its clock started over
+        thread::sleep(Duration::from_millis(10));
+        let mut input = source.new_input();
+        input(0, &[0x90, 62, 100], &mut ());
+        input(1_000, &[0x90, 63, 100], &mut ());
+
+        let received: Vec<_> = source.receiver.try_iter().collect();
+        assert_eq!(
+            received.iter().map(|timed| timed.value.clone()).collect::<Vec<_>>(),
+            [note_on(60), note_on(61), note_on(62), note_on(63)]
+        );
+
+        // and the timestamps carry on from where they were, instead of jumping back
+        let stamps: Vec<_> = received.iter().map(|timed| timed.since_start).collect();
+        assert_eq!(stamps[1] - stamps[0], Duration::from_millis(1));
+        assert!(stamps[2] >= stamps[0] + Duration::from_millis(10));
+        assert_eq!(stamps[3] - stamps[2], Duration::from_millis(1));
+    }
+
+    #[test]
+    fn new_source_starts_disconnected() {
+        let mut source = MidirManagedSource::new("test");
+
+        assert!(!source.is_connected());
+        assert_eq!(source.port_name(), None);
+        assert!(source.disconnect().is_none());
+    }
+
+    #[test]
+    fn aggregate_interleaves_inputs_by_timestamp() {
+        let (merge_sender, merge_receiver) = mpsc::channel();
+        let (sender, receiver) = mpsc::channel();
+
+        let mut first = aggregate_input(0, merge_sender.clone());
+        let mut second = aggregate_input(1, merge_sender);
+
+        // each device has a clock of its own, which gets lined up with the stream's
+        first.input_messages([0x90, 60, 100], Duration::ZERO, Duration::from_secs(100));
+        first.input_messages(
+            [0x90, 61, 100],
+            Duration::from_millis(1),
+            Duration::from_millis(100_020),
+        );
+        second.input_messages([0x90, 70, 100], Duration::from_millis(5), Duration::from_secs(7));
+        second.input_messages([0x90, 71, 100], Duration::from_millis(6), Duration::from_millis(7_010));
+        second.input_messages([0x90, 72, 100], Duration::from_millis(7), Duration::from_millis(7_030));
+        drop((first, second));
+
+        // a long window, so everything is held until the inputs hang up
+        merge_inputs(merge_receiver, sender, Instant::now(), Duration::from_secs(60));
+
+        let merged: Vec<_> = receiver
+            .try_iter()
+            .map(|timed| {
+                (
+                    timed.since_start.as_millis(),
+                    timed.value.0,
+                    timed.value.1.note().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(merged, [(0, 0, 60), (5, 1, 70), (15, 1, 71), (20, 0, 61), (35, 1, 72)]);
+    }
+
+    /// Records what's written, and fails with the given error while `failing` is set. Clones
+    /// share everything.
+    #[derive(Clone, Default)]
+    struct MockPort {
+        written: Arc<Mutex<Vec<Vec<u8>>>>,
+        failing: Arc<Mutex<Option<io::ErrorKind>>>,
+    }
+
+    impl MockPort {
+        fn written(&self) -> Vec<Vec<u8>> {
+            self.written.lock().unwrap().clone()
+        }
+
+        fn fail_with(&self, kind: Option<io::ErrorKind>) {
+            *self.failing.lock().unwrap() = kind;
+        }
+    }
+
+    impl io::Write for MockPort {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            if let Some(kind) = *self.failing.lock().unwrap() {
+                return Err(io::Error::from(kind));
+            }
+
+            self.written.lock().unwrap().push(buffer.to_vec());
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn wait_for_error(sink: &MidirSink) -> io::Error {
+        loop {
+            match sink.take_error() {
+                Some(err) => return err,
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+    }
+
+    #[test]
+    fn sink_writes_each_message_in_one_go() {
+        let port = MockPort::default();
+        let (handle, sink) = spawn_sink(port.clone());
+
+        sink.sender.send(note_on(60)).unwrap();
+        sink.sender.send(MidiData::SysRt(crate::midi::SysRt::Start)).unwrap();
+        sink.stop(handle).unwrap();
+
+        assert_eq!(port.written(), [vec![0x90, 60, 100], vec![0xFA]]);
+    }
+
+    #[test]
+    fn write_error_after_the_port_closes_is_observable() {
+        let port = MockPort::default();
+        let (handle, sink) = spawn_sink(port.clone());
+
+        port.fail_with(Some(io::ErrorKind::BrokenPipe));
+        sink.sender.send(note_on(60)).unwrap();
+
+        assert_eq!(wait_for_error(&sink).kind(), io::ErrorKind::BrokenPipe);
+
+        // the thread stops once the port's gone
+        handle.join().unwrap();
+        assert!(sink.sender.send(note_on(61)).is_err());
+        assert!(sink.take_error().is_none());
+        assert!(port.written().is_empty());
+    }
+
+    #[test]
+    fn stop_returns_an_error_that_wasnt_taken() {
+        let port = MockPort::default();
+        let (handle, sink) = spawn_sink(port.clone());
+
+        port.fail_with(Some(io::ErrorKind::BrokenPipe));
+        sink.sender.send(note_on(60)).unwrap();
+
+        assert_eq!(sink.stop(handle).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn invalid_data_doesnt_stop_the_sink() {
+        let port = MockPort::default();
+        let (handle, sink) = spawn_sink(port.clone());
+
+        port.fail_with(Some(io::ErrorKind::InvalidData));
+        sink.sender.send(note_on(60)).unwrap();
+        assert_eq!(wait_for_error(&sink).kind(), io::ErrorKind::InvalidData);
+
+        port.fail_with(None);
+        sink.sender.send(note_on(61)).unwrap();
+
+        sink.stop(handle).unwrap();
+        assert_eq!(port.written(), [vec![0x90, 61, 100]]);
+    }
+
+    #[test]
+    fn source_stamps_follow_the_device_after_rebasing() {
+        let (sender, receiver) = mpsc::channel();
+        let mut source = midi_source(sender);
+
+        source.input_messages([0x90, 60, 100], Duration::from_millis(2), Duration::from_secs(1));
+        // the device's stamps are what count, not when the callback happened to run
+        source.input_messages([0x90, 61, 100], Duration::from_millis(30), Duration::from_millis(1_005));
+
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [
+                TimedValue {
+                    since_start: Duration::from_millis(2),
+                    value: note_on(60),
+                },
+                TimedValue {
+                    since_start: Duration::from_millis(7),
+                    value: note_on(61),
+                },
+            ]
+        );
+    }
 }
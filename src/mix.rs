@@ -0,0 +1,102 @@
+//! Channel up/down-mixing for the stream adapters.
+//!
+//! A [`ChannelMixer`] turns frames with one channel count into frames with another, using a plain
+//! mix matrix. See [`StreamSink::set_channel_mixer`](crate::StreamSink::set_channel_mixer) and
+//! [`StreamSource::set_channel_mixer`](crate::StreamSource::set_channel_mixer).
+
+use nalgebra::DMatrix;
+
+/// Maps frames of `in_channels` samples to frames of `out_channels` samples. Each output channel
+/// is a weighted sum of the input channels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMixer {
+    /// One row per output channel, one column per input channel
+    matrix: DMatrix<f32>,
+}
+
+impl ChannelMixer {
+    /// Creates a mixer from a matrix with one row per output channel and one column per input
+    /// channel. Panics if either dimension is 0.
+    pub fn new(matrix: DMatrix<f32>) -> ChannelMixer {
+        assert!(
+            matrix.nrows() > 0 && matrix.ncols() > 0,
+            "a mix matrix needs at least one input and one output channel"
+        );
+
+        ChannelMixer { matrix }
+    }
+
+    /// Copies a mono input to every one of `out_channels`.
+    pub fn duplicate(out_channels: usize) -> ChannelMixer {
+        ChannelMixer::new(DMatrix::from_element(out_channels, 1, 1.0))
+    }
+
+    /// Averages `in_channels` down to mono.
+    pub fn downmix(in_channels: usize) -> ChannelMixer {
+        ChannelMixer::new(DMatrix::from_element(1, in_channels, 1.0 / in_channels as f32))
+    }
+
+    pub fn in_channels(&self) -> usize {
+        self.matrix.ncols()
+    }
+
+    pub fn out_channels(&self) -> usize {
+        self.matrix.nrows()
+    }
+
+    pub fn matrix(&self) -> &DMatrix<f32> {
+        &self.matrix
+    }
+
+    /// Mixes one frame. `frame_in` must be `in_channels` long and `frame_out` `out_channels` long.
+    pub fn mix(&self, frame_in: &[f32], frame_out: &mut [f32]) {
+        debug_assert_eq!(frame_in.len(), self.in_channels());
+        debug_assert_eq!(frame_out.len(), self.out_channels());
+
+        for (out_i, sample_out) in frame_out.iter_mut().enumerate() {
+            *sample_out = frame_in
+                .iter()
+                .enumerate()
+                .map(|(in_i, sample_in)| self.matrix[(out_i, in_i)] * sample_in)
+                .sum();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_copies_mono_everywhere() {
+        let mut out = [0.0; 3];
+        ChannelMixer::duplicate(3).mix(&[0.5], &mut out);
+
+        assert_eq!(out, [0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn downmix_averages() {
+        let mut out = [0.0];
+        ChannelMixer::downmix(2).mix(&[0.2, 0.6], &mut out);
+
+        assert!((out[0] - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn custom_matrix_weights_each_input() {
+        // swap left and right, with a bit of left bleeding into the new left
+        let mixer = ChannelMixer::new(DMatrix::from_row_slice(2, 2, &[0.5, 1.0, 1.0, 0.0]));
+        let mut out = [0.0; 2];
+        mixer.mix(&[1.0, 2.0], &mut out);
+
+        assert_eq!((mixer.in_channels(), mixer.out_channels()), (2, 2));
+        assert_eq!(out, [2.5, 1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one input and one output channel")]
+    fn empty_matrix_panics() {
+        ChannelMixer::new(DMatrix::zeros(0, 2));
+    }
+}
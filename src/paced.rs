@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use crate::{Clock, MonotonicClock};
+
+/// Wraps the producing half of a ring, and blocks after each push for however long it takes real
+/// time to catch up with the frames pushed so far, at a nominal sample rate. This stands in for an
+/// audio callback when feeding a [`StreamSink`](crate::StreamSink) from a plain thread (e.g. in
+/// tests or examples).
+///
+/// Timing is measured from the first push, and sleeps are worked out from the total, so rounding
+/// in the sleeps doesn't add up over time. Time comes from a [`Clock`] (real time unless
+/// [`PacedProducer::with_clock`] says otherwise).
+pub struct PacedProducer<C = MonotonicClock> {
+    ring_out: rtrb::Producer<f32>,
+    channels: usize,
+    sample_rate: f64,
+    clock: C,
+    /// When the first push happened, according to `clock`
+    start: Option<Duration>,
+    /// Frames offered so far (whether or not they fit)
+    frames_pushed: u64,
+    /// Frames that didn't fit in the ring
+    frames_dropped: u64,
+}
+
+impl PacedProducer {
+    /// * `ring_out` - the `Producer` half of a `rtrb` ring buffer (interleaved)
+    /// * `channels` - the number of channels
+    /// * `sample_rate` - how many frames per second to push
+    pub fn new(ring_out: rtrb::Producer<f32>, channels: usize, sample_rate: f64) -> PacedProducer {
+        PacedProducer::with_clock(ring_out, channels, sample_rate, MonotonicClock::new())
+    }
+}
+
+impl<C: Clock> PacedProducer<C> {
+    /// Same as [`PacedProducer::new`], but paced against `clock` (e.g. a
+    /// [`MockClock`](crate::MockClock), to run without really sleeping).
+    pub fn with_clock(ring_out: rtrb::Producer<f32>, channels: usize, sample_rate: f64, clock: C) -> PacedProducer<C> {
+        assert!(channels > 0, "there must be at least one channel");
+        assert!(sample_rate > 0.0, "sample rate must be positive");
+
+        PacedProducer {
+            ring_out,
+            channels,
+            sample_rate,
+            clock,
+            start: None,
+            frames_pushed: 0,
+            frames_dropped: 0,
+        }
+    }
+
+    /// Pushes as many whole frames of `samples` (interleaved) as fit in the ring, then blocks until
+    /// they're due at the nominal rate. Frames that don't fit are dropped, but still count towards
+    /// the pacing, so a full ring doesn't make the producer rush. Returns how many frames fit.
+    pub fn push(&mut self, samples: &[f32]) -> usize {
+        debug_assert_eq!(samples.len() % self.channels, 0);
+
+        let start = *self.start.get_or_insert_with(|| self.clock.now());
+
+        let frames = samples.len() / self.channels;
+        let accepted = frames.min(self.ring_out.slots() / self.channels);
+
+        if let Ok(chunk) = self.ring_out.write_chunk_uninit(accepted * self.channels) {
+            chunk.fill_from_iter(samples.iter().copied());
+        }
+
+        self.frames_pushed += frames as u64;
+        self.frames_dropped += (frames - accepted) as u64;
+
+        let due = Duration::from_secs_f64(self.frames_pushed as f64 / self.sample_rate);
+        let elapsed = self.clock.now().saturating_sub(start);
+        if due > elapsed {
+            self.clock.sleep(due - elapsed);
+        }
+
+        accepted
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The rate this was asked to push at, in frames per second.
+    pub fn nominal_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// The rate frames have actually been pushed at since the first push, in frames per second.
+    /// Mostly useful for checking that the thread is keeping up.
+    pub fn achieved_rate(&self) -> f64 {
+        let Some(start) = self.start else {
+            return 0.0;
+        };
+
+        let elapsed = self.clock.now().saturating_sub(start).as_secs_f64();
+        if elapsed > 0.0 {
+            self.frames_pushed as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// How many frames have been pushed (or dropped) so far.
+    pub fn frames_pushed(&self) -> u64 {
+        self.frames_pushed
+    }
+
+    /// How many frames were dropped because the ring was full.
+    pub fn frames_dropped(&self) -> u64 {
+        self.frames_dropped
+    }
+
+    pub fn ring_out(&mut self) -> &mut rtrb::Producer<f32> {
+        &mut self.ring_out
+    }
+
+    pub fn into_inner(self) -> rtrb::Producer<f32> {
+        self.ring_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rtrb::RingBuffer;
+
+    use super::*;
+    use crate::MockClock;
+
+    #[test]
+    fn one_second_paces_about_sample_rate_frames() {
+        let (producer, mut consumer) = RingBuffer::new(4096);
+        let clock = MockClock::new();
+        let mut paced = PacedProducer::with_clock(producer, 2, 48_000.0, clock.clone());
+
+        // blocks of 441 frames, which don't divide a second evenly
+        while clock.now() < Duration::from_secs(1) {
+            paced.push(&[0.0; 882]);
+            while consumer.pop().is_ok() {}
+        }
+
+        assert!(
+            paced.frames_pushed().abs_diff(48_000) <= 441,
+            "{}",
+            paced.frames_pushed()
+        );
+        assert!(
+            (paced.achieved_rate() - 48_000.0).abs() < 1.0,
+            "{}",
+            paced.achieved_rate()
+        );
+        assert_eq!(paced.nominal_rate(), 48_000.0);
+        assert_eq!(paced.frames_dropped(), 0);
+    }
+
+    #[test]
+    fn full_ring_drops_frames_without_rushing() {
+        let (producer, _consumer) = RingBuffer::new(100);
+        let clock = MockClock::new();
+        let mut paced = PacedProducer::with_clock(producer, 1, 1000.0, clock.clone());
+
+        assert_eq!(paced.push(&[0.0; 80]), 80);
+        assert_eq!(paced.push(&[0.0; 80]), 20);
+
+        assert_eq!(paced.frames_dropped(), 60);
+        // the dropped frames still took their time
+        assert_eq!(clock.now(), Duration::from_millis(160));
+    }
+
+    #[test]
+    fn real_time_isnt_outrun() {
+        let (producer, mut consumer) = RingBuffer::new(1024);
+        let mut paced = PacedProducer::new(producer, 1, 10_000.0);
+        let start = std::time::Instant::now();
+
+        for _ in 0..20 {
+            paced.push(&[0.0; 100]);
+            while consumer.pop().is_ok() {}
+        }
+
+        // 2000 frames at 10kHz
+        assert!(start.elapsed() >= Duration::from_millis(190), "{:?}", start.elapsed());
+    }
+}
@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use crate::{StreamMapper, TimedValue};
+
+const SECS_PER_MINUTE: f64 = 60.0;
+
+/// Snaps timestamps to a tempo grid, optionally with swing.
+///
+/// Swing moves every odd step of the grid (the off-beats) later by a fraction of a step, or earlier
+/// if it's negative.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantizer {
+    grid: Duration,
+    swing: f64,
+}
+
+impl Quantizer {
+    /// A grid of `steps_per_beat` steps per beat at `bpm`, e.g. `Quantizer::new(120.0, 4)` for 16th
+    /// notes at 120 BPM.
+    pub fn new(bpm: f64, steps_per_beat: u32) -> Quantizer {
+        assert!(bpm > 0.0, "tempo must be positive");
+        assert!(steps_per_beat > 0, "there must be at least one step per beat");
+
+        Quantizer::with_grid(Duration::from_secs_f64(SECS_PER_MINUTE / bpm / steps_per_beat as f64))
+    }
+
+    /// A grid with steps `grid` apart.
+    pub fn with_grid(grid: Duration) -> Quantizer {
+        assert!(!grid.is_zero(), "grid steps can't be zero length");
+
+        Quantizer { grid, swing: 0.0 }
+    }
+
+    /// Sets how far odd steps are pushed, as a fraction of a step. Has to be strictly between -1
+    /// and 1, so swung steps never pass their neighbours.
+    pub fn set_swing(&mut self, swing: f64) {
+        assert!(swing > -1.0 && swing < 1.0, "swing must be between -1 and 1");
+
+        self.swing = swing;
+    }
+
+    pub fn swing(&self) -> f64 {
+        self.swing
+    }
+
+    pub fn grid(&self) -> Duration {
+        self.grid
+    }
+
+    /// The step nearest to `time`. Halfway between two steps rounds to the later one, and a time
+    /// exactly on a step stays on it.
+    fn nearest_step(&self, time: Duration) -> u128 {
+        let grid = self.grid.as_nanos();
+
+        (time.as_nanos() + grid / 2) / grid
+    }
+
+    /// Offsets `time` by the swing of `step`.
+    fn swing_offset(&self, step: u128, time: Duration) -> Duration {
+        if step.is_multiple_of(2) || self.swing == 0.0 {
+            return time;
+        }
+
+        let offset = self.grid.mul_f64(self.swing.abs());
+        if self.swing > 0.0 {
+            time + offset
+        } else {
+            time.saturating_sub(offset)
+        }
+    }
+
+    /// Snaps `time` to the nearest step, then applies swing.
+    pub fn quantize(&self, time: Duration) -> Duration {
+        let step = self.nearest_step(time);
+        let snapped = Duration::from_nanos(u64::try_from(step * self.grid.as_nanos()).unwrap_or(u64::MAX));
+
+        self.swing_offset(step, snapped)
+    }
+
+    /// Only applies swing, leaving `time` where it is relative to its nearest step. Note that with
+    /// negative swing this can move an event before one that came just ahead of it.
+    pub fn apply_swing(&self, time: Duration) -> Duration {
+        self.swing_offset(self.nearest_step(time), time)
+    }
+
+    /// Same as [`Quantizer::quantize`], for a whole value.
+    pub fn quantize_value<T>(&self, value: TimedValue<T>) -> TimedValue<T> {
+        TimedValue {
+            since_start: self.quantize(value.since_start),
+            value: value.value,
+        }
+    }
+
+    /// A mapping stage that quantizes each incoming value. Quantizing can't swap two values around,
+    /// but the output is also clamped so it never goes backwards in time (which can happen if the
+    /// input wasn't in order to begin with).
+    pub fn mapper<T>(self) -> StreamMapper<TimedValue<T>, T>
+    where
+        T: Send + 'static,
+    {
+        let mut last = Duration::ZERO;
+
+        StreamMapper::new(move |values_in, _| {
+            let value = self.quantize_value(values_in.pop_front()?);
+            last = last.max(value.since_start);
+
+            Some(TimedValue {
+                since_start: last,
+                value: value.value,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// 16th notes at 120 BPM, so steps are 125ms apart.
+    fn sixteenths() -> Quantizer {
+        Quantizer::new(120.0, 4)
+    }
+
+    #[test]
+    fn slightly_off_notes_snap_to_sixteenths() {
+        let quantizer = sixteenths();
+        assert_eq!(quantizer.grid(), ms(125));
+
+        for (played, expected) in [(3, 0), (118, 125), (260, 250), (371, 375), (505, 500)] {
+            assert_eq!(quantizer.quantize(ms(played)), ms(expected), "played at {played}ms");
+        }
+    }
+
+    #[test]
+    fn boundaries_stay_put_and_halfway_rounds_up() {
+        let quantizer = sixteenths();
+
+        assert_eq!(quantizer.quantize(ms(250)), ms(250));
+        assert_eq!(quantizer.quantize(Duration::from_micros(62_500)), ms(125));
+        assert_eq!(quantizer.quantize(Duration::from_micros(62_499)), ms(0));
+    }
+
+    #[test]
+    fn swing_only_moves_off_beats() {
+        let mut quantizer = sixteenths();
+        quantizer.set_swing(0.2);
+
+        assert_eq!(quantizer.quantize(ms(120)), ms(150));
+        assert_eq!(quantizer.quantize(ms(255)), ms(250));
+
+        // swing without quantizing keeps the player's timing
+        assert_eq!(quantizer.apply_swing(ms(130)), ms(155));
+    }
+
+    #[test]
+    fn mapper_never_goes_backwards() {
+        let mut quantizer = sixteenths();
+        quantizer.set_swing(-0.5);
+        let mut mapper = quantizer.mapper();
+
+        // the second one came in late, and would land before the first
+        for (time, value) in [(240, 'a'), (130, 'b'), (370, 'c')] {
+            mapper.values_in.push_back(TimedValue {
+                since_start: ms(time),
+                value,
+            });
+        }
+
+        let stamps: Vec<_> = std::iter::from_fn(|| mapper.step(Duration::ZERO))
+            .map(|timed| (timed.since_start, timed.value))
+            .collect();
+
+        assert_eq!(
+            stamps,
+            [(ms(250), 'a'), (ms(250), 'b'), (Duration::from_micros(312_500), 'c')]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "swing must be between -1 and 1")]
+    fn full_step_of_swing_is_rejected() {
+        sixteenths().set_swing(1.0);
+    }
+}
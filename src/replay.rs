@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use crate::TimedValue;
+
+/// Records a stream of [`TimedValue`]s into memory, for replaying later with a [`Player`].
+/// Timestamps are stored relative to the first recorded value, so a recording always starts at 0.
+#[derive(Debug, Clone)]
+pub struct Recorder<T> {
+    values: Vec<TimedValue<T>>,
+    first: Option<Duration>,
+}
+
+impl<T> Default for Recorder<T> {
+    fn default() -> Self {
+        Recorder {
+            values: Vec::new(),
+            first: None,
+        }
+    }
+}
+
+impl<T> Recorder<T> {
+    pub fn new() -> Recorder<T> {
+        Recorder::default()
+    }
+
+    /// Adds `value` to the recording. Values are expected in order; one stamped before the first
+    /// recorded value is clamped to the start.
+    pub fn record(&mut self, value: TimedValue<T>) {
+        let first = *self.first.get_or_insert(value.since_start);
+
+        self.values.push(TimedValue {
+            since_start: value.since_start.saturating_sub(first),
+            value: value.value,
+        });
+    }
+
+    pub fn values(&self) -> &[TimedValue<T>] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Time from the first recorded value to the last.
+    pub fn duration(&self) -> Duration {
+        self.values.last().map_or(Duration::ZERO, |value| value.since_start)
+    }
+
+    pub fn into_values(self) -> Vec<TimedValue<T>> {
+        self.values
+    }
+
+    pub fn into_player(self) -> Player<T> {
+        Player::new(self.values)
+    }
+}
+
+/// Plays back a recording (from a [`Recorder`], or any list of values in order) with the same
+/// relative timing. Nothing happens on its own: call [`Player::poll`] regularly, and everything
+/// that has come due is handed back, restamped relative to when playback started.
+#[derive(Debug, Clone)]
+pub struct Player<T> {
+    values: Vec<TimedValue<T>>,
+    /// Index of the next value to play
+    next: usize,
+    /// When playback started (in the same timebase as `now` in `poll`)
+    start: Option<Duration>,
+}
+
+impl<T> Player<T> {
+    pub fn new(values: Vec<TimedValue<T>>) -> Player<T> {
+        Player {
+            values,
+            next: 0,
+            start: None,
+        }
+    }
+
+    /// Starts playback at `now`. Otherwise it starts at the first [`Player::poll`].
+    pub fn start(&mut self, now: Duration) {
+        self.start = Some(now);
+    }
+
+    /// When the next value is due, or `None` if playback hasn't started or is finished.
+    pub fn next_due(&self) -> Option<Duration> {
+        Some(self.start? + self.values.get(self.next)?.since_start)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.values.len()
+    }
+
+    /// Goes back to the beginning. Playback starts again at the next [`Player::start`] or
+    /// [`Player::poll`].
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.start = None;
+    }
+}
+
+impl<T: Clone> Player<T> {
+    /// Returns the next value that's due as of `now`, or `None` if there isn't one (yet). Call this
+    /// until it returns `None` to catch up.
+    pub fn poll(&mut self, now: Duration) -> Option<TimedValue<T>> {
+        let start = *self.start.get_or_insert(now);
+        let value = self.values.get(self.next)?;
+        let due = start + value.since_start;
+
+        if due > now {
+            return None;
+        }
+
+        self.next += 1;
+
+        Some(TimedValue {
+            since_start: due,
+            value: value.value.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Three events, the first of them 1s into the stream being recorded.
+    fn recording() -> Recorder<&'static str> {
+        let mut recorder = Recorder::new();
+        for (time, value) in [(1000, "a"), (1100, "b"), (1350, "c")] {
+            recorder.record(TimedValue {
+                since_start: ms(time),
+                value,
+            });
+        }
+
+        recorder
+    }
+
+    #[test]
+    fn recording_starts_at_zero() {
+        let recorder = recording();
+        let stamps: Vec<_> = recorder.values().iter().map(|timed| timed.since_start).collect();
+
+        assert_eq!(stamps, [ms(0), ms(100), ms(350)]);
+        assert_eq!(recorder.duration(), ms(350));
+        assert_eq!(recorder.len(), 3);
+    }
+
+    #[test]
+    fn player_emits_at_the_recorded_offsets() {
+        let mut player = recording().into_player();
+        player.start(ms(5000));
+
+        // polled every 10ms
+        let mut played = Vec::new();
+        for now in (5000..5500).step_by(10).map(ms) {
+            while let Some(timed) = player.poll(now) {
+                played.push((now, timed));
+            }
+        }
+
+        let played: Vec<_> = played
+            .into_iter()
+            .map(|(polled_at, timed)| (polled_at, timed.since_start, timed.value))
+            .collect();
+        assert_eq!(
+            played,
+            [
+                (ms(5000), ms(5000), "a"),
+                (ms(5100), ms(5100), "b"),
+                (ms(5350), ms(5350), "c")
+            ]
+        );
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn late_poll_catches_up_with_the_original_stamps() {
+        let mut player = recording().into_player();
+
+        // starts at the first poll
+        assert_eq!(player.poll(ms(200)).map(|timed| timed.value), Some("a"));
+        assert_eq!(player.next_due(), Some(ms(300)));
+
+        let caught_up: Vec<_> = std::iter::from_fn(|| player.poll(ms(1000)))
+            .map(|timed| timed.since_start)
+            .collect();
+        assert_eq!(caught_up, [ms(300), ms(550)]);
+        assert_eq!(player.next_due(), None);
+    }
+
+    #[test]
+    fn rewind_plays_it_again() {
+        let mut player = recording().into_player();
+        while player.poll(ms(1000)).is_some() {}
+
+        player.rewind();
+        assert!(!player.is_finished());
+        assert_eq!(player.poll(ms(50)).map(|timed| timed.since_start), Some(ms(50)));
+    }
+}
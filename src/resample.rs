@@ -1,7 +1,115 @@
-use std::ops::IndexMut;
+//! Interpolation kernels, and the resampler built on them.
+//!
+//! Without the `std` feature only the single-channel kernels are available ([`resample`],
+//! [`resample_with`], [`resample_sinc`], and friends); [`Resampler`] and [`convert_slice`] need
+//! `std`.
 
+use alloc::vec::Vec;
+use core::{
+    f64::consts::PI,
+    ops::{Index, IndexMut},
+};
+
+#[cfg(feature = "std")]
+use nalgebra::{DMatrix, DMatrixView};
+
+use self::math::{cos, sin};
+
+/// `core` doesn't have trig functions, so they come from `libm` without `std`.
+mod math {
+    #[cfg(feature = "std")]
+    pub fn sin(x: f64) -> f64 {
+        x.sin()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn cos(x: f64) -> f64 {
+        x.cos()
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub use libm::{cos, sin};
+}
+
+/// How many frames of history the default (Hermite) interpolation uses.
 pub const FRAME_LOOKBACK: usize = 4;
+/// Default number of callbacks the stream types average the ring fill over (see
+/// [`StreamSink::set_rolling_avg_length`](crate::StreamSink::set_rolling_avg_length)).
 pub const ROLLING_AVG_LENGTH: usize = 8;
+/// The highest resample ratio the stream types will use. Scratch space is sized based on this.
+pub const MAX_RESAMPLE_RATIO: f64 = 8.0;
+
+/// Which kernel to interpolate between samples with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Straight line between the two nearest points. The cheapest option (2 frames of history), but
+    /// it dulls the highs a little.
+    Linear,
+    /// 4-point cubic Hermite. Cheap, and plenty for clock compensation.
+    #[default]
+    Hermite,
+    /// Blackman-windowed sinc over `taps` points (must be even and at least 2). More expensive, but
+    /// has much less distortion for larger ratio changes.
+    ///
+    /// The kernel is sampled at `oversample` phases between each pair of input samples (at least 1),
+    /// and the weights for a given time are interpolated between the two nearest phases. A
+    /// [`Resampler`] precomputes these into a [`SincTable`] when it's created, so 256 or so phases
+    /// don't cost anything at runtime beyond the memory. It also moves the cutoff down to follow
+    /// the output's Nyquist frequency when downsampling, recomputing the table whenever the ratio
+    /// changes by more than a couple percent.
+    Sinc { taps: usize, oversample: usize },
+}
+
+impl Interpolation {
+    /// How many frames of history this kernel needs.
+    pub fn lookback(&self) -> usize {
+        match self {
+            Interpolation::Linear => 2,
+            Interpolation::Hermite => FRAME_LOOKBACK,
+            Interpolation::Sinc { taps, .. } => *taps,
+        }
+    }
+
+    /// Computes the weight of each value in the history when interpolating `t` of the way between
+    /// the two middle values, so that the result is the dot product of `weights` and the history.
+    /// `weights` must be [`Interpolation::lookback`] long.
+    pub fn weights(&self, t: f32, weights: &mut [f32]) {
+        debug_assert_eq!(weights.len(), self.lookback());
+
+        match self {
+            Interpolation::Linear => {
+                weights[0] = 1.0 - t;
+                weights[1] = t;
+            }
+            Interpolation::Hermite => {
+                // the same Catmull-Rom spline as `hermite_interpolate`, split up by input
+                let t2 = t * t;
+                let t3 = t2 * t;
+
+                weights[0] = 0.5 * (-t3 + 2.0 * t2 - t);
+                weights[1] = 0.5 * (3.0 * t3 - 5.0 * t2 + 2.0);
+                weights[2] = 0.5 * (-3.0 * t3 + 4.0 * t2 + t);
+                weights[3] = 0.5 * (t3 - t2);
+            }
+            Interpolation::Sinc { taps, oversample } => {
+                let phases = SincPhases::new(*taps, *oversample, t);
+
+                for (i, weight) in weights.iter_mut().enumerate() {
+                    *weight = phases.weight(i) as f32;
+                }
+            }
+        }
+    }
+
+    /// Interpolates between the two middle values of `last`, `t` of the way.
+    pub fn interpolate(&self, last: &impl Index<usize, Output = f32>, t: f32) -> f32 {
+        match self {
+            Interpolation::Linear => last[0] + (last[1] - last[0]) * t,
+            Interpolation::Hermite => hermite_interpolate(last[0], last[1], last[2], last[3], t),
+            Interpolation::Sinc { taps, oversample } => sinc_interpolate(last, *taps, *oversample, t),
+        }
+    }
+}
 
 pub(crate) fn hermite_interpolate(x0: f32, x1: f32, x2: f32, x3: f32, t: f32) -> f32 {
     let diff = x1 - x2;
@@ -12,27 +120,397 @@ pub(crate) fn hermite_interpolate(x0: f32, x1: f32, x2: f32, x3: f32, t: f32) ->
     0.5 * ((c3 * t + c2) * t + c1) * t + x1
 }
 
+/// `f64` version of [`hermite_interpolate`], for when the extra precision matters.
+fn hermite_interpolate_f64(x0: f64, x1: f64, x2: f64, x3: f64, t: f64) -> f64 {
+    let diff = x1 - x2;
+    let c1 = x2 - x0;
+    let c3 = x3 - x0 + 3.0 * diff;
+    let c2 = -(2.0 * diff + c1 + c3);
+
+    0.5 * ((c3 * t + c2) * t + c1) * t + x1
+}
+
+/// Blackman-windowed sinc, where `half_width` is where the window reaches zero and `cutoff` is the
+/// cutoff frequency as a fraction of Nyquist.
+fn windowed_sinc(x: f64, half_width: f64, cutoff: f64) -> f64 {
+    if x.abs() >= half_width {
+        return 0.0;
+    }
+
+    let sinc = if x == 0.0 {
+        1.0
+    } else {
+        sin(PI * cutoff * x) / (PI * cutoff * x)
+    };
+    let window = 0.42 + 0.5 * cos(PI * x / half_width) + 0.08 * cos(2.0 * PI * x / half_width);
+
+    sinc * window
+}
+
+/// The weight of history value `i` for a kernel centered `t` of the way between the two middle
+/// values, before normalizing.
+fn sinc_weight(taps: usize, i: usize, t: f64, cutoff: f64) -> f64 {
+    let center = (taps / 2 - 1) as f64 + t;
+
+    windowed_sinc(i as f64 - center, (taps / 2) as f64, cutoff)
+}
+
+/// Sum of the weights for `t`, to normalize by so DC passes through unchanged.
+fn sinc_weight_sum(taps: usize, t: f64, cutoff: f64) -> f64 {
+    (0..taps).map(|i| sinc_weight(taps, i, t, cutoff)).sum()
+}
+
+/// The two table phases on either side of a time fraction, and how far between them it is.
+struct SincPhases {
+    taps: usize,
+    /// Index of the phase just before (or on) the time
+    index: usize,
+    oversample: usize,
+    /// How far between the phases, [0.0, 1.0)
+    mix: f64,
+    sums: (f64, f64),
+}
+
+impl SincPhases {
+    fn new(taps: usize, oversample: usize, t: f32) -> SincPhases {
+        debug_assert!(taps >= 2 && taps.is_multiple_of(2));
+        debug_assert!(oversample >= 1);
+
+        let position = t as f64 * oversample as f64;
+        let index = (position as usize).min(oversample - 1);
+
+        SincPhases {
+            taps,
+            index,
+            oversample,
+            mix: position - index as f64,
+            sums: (
+                sinc_weight_sum(taps, index as f64 / oversample as f64, 1.0),
+                sinc_weight_sum(taps, (index + 1) as f64 / oversample as f64, 1.0),
+            ),
+        }
+    }
+
+    /// Normalized weight of history value `i`.
+    fn weight(&self, i: usize) -> f64 {
+        let before = sinc_weight(self.taps, i, self.index as f64 / self.oversample as f64, 1.0) / self.sums.0;
+        let after = sinc_weight(self.taps, i, (self.index + 1) as f64 / self.oversample as f64, 1.0) / self.sums.1;
+
+        before + (after - before) * self.mix
+    }
+}
+
+pub(crate) fn sinc_interpolate(last: &impl Index<usize, Output = f32>, taps: usize, oversample: usize, t: f32) -> f32 {
+    let phases = SincPhases::new(taps, oversample, t);
+
+    (0..taps).map(|i| last[i] as f64 * phases.weight(i)).sum::<f64>() as f32
+}
+
+/// Precomputed weights for [`Interpolation::Sinc`], one row of `taps` weights per phase. Computing
+/// the kernel means a `sin` and two `cos` per tap, so with a table a sinc kernel only costs the dot
+/// product (plus a blend between two rows).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SincTable {
+    taps: usize,
+    oversample: usize,
+    cutoff: f64,
+    /// `oversample + 1` rows (the last one is the next sample over, for blending into), each
+    /// normalized so DC passes through unchanged
+    table: Vec<f32>,
+}
+
+impl SincTable {
+    /// Builds the table, with the cutoff at the input's Nyquist frequency (the same kernel as
+    /// [`Interpolation::Sinc`]). This allocates, so it shouldn't be done on the audio thread.
+    ///
+    /// * `taps` - points in the kernel (must be even and at least 2)
+    /// * `oversample` - phases per input sample (must be at least 1)
+    pub fn new(taps: usize, oversample: usize) -> SincTable {
+        SincTable::with_cutoff(taps, oversample, 1.0)
+    }
+
+    /// Same as [`SincTable::new`], but with the cutoff at `cutoff` times the input's Nyquist
+    /// frequency. When downsampling at a fixed ratio, something like
+    /// `ANTI_ALIAS_CUTOFF / resample_ratio` keeps everything above the output's Nyquist frequency
+    /// from aliasing.
+    pub fn with_cutoff(taps: usize, oversample: usize, cutoff: f64) -> SincTable {
+        assert_valid_interpolation(&Interpolation::Sinc { taps, oversample });
+
+        let mut table = SincTable {
+            taps,
+            oversample,
+            cutoff,
+            table: alloc::vec![0.0; (oversample + 1) * taps],
+        };
+        table.set_cutoff(cutoff);
+
+        table
+    }
+
+    pub fn taps(&self) -> usize {
+        self.taps
+    }
+
+    pub fn oversample(&self) -> usize {
+        self.oversample
+    }
+
+    /// The cutoff, as a fraction of the input's Nyquist frequency.
+    pub fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+
+    /// Recomputes the table for a new cutoff. It's recomputed in place, so this doesn't allocate,
+    /// but it does evaluate the whole kernel (`(oversample + 1) * taps` points).
+    pub fn set_cutoff(&mut self, cutoff: f64) {
+        assert!(cutoff > 0.0 && cutoff <= 1.0, "cutoff must be in (0.0, 1.0]");
+
+        for (phase, row) in self.table.chunks_exact_mut(self.taps).enumerate() {
+            let t = phase as f64 / self.oversample as f64;
+            let sum = sinc_weight_sum(self.taps, t, cutoff);
+
+            for (i, weight) in row.iter_mut().enumerate() {
+                *weight = (sinc_weight(self.taps, i, t, cutoff) / sum) as f32;
+            }
+        }
+
+        self.cutoff = cutoff;
+    }
+
+    /// Same as [`Interpolation::weights`], from the table.
+    pub fn weights(&self, t: f32, weights: &mut [f32]) {
+        debug_assert_eq!(weights.len(), self.taps);
+
+        let position = t * self.oversample as f32;
+        let index = (position as usize).min(self.oversample - 1);
+        let mix = position - index as f32;
+
+        let before = &self.table[(index * self.taps)..((index + 1) * self.taps)];
+        let after = &self.table[((index + 1) * self.taps)..((index + 2) * self.taps)];
+
+        for ((weight, before), after) in weights.iter_mut().zip(before).zip(after) {
+            *weight = before + (after - before) * mix;
+        }
+    }
+
+    /// Same as [`Interpolation::interpolate`], from the table.
+    pub fn interpolate(&self, last: &impl Index<usize, Output = f32>, t: f32) -> f32 {
+        let position = t * self.oversample as f32;
+        let index = (position as usize).min(self.oversample - 1);
+        let mix = position - index as f32;
+
+        let before = &self.table[(index * self.taps)..((index + 1) * self.taps)];
+        let after = &self.table[((index + 1) * self.taps)..((index + 2) * self.taps)];
+
+        (0..self.taps)
+            .map(|i| last[i] * (before[i] + (after[i] - before[i]) * mix))
+            .sum()
+    }
+}
+
+/// Fraction of the output Nyquist frequency the anti-aliasing filter's cutoff is placed at.
+pub const ANTI_ALIAS_CUTOFF: f64 = 0.9;
+
+/// How far (as a fraction) the ideal sinc cutoff can drift from the one a [`Resampler`]'s table
+/// was built for before the table is recomputed. Small ratio changes (like clock compensation)
+/// never get there.
+#[cfg(feature = "std")]
+const SINC_CUTOFF_TOLERANCE: f64 = 0.02;
+
+/// Where a sinc kernel's cutoff goes for `resample_ratio`, as a fraction of the input's Nyquist
+/// frequency: just under the output's Nyquist frequency when downsampling, and never above the
+/// input's.
+#[cfg(feature = "std")]
+fn sinc_cutoff(resample_ratio: f64) -> f64 {
+    (ANTI_ALIAS_CUTOFF / resample_ratio).min(1.0)
+}
+
+/// Biquad section (RBJ cookbook low-pass).
+#[derive(Debug, Clone, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    /// * `cutoff` - cutoff frequency, as a fraction of the sample rate
+    /// * `q` - quality factor
+    fn set_low_pass(&mut self, cutoff: f64, q: f64) {
+        let w0 = 2.0 * PI * cutoff;
+        let cos_w0 = cos(w0);
+        let alpha = sin(w0) / (2.0 * q);
+        let a0 = 1.0 + alpha;
+
+        self.b0 = (1.0 - cos_w0) / 2.0 / a0;
+        self.b1 = (1.0 - cos_w0) / a0;
+        self.b2 = self.b0;
+        self.a1 = -2.0 * cos_w0 / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2 - self.a1 * self.y1 - self.a2 * self.y2;
+
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+
+        y
+    }
+}
+
+/// A 4th order Butterworth low-pass filter to run input through before it's resampled, so that
+/// downsampling doesn't alias. The cutoff follows the resample ratio (see [`AntiAlias::set_ratio`]).
+#[derive(Debug, Clone)]
+pub struct AntiAlias {
+    stages: [Biquad; 2],
+    resample_ratio: f64,
+}
+
+impl Default for AntiAlias {
+    fn default() -> Self {
+        AntiAlias::new()
+    }
+}
+
+impl AntiAlias {
+    /// Creates a filter set up for a resample ratio of 1.0.
+    pub fn new() -> AntiAlias {
+        let mut filter = AntiAlias {
+            stages: [Biquad::default(), Biquad::default()],
+            resample_ratio: 0.0,
+        };
+
+        filter.set_ratio(1.0);
+
+        filter
+    }
+
+    /// Moves the cutoff to just under the output's Nyquist frequency. Ratios below 1.0 (upsampling)
+    /// are treated as 1.0, so the cutoff never goes above the input's Nyquist frequency.
+    pub fn set_ratio(&mut self, resample_ratio: f64) {
+        let resample_ratio = resample_ratio.max(1.0);
+
+        if resample_ratio == self.resample_ratio {
+            return;
+        }
+
+        let cutoff = 0.5 * ANTI_ALIAS_CUTOFF / resample_ratio;
+
+        // Q values for a 4th order Butterworth
+        self.stages[0].set_low_pass(cutoff, 0.541_196_1);
+        self.stages[1].set_low_pass(cutoff, 1.306_563);
+        self.resample_ratio = resample_ratio;
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let mut sample = sample as f64;
+
+        for stage in &mut self.stages {
+            sample = stage.process(sample);
+        }
+
+        sample as f32
+    }
+
+    /// Clears the filter's history.
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.x1 = 0.0;
+            stage.x2 = 0.0;
+            stage.y1 = 0.0;
+            stage.y2 = 0.0;
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[inline]
+fn dot(weights: &[f32], history: &[f32]) -> f32 {
+    // 4 independent accumulators, so this vectorizes nicely
+    let mut sums = [0.0; 4];
+
+    let weight_chunks = weights.chunks_exact(4);
+    let history_chunks = history.chunks_exact(4);
+    let remainder: f32 = weight_chunks
+        .remainder()
+        .iter()
+        .zip(history_chunks.remainder())
+        .map(|(weight, x)| weight * x)
+        .sum();
+
+    for (weight, x) in weight_chunks.zip(history_chunks) {
+        for i in 0..4 {
+            sums[i] += weight[i] * x[i];
+        }
+    }
+
+    sums.iter().sum::<f32>() + remainder
+}
+
 #[inline]
 pub fn new_samples_needed(resample_ratio: f64, time: f64) -> usize {
     (time + resample_ratio) as usize
 }
 
-/// Resample between arbitrary input and output
+/// The most new samples [`resample`] could need for any `time` in [0.0, 1.0), given a maximum ratio.
+#[inline]
+pub fn max_samples_needed(max_resample_ratio: f64) -> usize {
+    new_samples_needed(max_resample_ratio, 1.0)
+}
+
+/// The result of one call to [`resample`] (or [`resample_with`]/[`resample_f64`]).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResampleStep<S = f32> {
+    /// The output sample
+    pub sample: S,
+    /// The new time fraction, to pass into the next call
+    pub time: f64,
+    /// How many samples were taken from `new_samples_in`. This is [`new_samples_needed`], unless
+    /// the iterator ran out early.
+    pub consumed: usize,
+}
+
+/// Resample between arbitrary input and output, using cubic Hermite interpolation. See
+/// [`resample_with`] for other kernels.
 ///
 /// # Arguments
 ///
 /// * `resample_ratio` - input_sample_rate / output_sample_rate
 /// * `new_samples_in` - an interator with _new_ incoming samples (use [`new_samples_needed`]
-///    to figure out how many new samples are needed)
-/// * `last` - a indexable container with the previous values
+///   to figure out how many new samples are needed)
+/// * `last` - a indexable container with the previous values (at least [`FRAME_LOOKBACK`] long)
 /// * `time` - ref to current time fraction [0.0, 1.0)
+///
+/// If `new_samples_in` runs out early, the newest sample is repeated rather than panicking.
 pub fn resample(
     resample_ratio: f64,
-    mut new_samples_in: impl Iterator<Item = f32>,
+    new_samples_in: impl Iterator<Item = f32>,
     last: &mut impl IndexMut<usize, Output = f32>,
+    time: f64,
+) -> ResampleStep {
+    resample_with(&Interpolation::Hermite, resample_ratio, new_samples_in, last, time)
+}
+
+/// Same as [`resample`], but all the math is done in `f64`. Useful for control signals (automation
+/// curves and the like) where `f32` rounding adds up over long runs. Uses cubic Hermite interpolation.
+pub fn resample_f64(
+    resample_ratio: f64,
+    mut new_samples_in: impl Iterator<Item = f64>,
+    last: &mut impl IndexMut<usize, Output = f64>,
     mut time: f64,
-) -> (f32, f64) {
-    let out = hermite_interpolate(last[0], last[1], last[2], last[3], time as f32);
+) -> ResampleStep<f64> {
+    let out = hermite_interpolate_f64(last[0], last[1], last[2], last[3], time);
+    let mut consumed = 0;
 
     time += resample_ratio;
 
@@ -41,10 +519,887 @@ pub fn resample(
             last[i] = last[i + 1];
         }
 
-        last[FRAME_LOOKBACK - 1] = new_samples_in.next().unwrap();
+        if let Some(sample) = new_samples_in.next() {
+            last[FRAME_LOOKBACK - 1] = sample;
+            consumed += 1;
+        }
 
         time -= 1.0;
     }
 
-    (out, time)
+    ResampleStep {
+        sample: out,
+        time,
+        consumed,
+    }
+}
+
+/// Same as [`resample`], but with a choice of interpolation kernel. `last` must be at least
+/// [`Interpolation::lookback`] long.
+pub fn resample_with(
+    interpolation: &Interpolation,
+    resample_ratio: f64,
+    new_samples_in: impl Iterator<Item = f32>,
+    last: &mut impl IndexMut<usize, Output = f32>,
+    time: f64,
+) -> ResampleStep {
+    let out = interpolation.interpolate(last, time as f32);
+
+    advance(
+        interpolation.lookback(),
+        resample_ratio,
+        new_samples_in,
+        last,
+        out,
+        time,
+    )
+}
+
+/// Same as [`resample_with`] using [`Interpolation::Sinc`], but with the weights taken from a
+/// precomputed `table` instead of working out the kernel on every call. `last` must be at least
+/// [`SincTable::taps`] long.
+pub fn resample_sinc(
+    table: &SincTable,
+    resample_ratio: f64,
+    new_samples_in: impl Iterator<Item = f32>,
+    last: &mut impl IndexMut<usize, Output = f32>,
+    time: f64,
+) -> ResampleStep {
+    let out = table.interpolate(last, time as f32);
+
+    advance(table.taps(), resample_ratio, new_samples_in, last, out, time)
+}
+
+/// Moves `time` along by `resample_ratio`, shifting new samples into the history as it crosses
+/// over them.
+fn advance(
+    lookback: usize,
+    resample_ratio: f64,
+    mut new_samples_in: impl Iterator<Item = f32>,
+    last: &mut impl IndexMut<usize, Output = f32>,
+    out: f32,
+    mut time: f64,
+) -> ResampleStep {
+    let mut consumed = 0;
+
+    time += resample_ratio;
+
+    while time >= 1.0 {
+        for i in 0..(lookback - 1) {
+            last[i] = last[i + 1];
+        }
+
+        if let Some(sample) = new_samples_in.next() {
+            last[lookback - 1] = sample;
+            consumed += 1;
+        }
+
+        time -= 1.0;
+    }
+
+    ResampleStep {
+        sample: out,
+        time,
+        consumed,
+    }
+}
+
+/// A multi-channel resampler. It owns the history and time fraction for every channel, so a whole
+/// frame can be resampled at once. This is what [`crate::StreamSink`] and [`crate::StreamSource`]
+/// use internally, but it's also usable directly (for example, for offline conversion).
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    /// Channel count
+    channels: usize,
+    /// Kernel to resample with
+    interpolation: Interpolation,
+    /// Previous values (one column per channel)
+    last_frames: DMatrix<f32>,
+    /// Current time fraction [0.0, 1.0)
+    time: f64,
+    /// Per-channel anti-aliasing filters, if enabled
+    anti_alias: Option<Vec<AntiAlias>>,
+    /// Whether to replace non-finite input before it reaches the history
+    sanitize: bool,
+    /// Kernel weights for the current frame
+    weights: Vec<f32>,
+    /// Precomputed weights, if the kernel is a sinc
+    sinc_table: Option<SincTable>,
+}
+
+#[cfg(feature = "std")]
+impl Resampler {
+    /// Creates a resampler with silent history.
+    ///
+    /// * `channels` - the number of channels
+    /// * `interpolation` - which kernel to use
+    pub fn new(channels: usize, interpolation: Interpolation) -> Resampler {
+        assert_valid_interpolation(&interpolation);
+
+        Resampler {
+            channels,
+            interpolation,
+            last_frames: DMatrix::zeros(interpolation.lookback(), channels),
+            time: 0.0,
+            anti_alias: None,
+            sanitize: false,
+            weights: vec![0.0; interpolation.lookback()],
+            sinc_table: sinc_table(&interpolation),
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// Current time fraction [0.0, 1.0)
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// How far (in frames) the output lags behind the newest frame taken in.
+    pub fn delay(&self) -> f64 {
+        // interpolation happens between the two middle frames of the lookback
+        (self.interpolation.lookback() / 2) as f64 - self.time
+    }
+
+    /// Changes the interpolation kernel. This reallocates and clears the history (and builds the
+    /// table for a sinc kernel), so it shouldn't be called from the audio thread.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        assert_valid_interpolation(&interpolation);
+
+        self.interpolation = interpolation;
+        self.last_frames = DMatrix::zeros(interpolation.lookback(), self.channels);
+        self.weights = vec![0.0; interpolation.lookback()];
+        self.sinc_table = sinc_table(&interpolation);
+    }
+
+    /// Enables or disables low-pass filtering input before it's resampled, to keep it from aliasing
+    /// when downsampling (see [`AntiAlias`]). Off by default. Enabling it allocates, so it shouldn't be
+    /// called from the audio thread.
+    pub fn set_anti_aliasing(&mut self, enabled: bool) {
+        self.anti_alias = if enabled {
+            Some(vec![AntiAlias::new(); self.channels])
+        } else {
+            None
+        };
+    }
+
+    /// When enabled, any NaN or infinite input sample is replaced with the previous sample on that
+    /// channel before it enters the history. Otherwise a single bad sample would get stuck in the
+    /// history and turn every output after it into NaN. Off by default.
+    pub fn set_sanitize(&mut self, enabled: bool) {
+        self.sanitize = enabled;
+    }
+
+    pub fn sanitize(&self) -> bool {
+        self.sanitize
+    }
+
+    /// Clears the history, time fraction, and filter state.
+    pub fn reset(&mut self) {
+        self.last_frames.fill(0.0);
+        self.time = 0.0;
+
+        if let Some(filters) = &mut self.anti_alias {
+            filters.iter_mut().for_each(AntiAlias::reset);
+        }
+    }
+
+    /// How many new frames the next call to [`Resampler::step`] will consume.
+    #[inline]
+    pub fn frames_needed(&self, resample_ratio: f64) -> usize {
+        new_samples_needed(resample_ratio, self.time)
+    }
+
+    /// How many new frames producing the next `output_frames` frames will consume, at a constant
+    /// `resample_ratio`.
+    pub fn frames_needed_for(&self, output_frames: usize, resample_ratio: f64) -> usize {
+        let mut time = self.time;
+        let mut needed = 0;
+
+        for _ in 0..output_frames {
+            needed += new_samples_needed(resample_ratio, time);
+            time += resample_ratio;
+
+            while time >= 1.0 {
+                time -= 1.0;
+            }
+        }
+
+        needed
+    }
+
+    /// Shifts a frame into the history without producing any output. Useful for priming the
+    /// history before resampling starts.
+    pub fn push_frame(&mut self, frame: &[f32]) {
+        debug_assert_eq!(frame.len(), self.channels);
+
+        let lookback = self.interpolation.lookback();
+
+        for (channel_i, sample) in frame.iter().enumerate() {
+            let mut channel = self.last_frames.column_mut(channel_i);
+            let sample = sanitize_sample(self.sanitize, *sample, channel[lookback - 1]);
+
+            let sample = match &mut self.anti_alias {
+                Some(filters) => filters[channel_i].process(sample),
+                None => sample,
+            };
+
+            for i in 0..(lookback - 1) {
+                channel[i] = channel[i + 1];
+            }
+
+            channel[lookback - 1] = sample;
+        }
+    }
+
+    /// Produces one output frame, consuming the first [`Resampler::frames_needed`] rows of
+    /// `new_frames` (one column per channel).
+    pub fn step(&mut self, resample_ratio: f64, new_frames: DMatrixView<f32>, frame_out: &mut [f32]) {
+        debug_assert_eq!(frame_out.len(), self.channels);
+
+        self.step_with(resample_ratio, new_frames, |channel_i, sample| {
+            frame_out[channel_i] = sample;
+        });
+    }
+
+    /// Same as calling [`resample_with`] on every channel, except the kernel weights only depend on
+    /// `time`, so they're computed once per frame and shared between all the channels.
+    fn step_with(&mut self, resample_ratio: f64, new_frames: DMatrixView<f32>, mut out: impl FnMut(usize, f32)) {
+        debug_assert_eq!(new_frames.ncols(), self.channels);
+
+        let lookback = self.interpolation.lookback();
+        let needed = self.frames_needed(resample_ratio);
+        let available = needed.min(new_frames.nrows());
+
+        match &mut self.sinc_table {
+            Some(table) => {
+                let cutoff = sinc_cutoff(resample_ratio);
+                if (cutoff - table.cutoff()).abs() > table.cutoff() * SINC_CUTOFF_TOLERANCE {
+                    table.set_cutoff(cutoff);
+                }
+
+                table.weights(self.time as f32, &mut self.weights);
+            }
+            None => self.interpolation.weights(self.time as f32, &mut self.weights),
+        }
+
+        if let Some(filters) = &mut self.anti_alias {
+            filters.iter_mut().for_each(|filter| filter.set_ratio(resample_ratio));
+        }
+
+        // columns are contiguous, so each channel's history is one chunk
+        for (channel_i, history) in self.last_frames.as_mut_slice().chunks_exact_mut(lookback).enumerate() {
+            out(channel_i, dot(&self.weights, history));
+
+            for sample in new_frames.column(channel_i).iter().take(available) {
+                let sample = sanitize_sample(self.sanitize, *sample, history[lookback - 1]);
+
+                let sample = match &mut self.anti_alias {
+                    Some(filters) => filters[channel_i].process(sample),
+                    None => sample,
+                };
+
+                history.copy_within(1.., 0);
+                history[lookback - 1] = sample;
+            }
+
+            // if there wasn't enough input, repeat the newest sample (like `resample_with` does)
+            for _ in available..needed {
+                history.copy_within(1.., 0);
+            }
+        }
+
+        self.time += resample_ratio;
+
+        while self.time >= 1.0 {
+            self.time -= 1.0;
+        }
+    }
+
+    /// Resamples as much of `input` as possible into `output` (both with one column per channel, one
+    /// row per frame). Stops when either `output` is full or `input` doesn't have enough frames for
+    /// the next output frame. Returns `(frames consumed, frames produced)`.
+    pub fn process(&mut self, input: &DMatrix<f32>, output: &mut DMatrix<f32>, resample_ratio: f64) -> (usize, usize) {
+        assert_eq!(input.ncols(), self.channels);
+        assert_eq!(output.ncols(), self.channels);
+
+        let mut consumed = 0;
+        let mut produced = 0;
+
+        while produced < output.nrows() {
+            let needed = self.frames_needed(resample_ratio);
+
+            if consumed + needed > input.nrows() {
+                break;
+            }
+
+            self.step_with(resample_ratio, input.rows(consumed, needed), |channel_i, sample| {
+                output[(produced, channel_i)] = sample;
+            });
+
+            consumed += needed;
+            produced += 1;
+        }
+
+        (consumed, produced)
+    }
+
+    /// Produces exactly `output.nrows()` frames, pulling as many frames from `input` as that takes
+    /// (see [`Resampler::frames_needed_for`] to size `input` up front). Returns how many frames of
+    /// `input` were consumed. If `input` runs short, its newest frame is repeated for the rest of
+    /// the output, the same as [`Resampler::step`].
+    pub fn process_exact(&mut self, input: &DMatrix<f32>, output: &mut DMatrix<f32>, resample_ratio: f64) -> usize {
+        assert_eq!(input.ncols(), self.channels);
+        assert_eq!(output.ncols(), self.channels);
+
+        let mut consumed = 0;
+
+        for produced in 0..output.nrows() {
+            let available = self.frames_needed(resample_ratio).min(input.nrows() - consumed);
+
+            self.step_with(resample_ratio, input.rows(consumed, available), |channel_i, sample| {
+                output[(produced, channel_i)] = sample;
+            });
+
+            consumed += available;
+        }
+
+        consumed
+    }
+}
+
+/// Swaps a non-finite `sample` for `previous`, if `enabled`.
+#[cfg(feature = "std")]
+#[inline]
+fn sanitize_sample(enabled: bool, sample: f32, previous: f32) -> f32 {
+    if enabled && !sample.is_finite() {
+        previous
+    } else {
+        sample
+    }
+}
+
+fn assert_valid_interpolation(interpolation: &Interpolation) {
+    if let Interpolation::Sinc { taps, oversample } = interpolation {
+        assert!(
+            *taps >= 2 && taps.is_multiple_of(2),
+            "sinc taps must be even and at least 2"
+        );
+        assert!(*oversample >= 1, "sinc oversampling must be at least 1");
+    }
+}
+
+#[cfg(feature = "std")]
+fn sinc_table(interpolation: &Interpolation) -> Option<SincTable> {
+    match interpolation {
+        Interpolation::Sinc { taps, oversample } => Some(SincTable::with_cutoff(*taps, *oversample, sinc_cutoff(1.0))),
+        _ => None,
+    }
+}
+
+/// Resamples a whole buffer of interleaved audio in one go, using cubic Hermite interpolation. The
+/// edges are handled by repeating the first and last frames, so the output is aligned with the input
+/// and always `ceil(input frames / resample_ratio)` frames long.
+///
+/// * `input` - interleaved samples (length must be a multiple of `channels`)
+/// * `channels` - the number of channels
+/// * `resample_ratio` - input_sample_rate / output_sample_rate
+#[cfg(feature = "std")]
+pub fn convert_slice(input: &[f32], channels: usize, resample_ratio: f64) -> Vec<f32> {
+    assert!(channels > 0, "there must be at least one channel");
+    assert_eq!(input.len() % channels, 0);
+    assert!(resample_ratio > 0.0, "resample ratio must be positive");
+
+    let frames_in = input.len() / channels;
+
+    if frames_in == 0 {
+        return Vec::new();
+    }
+
+    let frames_out = (frames_in as f64 / resample_ratio).ceil() as usize;
+    let frame = |i: usize| {
+        let i = i.min(frames_in - 1);
+
+        &input[(i * channels)..((i + 1) * channels)]
+    };
+
+    let mut resampler = Resampler::new(channels, Interpolation::Hermite);
+    let lookback = resampler.interpolation().lookback();
+
+    // prime the history so the first output lands exactly on the first input frame
+    for i in 0..lookback {
+        resampler.push_frame(frame(i.saturating_sub(lookback / 2 - 1)));
+    }
+
+    let mut next_frame_in = lookback / 2 + 1;
+    let mut scratch = DMatrix::zeros(max_samples_needed(resample_ratio), channels);
+    let mut output = vec![0.0; frames_out * channels];
+
+    for frame_out in output.chunks_exact_mut(channels) {
+        let needed = resampler.frames_needed(resample_ratio);
+
+        for row in 0..needed {
+            // past the end, the last frame is repeated to flush out the tail
+            scratch.row_mut(row).copy_from_slice(frame(next_frame_in + row));
+        }
+
+        resampler.step(resample_ratio, scratch.rows(0, needed), frame_out);
+        next_frame_in += needed;
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ResampleFixture;
+
+    #[test]
+    fn ratio_of_four_produces_a_quarter_of_the_frames() {
+        let mut resampler = Resampler::new(2, Interpolation::Hermite);
+        let input = DMatrix::from_fn(400, 2, |row, channel| (row * 2 + channel) as f32);
+        let mut output = DMatrix::zeros(200, 2);
+
+        let (consumed, produced) = resampler.process(&input, &mut output, 4.0);
+
+        assert_eq!(produced, 100);
+        assert_eq!(consumed, 400);
+    }
+
+    #[test]
+    fn resample_repeats_the_newest_sample_when_input_runs_out() {
+        let mut last = [1.0, 2.0, 3.0, 4.0];
+
+        // a ratio of 4 wants 4 new samples, but only gets 1
+        let step = resample(4.0, [5.0].into_iter(), &mut last, 0.0);
+
+        assert_eq!(step.consumed, 1);
+        assert_eq!(step.sample, 2.0);
+        assert_eq!(last, [5.0, 5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn convert_slice_handles_large_ratios() {
+        let input: Vec<f32> = (0..1000).map(|i| i as f32).collect();
+
+        assert_eq!(convert_slice(&input, 1, 4.0).len(), 250);
+        assert_eq!(convert_slice(&input, 1, MAX_RESAMPLE_RATIO).len(), 125);
+    }
+
+    #[test]
+    fn sinc_has_less_distortion_than_hermite() {
+        let fixture = |interpolation| ResampleFixture {
+            frequency: 10_000.0,
+            frames: 8192,
+            ppm_offset: 50_000.0,
+            interpolation,
+            ..ResampleFixture::default()
+        };
+
+        let hermite = fixture(Interpolation::Hermite).run().thd_n;
+        let sinc = fixture(Interpolation::Sinc {
+            taps: 8,
+            oversample: 256,
+        })
+        .run()
+        .thd_n;
+
+        assert!(sinc < hermite / 20.0, "sinc: {sinc}, hermite: {hermite}");
+    }
+
+    fn rms(samples: &[f32]) -> f64 {
+        (samples.iter().map(|x| (*x as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    /// RMS of a 16 kHz tone (at 48 kHz) after halving its sample rate, past the filter's settling.
+    fn downsampled_tone_rms(anti_aliasing: bool) -> f64 {
+        let tone = crate::testing::sine(16_000.0, 48_000.0, 4096);
+
+        let mut resampler = Resampler::new(1, Interpolation::Hermite);
+        resampler.set_anti_aliasing(anti_aliasing);
+
+        let input = DMatrix::from_column_slice(tone.len(), 1, &tone);
+        let mut output = DMatrix::zeros(2048, 1);
+        let (_, produced) = resampler.process(&input, &mut output, 2.0);
+
+        rms(&output.as_slice()[256..produced])
+    }
+
+    #[test]
+    fn anti_aliasing_attenuates_tones_above_the_output_nyquist() {
+        let aliased = downsampled_tone_rms(false);
+        let filtered = downsampled_tone_rms(true);
+
+        // without the filter it folds back down to 8 kHz at nearly full level
+        assert!(aliased > 0.1, "{aliased}");
+        // a 4th order filter at 10.8 kHz gets it at least 20 dB down
+        assert!(filtered < aliased / 10.0, "filtered: {filtered}, aliased: {aliased}");
+    }
+
+    /// RMS of what comes out when a tone 20% above the output's Nyquist frequency is downsampled
+    /// by `ratio` with no anti-aliasing filter, i.e. how much of it aliases back down.
+    fn aliased_rms(interpolation: Interpolation, ratio: f64) -> f64 {
+        let frequency = 1.2 * 24_000.0 / ratio;
+        let tone = crate::testing::sine(frequency, 48_000.0, 8192);
+
+        let mut resampler = Resampler::new(1, interpolation);
+        let input = DMatrix::from_column_slice(tone.len(), 1, &tone);
+        let mut output = DMatrix::zeros(8192, 1);
+        let (_, produced) = resampler.process(&input, &mut output, ratio);
+
+        rms(&output.as_slice()[64..produced])
+    }
+
+    #[test]
+    fn sinc_rejects_aliasing_better_than_hermite_across_a_downsampling_sweep() {
+        for ratio in [1.25, 1.5, 2.0, 3.0, 4.0] {
+            let hermite = aliased_rms(Interpolation::Hermite, ratio);
+            let sinc = aliased_rms(
+                Interpolation::Sinc {
+                    taps: 32,
+                    oversample: 256,
+                },
+                ratio,
+            );
+
+            // Hermite lets nearly all of it through; the sinc kernel's cutoff follows the ratio.
+            // It's stretched wider at bigger ratios, so there's less to spare there, but still
+            // over 18 dB
+            assert!(hermite > 0.4, "ratio {ratio}: {hermite}");
+            assert!(sinc < hermite / 8.0, "ratio {ratio}: sinc: {sinc}, hermite: {hermite}");
+        }
+    }
+
+    #[test]
+    fn anti_aliasing_passes_tones_below_the_output_nyquist() {
+        let tone = crate::testing::sine(2_000.0, 48_000.0, 4096);
+
+        let mut resampler = Resampler::new(1, Interpolation::Hermite);
+        resampler.set_anti_aliasing(true);
+
+        let input = DMatrix::from_column_slice(tone.len(), 1, &tone);
+        let mut output = DMatrix::zeros(2048, 1);
+        let (_, produced) = resampler.process(&input, &mut output, 2.0);
+
+        let level = rms(&output.as_slice()[256..produced]);
+        assert!((level - 0.5_f64.sqrt()).abs() < 0.05, "{level}");
+    }
+
+    /// A ramp per channel, each with its own slope and offset.
+    fn ramps(frames: usize, channels: usize) -> DMatrix<f32> {
+        DMatrix::from_fn(frames, channels, |row, channel| {
+            channel as f32 * 100.0 + row as f32 * (channel + 1) as f32 * 0.5
+        })
+    }
+
+    fn channels_are_independent(channels: usize) {
+        let input = ramps(200, channels);
+        let mut output = DMatrix::zeros(150, channels);
+        let (consumed, produced) = Resampler::new(channels, Interpolation::Hermite).process(&input, &mut output, 1.25);
+
+        for channel in 0..channels {
+            let mut alone = Resampler::new(1, Interpolation::Hermite);
+            let channel_in = DMatrix::from_column_slice(200, 1, input.column(channel).as_slice());
+            let mut channel_out = DMatrix::zeros(150, 1);
+
+            assert_eq!(alone.process(&channel_in, &mut channel_out, 1.25), (consumed, produced));
+            assert_eq!(output.column(channel), channel_out.column(0));
+
+            // Hermite reproduces a ramp exactly, so once the silent history is flushed out each
+            // output frame moves along the ramp by `ratio * slope`
+            let slope = (channel + 1) as f32 * 0.5;
+            for pair in output.column(channel).as_slice()[4..produced].windows(2) {
+                assert!((pair[1] - pair[0] - 1.25 * slope).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn mono_resamples_correctly() {
+        channels_are_independent(1);
+    }
+
+    #[test]
+    fn stereo_channels_are_independent() {
+        channels_are_independent(2);
+    }
+
+    #[test]
+    fn eight_channels_are_independent() {
+        channels_are_independent(8);
+    }
+
+    #[test]
+    fn convert_slice_keeps_a_sine_at_its_frequency() {
+        let input = crate::testing::sine(1000.0, 44_100.0, 44_100);
+
+        let output = convert_slice(&input, 1, 44_100.0 / 48_000.0);
+
+        assert_eq!(output.len(), 48_000);
+
+        // 1 kHz is a cycle every 48 samples at the new rate
+        let rising_zero_crossings = output.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+        assert!((999..=1000).contains(&rising_zero_crossings), "{rising_zero_crossings}");
+        let thd_n = crate::testing::thd_n(&output, 1000.0 / 48_000.0);
+        assert!(thd_n < 1e-3, "{thd_n}");
+    }
+
+    #[test]
+    fn convert_slice_lines_up_with_the_input() {
+        let input = [0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0];
+
+        // at a ratio of 1 it's a straight copy, edges included
+        assert_eq!(convert_slice(&input, 2, 1.0), input);
+        assert!(convert_slice(&[], 2, 1.5).is_empty());
+    }
+
+    /// One channel's worth of new samples for the scalar kernels.
+    type ChannelIn = alloc::vec::IntoIter<f32>;
+
+    /// Steps a 3 channel [`Resampler`] and a scalar kernel (run on each channel separately) through
+    /// the same input at a wobbling ratio, checking they stay within float tolerance of each other.
+    fn batched_matches_scalar(
+        interpolation: Interpolation,
+        mut scalar: impl FnMut(f64, ChannelIn, &mut Vec<f32>, f64) -> ResampleStep,
+    ) {
+        let channels = 3;
+        let mut resampler = Resampler::new(channels, interpolation);
+        let mut histories = vec![vec![0.0; interpolation.lookback()]; channels];
+        let mut time = 0.0;
+
+        let mut next_frame = 0;
+        let mut frame_out = vec![0.0; channels];
+
+        for i in 0..2000 {
+            let ratio = 1.0 + 0.008 * (i as f64 * 0.01).sin();
+            let needed = resampler.frames_needed(ratio);
+            let new_frames = DMatrix::from_fn(needed, channels, |row, channel| {
+                ((next_frame + row) as f32 * 0.37 * (channel + 1) as f32).sin()
+            });
+
+            resampler.step(ratio, new_frames.rows(0, needed), &mut frame_out);
+
+            let mut next_time = time;
+            for (channel, history) in histories.iter_mut().enumerate() {
+                let new_samples: Vec<f32> = new_frames.column(channel).iter().copied().collect();
+                let step = scalar(ratio, new_samples.into_iter(), history, time);
+
+                assert!(
+                    (step.sample - frame_out[channel]).abs() < 1e-5,
+                    "frame {i}, channel {channel}: {} vs {}",
+                    step.sample,
+                    frame_out[channel]
+                );
+                next_time = step.time;
+            }
+
+            time = next_time;
+            next_frame += needed;
+        }
+    }
+
+    #[test]
+    fn batched_linear_matches_scalar() {
+        batched_matches_scalar(Interpolation::Linear, |ratio, new_samples, history, time| {
+            resample_with(&Interpolation::Linear, ratio, new_samples, history, time)
+        });
+    }
+
+    #[test]
+    fn batched_hermite_matches_scalar() {
+        batched_matches_scalar(Interpolation::Hermite, |ratio, new_samples, history, time| {
+            resample(ratio, new_samples, history, time)
+        });
+    }
+
+    #[test]
+    fn batched_sinc_matches_scalar() {
+        // the ratio stays close enough to 1 that the resampler keeps its initial cutoff
+        let table = SincTable::with_cutoff(8, 64, ANTI_ALIAS_CUTOFF);
+
+        batched_matches_scalar(
+            Interpolation::Sinc {
+                taps: 8,
+                oversample: 64,
+            },
+            |ratio, new_samples, history, time| resample_sinc(&table, ratio, new_samples, history, time),
+        );
+    }
+
+    #[test]
+    fn dot_matches_a_plain_sum() {
+        for len in [1, 3, 4, 7, 8, 16, 33] {
+            let weights: Vec<f32> = (0..len).map(|i| (i as f32 * 0.7).cos()).collect();
+            let history: Vec<f32> = (0..len).map(|i| (i as f32 * 1.3).sin()).collect();
+
+            let plain: f32 = weights.iter().zip(&history).map(|(weight, x)| weight * x).sum();
+
+            assert!((dot(&weights, &history) - plain).abs() < 1e-5, "length {len}");
+        }
+    }
+
+    #[test]
+    fn f64_ramp_keeps_its_precision_over_long_runs() {
+        // a slow automation ramp sitting on a large offset, where an f32 only has about 0.06 of
+        // resolution
+        let ramp = |i: usize| 1_000_000.0 + i as f64 * 0.001;
+        let ratio = 1.001;
+
+        let mut last_f64 = [ramp(0); FRAME_LOOKBACK];
+        let mut last_f32 = [ramp(0) as f32; FRAME_LOOKBACK];
+        let (mut time_f64, mut time_f32) = (0.0, 0.0);
+        let (mut next_f64, mut next_f32) = (1, 1);
+        let (mut outputs_f64, mut outputs_f32) = (Vec::new(), Vec::new());
+
+        for _ in 0..100_000 {
+            let step = resample_f64(ratio, (next_f64..).map(ramp), &mut last_f64, time_f64);
+            next_f64 += step.consumed;
+            time_f64 = step.time;
+            outputs_f64.push(step.sample);
+
+            let step = resample(ratio, (next_f32..).map(|i| ramp(i) as f32), &mut last_f32, time_f32);
+            next_f32 += step.consumed;
+            time_f32 = step.time;
+            outputs_f32.push(step.sample as f64);
+        }
+
+        // every output moves `ratio` samples along the ramp
+        let worst_error = |outputs: &[f64]| {
+            outputs[10..]
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0] - ratio * 0.001).abs())
+                .fold(0.0, f64::max)
+        };
+
+        assert!(worst_error(&outputs_f64) < 1e-6, "{}", worst_error(&outputs_f64));
+        assert!(worst_error(&outputs_f32) > 1e-2, "{}", worst_error(&outputs_f32));
+    }
+
+    #[test]
+    fn process_exact_pulls_what_it_needs() {
+        let mut resampler = Resampler::new(1, Interpolation::Linear);
+        let input = ramps(512, 1);
+        let mut output = DMatrix::zeros(128, 1);
+
+        let needed = resampler.frames_needed_for(128, 1.5);
+        let consumed = resampler.process_exact(&input, &mut output, 1.5);
+
+        assert_eq!(consumed, needed);
+        assert!(consumed.abs_diff(192) <= 1, "consumed {consumed} frames");
+
+        // and the next block carries on where that one left off
+        let rest = input.rows(consumed, 512 - consumed).into_owned();
+        let consumed_next = resampler.process_exact(&rest, &mut output, 1.5);
+        assert!((consumed + consumed_next).abs_diff(384) <= 1);
+    }
+
+    #[test]
+    fn process_exact_fills_the_output_when_input_runs_short() {
+        let mut resampler = Resampler::new(1, Interpolation::Linear);
+        let input = ramps(16, 1);
+        let mut output = DMatrix::from_element(128, 1, f32::NAN);
+
+        let consumed = resampler.process_exact(&input, &mut output, 1.5);
+
+        assert_eq!(consumed, 16);
+        assert!(output.iter().all(|sample| sample.is_finite()));
+        // the newest frame is held once the input's gone
+        assert_eq!(output[(127, 0)], input[(15, 0)]);
+    }
+
+    #[test]
+    fn consumed_matches_samples_needed() {
+        for ratio in [0.5, 0.99, 1.0, 1.01, 1.5, 2.0, 3.7] {
+            for start_time in [0.0, 0.25, 0.5, 0.999] {
+                let mut last = [0.0; FRAME_LOOKBACK];
+                let mut time = start_time;
+
+                // several calls in a row, so the time offsets come from the ratio as well
+                for _ in 0..20 {
+                    let needed = new_samples_needed(ratio, time);
+                    let step = resample(ratio, core::iter::repeat(1.0), &mut last, time);
+
+                    assert_eq!(step.consumed, needed, "ratio {ratio}, time {time}");
+                    assert!((0.0..1.0).contains(&step.time));
+                    time = step.time;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn consumed_matches_for_every_kernel() {
+        let table = SincTable::new(16, 32);
+
+        for ratio in [0.5, 1.01, 2.5] {
+            let mut last = [0.0; 16];
+            let mut time = 0.3;
+
+            for _ in 0..20 {
+                let needed = new_samples_needed(ratio, time);
+
+                let linear = resample_with(&Interpolation::Linear, ratio, core::iter::repeat(1.0), &mut last, time);
+                let sinc = resample_sinc(&table, ratio, core::iter::repeat(1.0), &mut last, time);
+                let f64_step = resample_f64(ratio, core::iter::repeat(1.0), &mut [0.0; 4], time);
+
+                assert_eq!(linear.consumed, needed);
+                assert_eq!(sinc.consumed, needed);
+                assert_eq!(f64_step.consumed, needed);
+                assert_eq!((linear.time, sinc.time), (f64_step.time, f64_step.time));
+                time = linear.time;
+            }
+        }
+    }
+
+    /// Resamples a ramp with a NaN in the middle of it (at frame 50), alongside the clean ramp.
+    fn resample_with_nan(resampler: &mut Resampler) -> (DMatrix<f32>, DMatrix<f32>) {
+        let clean = ramps(100, 1);
+        let mut poisoned = clean.clone();
+        poisoned[(50, 0)] = f32::NAN;
+
+        let mut reference = resampler.clone();
+        let mut output = DMatrix::zeros(100, 1);
+        let mut expected = DMatrix::zeros(100, 1);
+        resampler.process(&poisoned, &mut output, 1.0);
+        reference.process(&clean, &mut expected, 1.0);
+
+        (output, expected)
+    }
+
+    #[test]
+    fn sanitize_recovers_from_a_nan() {
+        let mut resampler = Resampler::new(1, Interpolation::Hermite);
+        resampler.set_sanitize(true);
+
+        let (output, expected) = resample_with_nan(&mut resampler);
+
+        assert!(output.iter().all(|sample| sample.is_finite()));
+        // once the replacement's out of the history, it's as if the NaN never happened
+        assert_eq!(
+            output.rows(50 + FRAME_LOOKBACK, 50 - FRAME_LOOKBACK),
+            expected.rows(50 + FRAME_LOOKBACK, 50 - FRAME_LOOKBACK)
+        );
+    }
+
+    #[test]
+    fn sanitize_keeps_nan_out_of_the_anti_aliasing_filter() {
+        let mut resampler = Resampler::new(1, Interpolation::Hermite);
+        resampler.set_anti_aliasing(true);
+
+        // without sanitizing, the filter's state holds on to the NaN for good
+        let (output, _) = resample_with_nan(&mut resampler.clone());
+        assert!(output[(99, 0)].is_nan());
+
+        resampler.set_sanitize(true);
+        let (output, _) = resample_with_nan(&mut resampler);
+        assert!(output.iter().all(|sample| sample.is_finite()));
+    }
 }
@@ -1,13 +1,186 @@
-use std::{collections::VecDeque, thread, time::Duration};
+use std::{collections::VecDeque, time::Duration};
 
 use nalgebra::DMatrix;
+use rtrb::RingBuffer;
 
 use crate::{
     lerp,
-    resample::{new_samples_needed, resample, FRAME_LOOKBACK, ROLLING_AVG_LENGTH},
+    mix::ChannelMixer,
+    resample::{max_samples_needed, Interpolation, Resampler, MAX_RESAMPLE_RATIO, ROLLING_AVG_LENGTH},
     CompensationStrategy, PidSettings,
 };
 
+type StateChangeFunc = Box<dyn FnMut(&CompensationStrategy) + Send>;
+
+/// How far (in frames) the resampler's output lags behind the newest sample it has taken in.
+fn resampler_delay(strategy: &CompensationStrategy, resampler: &Resampler) -> f64 {
+    match strategy {
+        CompensationStrategy::Resample { .. } => resampler.delay(),
+        CompensationStrategy::None | CompensationStrategy::Never => 0.0,
+    }
+}
+
+/// Average of the rolling window of ring slot counts, as a fraction of the ring's capacity.
+fn rolling_avg(rolling_ring_avg: &[usize], ring_size: usize) -> f64 {
+    rolling_ring_avg.iter().map(|x| *x as f64).sum::<f64>() / rolling_ring_avg.len() as f64 / ring_size as f64
+}
+
+/// The resample ratio `strategy` is using, or 1.0 if it isn't resampling.
+fn current_ratio(strategy: &CompensationStrategy) -> f64 {
+    match strategy {
+        CompensationStrategy::Resample { resample_ratio, .. } => *resample_ratio,
+        CompensationStrategy::None | CompensationStrategy::Never => 1.0,
+    }
+}
+
+/// Clamps a PID-driven `ratio` to within `limit` (a fraction) of `center`, returning whether it had
+/// to.
+fn limit_ratio(ratio: f64, center: f64, limit: Option<f64>) -> (f64, bool) {
+    match limit {
+        Some(limit) => {
+            let clamped = ratio.clamp(center * (1.0 - limit), center * (1.0 + limit));
+
+            (clamped, clamped != ratio)
+        }
+        None => (ratio, false),
+    }
+}
+
+/// Watches the ring's fill for a steady creep in one direction, so compensation can start on drift
+/// alone, before there are enough xruns (see [`StreamSink::set_drift_threshold`]).
+#[derive(Debug, Clone, Default)]
+struct DriftWatch {
+    /// Fill (in frames) when watching started
+    start_fill: Option<f64>,
+    /// Frames processed since then
+    frames: u64,
+    detected: bool,
+}
+
+impl DriftWatch {
+    /// Takes the fill at the start of a callback that's about to process `block_frames` frames.
+    /// Drift is the change in fill per frame processed; the fill also jitters by about a block
+    /// either way, so changes smaller than that are ignored.
+    fn update(&mut self, fill: f64, block_frames: usize, threshold: f64) {
+        match self.start_fill {
+            Some(start_fill) => {
+                let change = (fill - start_fill).abs();
+
+                if change > 2.0 * block_frames as f64 && change > threshold * self.frames as f64 {
+                    self.detected = true;
+                }
+            }
+            None => {
+                self.start_fill = Some(fill);
+                self.frames = 0;
+            }
+        }
+
+        self.frames += block_frames as u64;
+    }
+
+    /// Starts measuring over, e.g. after an xrun made the fill jump.
+    fn restart(&mut self) {
+        self.start_fill = None;
+    }
+
+    fn reset(&mut self) {
+        *self = DriftWatch::default();
+    }
+}
+
+/// A snapshot of a [`StreamSink`]'s health, from [`StreamSink::metrics`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SinkMetrics {
+    pub xruns: u64,
+    pub underruns: u64,
+    pub overruns: u64,
+    pub strategy: CompensationStrategy,
+    /// The ratio currently being resampled at (1.0 if compensation isn't active)
+    pub resample_ratio: f64,
+    /// Rolling average of how full the ring is, as a fraction of its capacity
+    pub average_fill: f64,
+    /// Accumulated integral term of the PID
+    pub integral: f64,
+    /// See [`StreamSink::latency_frames`]
+    pub latency_frames: f64,
+    /// Whether the ratio limit was hit on the last callback (see [`StreamSink::set_ratio_limit`])
+    pub ratio_saturated: bool,
+    /// How many callbacks hit the ratio limit
+    pub ratio_saturations: u64,
+}
+
+/// A snapshot of a [`StreamSource`]'s health, from [`StreamSource::metrics`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceMetrics {
+    pub xruns: u64,
+    pub strategy: CompensationStrategy,
+    /// The ratio currently being resampled at (1.0 if compensation isn't active)
+    pub resample_ratio: f64,
+    /// Rolling average of how full the ring is, as a fraction of its capacity
+    pub average_fill: f64,
+    /// Accumulated integral term of the PID
+    pub integral: f64,
+    /// See [`StreamSource::latency_frames`]
+    pub latency_frames: f64,
+    /// Whether the ratio limit was hit on the last callback (see [`StreamSource::set_ratio_limit`])
+    pub ratio_saturated: bool,
+    /// How many callbacks hit the ratio limit
+    pub ratio_saturations: u64,
+}
+
+/// What happened during a call to [`StreamSink::output_samples_with_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaybackStatus {
+    /// Number of whole frames of valid audio written into the output buffer
+    pub frames_written: usize,
+    /// Whether the ring ran out of samples before the output buffer was filled
+    pub underran: bool,
+    /// Whether the ring was completely full at the start of the callback
+    pub overran: bool,
+}
+
+/// What a [`StreamSink`] writes into the rest of the output buffer after the ring underruns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnderrunFill {
+    /// Fill the rest of the buffer with zeros
+    Silence,
+    /// Linearly fade out from the last valid frame over `len` frames, then fill with zeros
+    Fade { len: usize },
+}
+
+/// What a [`StreamSink`] does to samples outside of ±1.0 before handing them to the device. Each
+/// sample is handled on its own, so none of these add latency or depend on the sample rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputLimiter {
+    /// Pass everything through untouched
+    #[default]
+    Off,
+    /// Clamp to ±1.0. Transparent below that, harsh above it
+    HardClip,
+    /// `tanh`, which bends smoothly towards ±1.0. Gentler on overshoots, but it compresses louder
+    /// in-range signals a little too
+    SoftClip,
+}
+
+impl OutputLimiter {
+    fn apply(&self, samples: &mut [f32]) {
+        match self {
+            OutputLimiter::Off => {}
+            OutputLimiter::HardClip => {
+                for sample in samples {
+                    *sample = sample.clamp(-1.0, 1.0);
+                }
+            }
+            OutputLimiter::SoftClip => {
+                for sample in samples {
+                    *sample = sample.tanh();
+                }
+            }
+        }
+    }
+}
+
 /// A stream sink, to be called from an audio callback. Using half of a ring
 /// buffer, it will automatically compensate for xruns by resampling in real-time
 /// (currently implemented using a PID targeting half ring capacity).
@@ -19,27 +192,60 @@ pub struct StreamSink {
     /// Total ring size
     ring_size: usize,
 
-    /// Previous values (for resampling)
-    last_frames: DMatrix<f32>,
+    /// Resampler (used once compensation kicks in)
+    resampler: Resampler,
 
     /// PID settings
     pid_settings: PidSettings,
     /// Values for calculating rolling average of available ring slots
-    rolling_ring_avg: [usize; ROLLING_AVG_LENGTH],
+    rolling_ring_avg: Vec<usize>,
     /// Integral part of PID
     ring_integral: f64,
     /// Last available slot average (for derivative part of PID)
     last_avg: f64,
-    /// \# of xruns
+    /// \# of xruns (`underruns + overruns`)
     pub xruns: u64,
+    /// \# of times the ring ran dry (the producer is too slow)
+    pub underruns: u64,
+    /// \# of times the ring was full (the producer is too fast)
+    pub overruns: u64,
 
     /// \# of xruns before starting compensation
     compensation_start_threshold: u64,
+    /// `Some(true)` if compensation was forced on, `Some(false)` if it was forced off, and `None`
+    /// if it's up to the xrun count
+    forced: Option<bool>,
+    /// Ratio set with `set_fixed_ratio`, which takes over from the PID
+    fixed_ratio: Option<f64>,
+    /// Ratio the PID's corrections are applied on top of (see `set_nominal_ratio`)
+    nominal_ratio: f64,
     /// Compensation strategy
     strategy: CompensationStrategy,
 
     /// Scratch for use during resampling
     resample_scratch: DMatrix<f32>,
+    /// Scratch for a single frame
+    frame_scratch: Vec<f32>,
+
+    /// What to do with the rest of the output buffer on underrun
+    underrun_fill: UnderrunFill,
+    /// Last frame written to the output (for fading out on underrun)
+    last_output_frame: Vec<f32>,
+    /// Maps the ring's channels to the output's (after resampling)
+    mixer: Option<ChannelMixer>,
+    /// Whether a full ring is drained back down to the target fill
+    drain_on_overrun: bool,
+    /// Applied to the output after resampling and mixing
+    output_limiter: OutputLimiter,
+    /// Start compensating once the ring drifts faster than this (frames of fill per frame played)
+    drift_threshold: Option<f64>,
+    drift: DriftWatch,
+    /// How far (as a fraction) the PID may take the ratio from the nominal ratio
+    ratio_limit: Option<f64>,
+    ratio_saturated: bool,
+    ratio_saturations: u64,
+    /// Called when the compensation strategy changes
+    on_state_change: Option<StateChangeFunc>,
 }
 
 impl StreamSink {
@@ -49,31 +255,62 @@ impl StreamSink {
     /// * `channels` - the number of channels
     /// * `compensation_start_threshold` - the number of xruns
     /// * `pid_settings` - various PID settings
+    ///
+    /// Panics if `channels` is 0, if the ring's capacity isn't a whole number of frames, or if
+    /// `pid_settings` are invalid (see [`PidSettings::validate`]).
     pub fn new(
         ring_in: rtrb::Consumer<f32>,
         channels: usize,
         compensation_start_threshold: u64,
         pid_settings: PidSettings,
     ) -> StreamSink {
+        if let Err(err) = pid_settings.validate() {
+            panic!("invalid PID settings: {err}");
+        }
+
         let ring_size = ring_in.buffer().capacity();
 
+        assert!(channels > 0, "there must be at least one channel");
+        assert!(
+            ring_size.is_multiple_of(channels),
+            "ring capacity ({ring_size}) must be a multiple of the channel count ({channels})"
+        );
+
         StreamSink {
             ring_in,
             ring_size,
             channels,
-            last_frames: DMatrix::zeros(FRAME_LOOKBACK, channels),
+            resampler: Resampler::new(channels, Interpolation::default()),
             pid_settings,
-            rolling_ring_avg: [0; ROLLING_AVG_LENGTH],
+            rolling_ring_avg: vec![0; ROLLING_AVG_LENGTH],
             ring_integral: 0.0,
             last_avg: 0.0,
             strategy: CompensationStrategy::None,
             compensation_start_threshold,
-            resample_scratch: DMatrix::zeros(4, channels),
+            forced: None,
+            fixed_ratio: None,
+            nominal_ratio: 1.0,
+            resample_scratch: DMatrix::zeros(max_samples_needed(MAX_RESAMPLE_RATIO), channels),
+            frame_scratch: vec![0.0; channels],
             xruns: 0,
+            underruns: 0,
+            overruns: 0,
+            underrun_fill: UnderrunFill::Silence,
+            last_output_frame: vec![0.0; channels],
+            mixer: None,
+            drain_on_overrun: true,
+            output_limiter: OutputLimiter::Off,
+            drift_threshold: None,
+            drift: DriftWatch::default(),
+            ratio_limit: Some(DEFAULT_RATIO_LIMIT),
+            ratio_saturated: false,
+            ratio_saturations: 0,
+            on_state_change: None,
         }
     }
 
-    /// Creates a stream sink with defaults (see [`StreamSink::new`]).
+    /// Creates a stream sink with defaults (see [`StreamSink::new`], or [`StreamSinkBuilder`] to
+    /// only override some of them).
     ///
     /// * `ring_in` - the `Consumer` half of a `rtrb` ring buffer (interleaved)
     /// * `channels` - the number of channels
@@ -81,39 +318,271 @@ impl StreamSink {
         Self::new(ring_in, channels, 15, PidSettings::default())
     }
 
+    /// Channel count of the ring.
     pub fn channels(&self) -> usize {
         self.channels
     }
 
+    /// Channel count of the buffers passed to [`StreamSink::output_samples`]. Same as
+    /// [`StreamSink::channels`] unless there's a channel mixer.
+    pub fn output_channels(&self) -> usize {
+        self.mixer.as_ref().map_or(self.channels, ChannelMixer::out_channels)
+    }
+
+    /// Mixes the ring's channels into a different number of output channels, e.g. to play a mono
+    /// ring on a stereo device. Mixing happens after resampling. This allocates, so it's best done
+    /// before the stream starts.
+    ///
+    /// Panics if the mixer's input channel count doesn't match the ring's.
+    pub fn set_channel_mixer(&mut self, mixer: ChannelMixer) {
+        assert_eq!(
+            mixer.in_channels(),
+            self.channels,
+            "the mixer's input must match the ring's channel count"
+        );
+
+        self.last_output_frame = vec![0.0; mixer.out_channels()];
+        self.mixer = Some(mixer);
+    }
+
+    /// Goes back to passing the ring's channels straight through (see
+    /// [`StreamSink::set_channel_mixer`]).
+    pub fn clear_channel_mixer(&mut self) {
+        self.mixer = None;
+        self.last_output_frame = vec![0.0; self.channels];
+    }
+
+    pub fn channel_mixer(&self) -> Option<&ChannelMixer> {
+        self.mixer.as_ref()
+    }
+
     /// See what strategy is currently being used.
     pub fn get_strategy(&self) -> &CompensationStrategy {
         &self.strategy
     }
 
-    /// Ensures that interleaved data is never unaligned. This is useful in the case
-    /// that the sink is reading data, but underruns halfway through a frame. We need
-    /// to make sure that the ring buffer is left in an aligned state between calls.
-    fn preserve_alignment(&mut self, channel_i: usize) {
-        let align = (self.channels - channel_i) % self.channels;
+    pub fn pid_settings(&self) -> &PidSettings {
+        &self.pid_settings
+    }
+
+    /// Registers a function that's called with the new strategy whenever it changes during
+    /// [`StreamSink::output_samples`] (e.g. when compensation kicks in).
+    ///
+    /// This runs on the audio thread, so it must not block, allocate, or take locks.
+    pub fn set_on_state_change(&mut self, on_state_change: impl FnMut(&CompensationStrategy) + Send + 'static) {
+        self.on_state_change = Some(Box::new(on_state_change));
+    }
+
+    fn notify_state_change(&mut self) {
+        if let Some(on_state_change) = &mut self.on_state_change {
+            on_state_change(&self.strategy);
+        }
+    }
+
+    /// Sets which interpolation kernel to use when resampling (defaults to
+    /// [`Interpolation::Hermite`]). This reallocates the resampling history, so it shouldn't be
+    /// called from the audio thread, and is best done before the stream starts.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.resampler.set_interpolation(interpolation);
+    }
+
+    /// Enables or disables anti-aliasing before resampling (see [`Resampler::set_anti_aliasing`]).
+    pub fn set_anti_aliasing(&mut self, enabled: bool) {
+        self.resampler.set_anti_aliasing(enabled);
+    }
+
+    /// Enables or disables replacing NaN/infinite input while resampling (see
+    /// [`Resampler::set_sanitize`]). Passthrough (when compensation isn't active) is never touched.
+    pub fn set_sanitize(&mut self, enabled: bool) {
+        self.resampler.set_sanitize(enabled);
+    }
+
+    /// Sets how many callbacks the ring fill is averaged over before it's fed to the PID (defaults
+    /// to [`ROLLING_AVG_LENGTH`]). A longer window smooths out a jittery fill better, which suits
+    /// slow, stable clocks, but it also makes the loop react later. The derivative term is
+    /// computed from the averaged fill, so a longer window also tames it (and a very short one
+    /// makes it noisy); the gains may need retuning after changing this. This allocates, so it's
+    /// best done before the stream starts.
+    pub fn set_rolling_avg_length(&mut self, length: usize) {
+        assert!(length > 0, "the rolling average needs at least one value");
+
+        self.rolling_ring_avg = vec![0; length];
+    }
+
+    pub fn rolling_avg_length(&self) -> usize {
+        self.rolling_ring_avg.len()
+    }
+
+    /// Sets whether a full ring gets whole frames thrown away (oldest first) until it's back down
+    /// to the target fill (on by default). Without this, a producer that's persistently faster
+    /// keeps the ring pinned full until compensation catches up, and never does if compensation
+    /// is disabled. Turning it off keeps every sample, at the cost of a full ring's worth of
+    /// latency.
+    pub fn set_drain_on_overrun(&mut self, enabled: bool) {
+        self.drain_on_overrun = enabled;
+    }
+
+    /// Limits how far the PID can take the resample ratio from the nominal ratio, as a fraction
+    /// (defaults to [`DEFAULT_RATIO_LIMIT`], i.e. ±2000 ppm). Real clocks drift by far less than
+    /// that, so a ratio pinned at the limit means something is badly wrong (like a mismatched
+    /// sample rate) rather than drift; [`SinkMetrics::ratio_saturated`] says when that happens.
+    /// This is separate from `min_factor`/`max_factor` in the PID settings, which bound each step,
+    /// and doesn't apply to [`StreamSink::set_fixed_ratio`].
+    pub fn set_ratio_limit(&mut self, limit: f64) {
+        assert!(limit > 0.0 && limit < 1.0, "ratio limit must be between 0 and 1");
+
+        self.ratio_limit = Some(limit);
+    }
+
+    /// Lets the PID take the ratio as far as `min_factor`/`max_factor` allow.
+    pub fn clear_ratio_limit(&mut self) {
+        self.ratio_limit = None;
+        self.ratio_saturated = false;
+    }
+
+    pub fn ratio_limit(&self) -> Option<f64> {
+        self.ratio_limit
+    }
+
+    /// Also starts compensation (when it's on auto) once the ring's fill drifts by more than
+    /// `threshold` frames per frame played, e.g. `0.0005` for a clock that's 500 ppm off. Unlike
+    /// the xrun count, this catches a producer that's only slightly fast or slow, long before the
+    /// ring actually runs dry or fills up. The xrun count still applies too; to go by drift alone,
+    /// set `compensation_start_threshold` to `u64::MAX`.
+    ///
+    /// Drift is measured from the first callback with `measure_xruns` set, and the measurement
+    /// starts over after an xrun. The fill naturally moves around by a block or so, so it has to
+    /// change by more than two blocks before drift counts; the smaller the drift, the longer that
+    /// takes.
+    pub fn set_drift_threshold(&mut self, threshold: f64) {
+        assert!(threshold > 0.0, "drift threshold must be positive");
+
+        self.drift_threshold = Some(threshold);
+        self.drift.restart();
+    }
+
+    /// Goes back to only counting xruns to decide when to compensate.
+    pub fn clear_drift_threshold(&mut self) {
+        self.drift_threshold = None;
+        self.drift.reset();
+    }
+
+    pub fn drift_threshold(&self) -> Option<f64> {
+        self.drift_threshold
+    }
 
-        for _ in 0..align {
-            while self.ring_in.pop().is_err() {
-                thread::sleep(Duration::from_micros(50));
+    /// Sets how to keep the output within ±1.0 (defaults to [`OutputLimiter::Off`]). This runs last,
+    /// after resampling and channel mixing.
+    pub fn set_output_limiter(&mut self, output_limiter: OutputLimiter) {
+        self.output_limiter = output_limiter;
+    }
+
+    /// Sets what is written into the rest of the output buffer when the ring underruns (defaults to
+    /// [`UnderrunFill::Silence`]).
+    pub fn set_underrun_fill(&mut self, underrun_fill: UnderrunFill) {
+        self.underrun_fill = underrun_fill;
+    }
+
+    /// Fills the part of `buffer_out` starting at frame `frames_written` according to `underrun_fill`.
+    fn fill_underrun(&self, buffer_out: &mut [f32], frames_written: usize) {
+        let channels = self.output_channels();
+        let (written, unwritten) = buffer_out.split_at_mut(frames_written * channels);
+
+        let start_frame = if frames_written > 0 {
+            &written[(written.len() - channels)..]
+        } else {
+            &self.last_output_frame[..]
+        };
+
+        let fade_len = match self.underrun_fill {
+            UnderrunFill::Silence => 0,
+            UnderrunFill::Fade { len } => len,
+        };
+
+        for (frame_i, frame) in unwritten.chunks_exact_mut(channels).enumerate() {
+            let gain = if frame_i < fade_len {
+                1.0 - (frame_i + 1) as f32 / (fade_len + 1) as f32
+            } else {
+                0.0
+            };
+
+            for (sample_out, start) in frame.iter_mut().zip(start_frame) {
+                *sample_out = start * gain;
             }
         }
     }
 
-    fn handle_xrun(&mut self, measure_xruns: bool) {
+    /// Pops the next frame into `frame_scratch`. The ring is only ever read a whole frame at a time,
+    /// so if the producer is partway through writing one, it's left for the next call (and this
+    /// returns false) rather than splitting it.
+    fn pop_frame(&mut self) -> bool {
+        match self.ring_in.read_chunk(self.channels) {
+            Ok(chunk) => {
+                for (sample_out, sample) in self.frame_scratch.iter_mut().zip(chunk) {
+                    *sample_out = sample;
+                }
+
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Writes `frame_scratch` (one frame in the ring's layout) into output frame `frame_i`, through
+    /// the mixer if there is one.
+    fn write_frame(&self, buffer_out: &mut [f32], frame_i: usize) {
+        match &self.mixer {
+            Some(mixer) => {
+                let channels = mixer.out_channels();
+                mixer.mix(
+                    &self.frame_scratch,
+                    &mut buffer_out[(frame_i * channels)..((frame_i + 1) * channels)],
+                );
+            }
+            None => buffer_out[(frame_i * self.channels)..((frame_i + 1) * self.channels)]
+                .copy_from_slice(&self.frame_scratch),
+        }
+    }
+
+    fn compensation_due(&self) -> bool {
+        self.fixed_ratio.is_some()
+            || self.nominal_ratio != 1.0
+            || self
+                .forced
+                .unwrap_or(self.xruns >= self.compensation_start_threshold || self.drift.detected)
+    }
+
+    fn handle_underrun(&mut self, measure_xruns: bool) {
+        // the fill stops tracking the clocks while there's nothing to play
+        self.drift.restart();
+
         // if it's during the startup phase, don't count xruns
         if measure_xruns {
-            self.xruns += 1;
+            self.underruns = self.underruns.saturating_add(1);
+            self.xruns = self.xruns.saturating_add(1);
         }
     }
 
-    fn clean_up(&mut self, channel_i: usize, measure_xruns: bool) {
-        // make sure we don't get channels unaligned
-        self.preserve_alignment(channel_i);
-        self.handle_xrun(measure_xruns);
+    fn handle_overrun(&mut self, measure_xruns: bool) {
+        self.drift.restart();
+
+        if measure_xruns {
+            self.overruns = self.overruns.saturating_add(1);
+            self.xruns = self.xruns.saturating_add(1);
+        }
+    }
+
+    /// Throws away the oldest samples until the ring is back down to the target fill, so a full
+    /// ring doesn't stay pinned there (with the producer dropping everything new).
+    fn drain_to_target(&mut self) {
+        let target = (self.ring_size as f64 * self.pid_settings.target_fill) as usize;
+        let target = target - target % self.channels;
+        let excess = self.ring_in.slots().saturating_sub(target);
+
+        // the ring is always left aligned between calls, and `excess` is a whole number of frames
+        if let Ok(chunk) = self.ring_in.read_chunk(excess) {
+            chunk.commit_all();
+        }
     }
 
     /// Meant to be called from an audio callback. This outputs the stream into whatever buffer the
@@ -121,31 +590,66 @@ impl StreamSink {
     /// start resampling by trying to keep the ring at half capacity (implemented with rolling average
     /// and PID).
     ///
+    /// See [`StreamSink::output_samples_with_status`] if you need to know how much of `buffer_out`
+    /// was actually written.
+    ///
     /// * `buffer_out` - audio callback buffer to be written into
     /// * `measure_xruns` - whether to measure xruns. Helpful for startup, as there may be some xruns
-    ///    while things are all getting set up (which should not be counted for compensation check).
+    ///   while things are all getting set up (which should not be counted for compensation check).
     pub fn output_samples(&mut self, buffer_out: &mut [f32], measure_xruns: bool) {
-        debug_assert_eq!(buffer_out.len() % self.channels, 0);
+        self.output_samples_with_status(buffer_out, measure_xruns);
+    }
+
+    /// Same as [`StreamSink::output_samples`], but reports how many frames were written and whether
+    /// the ring under- or overran. If the ring underruns, anything in `buffer_out` past
+    /// `frames_written` is filled according to [`StreamSink::set_underrun_fill`].
+    ///
+    /// * `buffer_out` - audio callback buffer to be written into
+    /// * `measure_xruns` - whether to measure xruns (see [`StreamSink::output_samples`])
+    pub fn output_samples_with_status(&mut self, buffer_out: &mut [f32], measure_xruns: bool) -> PlaybackStatus {
+        let out_channels = self.output_channels();
+        debug_assert_eq!(buffer_out.len() % out_channels, 0);
+
+        let frames_out_len = buffer_out.len() / out_channels;
+        let mut ring_slots = self.ring_in.slots();
 
-        let frames_out_len = buffer_out.len() / self.channels;
-        let ring_slots = self.ring_in.slots();
+        let mut status = PlaybackStatus {
+            frames_written: frames_out_len,
+            underran: false,
+            overran: false,
+        };
+
+        // the PID sees how full the ring was before any draining, so overruns push the ratio up
+        // just like underruns push it down
+        let measured_slots = ring_slots;
 
         if ring_slots == self.ring_size {
-            self.handle_xrun(measure_xruns);
-            // don't end function because of overrun
+            self.handle_overrun(measure_xruns);
+            status.overran = true;
+
+            // don't end function because of overrun, just get rid of the backlog
+            if self.drain_on_overrun {
+                self.drain_to_target();
+                ring_slots = self.ring_in.slots();
+            }
         }
 
-        if self.xruns >= self.compensation_start_threshold {
-            let avg = self.rolling_ring_avg.iter().map(|x| *x as f64).sum::<f64>()
-                / self.rolling_ring_avg.len() as f64
-                / self.ring_size as f64;
+        if let Some(drift_threshold) = self.drift_threshold {
+            if measure_xruns && !self.compensation_due() {
+                self.drift
+                    .update((ring_slots / self.channels) as f64, frames_out_len, drift_threshold);
+            }
+        }
 
-            // target is half of capacity
-            // TODO: let target be more flexible
-            let target = 0.5;
-            let error = avg - target;
+        if self.compensation_due() {
+            let avg = rolling_avg(&self.rolling_ring_avg, self.ring_size);
 
-            self.ring_integral += error;
+            let error = avg - self.pid_settings.target_fill;
+
+            // don't wind up the integral while the ratio is fixed (or pinned at its limit)
+            if self.fixed_ratio.is_none() && !self.ratio_saturated {
+                self.ring_integral += error;
+            }
 
             // PID controls
             let proportional = error * self.pid_settings.prop_factor;
@@ -155,30 +659,47 @@ impl StreamSink {
             let new_factor = (proportional + integrative + derivative)
                 .max(self.pid_settings.min_factor)
                 .min(self.pid_settings.max_factor);
-            let new_ratio = 2_f64.powf(new_factor);
+            let new_ratio = match self.fixed_ratio {
+                Some(fixed_ratio) => fixed_ratio,
+                None => {
+                    let (ratio, saturated) = limit_ratio(
+                        self.nominal_ratio * 2_f64.powf(new_factor),
+                        self.nominal_ratio,
+                        self.ratio_limit,
+                    );
+                    self.ratio_saturated = saturated;
+                    if saturated {
+                        self.ratio_saturations = self.ratio_saturations.saturating_add(1);
+                    }
 
-            if let CompensationStrategy::None = self.strategy {
-                // we've drifted enough that we should start using a strategy
-                println!("sample rate compensation activated");
+                    ratio
+                }
+            }
+            .min(MAX_RESAMPLE_RATIO);
 
+            if let CompensationStrategy::None = self.strategy {
+                // we've drifted enough that we should start using a strategy (announced through
+                // `on_state_change` below, since printing from the audio thread can block)
                 // reset integral so it doesn't overshoot
                 self.ring_integral = 0.0;
 
                 self.strategy = CompensationStrategy::Resample {
-                    resample_ratio: 1.0,
+                    resample_ratio: self.nominal_ratio,
                     time: 0.0,
                 };
+                self.notify_state_change();
+                self.resampler.reset();
 
-                // fill up `last` with values for hermite interpolation
-                'outer: for frame_i in 1..FRAME_LOOKBACK {
-                    for channel_i in 0..self.channels {
-                        if let Ok(sample_in) = self.ring_in.pop() {
-                            self.last_frames[(frame_i, channel_i)] = sample_in;
-                        } else {
-                            self.clean_up(channel_i, measure_xruns);
-                            break 'outer;
-                        }
+                // fill up the resampler's history with values for interpolation
+                for _ in 1..self.resampler.interpolation().lookback() {
+                    if !self.pop_frame() {
+                        self.handle_underrun(measure_xruns);
+                        status.underran = true;
+
+                        break;
                     }
+
+                    self.resampler.push_frame(&self.frame_scratch);
                 }
 
                 self.last_avg = avg;
@@ -189,312 +710,2455 @@ impl StreamSink {
         }
 
         self.rolling_ring_avg.rotate_left(1);
-        self.rolling_ring_avg[self.rolling_ring_avg.len() - 1] = ring_slots;
+        if let Some(newest) = self.rolling_ring_avg.last_mut() {
+            *newest = measured_slots;
+        }
 
         match self.strategy {
             CompensationStrategy::None | CompensationStrategy::Never => {
-                for (i, sample_out) in buffer_out.iter_mut().enumerate() {
-                    if let Ok(sample) = self.ring_in.pop() {
-                        *sample_out = sample;
-                    } else {
-                        self.clean_up(i % self.channels, measure_xruns);
+                for frame_i in 0..frames_out_len {
+                    if !self.pop_frame() {
+                        self.handle_underrun(measure_xruns);
+                        status.frames_written = frame_i;
+                        status.underran = true;
 
                         break;
                     }
+
+                    self.write_frame(buffer_out, frame_i);
                 }
             }
-            CompensationStrategy::Resample {
-                resample_ratio,
-                mut time,
-            } => {
-                'outer: for frame_i in 0..frames_out_len {
-                    let needed_new_samples = new_samples_needed(resample_ratio, time);
-                    let mut next_time: f64 = 0.0;
-
-                    for new_sample_i in 0..needed_new_samples {
-                        for channel_i in 0..self.channels {
-                            if let Ok(sample) = self.ring_in.pop() {
-                                self.resample_scratch[(new_sample_i, channel_i)] = sample;
-                            } else {
-                                self.clean_up(channel_i, measure_xruns);
-
-                                break 'outer;
-                            }
-                        }
-                    }
+            CompensationStrategy::Resample { resample_ratio, .. } => {
+                for frame_i in 0..frames_out_len {
+                    let needed_new_samples = self.resampler.frames_needed(resample_ratio);
 
-                    for (channel_i, mut channel) in self.last_frames.column_iter_mut().enumerate() {
-                        let (out, new_time) = resample(
-                            resample_ratio,
-                            self.resample_scratch.column(channel_i).iter().copied(),
-                            &mut channel,
-                            time,
-                        );
+                    // all or nothing, so an underrun doesn't lose the frames that were there
+                    let Ok(chunk) = self.ring_in.read_chunk(needed_new_samples * self.channels) else {
+                        self.handle_underrun(measure_xruns);
+                        status.frames_written = frame_i;
+                        status.underran = true;
 
-                        next_time = new_time;
+                        break;
+                    };
 
-                        buffer_out[frame_i * self.channels + channel_i] = out;
+                    for (i, sample) in chunk.into_iter().enumerate() {
+                        self.resample_scratch[(i / self.channels, i % self.channels)] = sample;
                     }
 
-                    time = next_time;
+                    self.resampler.step(
+                        resample_ratio,
+                        self.resample_scratch.rows(0, needed_new_samples),
+                        &mut self.frame_scratch,
+                    );
+                    self.write_frame(buffer_out, frame_i);
                 }
+
+                self.store_time();
             }
         }
-    }
 
-    /// Forces compensation to start
-    pub fn enable_compensation(&mut self) {
-        self.xruns = self.compensation_start_threshold;
-        self.strategy = CompensationStrategy::None;
-    }
+        self.output_limiter
+            .apply(&mut buffer_out[..(status.frames_written * out_channels)]);
 
-    /// Forces compensation to never happen
-    pub fn disable_compensation(&mut self) {
-        self.xruns = 0;
-        self.strategy = CompensationStrategy::Never;
-    }
+        if status.underran {
+            self.fill_underrun(buffer_out, status.frames_written);
+        }
 
-    /// Resets mode to auto (default mode), as well as resetting xruns.
-    pub fn reset_compensation(&mut self) {
-        self.xruns = 0;
-        self.strategy = CompensationStrategy::None;
+        if frames_out_len > 0 {
+            self.last_output_frame
+                .copy_from_slice(&buffer_out[((frames_out_len - 1) * out_channels)..]);
+        }
+
+        status
     }
-}
 
-pub struct StreamSource {
-    ring_out: rtrb::Producer<f32>,
-    channels: usize,
-    ring_size: usize,
+    /// How full the ring currently is, as a fraction of its capacity.
+    pub fn ring_fill(&self) -> f64 {
+        self.ring_in.slots() as f64 / self.ring_size as f64
+    }
 
-    last_frames: DMatrix<f32>,
-    local_buffer: VecDeque<f32>,
+    /// Estimated latency introduced by this sink, in frames. This includes samples waiting in the
+    /// ring and the delay of the resampler (if it's active).
+    pub fn latency_frames(&self) -> f64 {
+        self.ring_in.slots() as f64 / self.channels as f64 + resampler_delay(&self.strategy, &self.resampler)
+    }
 
-    /// PID settings
-    pid_settings: PidSettings,
-    /// Values for calculating rolling average of available ring slots
-    rolling_ring_avg: [usize; ROLLING_AVG_LENGTH],
-    /// Integral part of PID
-    ring_integral: f64,
-    /// Last available slot average (for derivative part of PID)
-    last_avg: f64,
-    /// \# of xruns
-    pub xruns: usize,
+    /// All of the health numbers at once. Cheap, so it can be called from the audio thread.
+    pub fn metrics(&self) -> SinkMetrics {
+        SinkMetrics {
+            xruns: self.xruns,
+            underruns: self.underruns,
+            overruns: self.overruns,
+            strategy: self.strategy,
+            resample_ratio: current_ratio(&self.strategy),
+            average_fill: rolling_avg(&self.rolling_ring_avg, self.ring_size),
+            integral: self.ring_integral,
+            latency_frames: self.latency_frames(),
+            ratio_saturated: self.ratio_saturated,
+            ratio_saturations: self.ratio_saturations,
+        }
+    }
 
-    /// \# of xruns before starting compensation
-    compensation_start_threshold: usize,
-    /// Compensation strategy
-    strategy: CompensationStrategy,
+    /// Estimated latency introduced by this sink (see [`StreamSink::latency_frames`]).
+    pub fn latency_duration(&self, sample_rate: f64) -> Duration {
+        Duration::from_secs_f64(self.latency_frames() / sample_rate)
+    }
 
-    /// Scratch for use during resampling
-    resample_scratch: DMatrix<f32>,
-}
+    /// Keeps the time reported by [`CompensationStrategy::Resample`] up to date with the resampler.
+    fn store_time(&mut self) {
+        if let CompensationStrategy::Resample { time, .. } = &mut self.strategy {
+            *time = self.resampler.time();
+        }
+    }
 
-impl StreamSource {
-    /// Creates a stream source.
+    /// Puts the sink back into the state it was in when it was constructed: PID accumulators, xruns,
+    /// and resampling history are all cleared, and compensation goes back to auto. Useful after a
+    /// transport stop/start or device change.
     ///
-    /// * `ring_out` - the `Producer` half of a `rtrb` ring buffer (interleaved)
-    /// * `channels` - the number of channels
-    /// * `compensation_start_threshold` - the number of xruns
-    /// * `startup_time` - how long to wait before measuring xruns
-    /// * `pid_settings` - various PID settings
-    pub fn new(
-        ring_out: rtrb::Producer<f32>,
-        channels: usize,
-        compensation_start_threshold: usize,
-        pid_settings: PidSettings,
-    ) -> StreamSource {
-        let ring_size = ring_out.buffer().capacity();
-
-        StreamSource {
-            ring_out,
-            channels,
-            ring_size,
-            last_frames: DMatrix::zeros(FRAME_LOOKBACK, channels),
-            local_buffer: VecDeque::with_capacity(ring_size),
-            pid_settings,
-            rolling_ring_avg: [0; ROLLING_AVG_LENGTH],
-            ring_integral: 0.0,
-            last_avg: 0.0,
-            xruns: 0,
-            compensation_start_threshold,
-            strategy: CompensationStrategy::None,
-            resample_scratch: DMatrix::zeros(4, channels),
+    /// * `drain_ring` - whether to also throw away everything currently waiting in the ring
+    pub fn reset(&mut self, drain_ring: bool) {
+        if drain_ring {
+            // the ring is always left aligned between calls, so this drains whole frames
+            let available = self.ring_in.slots();
+
+            if let Ok(chunk) = self.ring_in.read_chunk(available) {
+                chunk.commit_all();
+            }
         }
+
+        self.resampler.reset();
+        self.rolling_ring_avg.fill(0);
+        self.ring_integral = 0.0;
+        self.last_avg = 0.0;
+        self.last_output_frame.fill(0.0);
+        self.xruns = 0;
+        self.underruns = 0;
+        self.overruns = 0;
+        self.drift.reset();
+        self.ratio_saturated = false;
+        self.ratio_saturations = 0;
+        self.forced = None;
+        self.strategy = CompensationStrategy::None;
     }
 
-    /// Creates a stream source with defaults (see [`StreamSource::new`]).
-    ///
-    /// * `ring_out` - the `Producer` half of a `rtrb` ring buffer (interleaved)
-    /// * `channels` - the number of channels
-    pub fn with_defaults(ring_out: rtrb::Producer<f32>, channels: usize) -> StreamSource {
-        Self::new(ring_out, channels, 15, PidSettings::default())
+    /// Forces compensation to start, regardless of how many xruns there have been
+    pub fn enable_compensation(&mut self) {
+        self.forced = Some(true);
+        self.strategy = CompensationStrategy::None;
     }
 
-    pub fn channels(&self) -> usize {
-        self.channels
+    /// Forces compensation to never happen, regardless of how many xruns there have been
+    pub fn disable_compensation(&mut self) {
+        self.forced = Some(false);
+        self.strategy = CompensationStrategy::Never;
     }
 
-    /// See what strategy is currently being used.
-    pub fn get_strategy(&self) -> &CompensationStrategy {
-        &self.strategy
+    /// Whether compensation was forced on (`Some(true)`) or off (`Some(false)`) with
+    /// `enable_compensation`/`disable_compensation`. `None` means it's decided by the xrun count
+    /// (and drift, if [`StreamSink::set_drift_threshold`] was used).
+    pub fn forced_compensation(&self) -> Option<bool> {
+        self.forced
     }
 
-    /// Ensures that interleaved data in the ring is never unaligned. This is useful in the case
-    /// that the source is reading data, but overruns halfway through a frame. We need to make sure
-    /// that the ring buffer is left in an aligned state between calls.
-    fn preserve_alignment(&mut self, channel_i: usize) {
-        let align = (self.channels - channel_i) % self.channels;
+    /// Resamples at a fixed `ratio` (input frames per output frame) instead of letting the PID
+    /// steer, e.g. for varispeed or detuning. Above 1.0 plays back faster (and higher). The ratio
+    /// still glides there according to `factor_last_interp` in the PID settings, so setting it
+    /// every callback makes for smooth sweeps. Ratios above [`MAX_RESAMPLE_RATIO`] are clamped.
+    pub fn set_fixed_ratio(&mut self, ratio: f64) {
+        assert!(ratio > 0.0, "resample ratio must be positive");
 
-        for _ in 0..align {
-            while self.ring_out.push(0.0).is_err() {
-                thread::sleep(Duration::from_micros(50));
-            }
+        self.fixed_ratio = Some(ratio);
+
+        if let CompensationStrategy::Never = self.strategy {
+            self.strategy = CompensationStrategy::None;
         }
     }
 
-    fn handle_xrun(&mut self, measure_xruns: bool) {
-        // if it's during the startup phase, don't count xruns
-        if measure_xruns {
-            self.xruns += 1;
+    /// Sets a ratio (input frames per output frame) that the PID's drift corrections are applied on
+    /// top of, for when the producer runs at a known, different sample rate (e.g. `48000.0 /
+    /// 44100.0` for a 48k app on a 44.1k device). While this isn't 1.0, the sink always resamples,
+    /// regardless of the xrun count. Defaults to 1.0.
+    ///
+    /// Panics if `ratio` isn't positive or is above [`MAX_RESAMPLE_RATIO`].
+    pub fn set_nominal_ratio(&mut self, ratio: f64) {
+        assert!(ratio > 0.0, "resample ratio must be positive");
+        assert!(
+            ratio <= MAX_RESAMPLE_RATIO,
+            "nominal ratio {ratio} is above the maximum of {MAX_RESAMPLE_RATIO}"
+        );
+
+        self.nominal_ratio = ratio;
+
+        if let CompensationStrategy::Never = self.strategy {
+            self.strategy = CompensationStrategy::None;
         }
     }
 
-    fn clean_up(&mut self, channel_i: usize, measure_xruns: bool) {
-        // make sure we don't get channels unaligned
-        self.preserve_alignment(channel_i);
-        self.handle_xrun(measure_xruns);
+    pub fn nominal_ratio(&self) -> f64 {
+        self.nominal_ratio
+    }
 
-        // we're screwed regardless, but this should make sure local_buffer doesn't grow forever
-        self.local_buffer.clear();
+    /// Hands control back to the PID (see [`StreamSink::set_fixed_ratio`]).
+    pub fn clear_fixed_ratio(&mut self) {
+        self.fixed_ratio = None;
     }
 
-    pub fn input_samples(&mut self, buffer_in: impl IntoIterator<Item = f32>, buffer_len: usize, measure_xruns: bool) {
-        let ring_slots = self.ring_out.slots();
+    pub fn fixed_ratio(&self) -> Option<f64> {
+        self.fixed_ratio
+    }
 
-        if ring_slots < 10 {
+    /// Resets the xrun count without changing the compensation mode or strategy.
+    pub fn clear_xruns(&mut self) {
+        self.xruns = 0;
+        self.underruns = 0;
+        self.overruns = 0;
+    }
+
+    /// Resets mode to auto (default mode), as well as resetting xruns and detected drift.
+    pub fn reset_compensation(&mut self) {
+        self.xruns = 0;
+        self.underruns = 0;
+        self.overruns = 0;
+        self.drift.reset();
+        self.ratio_saturated = false;
+        self.ratio_saturations = 0;
+        self.forced = None;
+        self.strategy = CompensationStrategy::None;
+    }
+}
+
+/// Builds a [`StreamSink`] without a long list of positional arguments. Anything that isn't set
+/// keeps the same default as [`StreamSink::with_defaults`].
+#[derive(Debug, Clone)]
+pub struct StreamSinkBuilder {
+    compensation_start_threshold: u64,
+    pid_settings: PidSettings,
+    interpolation: Interpolation,
+    anti_aliasing: bool,
+    sanitize: bool,
+    underrun_fill: UnderrunFill,
+    channel_mixer: Option<ChannelMixer>,
+    rolling_avg_length: usize,
+    drain_on_overrun: bool,
+    output_limiter: OutputLimiter,
+    drift_threshold: Option<f64>,
+    ratio_limit: Option<f64>,
+}
+
+impl Default for StreamSinkBuilder {
+    fn default() -> Self {
+        StreamSinkBuilder {
+            compensation_start_threshold: 15,
+            pid_settings: PidSettings::default(),
+            interpolation: Interpolation::default(),
+            anti_aliasing: false,
+            sanitize: false,
+            underrun_fill: UnderrunFill::Silence,
+            channel_mixer: None,
+            rolling_avg_length: ROLLING_AVG_LENGTH,
+            drain_on_overrun: true,
+            output_limiter: OutputLimiter::Off,
+            drift_threshold: None,
+            ratio_limit: Some(DEFAULT_RATIO_LIMIT),
+        }
+    }
+}
+
+impl StreamSinkBuilder {
+    pub fn new() -> StreamSinkBuilder {
+        StreamSinkBuilder::default()
+    }
+
+    /// The number of xruns before compensation starts
+    pub fn compensation_start_threshold(mut self, compensation_start_threshold: u64) -> Self {
+        self.compensation_start_threshold = compensation_start_threshold;
+        self
+    }
+
+    pub fn pid_settings(mut self, pid_settings: PidSettings) -> Self {
+        self.pid_settings = pid_settings;
+        self
+    }
+
+    /// See [`StreamSink::set_interpolation`]
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// See [`StreamSink::set_anti_aliasing`]
+    pub fn anti_aliasing(mut self, enabled: bool) -> Self {
+        self.anti_aliasing = enabled;
+        self
+    }
+
+    /// See [`StreamSink::set_sanitize`]
+    pub fn sanitize(mut self, enabled: bool) -> Self {
+        self.sanitize = enabled;
+        self
+    }
+
+    /// See [`StreamSink::set_underrun_fill`]
+    pub fn underrun_fill(mut self, underrun_fill: UnderrunFill) -> Self {
+        self.underrun_fill = underrun_fill;
+        self
+    }
+
+    /// See [`StreamSink::set_channel_mixer`]
+    pub fn channel_mixer(mut self, mixer: ChannelMixer) -> Self {
+        self.channel_mixer = Some(mixer);
+        self
+    }
+
+    /// See [`StreamSink::set_rolling_avg_length`]
+    pub fn rolling_avg_length(mut self, length: usize) -> Self {
+        self.rolling_avg_length = length;
+        self
+    }
+
+    /// See [`StreamSink::set_drain_on_overrun`]
+    pub fn drain_on_overrun(mut self, enabled: bool) -> Self {
+        self.drain_on_overrun = enabled;
+        self
+    }
+
+    /// See [`StreamSink::set_output_limiter`]
+    pub fn output_limiter(mut self, output_limiter: OutputLimiter) -> Self {
+        self.output_limiter = output_limiter;
+        self
+    }
+
+    /// See [`StreamSink::set_drift_threshold`]
+    pub fn drift_threshold(mut self, threshold: f64) -> Self {
+        self.drift_threshold = Some(threshold);
+        self
+    }
+
+    /// See [`StreamSink::set_ratio_limit`]; `None` turns the limit off.
+    pub fn ratio_limit(mut self, limit: Option<f64>) -> Self {
+        self.ratio_limit = limit;
+        self
+    }
+
+    /// Creates the sink.
+    ///
+    /// * `ring_in` - the `Consumer` half of a `rtrb` ring buffer (interleaved)
+    /// * `channels` - the number of channels
+    ///
+    /// Panics under the same conditions as [`StreamSink::new`] and [`StreamSink::set_channel_mixer`].
+    pub fn build(self, ring_in: rtrb::Consumer<f32>, channels: usize) -> StreamSink {
+        let mut sink = StreamSink::new(ring_in, channels, self.compensation_start_threshold, self.pid_settings);
+
+        // avoid reallocating the resampler if nothing changed
+        if self.interpolation != Interpolation::default() {
+            sink.set_interpolation(self.interpolation);
+        }
+        sink.set_anti_aliasing(self.anti_aliasing);
+        sink.set_sanitize(self.sanitize);
+        sink.set_underrun_fill(self.underrun_fill);
+        sink.set_drain_on_overrun(self.drain_on_overrun);
+        sink.set_output_limiter(self.output_limiter);
+        if let Some(threshold) = self.drift_threshold {
+            sink.set_drift_threshold(threshold);
+        }
+        match self.ratio_limit {
+            Some(limit) => sink.set_ratio_limit(limit),
+            None => sink.clear_ratio_limit(),
+        }
+        if let Some(mixer) = self.channel_mixer {
+            sink.set_channel_mixer(mixer);
+        }
+        if self.rolling_avg_length != ROLLING_AVG_LENGTH {
+            sink.set_rolling_avg_length(self.rolling_avg_length);
+        }
+
+        sink
+    }
+}
+
+/// A [`StreamSink`] bundled together with the producing half of its ring. This is handy for tests,
+/// offline processing, or single-threaded pipelines where there isn't a separate thread to hold the
+/// `Producer`.
+pub struct LoopbackSink {
+    /// Outgoing samples (feeding `sink`)
+    ring_out: rtrb::Producer<f32>,
+    /// The sink being fed
+    pub sink: StreamSink,
+}
+
+impl LoopbackSink {
+    /// Creates a loopback sink, using [`StreamSink::with_defaults`] for the inner sink.
+    ///
+    /// * `ring_size` - the size of the ring, in frames
+    /// * `channels` - the number of channels
+    pub fn new(ring_size: usize, channels: usize) -> LoopbackSink {
+        let (ring_out, ring_in) = RingBuffer::new(ring_size * channels);
+
+        LoopbackSink {
+            ring_out,
+            sink: StreamSink::with_defaults(ring_in, channels),
+        }
+    }
+
+    /// Pushes interleaved samples into the ring. Only whole frames are accepted, so the ring always
+    /// stays aligned. Returns how many samples were accepted.
+    pub fn push_input(&mut self, samples: &[f32]) -> usize {
+        let channels = self.sink.channels();
+        let accepted = samples.len().min(self.ring_out.slots()) / channels * channels;
+
+        for sample in &samples[..accepted] {
+            self.ring_out.push(*sample).expect("ring space was checked above");
+        }
+
+        accepted
+    }
+
+    /// See [`StreamSink::output_samples`].
+    pub fn output_samples(&mut self, buffer_out: &mut [f32], measure_xruns: bool) {
+        self.sink.output_samples(buffer_out, measure_xruns);
+    }
+}
+
+/// Default for [`StreamSink::set_ratio_limit`] and [`StreamSource::set_ratio_limit`]: 2000 ppm.
+pub const DEFAULT_RATIO_LIMIT: f64 = 0.002;
+
+/// Default low water mark of a [`StreamSource`], as a fraction of the ring's capacity.
+pub const DEFAULT_LOW_WATER_MARK: f64 = 0.01;
+
+fn low_water_mark_slots(ring_size: usize, fraction: f64) -> usize {
+    // a completely full ring should always count
+    ((ring_size as f64 * fraction) as usize).max(1)
+}
+
+/// How many samples a [`StreamSource`]'s local buffer has to hold: a whole block, plus whatever the
+/// resampler left over from the last one (always less than one step's worth).
+fn local_buffer_capacity(max_block_frames: usize, channels: usize) -> usize {
+    (max_block_frames + max_samples_needed(MAX_RESAMPLE_RATIO)) * channels
+}
+
+pub struct StreamSource {
+    ring_out: rtrb::Producer<f32>,
+    channels: usize,
+    ring_size: usize,
+
+    resampler: Resampler,
+    /// Input waiting to be resampled. Sized up front so it never reallocates on the audio thread
+    local_buffer: VecDeque<f32>,
+    /// The most frames `input_samples` accepts at once
+    max_block_frames: usize,
+
+    /// PID settings
+    pid_settings: PidSettings,
+    /// Values for calculating rolling average of available ring slots
+    rolling_ring_avg: Vec<usize>,
+    /// Integral part of PID
+    ring_integral: f64,
+    /// Last available slot average (for derivative part of PID)
+    last_avg: f64,
+    /// \# of xruns
+    pub xruns: u64,
+
+    /// \# of xruns before starting compensation
+    compensation_start_threshold: u64,
+    /// `Some(true)` if compensation was forced on, `Some(false)` if it was forced off, and `None`
+    /// if it's up to the xrun count
+    forced: Option<bool>,
+    /// Ratio set with `set_fixed_ratio`, which takes over from the PID
+    fixed_ratio: Option<f64>,
+    /// Compensation strategy
+    strategy: CompensationStrategy,
+    /// If fewer than this many slots are free in the ring, it's counted as an xrun
+    low_water_mark: usize,
+    /// Start compensating once the ring drifts faster than this (frames of fill per frame taken in)
+    drift_threshold: Option<f64>,
+    drift: DriftWatch,
+    /// How far (as a fraction) the PID may take the ratio from 1.0
+    ratio_limit: Option<f64>,
+    ratio_saturated: bool,
+    ratio_saturations: u64,
+
+    /// Scratch for use during resampling
+    resample_scratch: DMatrix<f32>,
+    /// Scratch for a single frame
+    frame_scratch: Vec<f32>,
+    /// Maps the input's channels to the ring's (before resampling)
+    mixer: Option<ChannelMixer>,
+    /// Scratch for a single input frame, while mixing
+    mix_scratch: Vec<f32>,
+    /// Called when the compensation strategy changes
+    on_state_change: Option<StateChangeFunc>,
+}
+
+impl StreamSource {
+    /// Creates a stream source.
+    ///
+    /// * `ring_out` - the `Producer` half of a `rtrb` ring buffer (interleaved)
+    /// * `channels` - the number of channels
+    /// * `compensation_start_threshold` - the number of xruns
+    /// * `startup_time` - how long to wait before measuring xruns
+    /// * `pid_settings` - various PID settings
+    ///
+    /// Panics if `channels` is 0, if the ring's capacity isn't a whole number of frames, or if
+    /// `pid_settings` are invalid (see [`PidSettings::validate`]).
+    pub fn new(
+        ring_out: rtrb::Producer<f32>,
+        channels: usize,
+        compensation_start_threshold: u64,
+        pid_settings: PidSettings,
+    ) -> StreamSource {
+        if let Err(err) = pid_settings.validate() {
+            panic!("invalid PID settings: {err}");
+        }
+
+        let ring_size = ring_out.buffer().capacity();
+
+        assert!(channels > 0, "there must be at least one channel");
+        assert!(
+            ring_size.is_multiple_of(channels),
+            "ring capacity ({ring_size}) must be a multiple of the channel count ({channels})"
+        );
+
+        StreamSource {
+            ring_out,
+            channels,
+            ring_size,
+            resampler: Resampler::new(channels, Interpolation::default()),
+            local_buffer: VecDeque::with_capacity(local_buffer_capacity(ring_size / channels, channels)),
+            max_block_frames: ring_size / channels,
+            pid_settings,
+            rolling_ring_avg: vec![0; ROLLING_AVG_LENGTH],
+            ring_integral: 0.0,
+            last_avg: 0.0,
+            xruns: 0,
+            compensation_start_threshold,
+            forced: None,
+            fixed_ratio: None,
+            strategy: CompensationStrategy::None,
+            low_water_mark: low_water_mark_slots(ring_size, DEFAULT_LOW_WATER_MARK),
+            drift_threshold: None,
+            drift: DriftWatch::default(),
+            ratio_limit: Some(DEFAULT_RATIO_LIMIT),
+            ratio_saturated: false,
+            ratio_saturations: 0,
+            resample_scratch: DMatrix::zeros(max_samples_needed(MAX_RESAMPLE_RATIO), channels),
+            frame_scratch: vec![0.0; channels],
+            mixer: None,
+            mix_scratch: Vec::new(),
+            on_state_change: None,
+        }
+    }
+
+    /// Creates a stream source with defaults (see [`StreamSource::new`]).
+    ///
+    /// * `ring_out` - the `Producer` half of a `rtrb` ring buffer (interleaved)
+    /// * `channels` - the number of channels
+    pub fn with_defaults(ring_out: rtrb::Producer<f32>, channels: usize) -> StreamSource {
+        Self::new(ring_out, channels, 15, PidSettings::default())
+    }
+
+    /// Channel count of the ring.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Channel count of the samples passed to [`StreamSource::input_samples`]. Same as
+    /// [`StreamSource::channels`] unless there's a channel mixer.
+    pub fn input_channels(&self) -> usize {
+        self.mixer.as_ref().map_or(self.channels, ChannelMixer::in_channels)
+    }
+
+    /// Mixes the input's channels into the ring's channel count, e.g. to feed a stereo ring from a
+    /// mono device. Mixing happens before resampling. This allocates, so it's best done before the
+    /// stream starts.
+    ///
+    /// Panics if the mixer's output channel count doesn't match the ring's.
+    pub fn set_channel_mixer(&mut self, mixer: ChannelMixer) {
+        assert_eq!(
+            mixer.out_channels(),
+            self.channels,
+            "the mixer's output must match the ring's channel count"
+        );
+
+        self.mix_scratch = vec![0.0; mixer.in_channels()];
+        self.mixer = Some(mixer);
+    }
+
+    /// Goes back to passing the input straight into the ring (see
+    /// [`StreamSource::set_channel_mixer`]).
+    pub fn clear_channel_mixer(&mut self) {
+        self.mixer = None;
+    }
+
+    pub fn channel_mixer(&self) -> Option<&ChannelMixer> {
+        self.mixer.as_ref()
+    }
+
+    /// See what strategy is currently being used.
+    pub fn get_strategy(&self) -> &CompensationStrategy {
+        &self.strategy
+    }
+
+    pub fn pid_settings(&self) -> &PidSettings {
+        &self.pid_settings
+    }
+
+    /// Registers a function that's called with the new strategy whenever it changes during
+    /// [`StreamSource::input_samples`] (e.g. when compensation kicks in).
+    ///
+    /// This runs on the audio thread, so it must not block, allocate, or take locks.
+    pub fn set_on_state_change(&mut self, on_state_change: impl FnMut(&CompensationStrategy) + Send + 'static) {
+        self.on_state_change = Some(Box::new(on_state_change));
+    }
+
+    fn notify_state_change(&mut self) {
+        if let Some(on_state_change) = &mut self.on_state_change {
+            on_state_change(&self.strategy);
+        }
+    }
+
+    /// Sets which interpolation kernel to use when resampling (defaults to
+    /// [`Interpolation::Hermite`]). This reallocates the resampling history, so it shouldn't be
+    /// called from the audio thread, and is best done before the stream starts.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.resampler.set_interpolation(interpolation);
+    }
+
+    /// Enables or disables anti-aliasing before resampling (see [`Resampler::set_anti_aliasing`]).
+    pub fn set_anti_aliasing(&mut self, enabled: bool) {
+        self.resampler.set_anti_aliasing(enabled);
+    }
+
+    /// Enables or disables replacing NaN/infinite input while resampling (see
+    /// [`Resampler::set_sanitize`]). Passthrough (when compensation isn't active) is never touched.
+    pub fn set_sanitize(&mut self, enabled: bool) {
+        self.resampler.set_sanitize(enabled);
+    }
+
+    /// Sets how many callbacks the ring fill is averaged over before it's fed to the PID (defaults
+    /// to [`ROLLING_AVG_LENGTH`]). A longer window smooths out a jittery fill better, which suits
+    /// slow, stable clocks, but it also makes the loop react later. The derivative term is
+    /// computed from the averaged fill, so a longer window also tames it (and a very short one
+    /// makes it noisy); the gains may need retuning after changing this. This allocates, so it's
+    /// best done before the stream starts.
+    pub fn set_rolling_avg_length(&mut self, length: usize) {
+        assert!(length > 0, "the rolling average needs at least one value");
+
+        self.rolling_ring_avg = vec![0; length];
+    }
+
+    pub fn rolling_avg_length(&self) -> usize {
+        self.rolling_ring_avg.len()
+    }
+
+    /// Sets the most frames [`StreamSource::input_samples`] accepts in one call (defaults to the
+    /// ring's capacity in frames). The local buffer is sized from this, so it never has to grow on
+    /// the audio thread. This allocates, so it's best done before the stream starts.
+    pub fn set_max_block_frames(&mut self, frames: usize) {
+        assert!(frames > 0, "the maximum block size must be at least one frame");
+
+        let capacity = local_buffer_capacity(frames, self.channels);
+        self.local_buffer
+            .reserve(capacity.saturating_sub(self.local_buffer.len()));
+        self.max_block_frames = frames;
+    }
+
+    pub fn max_block_frames(&self) -> usize {
+        self.max_block_frames
+    }
+
+    /// Sets how close to full the ring may get before it is counted as an xrun, as a fraction of the
+    /// ring's capacity (defaults to [`DEFAULT_LOW_WATER_MARK`]).
+    pub fn set_low_water_mark(&mut self, fraction: f64) {
+        self.low_water_mark = low_water_mark_slots(self.ring_size, fraction);
+    }
+
+    /// Same as [`StreamSink::set_ratio_limit`], around a ratio of 1.0.
+    pub fn set_ratio_limit(&mut self, limit: f64) {
+        assert!(limit > 0.0 && limit < 1.0, "ratio limit must be between 0 and 1");
+
+        self.ratio_limit = Some(limit);
+    }
+
+    /// Lets the PID take the ratio as far as `min_factor`/`max_factor` allow.
+    pub fn clear_ratio_limit(&mut self) {
+        self.ratio_limit = None;
+        self.ratio_saturated = false;
+    }
+
+    pub fn ratio_limit(&self) -> Option<f64> {
+        self.ratio_limit
+    }
+
+    /// Same as [`StreamSink::set_drift_threshold`], with drift measured per frame taken in.
+    pub fn set_drift_threshold(&mut self, threshold: f64) {
+        assert!(threshold > 0.0, "drift threshold must be positive");
+
+        self.drift_threshold = Some(threshold);
+        self.drift.restart();
+    }
+
+    /// Goes back to only counting xruns to decide when to compensate.
+    pub fn clear_drift_threshold(&mut self) {
+        self.drift_threshold = None;
+        self.drift.reset();
+    }
+
+    pub fn drift_threshold(&self) -> Option<f64> {
+        self.drift_threshold
+    }
+
+    fn compensation_due(&self) -> bool {
+        self.fixed_ratio.is_some()
+            || self
+                .forced
+                .unwrap_or(self.xruns >= self.compensation_start_threshold || self.drift.detected)
+    }
+
+    fn handle_xrun(&mut self, measure_xruns: bool) {
+        self.drift.restart();
+
+        // if it's during the startup phase, don't count xruns
+        if measure_xruns {
+            self.xruns = self.xruns.saturating_add(1);
+        }
+    }
+
+    fn clean_up(&mut self, measure_xruns: bool) {
+        self.handle_xrun(measure_xruns);
+
+        // we're screwed regardless, but this should make sure local_buffer doesn't grow forever
+        self.local_buffer.clear();
+    }
+
+    /// Meant to be called from an audio callback. This pushes the (interleaved) samples the audio
+    /// callback provides into the ring, resampling them once there have been more xruns than
+    /// `compensation_start_threshold`.
+    ///
+    /// * `buffer_in` - interleaved samples; only the first `buffer_len` are used
+    /// * `buffer_len` - how many samples are in `buffer_in` (must be a whole number of frames, with
+    ///   [`StreamSource::input_channels`] samples per frame)
+    /// * `measure_xruns` - whether to measure xruns (see [`StreamSink::output_samples`])
+    ///
+    /// If `buffer_in` yields fewer than `buffer_len` samples, the whole call is ignored.
+    ///
+    /// Panics if `buffer_len` is more than [`StreamSource::max_block_frames`] frames; split bigger
+    /// blocks up across several calls.
+    pub fn input_samples(&mut self, buffer_in: impl IntoIterator<Item = f32>, buffer_len: usize, measure_xruns: bool) {
+        let input_channels = self.input_channels();
+
+        assert_eq!(buffer_len % input_channels, 0);
+        assert!(
+            buffer_len / input_channels <= self.max_block_frames,
+            "block of {} frames is larger than the maximum of {}",
+            buffer_len / input_channels,
+            self.max_block_frames
+        );
+        debug_assert_eq!(self.local_buffer.len() % self.channels, 0); // basic sanity check
+
+        let capacity = self.local_buffer.capacity();
+
+        let previous_len = self.local_buffer.len();
+        let complete = match &self.mixer {
+            Some(mixer) => {
+                let mut buffer_in = buffer_in.into_iter().take(buffer_len);
+                let mut complete = true;
+
+                'frames: for _ in 0..(buffer_len / mixer.in_channels()) {
+                    for sample_in in self.mix_scratch.iter_mut() {
+                        match buffer_in.next() {
+                            Some(sample) => *sample_in = sample,
+                            None => {
+                                complete = false;
+                                break 'frames;
+                            }
+                        }
+                    }
+
+                    mixer.mix(&self.mix_scratch, &mut self.frame_scratch);
+                    self.local_buffer.extend(self.frame_scratch.iter().copied());
+                }
+
+                complete
+            }
+            None => {
+                self.local_buffer.extend(buffer_in.into_iter().take(buffer_len));
+
+                self.local_buffer.len() - previous_len == buffer_len
+            }
+        };
+
+        debug_assert_eq!(self.local_buffer.capacity(), capacity, "local buffer reallocated");
+
+        if !complete {
+            // the iterator came up short, so drop what it did give rather than misalign the frames
+            self.local_buffer.truncate(previous_len);
+
+            return;
+        }
+
+        let ring_slots = self.ring_out.slots();
+
+        if ring_slots < self.low_water_mark {
             self.handle_xrun(measure_xruns);
         }
 
-        assert_eq!(buffer_len % self.channels, 0);
-        debug_assert_eq!(self.local_buffer.len() % self.channels, 0); // basic sanity check
+        if let Some(drift_threshold) = self.drift_threshold {
+            if measure_xruns && !self.compensation_due() {
+                self.drift.update(
+                    (ring_slots / self.channels) as f64,
+                    buffer_len / input_channels,
+                    drift_threshold,
+                );
+            }
+        }
+
+        if self.compensation_due() {
+            // the ring's free slots are what's tracked, so flip that around to get how full it is
+            let avg = 1.0 - rolling_avg(&self.rolling_ring_avg, self.ring_size);
+            let error = avg - self.pid_settings.target_fill;
+
+            // don't wind up the integral while the ratio is fixed (or pinned at its limit)
+            if self.fixed_ratio.is_none() && !self.ratio_saturated {
+                self.ring_integral += error;
+            }
+
+            // PID controls
+            let proportional = error * self.pid_settings.prop_factor;
+            let integrative = self.ring_integral * self.pid_settings.integ_factor;
+            let derivative = (avg - self.last_avg) * self.pid_settings.deriv_factor;
+
+            let new_factor = (proportional + integrative + derivative)
+                .max(self.pid_settings.min_factor)
+                .min(self.pid_settings.max_factor);
+            let new_ratio = match self.fixed_ratio {
+                Some(fixed_ratio) => fixed_ratio,
+                None => {
+                    let (ratio, saturated) = limit_ratio(2_f64.powf(new_factor), 1.0, self.ratio_limit);
+                    self.ratio_saturated = saturated;
+                    if saturated {
+                        self.ratio_saturations = self.ratio_saturations.saturating_add(1);
+                    }
+
+                    ratio
+                }
+            }
+            .min(MAX_RESAMPLE_RATIO);
+
+            if let CompensationStrategy::None = self.strategy {
+                // we've drifted enough that we should start using a strategy (announced through
+                // `on_state_change` below, since printing from the audio thread can block)
+                // reset integral so it doesn't overshoot
+                self.ring_integral = 0.0;
+
+                self.strategy = CompensationStrategy::Resample {
+                    resample_ratio: 1.0,
+                    time: 0.0,
+                };
+                self.notify_state_change();
+                self.resampler.reset();
+
+                // fill up the resampler's history with values for interpolation (as much as this
+                // buffer allows, the rest of the history stays silent)
+                for _ in 1..self.resampler.interpolation().lookback() {
+                    if self.local_buffer.len() < self.channels {
+                        break;
+                    }
+
+                    for (channel_i, sample) in self.local_buffer.drain(..self.channels).enumerate() {
+                        self.frame_scratch[channel_i] = sample;
+                    }
+
+                    self.resampler.push_frame(&self.frame_scratch);
+                }
+
+                self.last_avg = avg;
+            } else if let CompensationStrategy::Resample { resample_ratio, .. } = &mut self.strategy {
+                // lerp to help detune not to slide around too much
+                *resample_ratio = lerp(*resample_ratio, new_ratio, self.pid_settings.factor_last_interp);
+            }
+        }
+
+        self.rolling_ring_avg.rotate_left(1);
+        if let Some(newest) = self.rolling_ring_avg.last_mut() {
+            *newest = ring_slots;
+        }
+
+        match self.strategy {
+            CompensationStrategy::None | CompensationStrategy::Never => {
+                // only whole frames go in, so the ring is never left partway through one
+                let fits = self.local_buffer.len().min(self.ring_out.slots()) / self.channels * self.channels;
+
+                for sample in self.local_buffer.iter().take(fits) {
+                    self.ring_out.push(*sample).expect("ring space was checked above");
+                }
+
+                if fits < self.local_buffer.len() {
+                    self.clean_up(measure_xruns);
+                }
+
+                self.local_buffer.clear();
+            }
+            CompensationStrategy::Resample { resample_ratio, .. } => {
+                'outer: loop {
+                    let new_sample_count = self.resampler.frames_needed(resample_ratio);
+
+                    // do we have enough?
+                    if self.local_buffer.len() >= new_sample_count * self.channels {
+                        for (i, sample) in self
+                            .local_buffer
+                            .drain(0..(self.channels * new_sample_count))
+                            .enumerate()
+                        {
+                            self.resample_scratch[(i / self.channels, i % self.channels)] = sample;
+                        }
+
+                        self.resampler.step(
+                            resample_ratio,
+                            self.resample_scratch.rows(0, new_sample_count),
+                            &mut self.frame_scratch,
+                        );
+
+                        if self.ring_out.slots() < self.channels {
+                            self.clean_up(measure_xruns);
+
+                            break 'outer;
+                        }
+
+                        for sample in &self.frame_scratch {
+                            self.ring_out.push(*sample).expect("ring space was checked above");
+                        }
+                    } else {
+                        break;
+                    }
+                }
+
+                self.store_time();
+            }
+        }
+    }
+
+    /// Meant for the end of a stream: pushes out everything still held back, returning how many
+    /// frames went into the ring. While resampling, that's the input waiting in the local buffer
+    /// for the next output frame plus the resampler's delay, with the last frame repeated to fill
+    /// out the interpolation window (the same as [`convert_slice`](crate::resample::convert_slice)
+    /// does at the end).
+    ///
+    /// This leaves the resampler's history padded, so call [`StreamSource::reset`] before feeding
+    /// in a new stream. Stops early (counting an xrun) if the ring fills up.
+    pub fn flush(&mut self, measure_xruns: bool) -> usize {
+        let frames_left = self.local_buffer.len() / self.channels;
+
+        let CompensationStrategy::Resample { resample_ratio, .. } = self.strategy else {
+            let fits = frames_left.min(self.ring_out.slots() / self.channels);
+
+            for sample in self.local_buffer.drain(..(fits * self.channels)) {
+                self.ring_out.push(sample).expect("ring space was checked above");
+            }
+
+            if !self.local_buffer.is_empty() {
+                self.clean_up(measure_xruns);
+            }
+
+            return fits;
+        };
+
+        // outputs step `resample_ratio` input frames at a time, starting `delay` frames behind the
+        // newest frame taken in, so this is how many land on or before the last input frame
+        let frames_out = ((frames_left as f64 + self.resampler.delay()) / resample_ratio) as usize + 1;
+        let mut written = 0;
+
+        for _ in 0..frames_out {
+            if self.ring_out.slots() < self.channels {
+                self.clean_up(measure_xruns);
+
+                break;
+            }
+
+            // past the end of the input, the resampler repeats the newest frame
+            let available = self
+                .resampler
+                .frames_needed(resample_ratio)
+                .min(self.local_buffer.len() / self.channels);
+            for (i, sample) in self.local_buffer.drain(..(available * self.channels)).enumerate() {
+                self.resample_scratch[(i / self.channels, i % self.channels)] = sample;
+            }
+
+            self.resampler.step(
+                resample_ratio,
+                self.resample_scratch.rows(0, available),
+                &mut self.frame_scratch,
+            );
+
+            for sample in &self.frame_scratch {
+                self.ring_out.push(*sample).expect("ring space was checked above");
+            }
+            written += 1;
+        }
+
+        self.local_buffer.clear();
+        self.store_time();
+
+        written
+    }
+
+    /// How many more frames the ring can take right now, after whatever is still waiting in the
+    /// local buffer goes in. Feeding at most this much avoids overrunning the ring (and the
+    /// recovery that comes with it). This counts frames as they land in the ring: while resampling,
+    /// each input frame turns into roughly `1 / resample_ratio` of them.
+    pub fn available_frames(&self) -> usize {
+        let pending = self.local_buffer.len() / self.channels;
+
+        (self.ring_out.slots() / self.channels).saturating_sub(pending)
+    }
+
+    /// Estimated latency introduced by this source, in frames. This includes samples waiting in the
+    /// ring, samples buffered locally, and the delay of the resampler (if it's active).
+    pub fn latency_frames(&self) -> f64 {
+        let ring_frames = (self.ring_size - self.ring_out.slots()) as f64 / self.channels as f64;
+        let local_frames = self.local_buffer.len() as f64 / self.channels as f64;
+
+        ring_frames + local_frames + resampler_delay(&self.strategy, &self.resampler)
+    }
+
+    /// All of the health numbers at once. Cheap, so it can be called from the audio thread.
+    pub fn metrics(&self) -> SourceMetrics {
+        SourceMetrics {
+            xruns: self.xruns,
+            strategy: self.strategy,
+            resample_ratio: current_ratio(&self.strategy),
+            // the source tracks free slots, so flip it around
+            average_fill: 1.0 - rolling_avg(&self.rolling_ring_avg, self.ring_size),
+            integral: self.ring_integral,
+            latency_frames: self.latency_frames(),
+            ratio_saturated: self.ratio_saturated,
+            ratio_saturations: self.ratio_saturations,
+        }
+    }
+
+    /// Estimated latency introduced by this source (see [`StreamSource::latency_frames`]).
+    pub fn latency_duration(&self, sample_rate: f64) -> Duration {
+        Duration::from_secs_f64(self.latency_frames() / sample_rate)
+    }
+
+    /// Keeps the time reported by [`CompensationStrategy::Resample`] up to date with the resampler.
+    fn store_time(&mut self) {
+        if let CompensationStrategy::Resample { time, .. } = &mut self.strategy {
+            *time = self.resampler.time();
+        }
+    }
+
+    /// Puts the source back into the state it was in when it was constructed: PID accumulators,
+    /// xruns, resampling history, and any locally buffered input are all cleared, and compensation
+    /// goes back to auto. Samples already pushed into the ring are left alone.
+    pub fn reset(&mut self) {
+        self.resampler.reset();
+        self.local_buffer.clear();
+        self.rolling_ring_avg.fill(0);
+        self.ring_integral = 0.0;
+        self.last_avg = 0.0;
+        self.xruns = 0;
+        self.drift.reset();
+        self.ratio_saturated = false;
+        self.ratio_saturations = 0;
+        self.forced = None;
+        self.strategy = CompensationStrategy::None;
+    }
+
+    /// Forces compensation to start, regardless of how many xruns there have been
+    pub fn enable_compensation(&mut self) {
+        self.forced = Some(true);
+        self.strategy = CompensationStrategy::None;
+    }
+
+    /// Forces compensation to never happen, regardless of how many xruns there have been
+    pub fn disable_compensation(&mut self) {
+        self.forced = Some(false);
+        self.strategy = CompensationStrategy::Never;
+    }
+
+    /// Whether compensation was forced on (`Some(true)`) or off (`Some(false)`) with
+    /// `enable_compensation`/`disable_compensation`. `None` means it's decided by the xrun count
+    /// (and drift, if [`StreamSource::set_drift_threshold`] was used).
+    pub fn forced_compensation(&self) -> Option<bool> {
+        self.forced
+    }
+
+    /// Resamples at a fixed `ratio` (input frames per output frame) instead of letting the PID
+    /// steer, e.g. for varispeed or detuning. Above 1.0 squeezes the input into fewer frames. The
+    /// ratio still glides there according to `factor_last_interp` in the PID settings, so setting
+    /// it every callback makes for smooth sweeps. Ratios above [`MAX_RESAMPLE_RATIO`] are clamped.
+    pub fn set_fixed_ratio(&mut self, ratio: f64) {
+        assert!(ratio > 0.0, "resample ratio must be positive");
+
+        self.fixed_ratio = Some(ratio);
+
+        if let CompensationStrategy::Never = self.strategy {
+            self.strategy = CompensationStrategy::None;
+        }
+    }
+
+    /// Hands control back to the PID (see [`StreamSource::set_fixed_ratio`]).
+    pub fn clear_fixed_ratio(&mut self) {
+        self.fixed_ratio = None;
+    }
+
+    pub fn fixed_ratio(&self) -> Option<f64> {
+        self.fixed_ratio
+    }
+
+    /// Resets the xrun count without changing the compensation mode or strategy.
+    pub fn clear_xruns(&mut self) {
+        self.xruns = 0;
+    }
+
+    /// Resets mode to auto (default mode), as well as resetting xruns and detected drift.
+    pub fn auto_compensation(&mut self) {
+        self.xruns = 0;
+        self.drift.reset();
+        self.ratio_saturated = false;
+        self.ratio_saturations = 0;
+        self.forced = None;
+        self.strategy = CompensationStrategy::None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use rtrb::RingBuffer;
+
+    use super::*;
+
+    /// A sink on a ring of `ring_frames` frames, with the producer half to feed it.
+    fn sink_with_ring(ring_frames: usize, channels: usize) -> (rtrb::Producer<f32>, StreamSink) {
+        let (producer, consumer) = RingBuffer::new(ring_frames * channels);
+
+        (producer, StreamSink::with_defaults(consumer, channels))
+    }
+
+    fn push_all(producer: &mut rtrb::Producer<f32>, samples: impl IntoIterator<Item = f32>) {
+        for sample in samples {
+            producer.push(sample).unwrap();
+        }
+    }
+
+    #[test]
+    fn full_buffer_plays_cleanly() {
+        let (mut producer, mut sink) = sink_with_ring(16, 2);
+        push_all(&mut producer, (0..16).map(|i| i as f32));
+
+        let mut out = [0.0; 8];
+        let status = sink.output_samples_with_status(&mut out, true);
+
+        assert_eq!(
+            status,
+            PlaybackStatus {
+                frames_written: 4,
+                underran: false,
+                overran: false,
+            }
+        );
+        assert_eq!(out, [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(sink.xruns, 0);
+    }
+
+    #[test]
+    fn partial_underrun_reports_frames_written() {
+        let (mut producer, mut sink) = sink_with_ring(16, 2);
+        push_all(&mut producer, [0.1, 0.2, 0.3, 0.4]);
+
+        let mut out = [9.0; 8];
+        let status = sink.output_samples_with_status(&mut out, true);
+
+        assert_eq!(status.frames_written, 2);
+        assert!(status.underran);
+        assert!(!status.overran);
+        assert_eq!(&out[..4], &[0.1, 0.2, 0.3, 0.4]);
+        assert_eq!(sink.underruns, 1);
+        assert_eq!(sink.xruns, 1);
+    }
+
+    #[test]
+    fn full_ring_reports_overrun() {
+        let (mut producer, mut sink) = sink_with_ring(8, 1);
+        push_all(&mut producer, (0..8).map(|i| i as f32));
+
+        let mut out = [0.0; 2];
+        let status = sink.output_samples_with_status(&mut out, true);
+
+        assert!(status.overran);
+        assert!(!status.underran);
+        assert_eq!(status.frames_written, 2);
+        assert_eq!(sink.overruns, 1);
+        // drained back down to half full, oldest first
+        assert_eq!(out, [4.0, 5.0]);
+    }
+
+    #[test]
+    fn xruns_during_startup_are_not_counted() {
+        let (_producer, mut sink) = sink_with_ring(8, 1);
+
+        let status = sink.output_samples_with_status(&mut [0.0; 4], false);
+
+        assert!(status.underran);
+        assert_eq!(sink.xruns, 0);
+    }
+
+    #[test]
+    fn loopback_round_trips_a_block() {
+        let mut loopback = LoopbackSink::new(8, 2);
+
+        assert_eq!(loopback.push_input(&[0.1, 0.2, 0.3, 0.4]), 4);
+
+        let mut out = [0.0; 4];
+        loopback.output_samples(&mut out, true);
+
+        assert_eq!(out, [0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn loopback_only_accepts_whole_frames_that_fit() {
+        let mut loopback = LoopbackSink::new(2, 2);
+
+        // a trailing partial frame is never accepted
+        assert_eq!(loopback.push_input(&[0.1, 0.2, 0.3]), 2);
+        // only one more frame fits
+        assert_eq!(loopback.push_input(&[0.4, 0.5, 0.6, 0.7]), 2);
+    }
+
+    #[test]
+    fn underrun_fills_the_tail_with_silence() {
+        let (mut producer, mut sink) = sink_with_ring(8, 1);
+        push_all(&mut producer, [0.5, 0.5]);
+
+        let mut out = [9.0; 6];
+        sink.output_samples(&mut out, true);
+
+        assert_eq!(out, [0.5, 0.5, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn underrun_fades_out_from_the_last_frame() {
+        let (mut producer, mut sink) = sink_with_ring(16, 1);
+        sink.set_underrun_fill(UnderrunFill::Fade { len: 4 });
+        push_all(&mut producer, [1.0, 1.0]);
+
+        let mut out = [9.0; 8];
+        sink.output_samples(&mut out, true);
+
+        let tail = &out[2..];
+        assert!(tail.windows(2).all(|pair| pair[1] < pair[0] || pair[1] == 0.0));
+        assert!(tail[..4].iter().all(|sample| *sample > 0.0 && *sample < 1.0));
+        assert_eq!(&tail[4..], &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn fade_continues_from_the_previous_callback() {
+        let (mut producer, mut sink) = sink_with_ring(16, 1);
+        sink.set_underrun_fill(UnderrunFill::Fade { len: 2 });
+        push_all(&mut producer, [0.9]);
+        sink.output_samples(&mut [0.0], true);
+
+        // nothing at all this time, so the fade starts from the last frame played
+        let mut out = [9.0; 3];
+        sink.output_samples(&mut out, true);
+
+        assert!(out[0] > out[1] && out[1] > 0.0);
+        assert_eq!(out[2], 0.0);
+    }
+
+    /// A source on a ring of `ring_frames` frames, with the consumer half to drain it.
+    fn source_with_ring(ring_frames: usize, channels: usize) -> (StreamSource, rtrb::Consumer<f32>) {
+        let (producer, consumer) = RingBuffer::new(ring_frames * channels);
+
+        (StreamSource::with_defaults(producer, channels), consumer)
+    }
+
+    /// Fills a mono source's ring until only `free` slots are left, then feeds one more block so
+    /// it gets a look at that.
+    fn xruns_with_free_slots(source: &mut StreamSource, free: usize) -> u64 {
+        let filled = source.available_frames() - free;
+        source.input_samples(vec![0.0; filled], filled, true);
+        source.input_samples([0.0], 1, true);
+
+        source.xruns
+    }
+
+    #[test]
+    fn low_water_mark_scales_with_the_ring() {
+        // 10 free slots is plenty for a small ring...
+        let (mut small, _consumer) = source_with_ring(100, 1);
+        assert_eq!(xruns_with_free_slots(&mut small, 10), 0);
+
+        // ...but not for a big one (1% of 10000 is 100)
+        let (mut large, _consumer) = source_with_ring(10_000, 1);
+        assert_eq!(xruns_with_free_slots(&mut large, 10), 1);
+    }
+
+    #[test]
+    fn low_water_mark_is_configurable() {
+        let (mut source, _consumer) = source_with_ring(100, 1);
+        source.set_low_water_mark(0.2);
+
+        assert_eq!(xruns_with_free_slots(&mut source, 10), 1);
+    }
+
+    /// How far from 1.0 the ratio of a mono source aiming for `target_fill` ends up, with its
+    /// ring held a fifth full.
+    fn ratio_offset_at_a_fifth_full(target_fill: f64) -> f64 {
+        let (producer, mut consumer) = RingBuffer::new(100);
+        let mut source = StreamSource::new(producer, 1, 15, PidSettings::default().with_target_fill(target_fill));
+        source.set_rolling_avg_length(1);
+        source.input_samples(vec![0.0; 20], 20, true);
+
+        for i in 0..50 {
+            // let the rolling average see the steady fill first
+            if i == 2 {
+                source.enable_compensation();
+            }
+
+            source.input_samples([0.0; 10], 10, true);
+
+            while consumer.slots() > 20 {
+                consumer.pop().unwrap();
+            }
+        }
+
+        (source.metrics().resample_ratio - 1.0).abs()
+    }
+
+    #[test]
+    fn source_target_fill_sets_where_the_pid_aims() {
+        let on_target = ratio_offset_at_a_fifth_full(0.2);
+        let off_target = ratio_offset_at_a_fifth_full(0.5);
+
+        assert!(on_target < 1e-4, "{on_target}");
+        assert!(off_target > 10.0 * on_target, "{off_target} vs {on_target}");
+    }
+
+    #[test]
+    fn sink_latency_counts_the_ring() {
+        let (mut producer, sink) = sink_with_ring(32, 2);
+        push_all(&mut producer, vec![0.0; 20]);
+
+        assert_eq!(sink.latency_frames(), 10.0);
+        assert_eq!(sink.latency_duration(1000.0), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn sink_latency_includes_the_resampler_delay() {
+        let (mut producer, mut sink) = sink_with_ring(64, 1);
+        sink.set_fixed_ratio(1.0);
+        push_all(&mut producer, vec![0.0; 40]);
+
+        sink.output_samples(&mut [0.0; 4], true);
+
+        let CompensationStrategy::Resample { time, .. } = *sink.get_strategy() else {
+            panic!("sink should be resampling");
+        };
+        let ring_frames = producer.buffer().capacity() - producer.slots();
+        // Hermite interpolates between the middle two of its four frames
+        assert_eq!(sink.latency_frames(), ring_frames as f64 + 2.0 - time);
+    }
+
+    #[test]
+    fn source_latency_counts_the_ring_and_local_buffer() {
+        let (mut source, _consumer) = source_with_ring(32, 2);
+        source.input_samples(vec![0.0; 12], 12, true);
+
+        assert_eq!(source.latency_frames(), 6.0);
+
+        source.set_fixed_ratio(1.5);
+        source.input_samples(vec![0.0; 8], 8, true);
+
+        let CompensationStrategy::Resample { time, .. } = *source.get_strategy() else {
+            panic!("source should be resampling");
+        };
+        let ring_frames = (64 - source.ring_out.slots()) / 2;
+        let local_frames = source.local_buffer.len() / 2;
+        assert_eq!(
+            source.latency_frames(),
+            ring_frames as f64 + local_frames as f64 + 2.0 - time
+        );
+    }
+
+    /// Feeds a sine through `sink` a block at a time, returning everything it played.
+    fn play_sine(producer: &mut rtrb::Producer<f32>, sink: &mut StreamSink, blocks: usize) -> Vec<f32> {
+        let mut played = Vec::new();
+
+        for block in 0..blocks {
+            push_all(producer, (0..8).map(|i| ((block * 8 + i) as f32 * 0.1).sin()));
+
+            let mut out = [0.0; 8];
+            sink.output_samples(&mut out, true);
+            played.extend_from_slice(&out);
+        }
+
+        played
+    }
+
+    #[test]
+    fn reset_sink_matches_a_fresh_one() {
+        let (mut producer, mut sink) = sink_with_ring(64, 1);
+
+        // get it into a thoroughly used state: xruns, compensation, and leftovers in the ring
+        sink.output_samples(&mut [0.0; 8], true);
+        sink.enable_compensation();
+        play_sine(&mut producer, &mut sink, 10);
+        push_all(&mut producer, [0.5; 8]);
+
+        sink.reset(true);
+
+        let (mut fresh_producer, mut fresh) = sink_with_ring(64, 1);
+        assert_eq!(sink.metrics(), fresh.metrics());
+        assert_eq!(
+            play_sine(&mut producer, &mut sink, 20),
+            play_sine(&mut fresh_producer, &mut fresh, 20)
+        );
+        assert_eq!(sink.metrics(), fresh.metrics());
+    }
+
+    /// Feeds a sine into `source` a block at a time, returning everything that came out of the
+    /// ring.
+    fn record_sine(source: &mut StreamSource, consumer: &mut rtrb::Consumer<f32>, blocks: usize) -> Vec<f32> {
+        let mut recorded = Vec::new();
+
+        for block in 0..blocks {
+            source.input_samples((0..8).map(|i| ((block * 8 + i) as f32 * 0.1).sin()), 8, true);
+
+            while let Ok(sample) = consumer.pop() {
+                recorded.push(sample);
+            }
+        }
+
+        recorded
+    }
+
+    #[test]
+    fn reset_source_matches_a_fresh_one() {
+        let (mut source, mut consumer) = source_with_ring(64, 1);
+
+        source.set_fixed_ratio(1.3);
+        record_sine(&mut source, &mut consumer, 10);
+        source.clear_fixed_ratio();
+
+        source.reset();
+        // the reset source's ring is still whatever was left in it, so start both from empty
+        while consumer.pop().is_ok() {}
+
+        let (mut fresh, mut fresh_consumer) = source_with_ring(64, 1);
+        assert_eq!(source.metrics(), fresh.metrics());
+        assert_eq!(
+            record_sine(&mut source, &mut consumer, 20),
+            record_sine(&mut fresh, &mut fresh_consumer, 20)
+        );
+        assert_eq!(source.metrics(), fresh.metrics());
+    }
+
+    #[test]
+    fn sink_resamples_at_a_ratio_of_four() {
+        let (mut producer, mut sink) = sink_with_ring(512, 2);
+        sink.set_fixed_ratio(4.0);
+        // no gliding, so the ratio is 4 from the first callback
+        sink.pid_settings.factor_last_interp = 1.0;
+        push_all(&mut producer, vec![0.25; 400 * 2]);
+
+        let mut out = [0.0; 16 * 2];
+        for _ in 0..3 {
+            let status = sink.output_samples_with_status(&mut out, true);
+
+            assert_eq!(status.frames_written, 16);
+        }
+    }
+
+    #[test]
+    fn sink_uses_the_chosen_kernel_lookback() {
+        let (mut producer, consumer) = RingBuffer::new(64);
+        let mut sink = StreamSinkBuilder::new()
+            .interpolation(Interpolation::Sinc {
+                taps: 8,
+                oversample: 16,
+            })
+            .build(consumer, 1);
+        sink.set_fixed_ratio(1.0);
+        push_all(&mut producer, vec![0.0; 40]);
+
+        sink.output_samples(&mut [0.0; 4], true);
+
+        // 7 frames went into priming the history, and 4 more were played through it
+        assert_eq!(producer.buffer().capacity() - producer.slots(), 40 - 7 - 4);
+        assert_eq!(sink.latency_frames(), 29.0 + 4.0 - sink.resampler.time());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid PID settings")]
+    fn sink_rejects_invalid_pid_settings() {
+        let (_, consumer) = RingBuffer::new(64);
+
+        StreamSink::new(consumer, 1, 0, PidSettings::default().with_factor_range(0.1, -0.1));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid PID settings")]
+    fn source_rejects_invalid_pid_settings() {
+        let (producer, _) = RingBuffer::new(64);
+
+        StreamSource::new(producer, 1, 0, PidSettings::default().with_factor_last_interp(-1.0));
+    }
+
+    #[test]
+    fn default_builder_matches_with_defaults() {
+        let (mut producer, mut sink) = sink_with_ring(64, 1);
+        let (mut built_producer, built_consumer) = RingBuffer::new(64);
+        let mut built = StreamSinkBuilder::new().build(built_consumer, 1);
+
+        assert_eq!(built.channels(), sink.channels());
+        assert_eq!(built.output_channels(), sink.output_channels());
+        assert_eq!(built.get_strategy(), sink.get_strategy());
+        assert_eq!(built.ratio_limit(), sink.ratio_limit());
+        assert_eq!(built.drift_threshold(), sink.drift_threshold());
+        assert_eq!(built.rolling_avg_length(), sink.rolling_avg_length());
+        assert_eq!(built.forced_compensation(), sink.forced_compensation());
+        assert_eq!(
+            format!("{:?}", built.pid_settings()),
+            format!("{:?}", sink.pid_settings())
+        );
+        assert_eq!(built.metrics(), sink.metrics());
+
+        // enough underruns to start compensating, then the same audio through both
+        for _ in 0..20 {
+            sink.output_samples(&mut [0.0; 8], true);
+            built.output_samples(&mut [0.0; 8], true);
+        }
+        assert!(matches!(sink.get_strategy(), CompensationStrategy::Resample { .. }));
+        assert_eq!(
+            play_sine(&mut built_producer, &mut built, 20),
+            play_sine(&mut producer, &mut sink, 20)
+        );
+        assert_eq!(built.get_strategy(), sink.get_strategy());
+        assert_eq!(built.metrics(), sink.metrics());
+    }
+
+    #[test]
+    fn builder_overrides_stick() {
+        let (_, consumer) = RingBuffer::new(64);
+        let sink = StreamSinkBuilder::new()
+            .pid_settings(PidSettings::default().with_target_fill(0.7))
+            .ratio_limit(None)
+            .drift_threshold(0.01)
+            .rolling_avg_length(4)
+            .build(consumer, 2);
+
+        assert_eq!(sink.channels(), 2);
+        assert_eq!(sink.pid_settings().target_fill, 0.7);
+        assert_eq!(sink.ratio_limit(), None);
+        assert_eq!(sink.drift_threshold(), Some(0.01));
+        assert_eq!(sink.rolling_avg_length(), 4);
+    }
+
+    /// Collects every strategy the callback is called with.
+    fn record_state_changes() -> (
+        Arc<Mutex<Vec<CompensationStrategy>>>,
+        impl FnMut(&CompensationStrategy) + Send + 'static,
+    ) {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorder = changes.clone();
+
+        (changes, move |strategy: &CompensationStrategy| {
+            recorder.lock().unwrap().push(*strategy)
+        })
+    }
+
+    #[test]
+    fn sink_state_change_fires_once_when_compensation_starts() {
+        let (mut producer, mut sink) = sink_with_ring(64, 1);
+        let (changes, on_state_change) = record_state_changes();
+        sink.set_on_state_change(on_state_change);
+
+        // underruns until it crosses the threshold, then plenty of resampling after
+        for _ in 0..20 {
+            sink.output_samples(&mut [0.0; 8], true);
+        }
+        play_sine(&mut producer, &mut sink, 50);
+
+        let changes = changes.lock().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(
+            changes[0],
+            CompensationStrategy::Resample {
+                resample_ratio: 1.0,
+                ..
+            }
+        ));
+    }
 
-        self.local_buffer.extend(buffer_in);
+    #[test]
+    fn source_state_change_fires_once_when_compensation_starts() {
+        let (mut source, mut consumer) = source_with_ring(64, 1);
+        let (changes, on_state_change) = record_state_changes();
+        source.set_on_state_change(on_state_change);
 
-        if self.xruns > self.compensation_start_threshold {
-            // target is half of capacity
-            // TODO: let target be more flexible
-            let target = 0.5;
-            let avg = self.rolling_ring_avg.iter().map(|x| *x as f64).sum::<f64>()
-                / self.rolling_ring_avg.len() as f64
-                / self.ring_size as f64;
-            let error = avg - target;
+        source.enable_compensation();
+        record_sine(&mut source, &mut consumer, 50);
 
-            self.ring_integral += error;
+        let changes = changes.lock().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(changes[0], CompensationStrategy::Resample { .. }));
+    }
 
-            // PID controls
-            let proportional = error * self.pid_settings.prop_factor;
-            let integrative = self.ring_integral * self.pid_settings.integ_factor;
-            let derivative = (avg - self.last_avg) * self.pid_settings.deriv_factor;
+    fn is_resampling(strategy: &CompensationStrategy) -> bool {
+        matches!(strategy, CompensationStrategy::Resample { .. })
+    }
 
-            let new_factor = (proportional + integrative + derivative)
-                .max(self.pid_settings.min_factor)
-                .min(self.pid_settings.max_factor);
-            let new_ratio = 2_f64.powf(new_factor);
+    #[test]
+    fn forced_sink_compensates_without_xruns() {
+        let (mut producer, mut sink) = sink_with_ring(64, 1);
+        sink.enable_compensation();
 
-            if let CompensationStrategy::None = self.strategy {
-                // we've drifted enough that we should start using a strategy
-                println!("sample rate compensation activated");
+        push_all(&mut producer, [0.0; 32]);
+        sink.output_samples(&mut [0.0; 8], true);
 
-                // reset integral so it doesn't overshoot
-                self.ring_integral = 0.0;
+        assert_eq!(sink.forced_compensation(), Some(true));
+        assert_eq!(sink.xruns, 0);
+        assert!(is_resampling(sink.get_strategy()));
+    }
 
-                self.strategy = CompensationStrategy::Resample {
-                    resample_ratio: 1.0,
-                    time: 0.0,
-                };
+    #[test]
+    fn sink_crossing_the_threshold_isnt_forced() {
+        let (_producer, mut sink) = sink_with_ring(64, 1);
 
-                // fill up `last` with values for hermite interpolation
-                for frame_i in 1..FRAME_LOOKBACK {
-                    for channel_i in 0..self.channels {
-                        self.last_frames[(frame_i, channel_i)] = self.local_buffer.pop_front().unwrap();
-                    }
-                }
-            } else if let CompensationStrategy::Resample { resample_ratio, .. } = &mut self.strategy {
-                // lerp to help detune not to slide around too much
-                *resample_ratio = lerp(*resample_ratio, new_ratio, self.pid_settings.factor_last_interp);
+        for _ in 0..20 {
+            sink.output_samples(&mut [0.0; 8], true);
+        }
+
+        assert_eq!(sink.forced_compensation(), None);
+        assert!(sink.xruns >= 15);
+        assert!(is_resampling(sink.get_strategy()));
+
+        // clearing the count doesn't stop it
+        sink.clear_xruns();
+        assert_eq!(sink.xruns, 0);
+        assert!(is_resampling(sink.get_strategy()));
+    }
+
+    #[test]
+    fn disabled_sink_still_counts_xruns() {
+        let (_producer, mut sink) = sink_with_ring(64, 1);
+        sink.disable_compensation();
+
+        for _ in 0..20 {
+            sink.output_samples(&mut [0.0; 8], true);
+        }
+
+        assert_eq!(sink.forced_compensation(), Some(false));
+        assert_eq!(sink.xruns, 20);
+        assert_eq!(*sink.get_strategy(), CompensationStrategy::Never);
+
+        sink.reset_compensation();
+        assert_eq!(sink.forced_compensation(), None);
+        assert_eq!(sink.xruns, 0);
+    }
+
+    #[test]
+    fn forced_source_compensates_without_xruns() {
+        let (mut source, mut consumer) = source_with_ring(64, 1);
+        source.enable_compensation();
+
+        record_sine(&mut source, &mut consumer, 1);
+
+        assert_eq!(source.forced_compensation(), Some(true));
+        assert_eq!(source.xruns, 0);
+        assert!(is_resampling(source.get_strategy()));
+    }
+
+    #[test]
+    fn source_crossing_the_threshold_isnt_forced() {
+        // nobody's draining the ring, so it overruns
+        let (mut source, _consumer) = source_with_ring(64, 1);
+
+        for _ in 0..30 {
+            source.input_samples([0.0; 8], 8, true);
+        }
+
+        assert_eq!(source.forced_compensation(), None);
+        assert!(source.xruns >= 15);
+        assert!(is_resampling(source.get_strategy()));
+
+        source.clear_xruns();
+        assert_eq!(source.xruns, 0);
+        assert!(is_resampling(source.get_strategy()));
+    }
+
+    fn resample_ratio(strategy: &CompensationStrategy) -> f64 {
+        match strategy {
+            CompensationStrategy::Resample { resample_ratio, .. } => *resample_ratio,
+            _ => 1.0,
+        }
+    }
+
+    #[test]
+    fn persistently_full_ring_counts_overruns() {
+        let (mut producer, mut sink) = sink_with_ring(64, 1);
+
+        for _ in 0..40 {
+            // the producer keeps topping it up
+            let free = producer.slots();
+            push_all(&mut producer, vec![0.0; free]);
+            sink.output_samples(&mut [0.0; 8], true);
+
+            // drained, instead of staying pinned
+            assert!(sink.ring_fill() < 1.0);
+        }
+
+        assert!(sink.overruns >= 15);
+        assert_eq!(sink.underruns, 0);
+        // and the PID reads faster through the ring
+        assert!(resample_ratio(sink.get_strategy()) > 1.0);
+    }
+
+    #[test]
+    fn persistently_empty_ring_counts_underruns() {
+        let (mut producer, mut sink) = sink_with_ring(64, 1);
+
+        for _ in 0..40 {
+            // the producer only manages half a block each time
+            push_all(&mut producer, [0.0; 4]);
+            sink.output_samples(&mut [0.0; 8], true);
+        }
+
+        assert!(sink.underruns >= 15);
+        assert_eq!(sink.overruns, 0);
+        assert!(resample_ratio(sink.get_strategy()) < 1.0);
+    }
+
+    /// Plays a 1 kHz sine (at 48 kHz) through a sink at a fixed `ratio`, returning the THD+N of the
+    /// output measured at the pitch it should have come out at.
+    fn fixed_ratio_pitch_error(interpolation: Interpolation, ratio: f64) -> f64 {
+        let (mut producer, consumer) = RingBuffer::new(4096);
+        let mut sink = StreamSinkBuilder::new()
+            .interpolation(interpolation)
+            // jump straight to the fixed ratio instead of gliding there
+            .pid_settings(PidSettings::default().with_factor_last_interp(1.0))
+            .build(consumer, 1);
+        sink.set_fixed_ratio(ratio);
+        push_all(&mut producer, crate::testing::sine(1000.0, 48000.0, 4000));
+
+        let mut played = vec![0.0; (3000.0 / ratio) as usize / 64 * 64];
+        for block in played.chunks_mut(64) {
+            sink.output_samples(block, true);
+        }
+
+        // skip the start, where the history was still silent
+        crate::testing::thd_n(&played[64..], 1000.0 * ratio / 48000.0)
+    }
+
+    #[test]
+    fn every_kernel_resamples_to_the_right_pitch() {
+        for interpolation in [
+            Interpolation::Linear,
+            Interpolation::Hermite,
+            Interpolation::Sinc {
+                taps: 8,
+                oversample: 64,
+            },
+        ] {
+            let error = fixed_ratio_pitch_error(interpolation, 1.25);
+            // anything at the wrong pitch is almost all residual
+            assert!(error < 0.01, "{interpolation:?}: THD+N of {error}");
+        }
+    }
+
+    /// How many frames priming the resampler's history takes out of the ring.
+    fn frames_primed(interpolation: Interpolation) -> usize {
+        let (mut producer, consumer) = RingBuffer::new(64);
+        let mut sink = StreamSinkBuilder::new().interpolation(interpolation).build(consumer, 1);
+        sink.set_fixed_ratio(1.0);
+        push_all(&mut producer, vec![0.0; 40]);
+
+        sink.output_samples(&mut [0.0; 4], true);
+
+        40 - 4 - (producer.buffer().capacity() - producer.slots())
+    }
+
+    #[test]
+    fn linear_needs_less_lookback_than_hermite() {
+        assert_eq!(frames_primed(Interpolation::Linear), 1);
+        assert_eq!(frames_primed(Interpolation::Hermite), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one channel")]
+    fn sink_rejects_zero_channels() {
+        let (_, consumer) = RingBuffer::new(64);
+
+        StreamSink::with_defaults(consumer, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be a multiple of the channel count")]
+    fn sink_rejects_a_ring_of_partial_frames() {
+        let (_, consumer) = RingBuffer::new(64);
+
+        StreamSink::with_defaults(consumer, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one channel")]
+    fn source_rejects_zero_channels() {
+        let (producer, _) = RingBuffer::new(64);
+
+        StreamSource::with_defaults(producer, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be a multiple of the channel count")]
+    fn source_rejects_a_ring_of_partial_frames() {
+        let (producer, _) = RingBuffer::new(64);
+
+        StreamSource::with_defaults(producer, 3);
+    }
+
+    #[test]
+    fn whole_frame_rings_are_accepted() {
+        let (producer, consumer) = RingBuffer::new(96);
+
+        assert_eq!(StreamSink::with_defaults(consumer, 3).channels(), 3);
+        assert_eq!(StreamSource::with_defaults(producer, 3).channels(), 3);
+    }
+
+    #[test]
+    fn short_iterator_is_ignored() {
+        let (mut source, mut consumer) = source_with_ring(64, 2);
+
+        // claims 4 frames, but only has 3 and a half
+        source.input_samples([0.1; 7], 8, true);
+        assert_eq!(source.latency_frames(), 0.0);
+        assert_eq!(consumer.slots(), 0);
+        assert_eq!(source.xruns, 0);
+
+        // and the next block still lines up
+        source.input_samples([0.1, 0.2, 0.3, 0.4], 4, true);
+        assert_eq!(consumer.slots(), 4);
+        assert_eq!(
+            consumer.read_chunk(4).unwrap().into_iter().collect::<Vec<_>>(),
+            [0.1, 0.2, 0.3, 0.4]
+        );
+    }
+
+    #[test]
+    fn short_iterator_is_ignored_while_resampling() {
+        let (mut source, mut consumer) = source_with_ring(64, 1);
+        source.enable_compensation();
+        record_sine(&mut source, &mut consumer, 4);
+        let latency = source.latency_frames();
+
+        source.input_samples([0.5; 3], 8, true);
+
+        assert_eq!(source.latency_frames(), latency);
+        assert!(matches!(source.get_strategy(), CompensationStrategy::Resample { .. }));
+    }
+
+    #[test]
+    fn sink_metrics_reflect_compensation() {
+        let (mut producer, mut sink) = sink_with_ring(64, 1);
+        assert_eq!(sink.metrics().strategy, CompensationStrategy::None);
+
+        // starved into compensating, then kept short of the target
+        for _ in 0..20 {
+            sink.output_samples(&mut [0.0; 8], true);
+        }
+        play_sine(&mut producer, &mut sink, 10);
+
+        let metrics = sink.metrics();
+        assert_eq!(metrics.strategy, *sink.get_strategy());
+        assert_eq!(metrics.resample_ratio, resample_ratio(sink.get_strategy()));
+        assert!(metrics.resample_ratio < 1.0);
+        assert!(metrics.underruns >= 15);
+        assert_eq!(metrics.xruns, metrics.underruns + metrics.overruns);
+        assert!(metrics.average_fill < 0.5);
+        assert_eq!(metrics.latency_frames, sink.latency_frames());
+        // that far off target, the ratio is pinned at its limit (and the integral held still)
+        assert!(metrics.ratio_saturated);
+        assert!(metrics.ratio_saturations > 0);
+        assert_eq!(metrics.integral, 0.0);
+    }
+
+    #[test]
+    fn source_metrics_reflect_compensation() {
+        // nobody's draining the ring, so it fills past the target
+        let (mut source, _consumer) = source_with_ring(1024, 1);
+        source.enable_compensation();
+        for _ in 0..110 {
+            source.input_samples([0.0; 8], 8, true);
+        }
+
+        let metrics = source.metrics();
+        assert_eq!(metrics.strategy, *source.get_strategy());
+        assert_eq!(metrics.resample_ratio, resample_ratio(source.get_strategy()));
+        assert!(metrics.average_fill > 0.5);
+        // squeezing the input into fewer frames to let the ring drain
+        assert!(metrics.resample_ratio > 1.0);
+        assert!(metrics.integral > 0.0);
+        assert_eq!(metrics.latency_frames, source.latency_frames());
+    }
+
+    #[test]
+    fn fixed_ratio_sweep_tracks_the_pitch() {
+        for ratio in [0.5, 0.8, 1.0, 1.2, 1.5, 2.0] {
+            let error = fixed_ratio_pitch_error(Interpolation::Hermite, ratio);
+            assert!(error < 0.01, "ratio {ratio}: THD+N of {error}");
+        }
+    }
+
+    #[test]
+    fn fixed_ratio_glides_with_factor_last_interp() {
+        let (mut producer, mut sink) = sink_with_ring(4096, 1);
+        sink.set_fixed_ratio(1.5);
+
+        let mut ratios = Vec::new();
+        for _ in 0..40 {
+            push_all(&mut producer, [0.0; 12]);
+            sink.output_samples(&mut [0.0; 8], true);
+            ratios.push(resample_ratio(sink.get_strategy()));
+        }
+
+        // factor_last_interp is 0.1, so it takes a while to get there, without overshooting
+        assert!(ratios[1] < 1.1, "{ratios:?}");
+        assert!(ratios.windows(2).all(|pair| pair[0] <= pair[1] && pair[1] <= 1.5));
+        assert!((ratios[39] - 1.5).abs() < 0.01, "{ratios:?}");
+
+        // and the PID doesn't get a say while it's fixed
+        assert_eq!(sink.metrics().integral, 0.0);
+    }
+
+    #[test]
+    fn fixed_ratio_on_the_source_scales_the_output() {
+        for ratio in [0.5, 2.0] {
+            let (producer, consumer) = RingBuffer::new(8192);
+            let mut source = StreamSource::new(producer, 1, 15, PidSettings::default().with_factor_last_interp(1.0));
+            source.set_fixed_ratio(ratio);
+
+            for _ in 0..64 {
+                source.input_samples([0.0; 32], 32, true);
             }
+
+            let expected = 64.0 * 32.0 / ratio;
+            let written = consumer.slots() as f64;
+            // give or take what went into priming the resampler and what's still in its buffer
+            assert!(
+                (written - expected).abs() < expected * 0.02,
+                "ratio {ratio}: {written} frames instead of {expected}"
+            );
         }
+    }
 
-        self.rolling_ring_avg.rotate_left(1);
-        self.rolling_ring_avg[self.rolling_ring_avg.len() - 1] = ring_slots;
+    fn rms(samples: &[f32]) -> f64 {
+        (samples.iter().map(|&sample| (sample as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    }
 
-        match self.strategy {
-            CompensationStrategy::None | CompensationStrategy::Never => {
-                for (i, sample) in self.local_buffer.iter().enumerate() {
-                    if self.ring_out.push(*sample).is_err() {
-                        self.clean_up(i % self.channels, measure_xruns);
+    #[test]
+    fn mono_ring_is_duplicated_to_stereo() {
+        let (mut producer, mut sink) = sink_with_ring(16, 1);
+        sink.set_channel_mixer(ChannelMixer::duplicate(2));
+        push_all(&mut producer, [0.1, 0.2, 0.3, 0.4]);
 
-                        return;
-                    }
-                }
+        let mut out = [0.0; 8];
+        sink.output_samples(&mut out, true);
 
-                self.local_buffer.clear();
+        assert_eq!(sink.output_channels(), 2);
+        assert_eq!(out, [0.1, 0.1, 0.2, 0.2, 0.3, 0.3, 0.4, 0.4]);
+    }
+
+    #[test]
+    fn stereo_input_is_downmixed_to_mono_keeping_the_rms() {
+        let (mut source, mut consumer) = source_with_ring(1024, 1);
+        source.set_channel_mixer(ChannelMixer::downmix(2));
+
+        let mono = crate::testing::sine(440.0, 48_000.0, 512);
+        let stereo: Vec<f32> = mono.iter().flat_map(|&sample| [sample, sample]).collect();
+        source.input_samples(stereo.iter().copied(), stereo.len(), true);
+
+        let mut recorded = Vec::new();
+        while let Ok(sample) = consumer.pop() {
+            recorded.push(sample);
+        }
+
+        assert_eq!(source.input_channels(), 2);
+        assert_eq!(recorded.len(), 512);
+        assert!((rms(&recorded) - rms(&stereo)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mixing_composes_with_resampling() {
+        let (mut producer, consumer) = RingBuffer::new(4096);
+        let mut sink = StreamSink::new(consumer, 1, 15, PidSettings::default().with_factor_last_interp(1.0));
+        sink.set_channel_mixer(ChannelMixer::duplicate(2));
+        sink.set_fixed_ratio(2.0);
+
+        let mono = crate::testing::sine(440.0, 48_000.0, 1024);
+        push_all(&mut producer, mono.iter().copied());
+
+        // the ratio takes effect from the next callback
+        let mut out = vec![0.0; 2 * 256];
+        sink.output_samples(&mut out, true);
+        let before = producer.slots();
+        sink.output_samples(&mut out, true);
+
+        // both channels get the same resampled signal, and the ratio still applies
+        assert!(out.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+        assert!(
+            (producer.slots() - before).abs_diff(512) <= 4,
+            "{}",
+            producer.slots() - before
+        );
+    }
+
+    #[test]
+    fn source_local_buffer_never_reallocates() {
+        let (mut source, mut consumer) = source_with_ring(1024, 2);
+        source.set_max_block_frames(64);
+        source.set_fixed_ratio(0.7);
+
+        // warm up, and let the ratio settle
+        for _ in 0..64 {
+            source.input_samples([0.25; 128], 128, true);
+            while consumer.pop().is_ok() {}
+        }
+
+        let capacity = source.local_buffer.capacity();
+        for i in 0..1000 {
+            // the biggest block allowed, with the consumer falling behind now and then
+            source.input_samples([0.25; 128], 128, true);
+            if i % 7 != 0 {
+                while consumer.pop().is_ok() {}
             }
-            CompensationStrategy::Resample {
-                resample_ratio,
-                mut time,
-            } => {
-                loop {
-                    let new_sample_count = new_samples_needed(resample_ratio, time);
+        }
 
-                    // do we have enough?
-                    if self.local_buffer.len() >= new_sample_count * self.channels {
-                        for channel_i in 0..self.channels {
-                            for i in 0..new_sample_count {
-                                self.resample_scratch[(i, channel_i)] =
-                                    self.local_buffer[i * self.channels + channel_i];
-                            }
+        assert_eq!(source.local_buffer.capacity(), capacity);
+    }
 
-                            let (out, new_time) = resample(
-                                resample_ratio,
-                                self.resample_scratch.column(channel_i).iter().copied(),
-                                &mut self.last_frames.column_mut(channel_i),
-                                time,
-                            );
+    #[test]
+    #[should_panic(expected = "larger than the maximum")]
+    fn source_rejects_blocks_over_the_maximum() {
+        let (mut source, _consumer) = source_with_ring(1024, 1);
+        source.set_max_block_frames(64);
 
-                            time = new_time;
+        source.input_samples([0.0; 65], 65, true);
+    }
 
-                            if self.ring_out.push(out).is_err() {
-                                self.clean_up(channel_i, measure_xruns);
+    #[test]
+    fn available_frames_on_a_partly_full_ring() {
+        let (mut source, mut consumer) = source_with_ring(64, 2);
+        assert_eq!(source.available_frames(), 64);
 
-                                return;
-                            }
-                        }
+        source.input_samples([0.0; 40], 40, true);
+        assert_eq!(source.available_frames(), 44);
 
-                        self.local_buffer.drain(0..(self.channels * new_sample_count));
-                    } else {
-                        return;
-                    }
+        // exactly filling it doesn't overrun
+        source.input_samples(vec![0.0; 88], 88, true);
+        assert_eq!(source.available_frames(), 0);
+        assert_eq!(source.xruns, 0);
+
+        consumer.read_chunk(20).unwrap().commit_all();
+        assert_eq!(source.available_frames(), 10);
+    }
+
+    #[test]
+    fn available_frames_counts_whats_still_pending() {
+        let (producer, _consumer) = RingBuffer::new(128);
+        let mut source = StreamSource::new(producer, 2, 15, PidSettings::default().with_factor_last_interp(1.0));
+        source.set_fixed_ratio(4.0);
+
+        // 3 frames isn't enough for an output frame at a ratio of 4, so some are left waiting
+        for _ in 0..4 {
+            source.input_samples([0.0; 6], 6, true);
+        }
+
+        let pending = source.local_buffer.len() / 2;
+        assert!(pending > 0);
+        assert_eq!(source.available_frames(), source.ring_out.slots() / 2 - pending);
+    }
+
+    /// Runs a compensating sink at its target fill, then steps the fill up by a quarter of the ring
+    /// and holds it there. Returns how many callbacks it takes the ratio to get most of the way to
+    /// its limit.
+    fn callbacks_to_react_to_a_step(rolling_avg_length: usize) -> usize {
+        let (mut producer, consumer) = RingBuffer::new(1024);
+        let mut sink = StreamSink::new(consumer, 1, 15, PidSettings::default().with_factor_last_interp(1.0));
+        sink.set_rolling_avg_length(rolling_avg_length);
+        sink.enable_compensation();
+
+        let mut callback = |level: usize| {
+            while 1024 - producer.slots() < level {
+                producer.push(0.0).unwrap();
+            }
+
+            sink.output_samples(&mut [0.0; 32], true);
+            resample_ratio(sink.get_strategy())
+        };
+
+        for _ in 0..64 {
+            callback(512);
+        }
+
+        (1..).find(|_| callback(768) > 1.0 + 0.9 * DEFAULT_RATIO_LIMIT).unwrap()
+    }
+
+    #[test]
+    fn short_rolling_average_reacts_sooner() {
+        let short = callbacks_to_react_to_a_step(2);
+        let default = callbacks_to_react_to_a_step(ROLLING_AVG_LENGTH);
+        let long = callbacks_to_react_to_a_step(32);
+
+        assert!(short < default && default < long, "{short}, {default}, {long}");
+    }
+
+    /// Feeds a stereo sink twice as fast as it plays, returning the ring fill (as a fraction) after
+    /// each callback. Each sample is its frame number, plus 0.5 on the right channel, and every
+    /// output frame is checked to still be a left/right pair.
+    fn fill_with_a_double_speed_producer(drain_on_overrun: bool) -> Vec<f64> {
+        let (mut producer, mut sink) = sink_with_ring(256, 2);
+        sink.disable_compensation();
+        sink.set_drain_on_overrun(drain_on_overrun);
+
+        let mut next_frame = 0;
+        let mut out = [0.0; 64];
+        (0..40)
+            .map(|_| {
+                for _ in 0..64.min(producer.slots() / 2) {
+                    push_all(&mut producer, [next_frame as f32, next_frame as f32 + 0.5]);
+                    next_frame += 1;
+                }
+
+                sink.output_samples(&mut out, true);
+                for frame in out.chunks_exact(2) {
+                    assert_eq!(frame[1], frame[0] + 0.5, "misaligned frame {frame:?}");
                 }
+
+                1.0 - producer.slots() as f64 / 512.0
+            })
+            .collect()
+    }
+
+    /// The longest run of callbacks that ended with the ring (nearly) full.
+    fn sink_pinned_for(fills: &[f64]) -> usize {
+        fills.split(|&fill| fill < 0.85).map(<[f64]>::len).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn draining_a_full_ring_recovers_headroom() {
+        let fills = fill_with_a_double_speed_producer(true);
+
+        // every time it fills up, it's knocked back below the target (less what was just played)
+        assert_eq!(sink_pinned_for(&fills), 0, "{fills:?}");
+        assert!(fills.iter().filter(|&&fill| fill < 0.5).count() > 5, "{fills:?}");
+    }
+
+    #[test]
+    fn without_draining_the_ring_stays_full() {
+        let fills = fill_with_a_double_speed_producer(false);
+
+        assert!(sink_pinned_for(&fills) > 20, "{fills:?}");
+    }
+
+    /// Plays a hot block (+2.0 and -2.0, plus a quiet 0.25) through a sink with `limiter`.
+    fn play_hot(limiter: OutputLimiter) -> [f32; 3] {
+        let (mut producer, mut sink) = sink_with_ring(16, 1);
+        sink.set_output_limiter(limiter);
+        push_all(&mut producer, [2.0, -2.0, 0.25]);
+
+        let mut out = [0.0; 3];
+        sink.output_samples(&mut out, true);
+
+        out
+    }
+
+    #[test]
+    fn hard_clip_clamps_to_one() {
+        assert_eq!(play_hot(OutputLimiter::HardClip), [1.0, -1.0, 0.25]);
+    }
+
+    #[test]
+    fn soft_clip_tames_without_going_over() {
+        let out = play_hot(OutputLimiter::SoftClip);
+
+        assert!(out[0] <= 1.0 && out[0] > 0.95, "{out:?}");
+        assert_eq!(out[1], -out[0]);
+        // quiet samples are only slightly compressed
+        assert!((out[2] - 0.25).abs() < 0.01, "{out:?}");
+    }
+
+    #[test]
+    fn limiter_off_passes_through() {
+        assert_eq!(OutputLimiter::default(), OutputLimiter::Off);
+        assert_eq!(play_hot(OutputLimiter::Off), [2.0, -2.0, 0.25]);
+    }
+
+    #[test]
+    fn underrun_mid_frame_leaves_the_ring_aligned() {
+        let (mut producer, mut sink) = sink_with_ring(16, 2);
+        // a frame and a half: the producer's partway through writing the second
+        push_all(&mut producer, [0.1, 0.2, 0.3]);
+
+        let start = std::time::Instant::now();
+        let mut out = [9.0; 4];
+        let status = sink.output_samples_with_status(&mut out, true);
+
+        // only the whole frame is taken, and nothing waits on the rest
+        assert!(start.elapsed() < std::time::Duration::from_millis(50));
+        assert_eq!(status.frames_written, 1);
+        assert!(status.underran);
+        assert_eq!(out, [0.1, 0.2, 0.0, 0.0]);
+
+        // the half frame was left where it was, so once it's finished it comes out in one piece
+        push_all(&mut producer, [0.4, 0.5, 0.6]);
+        let mut out = [0.0; 4];
+        sink.output_samples(&mut out, true);
+        assert_eq!(out, [0.3, 0.4, 0.5, 0.6]);
+    }
+
+    #[test]
+    fn overrun_mid_frame_leaves_the_ring_aligned() {
+        let (mut source, mut consumer) = source_with_ring(2, 2);
+        source.input_samples([0.1, 0.2, 0.3, 0.4], 4, true);
+
+        // the consumer's partway through reading a frame, so there's one slot free
+        consumer.pop().unwrap();
+
+        let start = std::time::Instant::now();
+        source.input_samples([0.5, 0.6], 2, true);
+        assert!(start.elapsed() < std::time::Duration::from_millis(50));
+
+        // the frame that didn't fit was dropped whole, rather than half of it going in
+        assert!(source.xruns > 0);
+        let rest: Vec<f32> = std::iter::from_fn(|| consumer.pop().ok()).collect();
+        assert_eq!(rest, [0.2, 0.3, 0.4]);
+    }
+
+    /// Feeds a ramp of 128 frames through a source resampling at a fixed `ratio`, returning how
+    /// many frames `flush` pushed out and everything that ended up in the ring.
+    fn flush_a_ramp(ratio: f64) -> (usize, Vec<f32>) {
+        let (producer, mut consumer) = RingBuffer::new(8192);
+        let mut source = StreamSource::new(producer, 1, 15, PidSettings::default().with_factor_last_interp(1.0));
+        source.set_fixed_ratio(ratio);
+        for block in 0..4 {
+            source.input_samples((0..32).map(|i| (block * 32 + i) as f32), 32, true);
+        }
+
+        let flushed = source.flush(true);
+        let mut recorded = Vec::new();
+        while let Ok(sample) = consumer.pop() {
+            recorded.push(sample);
+        }
+
+        (flushed, recorded)
+    }
+
+    #[test]
+    fn flush_emits_the_trailing_frames() {
+        // at a ratio of one, every input frame comes out, the tail included
+        let (flushed, recorded) = flush_a_ramp(1.0);
+        assert!(flushed > 0);
+        assert_eq!(recorded.len(), 128);
+        assert_eq!(
+            recorded[(128 - flushed)..],
+            (128 - flushed..128).map(|frame| frame as f32).collect::<Vec<_>>()[..]
+        );
+
+        // and squeezed, the last frame out still lands on the last frame in
+        for ratio in [0.5, 2.0] {
+            let (flushed, recorded) = flush_a_ramp(ratio);
+            assert!(flushed > 0, "ratio {ratio}");
+            assert_eq!(recorded.last(), Some(&127.0), "ratio {ratio}: {recorded:?}");
+        }
+    }
+
+    #[test]
+    fn flush_without_anything_held_back_is_a_no_op() {
+        let (mut source, mut consumer) = source_with_ring(64, 1);
+        source.input_samples([0.1, 0.2, 0.3], 3, true);
+
+        assert_eq!(source.flush(true), 0);
+        assert_eq!(consumer.pop(), Ok(0.1));
+        assert_eq!(consumer.slots(), 2);
+    }
+
+    /// Plays a sink whose producer runs 0.2% fast, for long enough to drift by a few blocks but
+    /// nowhere near enough to overrun, going by drift alone if `drift_threshold` is set.
+    fn sink_with_a_gently_fast_producer(drift_threshold: Option<f64>) -> StreamSink {
+        let (mut producer, consumer) = RingBuffer::new(4096);
+        let mut sink = StreamSink::new(consumer, 1, u64::MAX, PidSettings::default());
+        if let Some(threshold) = drift_threshold {
+            sink.set_drift_threshold(threshold);
+        }
+        push_all(&mut producer, [0.0; 2048]);
+
+        let mut fed = 0.0;
+        for _ in 0..3000 {
+            fed += 64.0 * 1.002;
+            while fed >= 1.0 {
+                producer.push(0.0).unwrap();
+                fed -= 1.0;
             }
+
+            sink.output_samples(&mut [0.0; 64], true);
         }
+
+        sink
     }
 
-    /// Forces compensation to start
-    pub fn enable_compensation(&mut self) {
-        self.xruns = self.compensation_start_threshold;
-        self.strategy = CompensationStrategy::None;
+    #[test]
+    fn gentle_drift_engages_compensation_without_xruns() {
+        let sink = sink_with_a_gently_fast_producer(Some(0.001));
+
+        assert_eq!(sink.xruns, 0);
+        assert!(is_resampling(sink.get_strategy()));
     }
 
-    /// Forces compensation to never happen
-    pub fn disable_compensation(&mut self) {
-        self.xruns = 0;
-        self.strategy = CompensationStrategy::Never;
+    #[test]
+    fn gentle_drift_goes_unnoticed_by_the_xrun_count() {
+        let sink = sink_with_a_gently_fast_producer(None);
+
+        assert_eq!(sink.xruns, 0);
+        assert_eq!(*sink.get_strategy(), CompensationStrategy::None);
     }
 
-    /// Resets mode to auto (default mode)
-    pub fn auto_compensation(&mut self) {
-        self.xruns = 0;
-        self.strategy = CompensationStrategy::None;
+    #[test]
+    fn drift_under_the_threshold_isnt_compensated() {
+        let sink = sink_with_a_gently_fast_producer(Some(0.005));
+
+        assert_eq!(*sink.get_strategy(), CompensationStrategy::None);
+    }
+
+    #[test]
+    fn gentle_drift_engages_source_compensation_without_xruns() {
+        let (producer, mut consumer) = RingBuffer::new(4096);
+        let mut source = StreamSource::new(producer, 1, u64::MAX, PidSettings::default());
+        source.set_drift_threshold(0.001);
+
+        // the consumer keeps about half the ring and reads 0.2% slower than the source writes
+        source.input_samples(vec![0.0; 2048], 2048, true);
+        let mut read = 0.0;
+        for _ in 0..3000 {
+            source.input_samples([0.0; 64], 64, true);
+
+            read += 64.0 / 1.002;
+            while read >= 1.0 {
+                consumer.pop().unwrap();
+                read -= 1.0;
+            }
+        }
+
+        assert_eq!(source.xruns, 0);
+        assert!(is_resampling(source.get_strategy()));
+    }
+
+    /// Plays a sink whose producer runs 10% fast, far more than any real clock drifts.
+    fn sink_with_a_mismatched_producer(ratio_limit: Option<f64>) -> StreamSink {
+        let (mut producer, consumer) = RingBuffer::new(4096);
+        let mut sink = StreamSink::new(consumer, 1, 15, PidSettings::default().with_factor_last_interp(1.0));
+        match ratio_limit {
+            Some(limit) => sink.set_ratio_limit(limit),
+            None => sink.clear_ratio_limit(),
+        }
+        sink.enable_compensation();
+        // keep the ring pinned full, instead of knocking it back to the target every time
+        sink.set_drain_on_overrun(false);
+
+        let mut fed = 0.0;
+        for _ in 0..2000 {
+            fed += 64.0 * 1.1;
+            while fed >= 1.0 {
+                let _ = producer.push(0.0);
+                fed -= 1.0;
+            }
+
+            sink.output_samples(&mut [0.0; 64], true);
+        }
+
+        sink
+    }
+
+    #[test]
+    fn extreme_mismatch_is_clamped_and_flagged() {
+        let sink = sink_with_a_mismatched_producer(Some(DEFAULT_RATIO_LIMIT));
+        let metrics = sink.metrics();
+
+        assert_eq!(resample_ratio(sink.get_strategy()), 1.0 + DEFAULT_RATIO_LIMIT);
+        assert!(metrics.ratio_saturated);
+        assert!(metrics.ratio_saturations > 0);
+    }
+
+    #[test]
+    fn without_a_limit_the_ratio_goes_past_it() {
+        let sink = sink_with_a_mismatched_producer(None);
+        let metrics = sink.metrics();
+
+        assert!(resample_ratio(sink.get_strategy()) > 1.01);
+        assert!(!metrics.ratio_saturated);
+        assert_eq!(metrics.ratio_saturations, 0);
+    }
+
+    #[test]
+    fn reset_clears_the_saturation() {
+        let mut sink = sink_with_a_mismatched_producer(Some(DEFAULT_RATIO_LIMIT));
+        sink.reset(true);
+
+        let metrics = sink.metrics();
+        assert!(!metrics.ratio_saturated);
+        assert_eq!(metrics.ratio_saturations, 0);
+    }
+
+    #[test]
+    fn source_ratio_is_clamped_and_flagged() {
+        let (producer, mut consumer) = RingBuffer::new(4096);
+        let mut source = StreamSource::new(producer, 1, 15, PidSettings::default().with_factor_last_interp(1.0));
+        source.enable_compensation();
+
+        // the consumer only reads 90% of what comes in
+        let mut read = 0.0;
+        for _ in 0..2000 {
+            source.input_samples([0.0; 64], 64, true);
+
+            read += 64.0 * 0.9;
+            while read >= 1.0 {
+                let _ = consumer.pop();
+                read -= 1.0;
+            }
+        }
+
+        let metrics = source.metrics();
+        assert!(metrics.ratio_saturated);
+        assert!(metrics.ratio_saturations > 0);
+        let ratio = resample_ratio(source.get_strategy());
+        assert!((ratio - 1.0).abs() <= DEFAULT_RATIO_LIMIT + 1e-12, "{ratio}");
+
+        source.reset();
+        assert!(!source.metrics().ratio_saturated);
+        assert_eq!(source.metrics().ratio_saturations, 0);
+    }
+
+    #[test]
+    fn sink_plays_at_the_maximum_nominal_ratio() {
+        let (mut producer, mut sink) = sink_with_ring(1024, 1);
+        sink.set_nominal_ratio(MAX_RESAMPLE_RATIO);
+        push_all(&mut producer, crate::testing::sine(100.0, 48000.0, 1024));
+
+        let mut out = [0.0; 64];
+        for _ in 0..2 {
+            sink.output_samples(&mut out, true);
+        }
+
+        // give or take the PID's corrections
+        let ratio = resample_ratio(sink.get_strategy());
+        assert!(ratio > 7.9 && ratio <= MAX_RESAMPLE_RATIO, "{ratio}");
+    }
+
+    #[test]
+    #[should_panic(expected = "above the maximum")]
+    fn sink_rejects_a_nominal_ratio_above_the_maximum() {
+        let (_producer, mut sink) = sink_with_ring(1024, 1);
+        sink.set_nominal_ratio(384_000.0 / 44_100.0);
     }
 }
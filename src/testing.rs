@@ -0,0 +1,466 @@
+//! Helpers for checking resampling quality, e.g. in regression tests.
+//!
+//! [`ResampleFixture`] generates a sine, runs it through a [`Resampler`] as if the two sides' clocks
+//! were `ppm_offset` apart, and measures the result. [`write_wav`] can dump any of the signals to
+//! disk for a listen.
+//!
+//! [`LinkedStreams`] connects a [`StreamSource`] to a [`StreamSink`] through one ring, with the two
+//! sides' callbacks running on mismatched (simulated) clocks, to check the whole chain end to end.
+
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use nalgebra::DMatrix;
+
+use crate::{
+    resample::{Interpolation, Resampler},
+    StreamSink, StreamSource,
+};
+
+/// How many output frames to ignore at the start, while the resampler's (silent) history is
+/// flushed out.
+const WARMUP_FRAMES: usize = 64;
+
+/// A sine run through a [`Resampler`] at a fixed clock offset.
+#[derive(Debug, Clone)]
+pub struct ResampleFixture {
+    /// Frequency of the test tone in Hz
+    pub frequency: f64,
+    pub sample_rate: f64,
+    /// Length of the input in frames
+    pub frames: usize,
+    /// How much faster (positive) or slower (negative) the input side's clock runs than the output
+    /// side's, in parts per million
+    pub ppm_offset: f64,
+    pub interpolation: Interpolation,
+    pub anti_aliasing: bool,
+}
+
+impl Default for ResampleFixture {
+    fn default() -> Self {
+        ResampleFixture {
+            frequency: 1000.0,
+            sample_rate: 48000.0,
+            frames: 48000,
+            ppm_offset: 0.0,
+            interpolation: Interpolation::default(),
+            anti_aliasing: false,
+        }
+    }
+}
+
+/// What came out of [`ResampleFixture::run`].
+#[derive(Debug, Clone)]
+pub struct ResampleReport {
+    /// The resampled signal
+    pub output: Vec<f32>,
+    /// Output frames / input frames
+    pub sample_ratio: f64,
+    /// Total harmonic distortion plus noise of the output (see [`thd_n`])
+    pub thd_n: f64,
+}
+
+impl ResampleFixture {
+    /// input sample rate / output sample rate, as the resampler sees it
+    pub fn resample_ratio(&self) -> f64 {
+        1.0 + self.ppm_offset * 1e-6
+    }
+
+    /// The test tone, at full scale.
+    pub fn signal(&self) -> Vec<f32> {
+        sine(self.frequency, self.sample_rate, self.frames)
+    }
+
+    pub fn run(&self) -> ResampleReport {
+        let resample_ratio = self.resample_ratio();
+        let signal = self.signal();
+
+        let mut resampler = Resampler::new(1, self.interpolation);
+        resampler.set_anti_aliasing(self.anti_aliasing);
+
+        let input = DMatrix::from_column_slice(signal.len(), 1, &signal);
+        let mut output = DMatrix::zeros((signal.len() as f64 / resample_ratio).ceil() as usize + 1, 1);
+        let (_, produced) = resampler.process(&input, &mut output, resample_ratio);
+
+        let output: Vec<f32> = output.as_slice()[..produced].to_vec();
+        let fundamental = self.frequency / self.sample_rate * resample_ratio;
+
+        ResampleReport {
+            thd_n: thd_n(output.get(WARMUP_FRAMES..).unwrap_or(&[]), fundamental),
+            sample_ratio: produced as f64 / signal.len() as f64,
+            output,
+        }
+    }
+}
+
+/// A full scale sine, `frames` long.
+pub fn sine(frequency: f64, sample_rate: f64, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .map(|i| (2.0 * PI * frequency * i as f64 / sample_rate).sin() as f32)
+        .collect()
+}
+
+/// Total harmonic distortion plus noise of `signal`: everything that isn't the fundamental,
+/// relative to the fundamental (as an amplitude ratio). `fundamental` is in cycles per sample.
+///
+/// Interpolation errors mostly show up as images and noise rather than clean harmonics, so this
+/// fits a sine at `fundamental` and measures what's left over.
+pub fn thd_n(signal: &[f32], fundamental: f64) -> f64 {
+    let basis = |i: usize| {
+        let phase = 2.0 * PI * fundamental * i as f64;
+
+        (phase.sin(), phase.cos())
+    };
+
+    // least squares fit of `a * sin + b * cos`
+    let (mut ss, mut sc, mut cc, mut xs, mut xc) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (i, sample) in signal.iter().enumerate() {
+        let (sin, cos) = basis(i);
+        let sample = *sample as f64;
+
+        ss += sin * sin;
+        sc += sin * cos;
+        cc += cos * cos;
+        xs += sample * sin;
+        xc += sample * cos;
+    }
+
+    let det = ss * cc - sc * sc;
+    if det == 0.0 {
+        return 0.0;
+    }
+
+    let a = (xs * cc - xc * sc) / det;
+    let b = (xc * ss - xs * sc) / det;
+
+    let (mut fit_power, mut residual_power) = (0.0, 0.0);
+    for (i, sample) in signal.iter().enumerate() {
+        let (sin, cos) = basis(i);
+        let fit = a * sin + b * cos;
+
+        fit_power += fit * fit;
+        residual_power += (*sample as f64 - fit).powi(2);
+    }
+
+    if fit_power == 0.0 {
+        return 0.0;
+    }
+
+    (residual_power / fit_power).sqrt()
+}
+
+/// Writes mono samples to a 32-bit float WAV file.
+pub fn write_wav(path: impl AsRef<Path>, samples: &[f32], sample_rate: u32) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let data_len = (samples.len() * 4) as u32;
+
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data_len).to_le_bytes())?;
+    file.write_all(b"WAVE")?;
+
+    file.write_all(b"fmt ")?;
+    file.write_all(&16_u32.to_le_bytes())?;
+    file.write_all(&3_u16.to_le_bytes())?; // IEEE float
+    file.write_all(&1_u16.to_le_bytes())?; // channels
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&(sample_rate * 4).to_le_bytes())?; // bytes per second
+    file.write_all(&4_u16.to_le_bytes())?; // bytes per frame
+    file.write_all(&32_u16.to_le_bytes())?; // bits per sample
+
+    file.write_all(b"data")?;
+    file.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        file.write_all(&sample.to_le_bytes())?;
+    }
+
+    file.flush()
+}
+
+/// A [`StreamSource`] feeding a [`StreamSink`] through a shared ring, with the source's device
+/// clock running `clock_offset` fast (or slow, if negative). Time is simulated, so runs are
+/// deterministic and as fast as the CPU allows.
+pub struct LinkedStreams {
+    pub source: StreamSource,
+    pub sink: StreamSink,
+    channels: usize,
+    buffer_frames: usize,
+    /// Callback periods, in units of the sink's callback period
+    source_period: f64,
+    /// When the next callback of either side is due
+    next_source: f64,
+    next_sink: f64,
+    /// Phase of the test tone fed into the source
+    phase: f64,
+    input: Vec<f32>,
+    output: Vec<f32>,
+    initial_ring_frames: f64,
+    /// Frames handed to the source so far
+    pub frames_in: u64,
+    /// Frames the sink has written out so far
+    pub frames_out: u64,
+}
+
+/// A snapshot taken after each sink callback by [`LinkedStreams::run`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkSample {
+    /// Ring fill, as a fraction of its capacity
+    pub fill: f64,
+    pub source_xruns: u64,
+    pub sink_xruns: u64,
+}
+
+impl LinkedStreams {
+    /// Builds a linked pair with default settings. Compensation is left to the sink (the source's
+    /// is disabled), the same as a duplex stream, so the two PIDs don't fight.
+    ///
+    /// * `ring_frames` - ring capacity in frames
+    /// * `channels` - the number of channels
+    /// * `buffer_frames` - how many frames each callback handles (on both sides)
+    /// * `clock_offset` - how much faster the source runs, e.g. `0.001` for 0.1%. Beyond the sink's
+    ///   ratio limit (see [`StreamSink::set_ratio_limit`]), it can't be compensated for
+    pub fn new(ring_frames: usize, channels: usize, buffer_frames: usize, clock_offset: f64) -> LinkedStreams {
+        let (producer, consumer) = rtrb::RingBuffer::new(ring_frames * channels);
+
+        let mut source = StreamSource::with_defaults(producer, channels);
+        source.disable_compensation();
+
+        LinkedStreams::from_parts(
+            source,
+            StreamSink::with_defaults(consumer, channels),
+            buffer_frames,
+            clock_offset,
+        )
+    }
+
+    /// Same as [`LinkedStreams::new`], but with a source and sink that were set up by hand. They
+    /// have to share a ring and channel count.
+    pub fn from_parts(
+        source: StreamSource,
+        sink: StreamSink,
+        buffer_frames: usize,
+        clock_offset: f64,
+    ) -> LinkedStreams {
+        assert_eq!(
+            source.channels(),
+            sink.channels(),
+            "source and sink must have the same channel count"
+        );
+        assert!(clock_offset > -1.0, "the source's clock can't stop");
+
+        let channels = sink.channels();
+        let initial_ring_frames = sink.latency_frames();
+
+        LinkedStreams {
+            source,
+            sink,
+            channels,
+            buffer_frames,
+            source_period: 1.0 / (1.0 + clock_offset),
+            next_source: 0.0,
+            next_sink: 0.0,
+            phase: 0.0,
+            input: vec![0.0; buffer_frames * channels],
+            output: vec![0.0; buffer_frames * channels],
+            initial_ring_frames,
+            frames_in: 0,
+            frames_out: 0,
+        }
+    }
+
+    /// Runs callbacks in time order until the sink has been called `sink_callbacks` times, returning
+    /// a snapshot per sink callback.
+    pub fn run(&mut self, sink_callbacks: usize) -> Vec<LinkSample> {
+        let mut samples = Vec::with_capacity(sink_callbacks);
+
+        while samples.len() < sink_callbacks {
+            if self.next_source <= self.next_sink {
+                self.source_callback();
+                self.next_source += self.source_period;
+            } else {
+                self.sink_callback();
+                self.next_sink += 1.0;
+
+                samples.push(LinkSample {
+                    fill: self.sink.ring_fill(),
+                    source_xruns: self.source.xruns,
+                    sink_xruns: self.sink.xruns,
+                });
+            }
+        }
+
+        samples
+    }
+
+    /// Frames that went into the source but can't be found anywhere: not written out by the sink
+    /// (after undoing the clock offset) and not waiting in the ring. Before compensation settles
+    /// this drifts (the sink isn't resampling yet, and overruns throw frames away), so compare it at
+    /// two points after convergence: the difference should stay within about a ring's worth, and a
+    /// steady climb means frames are being dropped or duplicated.
+    pub fn frames_unaccounted(&self) -> f64 {
+        let consumed = self.frames_out as f64 / self.source_period;
+        let buffered = self.sink.latency_frames() - self.initial_ring_frames;
+
+        self.frames_in as f64 - consumed - buffered
+    }
+
+    fn source_callback(&mut self) {
+        // a quiet 440 Hz tone (at 48 kHz) on every channel, so there's something to resample
+        for frame in self.input.chunks_exact_mut(self.channels) {
+            frame.fill((self.phase.sin() * 0.5) as f32);
+            self.phase = (self.phase + 2.0 * PI * 440.0 / 48000.0) % (2.0 * PI);
+        }
+
+        self.source
+            .input_samples(self.input.iter().copied(), self.input.len(), true);
+        self.frames_in += self.buffer_frames as u64;
+    }
+
+    fn sink_callback(&mut self) {
+        let status = self.sink.output_samples_with_status(&mut self.output, true);
+        self.frames_out += status.frames_written as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompensationStrategy;
+
+    /// Asserts the output came out `1 / resample_ratio` as long as the input, and clean.
+    fn assert_resampled_cleanly(fixture: &ResampleFixture) -> ResampleReport {
+        let report = fixture.run();
+
+        let expected_ratio = 1.0 / fixture.resample_ratio();
+        assert!(
+            (report.sample_ratio - expected_ratio).abs() < 2.0 / fixture.frames as f64,
+            "sample ratio {} instead of {expected_ratio}",
+            report.sample_ratio
+        );
+        assert!(report.thd_n < 1e-4, "THD+N of {}", report.thd_n);
+
+        report
+    }
+
+    #[test]
+    fn fast_input_clock_drops_frames() {
+        let report = assert_resampled_cleanly(&ResampleFixture {
+            ppm_offset: 1000.0,
+            ..ResampleFixture::default()
+        });
+
+        assert!(report.output.len() < 48000);
+    }
+
+    #[test]
+    fn slow_input_clock_adds_frames() {
+        let report = assert_resampled_cleanly(&ResampleFixture {
+            ppm_offset: -1000.0,
+            ..ResampleFixture::default()
+        });
+
+        assert!(report.output.len() > 48000);
+    }
+
+    #[test]
+    fn pure_sine_has_no_distortion() {
+        assert!(thd_n(&sine(1000.0, 48000.0, 4800), 1000.0 / 48000.0) < 1e-6);
+
+        let mut clipped = sine(1000.0, 48000.0, 4800);
+        clipped.iter_mut().for_each(|sample| *sample = sample.clamp(-0.5, 0.5));
+        assert!(thd_n(&clipped, 1000.0 / 48000.0) > 0.05);
+    }
+
+    #[test]
+    fn fixture_output_survives_a_wav_round_trip() {
+        let report = ResampleFixture {
+            frames: 4800,
+            ppm_offset: 500.0,
+            ..ResampleFixture::default()
+        }
+        .run();
+
+        let path = std::env::temp_dir().join(format!("clocked_fixture_{}.wav", std::process::id()));
+        write_wav(&path, &report.output, 48000).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 48000);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(
+            u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as usize,
+            report.output.len() * 4
+        );
+        assert_eq!(bytes.len(), 44 + report.output.len() * 4);
+
+        let samples: Vec<f32> = bytes[44..]
+            .chunks_exact(4)
+            .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+            .collect();
+        assert_eq!(samples, report.output);
+    }
+
+    /// Runs a mono source `clock_offset` fast into a sink, and checks that the sink settles on
+    /// making up the difference.
+    fn assert_linked_streams_converge(clock_offset: f64) {
+        let (producer, consumer) = rtrb::RingBuffer::new(1024);
+        let mut source = StreamSource::with_defaults(producer, 1);
+        source.disable_compensation();
+        // 1% is well past the default ratio limit
+        let mut sink = crate::StreamSinkBuilder::new()
+            .ratio_limit(Some(0.02))
+            .build(consumer, 1);
+        sink.enable_compensation();
+
+        let mut link = LinkedStreams::from_parts(source, sink, 64, clock_offset);
+
+        let settling = link.run(12_000);
+        let unaccounted_before = link.frames_unaccounted();
+        let settled = link.run(8_000);
+        let unaccounted_after = link.frames_unaccounted();
+
+        let last = settling.last().unwrap();
+        for sample in &settled {
+            // measured after the sink's callback, so a block below where the PID aims
+            assert!((sample.fill - 0.5).abs() < 0.15, "ring fill of {}", sample.fill);
+            assert_eq!(
+                (sample.source_xruns, sample.sink_xruns),
+                (last.source_xruns, last.sink_xruns)
+            );
+        }
+
+        match *link.sink.get_strategy() {
+            CompensationStrategy::Resample { resample_ratio, .. } => {
+                let expected = 1.0 + clock_offset;
+                assert!(
+                    (resample_ratio - expected).abs() < 0.001,
+                    "resample ratio of {resample_ratio} instead of {expected}"
+                );
+            }
+            strategy => panic!("not resampling: {strategy:?}"),
+        }
+
+        // nothing lost or duplicated once it settled (give or take the fill wandering a little), out
+        // of half a million frames
+        assert!(
+            (unaccounted_after - unaccounted_before).abs() < 128.0,
+            "{} frames went missing",
+            unaccounted_after - unaccounted_before
+        );
+    }
+
+    #[test]
+    fn linked_streams_converge_with_a_fast_source() {
+        assert_linked_streams_converge(0.01);
+    }
+
+    #[test]
+    fn linked_streams_converge_with_a_slow_source() {
+        assert_linked_streams_converge(-0.01);
+    }
+}
@@ -0,0 +1,378 @@
+//! Small math and buffer layout helpers used throughout the crate.
+
+use core::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Neg, Sub},
+    time::Duration,
+};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Linearly interpolates between `start` and `end`. An `amount` of 0 gives `start`, 1 gives `end`,
+/// and anything outside of that range extrapolates.
+pub fn lerp(start: f64, end: f64, amount: f64) -> f64 {
+    (end - start) * amount + start
+}
+
+/// Why [`interleave`] or [`deinterleave`] refused a set of buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterleaveError {
+    /// The planar channels aren't all the same length
+    UnevenChannels,
+    /// The interleaved buffer isn't `channels * frames` samples long
+    LengthMismatch { expected: usize, actual: usize },
+}
+
+impl fmt::Display for InterleaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterleaveError::UnevenChannels => write!(f, "planar channels have different lengths"),
+            InterleaveError::LengthMismatch { expected, actual } => {
+                write!(f, "interleaved buffer holds {actual} samples, expected {expected}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InterleaveError {}
+
+/// Checks that all of the planar channels are the same length, and that an interleaved buffer of
+/// `interleaved_len` samples holds exactly all of them.
+fn check_layout(channel_lens: impl Iterator<Item = usize>, interleaved_len: usize) -> Result<(), InterleaveError> {
+    let mut channels = 0;
+    let mut frames = None;
+
+    for len in channel_lens {
+        if *frames.get_or_insert(len) != len {
+            return Err(InterleaveError::UnevenChannels);
+        }
+
+        channels += 1;
+    }
+
+    let expected = channels * frames.unwrap_or(0);
+    if expected != interleaved_len {
+        return Err(InterleaveError::LengthMismatch {
+            expected,
+            actual: interleaved_len,
+        });
+    }
+
+    Ok(())
+}
+
+/// Interleaves one buffer per channel into `out` (frame by frame). Nothing is written unless all of
+/// the channels are the same length, and `out` fits them exactly.
+pub fn interleave(planar: &[&[f32]], out: &mut [f32]) -> Result<(), InterleaveError> {
+    check_layout(planar.iter().map(|channel| channel.len()), out.len())?;
+
+    for (channel_i, channel) in planar.iter().enumerate() {
+        for (sample_out, sample) in out.iter_mut().skip(channel_i).step_by(planar.len()).zip(channel.iter()) {
+            *sample_out = *sample;
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits `interleaved` back out into one buffer per channel. The channel count is taken from the
+/// number of buffers in `out`, which all need to be the same length.
+pub fn deinterleave(interleaved: &[f32], out: &mut [&mut [f32]]) -> Result<(), InterleaveError> {
+    check_layout(out.iter().map(|channel| channel.len()), interleaved.len())?;
+
+    let channels = out.len();
+    for (channel_i, channel) in out.iter_mut().enumerate() {
+        for (sample_out, sample) in channel
+            .iter_mut()
+            .zip(interleaved.iter().skip(channel_i).step_by(channels))
+        {
+            *sample_out = *sample;
+        }
+    }
+
+    Ok(())
+}
+
+/// A signed [`Duration`], for when the difference between two timestamps can go either way.
+///
+/// Comparisons go by the signed length, so `Positive(Duration::ZERO)` and
+/// `Negative(Duration::ZERO)` are equal.
+#[derive(Debug, Clone, Copy)]
+pub enum DeltaDuration {
+    Positive(Duration),
+    Negative(Duration),
+}
+
+impl DeltaDuration {
+    pub const ZERO: DeltaDuration = DeltaDuration::Positive(Duration::ZERO);
+
+    /// `first - second`
+    pub fn sub(first: Duration, second: Duration) -> DeltaDuration {
+        if second > first {
+            DeltaDuration::Negative(second - first)
+        } else {
+            DeltaDuration::Positive(first - second)
+        }
+    }
+
+    /// Applies this offset to `other`. Saturates at zero instead of going negative.
+    pub fn add_to(&self, other: Duration) -> Duration {
+        match self {
+            DeltaDuration::Positive(duration) => other + *duration,
+            DeltaDuration::Negative(duration) => other.saturating_sub(*duration),
+        }
+    }
+
+    /// Creates a `DeltaDuration` from a signed number of nanoseconds. Saturates if the magnitude
+    /// doesn't fit in a [`Duration`].
+    pub fn from_nanos(nanos: i128) -> DeltaDuration {
+        let magnitude = nanos.unsigned_abs();
+        let secs = (magnitude / NANOS_PER_SEC).min(u64::MAX as u128) as u64;
+        let duration = Duration::new(secs, (magnitude % NANOS_PER_SEC) as u32);
+
+        if nanos < 0 {
+            DeltaDuration::Negative(duration)
+        } else {
+            DeltaDuration::Positive(duration)
+        }
+    }
+
+    /// Signed length in nanoseconds.
+    pub fn as_nanos(&self) -> i128 {
+        match self {
+            DeltaDuration::Positive(duration) => duration.as_nanos() as i128,
+            DeltaDuration::Negative(duration) => -(duration.as_nanos() as i128),
+        }
+    }
+
+    /// Signed length in seconds.
+    pub fn as_secs_f64(&self) -> f64 {
+        match self {
+            DeltaDuration::Positive(duration) => duration.as_secs_f64(),
+            DeltaDuration::Negative(duration) => -duration.as_secs_f64(),
+        }
+    }
+
+    /// Length without the sign.
+    pub fn abs(&self) -> Duration {
+        match self {
+            DeltaDuration::Positive(duration) | DeltaDuration::Negative(duration) => *duration,
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.as_nanos() < 0
+    }
+}
+
+impl Default for DeltaDuration {
+    fn default() -> Self {
+        DeltaDuration::ZERO
+    }
+}
+
+impl From<Duration> for DeltaDuration {
+    fn from(duration: Duration) -> Self {
+        DeltaDuration::Positive(duration)
+    }
+}
+
+impl PartialEq for DeltaDuration {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_nanos() == other.as_nanos()
+    }
+}
+
+impl Eq for DeltaDuration {}
+
+impl PartialOrd for DeltaDuration {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DeltaDuration {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_nanos().cmp(&other.as_nanos())
+    }
+}
+
+impl Add for DeltaDuration {
+    type Output = DeltaDuration;
+
+    fn add(self, rhs: DeltaDuration) -> DeltaDuration {
+        DeltaDuration::from_nanos(self.as_nanos().saturating_add(rhs.as_nanos()))
+    }
+}
+
+impl Sub for DeltaDuration {
+    type Output = DeltaDuration;
+
+    fn sub(self, rhs: DeltaDuration) -> DeltaDuration {
+        DeltaDuration::from_nanos(self.as_nanos().saturating_sub(rhs.as_nanos()))
+    }
+}
+
+impl Neg for DeltaDuration {
+    type Output = DeltaDuration;
+
+    fn neg(self) -> DeltaDuration {
+        DeltaDuration::from_nanos(-self.as_nanos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: i128) -> DeltaDuration {
+        DeltaDuration::from_nanos(millis * 1_000_000)
+    }
+
+    #[test]
+    fn lerp_interpolates_and_extrapolates() {
+        assert_eq!(lerp(2.0, 4.0, 0.0), 2.0);
+        assert_eq!(lerp(2.0, 4.0, 0.5), 3.0);
+        assert_eq!(lerp(2.0, 4.0, 1.0), 4.0);
+        assert_eq!(lerp(2.0, 4.0, 2.0), 6.0);
+    }
+
+    #[test]
+    fn sub_goes_either_way() {
+        let one = Duration::from_millis(1);
+        let three = Duration::from_millis(3);
+
+        assert_eq!(
+            DeltaDuration::sub(three, one),
+            DeltaDuration::Positive(Duration::from_millis(2))
+        );
+        assert_eq!(
+            DeltaDuration::sub(one, three),
+            DeltaDuration::Negative(Duration::from_millis(2))
+        );
+    }
+
+    #[test]
+    fn add_to_saturates_at_zero() {
+        let ten = Duration::from_millis(10);
+
+        assert_eq!(millis(5).add_to(ten), Duration::from_millis(15));
+        assert_eq!(millis(-5).add_to(ten), Duration::from_millis(5));
+        assert_eq!(millis(-50).add_to(ten), Duration::ZERO);
+    }
+
+    #[test]
+    fn arithmetic_crosses_zero() {
+        assert_eq!(millis(3) + millis(-5), millis(-2));
+        assert_eq!(millis(-3) + millis(-5), millis(-8));
+        assert_eq!(millis(3) - millis(5), millis(-2));
+        assert_eq!(millis(-3) - millis(-5), millis(2));
+        assert_eq!(-millis(4), millis(-4));
+        assert_eq!(-millis(-4), millis(4));
+    }
+
+    #[test]
+    fn nanos_round_trip() {
+        for nanos in [0, 1, -1, 1_500_000_000, -1_500_000_000] {
+            assert_eq!(DeltaDuration::from_nanos(nanos).as_nanos(), nanos);
+        }
+
+        assert_eq!(
+            DeltaDuration::from_nanos(-1_500_000_000),
+            DeltaDuration::Negative(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn secs_f64_keeps_the_sign() {
+        assert_eq!(millis(1500).as_secs_f64(), 1.5);
+        assert_eq!(millis(-1500).as_secs_f64(), -1.5);
+        assert_eq!(millis(-1500).abs(), Duration::from_millis(1500));
+        assert!(millis(-1).is_negative());
+        assert!(!millis(0).is_negative());
+    }
+
+    #[test]
+    fn both_zeros_are_equal() {
+        assert_eq!(
+            DeltaDuration::Positive(Duration::ZERO),
+            DeltaDuration::Negative(Duration::ZERO)
+        );
+        assert_eq!(DeltaDuration::default(), DeltaDuration::ZERO);
+        assert!(millis(-2) < millis(-1));
+        assert!(millis(-1) < DeltaDuration::from(Duration::from_millis(1)));
+    }
+
+    /// `channels` channels of `frames` frames, where each sample is `channel * 100 + frame`.
+    fn planar(channels: usize, frames: usize) -> Vec<Vec<f32>> {
+        (0..channels)
+            .map(|channel| (0..frames).map(|frame| (channel * 100 + frame) as f32).collect())
+            .collect()
+    }
+
+    fn round_trips(channels: usize) {
+        let planar = planar(channels, 7);
+        let planar_refs: Vec<&[f32]> = planar.iter().map(Vec::as_slice).collect();
+
+        let mut interleaved = vec![0.0; channels * 7];
+        interleave(&planar_refs, &mut interleaved).unwrap();
+
+        for (i, sample) in interleaved.iter().enumerate() {
+            assert_eq!(*sample, ((i % channels) * 100 + i / channels) as f32);
+        }
+
+        let mut split = vec![vec![0.0; 7]; channels];
+        let mut split_refs: Vec<&mut [f32]> = split.iter_mut().map(Vec::as_mut_slice).collect();
+        deinterleave(&interleaved, &mut split_refs).unwrap();
+
+        assert_eq!(split, planar);
+    }
+
+    #[test]
+    fn mono_round_trips() {
+        round_trips(1);
+    }
+
+    #[test]
+    fn stereo_round_trips() {
+        round_trips(2);
+    }
+
+    #[test]
+    fn five_channels_round_trip() {
+        round_trips(5);
+    }
+
+    #[test]
+    fn interleaved_length_mismatch_is_an_error() {
+        let planar = planar(2, 4);
+        let planar_refs: Vec<&[f32]> = planar.iter().map(Vec::as_slice).collect();
+        let mut out = [-1.0; 7];
+
+        assert_eq!(
+            interleave(&planar_refs, &mut out),
+            Err(InterleaveError::LengthMismatch { expected: 8, actual: 7 })
+        );
+        // nothing was written
+        assert_eq!(out, [-1.0; 7]);
+
+        let mut left = [0.0; 4];
+        let mut right = [0.0; 4];
+        assert_eq!(
+            deinterleave(&[0.0; 9], &mut [&mut left, &mut right]),
+            Err(InterleaveError::LengthMismatch { expected: 8, actual: 9 })
+        );
+    }
+
+    #[test]
+    fn uneven_channels_are_an_error() {
+        let mut out = [0.0; 5];
+
+        assert_eq!(
+            interleave(&[&[0.0; 3], &[0.0; 2]], &mut out),
+            Err(InterleaveError::UnevenChannels)
+        );
+    }
+}